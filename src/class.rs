@@ -0,0 +1,539 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    context::Context,
+    error::RuntimeError,
+    scope::Scope,
+    source::Located,
+    stmt::{ClassDecl, FunDecl},
+    val::{call_user, Callable, Function, Val},
+};
+
+// Classes and instances have reference semantics and compare by identity.
+// Methods are looked up when they are accessed, so reading one through an
+// instance yields a bound method that remembers the instance as `this`.
+// Lookup starts at the class of the instance and goes up its superclasses,
+// while `super.m` starts at the superclass of the class whose method
+// contains it, whatever the class of `this` is.
+//
+// Calling a class creates an instance and passes the arguments to its
+// `init` method, if it has one. `init` always returns the instance, even
+// when called again on an existing one.
+
+pub struct Class {
+    pub name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Rc<FunDecl>>,
+    statics: HashMap<String, Rc<FunDecl>>,
+    // Class-level fields, which are constants
+    fields: HashMap<String, Val>,
+    // Scope the class was declared in, which its methods close over.
+    // For a subclass it also binds `super`
+    closure: Rc<Scope>,
+}
+
+#[derive(Clone)]
+pub struct Instance(Rc<Object>);
+
+struct Object {
+    class: Rc<Class>,
+    fields: RefCell<HashMap<String, Val>>,
+}
+
+impl Class {
+    pub fn new(
+        decl: &ClassDecl,
+        closure: Rc<Scope>,
+        superclass: Option<Rc<Class>>,
+        fields: HashMap<String, Val>,
+    ) -> Self {
+        let by_name = |methods: &[Rc<FunDecl>]| {
+            methods
+                .iter()
+                .map(|m| (m.name.clone(), Rc::clone(m)))
+                .collect()
+        };
+        Self {
+            name: decl.name.clone(),
+            superclass,
+            methods: by_name(&decl.methods),
+            statics: by_name(&decl.statics),
+            fields,
+            closure,
+        }
+    }
+
+    // The method along with the class that defines it
+    pub fn find_method(self: &Rc<Self>, name: &str) -> Option<(Rc<Class>, Rc<FunDecl>)> {
+        match self.methods.get(name) {
+            Some(method) => Some((Rc::clone(self), Rc::clone(method))),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    // Where the arguments of a call to the class are declared
+    pub fn init_decl(self: &Rc<Self>) -> Option<Located> {
+        self.find_method("init").map(|(_, init)| init.located())
+    }
+
+    // Statics are reached through the class or any of its subclasses, but
+    // not through instances. Fields shadow static methods of the same name
+    pub fn get(self: &Rc<Self>, name: &str) -> Result<Val, RuntimeError> {
+        let mut class = self;
+        loop {
+            if let Some(val) = class.fields.get(name) {
+                return Ok(val.clone());
+            }
+            if let Some(method) = class.statics.get(name) {
+                return Ok(Val::Func(Function::Static {
+                    class: Rc::clone(class),
+                    method: Rc::clone(method),
+                }));
+            }
+            match &class.superclass {
+                Some(superclass) => class = superclass,
+                None => {
+                    return Err(RuntimeError::UndefinedProperty {
+                        name: name.to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    pub fn call_static(
+        &self,
+        ctx: &mut Context,
+        method: &FunDecl,
+        args: Vec<Val>,
+    ) -> Result<Val, RuntimeError> {
+        call_user(ctx, method, &self.closure, args)
+    }
+
+    // Calls `method` with `this` bound to `receiver`
+    pub fn call_method(
+        &self,
+        ctx: &mut Context,
+        receiver: &Instance,
+        method: &FunDecl,
+        args: Vec<Val>,
+    ) -> Result<Val, RuntimeError> {
+        let scope = Scope::new_local(&self.closure);
+        scope.def("this", Val::Instance(receiver.clone()));
+        let ret = call_user(ctx, method, &scope, args)?;
+        if method.name == "init" {
+            Ok(Val::Instance(receiver.clone()))
+        } else {
+            Ok(ret)
+        }
+    }
+}
+
+impl Callable for Rc<Class> {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |(_, init)| init.arity())
+    }
+
+    fn min_arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |(_, init)| init.min_arity())
+    }
+
+    fn call(&self, ctx: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        let instance = Instance(Rc::new(Object {
+            class: Rc::clone(self),
+            fields: RefCell::new(HashMap::new()),
+        }));
+        match self.find_method("init") {
+            Some((class, init)) => class.call_method(ctx, &instance, &init, args),
+            None => Ok(Val::Instance(instance)),
+        }
+    }
+}
+
+impl Instance {
+    pub fn same(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    // Fields shadow methods of the same name
+    pub fn get(&self, name: &str) -> Result<Val, RuntimeError> {
+        if let Some(val) = self.0.fields.borrow().get(name) {
+            return Ok(val.clone());
+        }
+        self.bind(&self.0.class, name)
+    }
+
+    // Like `get`, but only for methods
+    pub fn method(&self, name: &str) -> Option<Val> {
+        self.bind(&self.0.class, name).ok()
+    }
+
+    // Looks `name` up as a method starting at `class`
+    pub fn bind(&self, class: &Rc<Class>, name: &str) -> Result<Val, RuntimeError> {
+        match class.find_method(name) {
+            Some((class, method)) => Ok(Val::Func(Function::Bound {
+                receiver: self.clone(),
+                class,
+                method,
+            })),
+            None => Err(RuntimeError::UndefinedProperty {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    pub fn set(&self, name: &str, val: Val) {
+        self.0.fields.borrow_mut().insert(name.to_string(), val);
+    }
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl std::fmt::Debug for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+impl std::fmt::Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.0.class.name)
+    }
+}
+
+// Fields are left out, since they may well refer back to the instance
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} instance>", self.0.class.name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{global, run};
+
+    #[test]
+    fn fields_and_methods() {
+        let env = run(r#"
+            class Counter {
+                incr(by) { this.n = this.n + by; return this; }
+            }
+            let c = Counter();
+            c.n = 1;
+            c.incr(2).incr(3);
+            let n = c.n;"#);
+        assert_eq!(global(&env, "n"), Val::Number(6.0));
+        assert_eq!(global(&env, "c").to_string(), "Counter instance");
+        assert_eq!(global(&env, "Counter").to_string(), "Counter");
+    }
+
+    #[test]
+    fn bound_method_sees_later_state() {
+        let env = run(r#"
+            class Box { get() { return this.v; } }
+            let b = Box();
+            b.v = "old";
+            let get = b.get;
+            b.v = "new";
+            let v = get();"#);
+        assert_eq!(global(&env, "v"), Val::String("new".into()));
+        assert_eq!(global(&env, "get").to_string(), "<bound fn Box.get/0>");
+    }
+
+    #[test]
+    fn bound_method_as_callback() {
+        let env = run(r#"
+            class Acc { add(i) { this.total = this.total + i; } }
+            let a = Acc();
+            a.total = 0;
+            times(4, a.add);
+            let total = a.total;"#);
+        assert_eq!(global(&env, "total"), Val::Number(6.0));
+    }
+
+    #[test]
+    fn bound_method_identity() {
+        let env = run(r#"
+            class A { m() {} }
+            let a = A(); let b = A();
+            let same = a.m == a.m;
+            let other = a.m == b.m;
+            let f = a.m;
+            f = f == a.m;"#);
+        assert_eq!(global(&env, "same"), Val::Boolean(true));
+        assert_eq!(global(&env, "other"), Val::Boolean(false));
+        assert_eq!(global(&env, "f"), Val::Boolean(true));
+    }
+
+    #[test]
+    fn field_shadows_method() {
+        let env = run(r#"
+            class A { m() { return "method"; } }
+            let a = A();
+            fn f() { return "field"; }
+            a.m = f;
+            let r = a.m();"#);
+        assert_eq!(global(&env, "r"), Val::String("field".into()));
+    }
+
+    #[test]
+    fn inheritance() {
+        let env = run(r#"
+            class A {
+                name() { return "A"; }
+                greet() { return "hi from " + this.name(); }
+            }
+            class B < A {
+                name() { return "B"; }
+            }
+            let greeting = B().greet();
+            let bound = B().greet;"#);
+        assert_eq!(global(&env, "greeting"), Val::String("hi from B".into()));
+        assert_eq!(global(&env, "bound").to_string(), "<bound fn A.greet/0>");
+    }
+
+    #[test]
+    fn super_reaches_grandparent() {
+        let env = run(r#"
+            class A { m() { return "A"; } }
+            class B < A {}
+            class C < B { m() { return "C" + super.m(); } }
+            let r = C().m();"#);
+        assert_eq!(global(&env, "r"), Val::String("CA".into()));
+    }
+
+    // `super` is bound where the method is written, so a call through an
+    // instance of a subclass does not move where the lookup starts
+    #[test]
+    fn super_is_lexical() {
+        let env = run(r#"
+            class A { m() { return "A"; } }
+            class B < A { m() { return "B" + super.m(); } }
+            class C < B {}
+            let r = C().m();
+            let m = C().m;
+            class D < B { get() { return super.m; } }
+            let s = D().get()();"#);
+        assert_eq!(global(&env, "r"), Val::String("BA".into()));
+        assert_eq!(global(&env, "m").to_string(), "<bound fn B.m/0>");
+        assert_eq!(global(&env, "s"), Val::String("BA".into()));
+    }
+
+    #[test]
+    fn statics() {
+        let env = run(r#"
+            class Math2 {
+                static PI = 3;
+                static square(x) { return x * x; }
+                static area(r) { return Math2.PI * Math2.square(r); }
+            }
+            class Sub < Math2 {}
+            let sq = Math2.square(3);
+            let area = Sub.area(2);
+            let f = Math2.square;"#);
+        assert_eq!(global(&env, "sq"), Val::Number(9.0));
+        assert_eq!(global(&env, "area"), Val::Number(12.0));
+        assert_eq!(global(&env, "f").to_string(), "<static fn Math2.square/1>");
+    }
+
+    #[test]
+    fn initializer() {
+        let env = run(r#"
+            class Point {
+                init(x, y) { this.x = x; this.y = y; }
+            }
+            class Origin < Point {
+                init() {
+                    super.init(0, 0);
+                    if (true) return;
+                    this.x = 1;
+                }
+            }
+            class Empty {}
+            let p = Point(1, 2);
+            let sum = p.x + p.y;
+            let o = Origin();
+            let x = o.x;
+            let e = Empty();"#);
+        assert_eq!(global(&env, "sum"), Val::Number(3.0));
+        assert_eq!(global(&env, "x"), Val::Number(0.0));
+        assert_eq!(global(&env, "e").to_string(), "Empty instance");
+    }
+
+    #[test]
+    fn direct_reinit() {
+        let env = run(r#"
+            class C { init(n) { this.n = n; } }
+            let c = C(1);
+            let d = c.init(2);
+            let same = d == c;
+            let n = c.n;"#);
+        assert_eq!(global(&env, "same"), Val::Boolean(true));
+        assert_eq!(global(&env, "n"), Val::Number(2.0));
+    }
+
+    #[test]
+    fn operators() {
+        let env = run(r#"
+            class Vec2 {
+                init(x, y) { this.x = x; this.y = y; }
+                plus(o) { return Vec2(this.x + o.x, this.y + o.y); }
+                minus(o) { return Vec2(this.x - o.x, this.y - o.y); }
+                times(k) { return Vec2(this.x * k, this.y * k); }
+                less(o) { return this.x * this.x + this.y * this.y < o.x * o.x + o.y * o.y; }
+                equals(o) { return this.x == o.x and this.y == o.y; }
+            }
+            let a = Vec2(1, 2);
+            let b = Vec2(3, 4);
+            let sum = a + b;
+            let diff = b - a;
+            let scaled = a * 3;
+            let lt = a < b;
+            let eq = a + b == Vec2(4, 6);
+            let ne = a != Vec2(1, 2);
+            class Plain {}
+            let p = Plain();
+            let same = p == p;
+            let other = p == Plain();"#);
+        let field = |name, f| match global(&env, name) {
+            Val::Instance(i) => i.get(f).unwrap(),
+            v => panic!("{v} is not an instance"),
+        };
+        assert_eq!(field("sum", "x"), Val::Number(4.0));
+        assert_eq!(field("sum", "y"), Val::Number(6.0));
+        assert_eq!(field("diff", "y"), Val::Number(2.0));
+        assert_eq!(field("scaled", "y"), Val::Number(6.0));
+        assert_eq!(global(&env, "lt"), Val::Boolean(true));
+        assert_eq!(global(&env, "eq"), Val::Boolean(true));
+        assert_eq!(global(&env, "ne"), Val::Boolean(false));
+        assert_eq!(global(&env, "same"), Val::Boolean(true));
+        assert_eq!(global(&env, "other"), Val::Boolean(false));
+    }
+
+    #[test]
+    fn errors() {
+        let fails = |src| {
+            let mut lox = crate::Interpreter::builder().build();
+            lox.run(&crate::source::SourceMap::new("test", src))
+                .unwrap_err()
+        };
+        use crate::error::{LoxError, ResolveError};
+        let at = |col| crate::lexer::Loc {
+            row: 0,
+            col,
+            offset: col,
+        };
+        let declared = |col| crate::source::Located {
+            source: "test".into(),
+            loc: at(col),
+        };
+        assert_eq!(
+            fails("class A {} A().x;"),
+            LoxError::Runtime(RuntimeError::UndefinedProperty {
+                name: "x".to_string()
+            })
+        );
+        assert_eq!(
+            fails("class A { m(a) {} } let m = A().m; m();"),
+            LoxError::Runtime(RuntimeError::Arity {
+                callee: Some("A.m".to_string()),
+                min: 1,
+                max: 1,
+                got: 0,
+                decl: Some(declared(10)),
+            })
+        );
+        assert!(matches!(
+            fails("let x = 1; x.y = 2;"),
+            LoxError::Runtime(RuntimeError::TypeMismatch { .. })
+        ));
+        assert_eq!(
+            fails("fn f() { return this; }"),
+            LoxError::Resolve(vec![ResolveError::ThisOutsideClass { loc: at(16) }])
+        );
+        assert_eq!(
+            fails("class P { init(x, y) {} } P(1);"),
+            LoxError::Runtime(RuntimeError::Arity {
+                callee: Some("P".to_string()),
+                min: 2,
+                max: 2,
+                got: 1,
+                decl: Some(declared(10)),
+            })
+        );
+        assert_eq!(
+            fails("class P { init() { return 1; } }"),
+            LoxError::Resolve(vec![ResolveError::ReturnFromInit { loc: at(19) }])
+        );
+        // Only the left operand is looked at
+        assert!(matches!(
+            fails("class V { times(k) { return this; } } 2 * V();"),
+            LoxError::Runtime(RuntimeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            fails("class V {} V() + V();"),
+            LoxError::Runtime(RuntimeError::Operands {
+                lhs: "instance",
+                rhs: "instance",
+                ..
+            })
+        ));
+        // Statics do not belong to instances
+        assert_eq!(
+            fails("class A { static s() {} } A().s();"),
+            LoxError::Runtime(RuntimeError::UndefinedProperty {
+                name: "s".to_string()
+            })
+        );
+        assert_eq!(
+            fails("class A { static s(x) {} } A.s();"),
+            LoxError::Runtime(RuntimeError::Arity {
+                callee: Some("A.s".to_string()),
+                min: 1,
+                max: 1,
+                got: 0,
+                decl: Some(declared(17)),
+            })
+        );
+        assert!(matches!(
+            fails("class A { static K = 1; } A.K = 2;"),
+            LoxError::Runtime(RuntimeError::TypeMismatch { .. })
+        ));
+        assert_eq!(
+            fails("class A { static s() { fn f() { return this; } } }"),
+            LoxError::Resolve(vec![ResolveError::ThisInStatic { loc: at(39) }])
+        );
+        assert_eq!(
+            fails("class A < A {}"),
+            LoxError::Resolve(vec![ResolveError::InheritsItself {
+                name: "A".to_string(),
+                loc: at(10)
+            }])
+        );
+        assert_eq!(
+            fails("class A { m() { super.m(); } } fn f() { super.m(); }"),
+            LoxError::Resolve(vec![
+                ResolveError::SuperOutsideSubclass { loc: at(16) },
+                ResolveError::SuperOutsideSubclass { loc: at(40) }
+            ])
+        );
+        assert_eq!(
+            fails("let N = 1; class A < N {}"),
+            LoxError::Runtime(RuntimeError::TypeMismatch {
+                msg: "a superclass must be a class"
+            })
+        );
+        assert_eq!(
+            fails("class A {} class B < A { m() { super.m(); } } B().m();"),
+            LoxError::Runtime(RuntimeError::UndefinedProperty {
+                name: "m".to_string()
+            })
+        );
+    }
+}
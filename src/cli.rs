@@ -0,0 +1,353 @@
+// Command line parsing, kept apart from `main` so it can be tested
+
+use lox::{
+    error::{Level, Warnings},
+    metrics::Thresholds,
+};
+
+pub const USAGE: &str = "\
+Usage: lox [options] [script] [-- args...]
+       lox <command> [options] [arguments]
+
+Commands:
+    run <script> [-- args...]   Run a script, passing it `args` (the default)
+    repl                        Start an interactive session (the default
+                                when no script is given)
+    fmt <script>                Print a script in canonical form
+    lint <script>               Report every warning, including strict ones
+    check <script>              Report errors and warnings without running
+    tokens <script>             Print the tokens of a script
+    ast <script>                Print the syntax tree of a script
+    explain <code>              Explain a diagnostic by the code in its
+                                header, such as E0101 in `error[E0101]`
+
+Options:
+    -h, --help                  Print this help
+    --strict                    Enable warnings that are off by default
+    --no-prelude                Do not load the standard prelude
+    --compat=jlox               Also accept `var` and `fun` for `let` and `fn`
+    --coerce-strings            Let `+` make a string of a number added to
+                                a string instead of failing
+    --strict-math               Fail on division by zero instead of giving
+                                `inf` or `nan`
+    --sandbox                   Run untrusted code: no `import`, and limits
+                                on calls, statements, strings and lists
+    --max-input=<bytes>         Largest line the REPL accepts (1 MiB)
+    --max-call-depth=<n>        Deepest a script may call before it fails
+                                with a stack overflow (256)
+    --deny-warnings             Fail to compile on any warning not allowed
+    --allow=<lint>              Do not report the warnings of a lint
+    --warn=<lint>               Report them, the default for every lint
+    --deny=<lint>               Report them as errors, so nothing runs
+
+Lint options:
+    --metrics                   Print a table of metrics for every function
+    --metrics-json              Print them as one JSON object per function
+    --max-complexity=<n>        Warn about functions over these limits
+    --max-depth=<n>
+    --max-params=<n>";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run { script: String, args: Vec<String> },
+    Repl,
+    Fmt(String),
+    Lint(String),
+    Check(String),
+    Tokens(String),
+    Ast(String),
+    Explain(String),
+    Help,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Metrics {
+    Table,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub strict: bool,
+    pub prelude: bool,
+    pub jlox: bool,
+    pub coerce_strings: bool,
+    pub strict_math: bool,
+    pub sandbox: bool,
+    pub max_input: Option<usize>,
+    pub max_call_depth: Option<usize>,
+    pub metrics: Option<Metrics>,
+    pub limits: Thresholds,
+    pub warnings: Warnings,
+}
+
+fn number(n: &str) -> Result<usize, String> {
+    n.parse().map_err(|_| format!("invalid number '{n}'"))
+}
+
+// The command the words left once options are taken out make up
+fn command(
+    mut words: Vec<String>,
+    script_args: Option<Vec<String>>,
+    lint_options: bool,
+) -> Result<Command, String> {
+    let (cmd, operands) = match words.first().map(String::as_str) {
+        Some(cmd @ ("run" | "repl" | "fmt" | "lint" | "check" | "tokens" | "ast" | "explain")) => {
+            (cmd.to_string(), words.split_off(1))
+        }
+        // A bare `lox script` runs it, and a bare `lox` starts the REPL
+        Some(_) => ("run".to_string(), words),
+        None => ("repl".to_string(), words),
+    };
+    if script_args.is_some() && cmd != "run" {
+        return Err(format!("'{cmd}' does not take script arguments"));
+    }
+    if lint_options && cmd != "lint" {
+        return Err(format!("'{cmd}' does not take lint options"));
+    }
+
+    Ok(match (cmd.as_str(), <[String; 1]>::try_from(operands)) {
+        ("repl", Err(operands)) if operands.is_empty() => Command::Repl,
+        ("run", Ok([script])) => Command::Run {
+            script,
+            args: script_args.unwrap_or_default(),
+        },
+        ("fmt", Ok([script])) => Command::Fmt(script),
+        ("lint", Ok([script])) => Command::Lint(script),
+        ("check", Ok([script])) => Command::Check(script),
+        ("tokens", Ok([script])) => Command::Tokens(script),
+        ("ast", Ok([script])) => Command::Ast(script),
+        ("explain", Ok([code])) => Command::Explain(code),
+        ("repl", _) => return Err("'repl' takes no arguments".to_string()),
+        ("explain", _) => return Err("'explain' takes a single code".to_string()),
+        (cmd, _) => return Err(format!("'{cmd}' takes a single script")),
+    })
+}
+
+impl Cli {
+    // Options may appear anywhere before `--`, everything after it is passed
+    // to the script
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mut strict = false;
+        let mut prelude = true;
+        let mut jlox = false;
+        let mut coerce_strings = false;
+        let mut strict_math = false;
+        let mut sandbox = false;
+        let mut max_input = None;
+        let mut max_call_depth = None;
+        let mut metrics = None;
+        let mut limits = Thresholds::default();
+        let mut warnings = Warnings::default();
+        let mut words = Vec::new();
+        let mut script_args = None;
+        let mut help = false;
+        for a in args.by_ref() {
+            match a.as_str() {
+                "--" => {
+                    script_args = Some(args.by_ref().collect());
+                    break;
+                }
+                "--help" | "-h" => help = true,
+                "--strict" => strict = true,
+                "--no-prelude" => prelude = false,
+                "--compat=jlox" => jlox = true,
+                opt if let Some(d) = opt.strip_prefix("--compat=") => {
+                    return Err(format!("unknown dialect '{d}'"))
+                }
+                "--coerce-strings" => coerce_strings = true,
+                "--strict-math" => strict_math = true,
+                "--sandbox" => sandbox = true,
+                "--deny-warnings" => warnings.default = Level::Deny,
+                opt if let Some(lint) = opt.strip_prefix("--allow=") => {
+                    warnings.set(lint, Level::Allow)?
+                }
+                opt if let Some(lint) = opt.strip_prefix("--warn=") => {
+                    warnings.set(lint, Level::Warn)?
+                }
+                opt if let Some(lint) = opt.strip_prefix("--deny=") => {
+                    warnings.set(lint, Level::Deny)?
+                }
+                "--metrics" => metrics = Some(Metrics::Table),
+                "--metrics-json" => metrics = Some(Metrics::Json),
+                opt if let Some(n) = opt.strip_prefix("--max-input=") => {
+                    max_input = Some(number(n)?)
+                }
+                opt if let Some(n) = opt.strip_prefix("--max-call-depth=") => {
+                    max_call_depth = Some(number(n)?)
+                }
+                opt if let Some(n) = opt.strip_prefix("--max-complexity=") => {
+                    limits.complexity = Some(number(n)?)
+                }
+                opt if let Some(n) = opt.strip_prefix("--max-depth=") => {
+                    limits.depth = Some(number(n)?)
+                }
+                opt if let Some(n) = opt.strip_prefix("--max-params=") => {
+                    limits.params = Some(number(n)?)
+                }
+                opt if opt.starts_with('-') => return Err(format!("unknown option '{opt}'")),
+                _ => words.push(a),
+            }
+        }
+
+        let lint_options = metrics.is_some() || limits != Thresholds::default();
+        let command = match help {
+            true => Command::Help,
+            false => command(words, script_args, lint_options)?,
+        };
+        Ok(Self {
+            command,
+            strict,
+            prelude,
+            jlox,
+            coerce_strings,
+            strict_math,
+            sandbox,
+            max_input,
+            max_call_depth,
+            metrics,
+            limits,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        Cli::parse(args.split_whitespace().map(String::from))
+    }
+
+    fn command(args: &str) -> Command {
+        parse(args).unwrap().command
+    }
+
+    fn script(name: &str) -> String {
+        name.to_string()
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(command(""), Command::Repl);
+        assert_eq!(
+            command("a.lox"),
+            Command::Run {
+                script: script("a.lox"),
+                args: vec![]
+            }
+        );
+        assert_eq!(
+            command("a.lox -- x y"),
+            Command::Run {
+                script: script("a.lox"),
+                args: vec![script("x"), script("y")]
+            }
+        );
+    }
+
+    #[test]
+    fn subcommands() {
+        assert_eq!(command("repl"), Command::Repl);
+        assert_eq!(
+            command("run a.lox -- --strict"),
+            Command::Run {
+                script: script("a.lox"),
+                args: vec![script("--strict")]
+            }
+        );
+        assert_eq!(command("fmt a.lox"), Command::Fmt(script("a.lox")));
+        assert_eq!(command("lint a.lox"), Command::Lint(script("a.lox")));
+        assert_eq!(command("check a.lox"), Command::Check(script("a.lox")));
+        assert_eq!(command("tokens a.lox"), Command::Tokens(script("a.lox")));
+        assert_eq!(command("ast a.lox"), Command::Ast(script("a.lox")));
+        assert_eq!(command("explain E0101"), Command::Explain(script("E0101")));
+        // Help whatever else is given
+        assert_eq!(command("--help"), Command::Help);
+        assert_eq!(command("-h"), Command::Help);
+        assert_eq!(command("run a.lox -h -- x"), Command::Help);
+    }
+
+    #[test]
+    fn options() {
+        let cli = parse("--strict run --no-prelude a.lox").unwrap();
+        assert!(cli.strict);
+        assert!(!cli.prelude);
+        let cli = parse("check a.lox").unwrap();
+        assert!(!cli.strict);
+        assert!(cli.prelude);
+        assert!(parse("--fast a.lox").is_err());
+        assert_eq!(parse("--max-input=10").unwrap().max_input, Some(10));
+        assert!(parse("--max-input=ten").is_err());
+        assert_eq!(
+            parse("--max-call-depth=50").unwrap().max_call_depth,
+            Some(50)
+        );
+        assert_eq!(parse("a.lox").unwrap().max_call_depth, None);
+        assert!(parse("--compat=jlox a.lox").unwrap().jlox);
+        assert!(!parse("a.lox").unwrap().jlox);
+        assert!(parse("--coerce-strings a.lox").unwrap().coerce_strings);
+        assert!(!parse("a.lox").unwrap().coerce_strings);
+        assert!(parse("--strict-math a.lox").unwrap().strict_math);
+        assert!(!parse("a.lox").unwrap().strict_math);
+        assert!(parse("--sandbox a.lox").unwrap().sandbox);
+        assert!(!parse("a.lox").unwrap().sandbox);
+        assert_eq!(
+            parse("--compat=clox a.lox"),
+            Err("unknown dialect 'clox'".to_string())
+        );
+    }
+
+    #[test]
+    fn lint_levels() {
+        let w = parse("--deny-warnings --allow=unused_variable a.lox")
+            .unwrap()
+            .warnings;
+        assert_eq!(w.default, Level::Deny);
+        let mut expected = Warnings::default();
+        expected.default = Level::Deny;
+        expected.set("unused_variable", Level::Allow).unwrap();
+        assert_eq!(w, expected);
+        assert_eq!(parse("a.lox").unwrap().warnings, Warnings::default());
+        assert_eq!(
+            parse("--deny=unused a.lox"),
+            Err("unknown lint 'unused'".to_string())
+        );
+    }
+
+    #[test]
+    fn lint_options() {
+        let cli = parse("lint --metrics-json --max-complexity=15 a.lox").unwrap();
+        assert_eq!(cli.metrics, Some(Metrics::Json));
+        assert_eq!(
+            cli.limits,
+            Thresholds {
+                complexity: Some(15),
+                ..Thresholds::default()
+            }
+        );
+        assert_eq!(parse("lint a.lox").unwrap().metrics, None);
+        assert_eq!(
+            parse("lint --metrics a.lox").unwrap().metrics,
+            Some(Metrics::Table)
+        );
+        assert!(parse("lint --max-depth=deep a.lox").is_err());
+        assert!(parse("check --metrics a.lox").is_err());
+        assert!(parse("--max-params=3 a.lox").is_err());
+    }
+
+    #[test]
+    fn wrong_operands() {
+        assert!(parse("a.lox b.lox").is_err());
+        assert!(parse("run").is_err());
+        assert!(parse("repl a.lox").is_err());
+        assert!(parse("fmt").is_err());
+        assert!(parse("fmt a.lox -- x").is_err());
+        assert!(parse("explain").is_err());
+        assert!(parse("explain E0101 E0102").is_err());
+        assert!(parse("-- x").is_err());
+    }
+}
@@ -0,0 +1,506 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use crate::{context::Context, error::RuntimeError, val::Val};
+
+// Lists and maps have reference semantics: copies of a value share the same
+// contents, and mutations through one of them are visible through all.
+// A frozen value is a view that shares the contents of the value it was
+// created from, but rejects any mutation made through it or through any
+// container reached from it.
+
+#[derive(Clone)]
+pub struct List {
+    items: Rc<RefCell<Vec<Val>>>,
+    frozen: bool,
+}
+
+#[derive(Clone)]
+pub struct Map {
+    entries: Rc<RefCell<BTreeMap<Rc<str>, Val>>>,
+    frozen: bool,
+}
+
+// The longest string a script may build by default, in bytes. Longer ones
+// fail with an error rather than taking the process down when allocating them
+pub const MAX_STRING_LEN: usize = 1 << 30;
+
+// Checks the length of a string about to be built, if it could be computed
+pub(crate) fn check_string_len(ctx: &Context, len: Option<usize>) -> Result<(), RuntimeError> {
+    match len {
+        Some(len) if len <= ctx.max_string_len => Ok(()),
+        _ => Err(RuntimeError::StringTooLong {
+            max: ctx.max_string_len,
+        }),
+    }
+}
+
+// Strings are sequences of Unicode scalar values: `len` counts them and
+// `s[i]` is the i-th one, as a string of its own. Both need the offsets of
+// the characters of a string, which the context of the interpreter keeps
+// for the last string used so that looping over one is linear.
+// No offsets for an ASCII string, where they are the indices themselves
+type Offsets = Option<Vec<usize>>;
+
+#[derive(Default)]
+pub(crate) struct LastString(Option<(Rc<str>, Offsets)>);
+
+impl LastString {
+    fn offsets(&mut self, s: &Rc<str>) -> Option<&[usize]> {
+        if !self.0.as_ref().is_some_and(|(l, _)| Rc::ptr_eq(l, s)) {
+            let offsets = (!s.is_ascii()).then(|| s.char_indices().map(|(i, _)| i).collect());
+            self.0 = Some((Rc::clone(s), offsets));
+        }
+        self.0.as_ref().and_then(|(_, o)| o.as_deref())
+    }
+}
+
+pub fn char_len(ctx: &mut Context, s: &Rc<str>) -> usize {
+    ctx.last_string.offsets(s).map_or(s.len(), <[_]>::len)
+}
+
+pub fn char_at(ctx: &mut Context, s: &Rc<str>, idx: &Val) -> Result<Val, RuntimeError> {
+    let offsets = ctx.last_string.offsets(s);
+    let len = offsets.map_or(s.len(), <[_]>::len);
+    let i = index(idx, len, "strings can only be indexed by numbers")?;
+    let start = offsets.map_or(i, |o| o[i]);
+    let c = s[start..]
+        .chars()
+        .next()
+        .expect("offsets are character boundaries");
+    Ok(Val::String(c.to_string().into()))
+}
+
+// Identity of a container's contents, used to detect cycles
+type Ptr = *const ();
+
+fn index(idx: &Val, len: usize, msg: &'static str) -> Result<usize, RuntimeError> {
+    match idx {
+        Val::Number(x) if x.fract() == 0.0 && *x >= 0.0 && (*x as usize) < len => Ok(*x as usize),
+        Val::Number(x) => Err(RuntimeError::IndexOutOfRange { idx: *x, len }),
+        _ => Err(RuntimeError::TypeMismatch { msg }),
+    }
+}
+
+impl List {
+    pub fn new(items: Vec<Val>) -> Self {
+        Self {
+            items: Rc::new(RefCell::new(items)),
+            frozen: false,
+        }
+    }
+
+    fn ptr(&self) -> Ptr {
+        Rc::as_ptr(&self.items) as Ptr
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+
+    fn index(&self, idx: &Val) -> Result<usize, RuntimeError> {
+        index(idx, self.len(), "lists can only be indexed by numbers")
+    }
+
+    pub fn get(&self, idx: &Val) -> Result<Val, RuntimeError> {
+        let i = self.index(idx)?;
+        Ok(self.view(self.items.borrow()[i].clone()))
+    }
+
+    pub fn set(&self, idx: &Val, val: Val) -> Result<(), RuntimeError> {
+        self.check_mut()?;
+        let i = self.index(idx)?;
+        self.items.borrow_mut()[i] = val;
+        Ok(())
+    }
+
+    pub fn push(&self, val: Val) -> Result<(), RuntimeError> {
+        self.check_mut()?;
+        self.items.borrow_mut().push(val);
+        Ok(())
+    }
+
+    fn check_mut(&self) -> Result<(), RuntimeError> {
+        if self.frozen {
+            Err(RuntimeError::Frozen { kind: "list" })
+        } else {
+            Ok(())
+        }
+    }
+
+    // Values reached through a frozen container are frozen too
+    fn view(&self, val: Val) -> Val {
+        if self.frozen {
+            val.freeze()
+        } else {
+            val
+        }
+    }
+
+    pub fn same(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.items, &other.items)
+    }
+}
+
+impl Map {
+    pub fn new(entries: BTreeMap<Rc<str>, Val>) -> Self {
+        Self {
+            entries: Rc::new(RefCell::new(entries)),
+            frozen: false,
+        }
+    }
+
+    fn ptr(&self) -> Ptr {
+        Rc::as_ptr(&self.entries) as Ptr
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    fn key(key: &Val) -> Result<Rc<str>, RuntimeError> {
+        match key {
+            Val::String(s) => Ok(Rc::clone(s)),
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "map keys must be strings",
+            }),
+        }
+    }
+
+    pub fn has(&self, key: &Val) -> Result<bool, RuntimeError> {
+        Ok(self.entries.borrow().contains_key(&Self::key(key)?))
+    }
+
+    pub fn get(&self, key: &Val) -> Result<Val, RuntimeError> {
+        let key = Self::key(key)?;
+        match self.entries.borrow().get(&key) {
+            Some(val) if self.frozen => Ok(val.clone().freeze()),
+            Some(val) => Ok(val.clone()),
+            None => Err(RuntimeError::UndefinedKey {
+                key: key.to_string(),
+            }),
+        }
+    }
+
+    pub fn set(&self, key: &Val, val: Val) -> Result<(), RuntimeError> {
+        if self.frozen {
+            return Err(RuntimeError::Frozen { kind: "map" });
+        }
+        self.entries.borrow_mut().insert(Self::key(key)?, val);
+        Ok(())
+    }
+
+    pub fn same(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.entries, &other.entries)
+    }
+}
+
+impl Val {
+    pub fn freeze(self) -> Val {
+        match self {
+            Val::List(l) => Val::List(List { frozen: true, ..l }),
+            Val::Map(m) => Val::Map(Map { frozen: true, ..m }),
+            v => v,
+        }
+    }
+
+    // Structural equality. `path` holds the pairs of containers being
+    // compared by the callers: meeting one of them again means a cycle,
+    // which is considered equal so far and is not followed again
+    fn eq_in(&self, other: &Val, path: &mut Vec<(Ptr, Ptr)>) -> bool {
+        let pair = match (self, other) {
+            (Val::List(a), Val::List(b)) => (a.ptr(), b.ptr()),
+            (Val::Map(a), Val::Map(b)) => (a.ptr(), b.ptr()),
+            // As in IEEE 754, NaN equals nothing, not even itself
+            (Val::Number(x), Val::Number(y)) => return x == y,
+            (Val::Boolean(a), Val::Boolean(b)) => return a == b,
+            (Val::String(s), Val::String(t)) => return s == t,
+            (Val::Nil, Val::Nil) => return true,
+            (Val::Func(f), Val::Func(g)) => return f == g,
+            (Val::HostCallable(f), Val::HostCallable(g)) => return Rc::ptr_eq(f, g),
+            (Val::Class(a), Val::Class(b)) => return Rc::ptr_eq(a, b),
+            (Val::Instance(a), Val::Instance(b)) => return a.same(b),
+            _ => return false,
+        };
+        if path.contains(&pair) {
+            return true;
+        }
+        path.push(pair);
+        let eq = match (self, other) {
+            (Val::List(a), Val::List(b)) => {
+                let (a, b) = (a.items.borrow(), b.items.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.eq_in(y, path))
+            }
+            (Val::Map(a), Val::Map(b)) => {
+                let (a, b) = (a.entries.borrow(), b.entries.borrow());
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((k, x), (l, y))| k == l && x.eq_in(y, path))
+            }
+            _ => unreachable!(),
+        };
+        path.pop();
+        eq
+    }
+
+    // Copies containers all the way down. Shared contents are copied
+    // separately, and cyclic values cannot be copied at all
+    pub fn deep_clone(&self) -> Result<Val, RuntimeError> {
+        self.clone_in(&mut Vec::new())
+    }
+
+    fn clone_in(&self, path: &mut Vec<Ptr>) -> Result<Val, RuntimeError> {
+        let ptr = match self {
+            Val::List(l) => l.ptr(),
+            Val::Map(m) => m.ptr(),
+            v => return Ok(v.clone()),
+        };
+        if path.contains(&ptr) {
+            return Err(RuntimeError::CyclicClone);
+        }
+        path.push(ptr);
+        let copy = match self {
+            Val::List(l) => Val::List(List::new(
+                l.items
+                    .borrow()
+                    .iter()
+                    .map(|v| v.clone_in(path))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Val::Map(m) => Val::Map(Map::new(
+                m.entries
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| Ok((Rc::clone(k), v.clone_in(path)?)))
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => unreachable!(),
+        };
+        path.pop();
+        Ok(copy)
+    }
+
+    // Containers show strings quoted and cycles as `[...]` or `{...}`
+    fn fmt_in(&self, f: &mut std::fmt::Formatter<'_>, path: &mut Vec<Ptr>) -> std::fmt::Result {
+        match self {
+            Val::String(s) => return write!(f, "\"{s}\""),
+            Val::List(l) if path.contains(&l.ptr()) => return write!(f, "[...]"),
+            Val::Map(m) if path.contains(&m.ptr()) => return write!(f, "{{...}}"),
+            Val::List(l) => {
+                path.push(l.ptr());
+                write!(f, "[")?;
+                for (i, v) in l.items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    v.fmt_in(f, path)?;
+                }
+                write!(f, "]")?;
+            }
+            Val::Map(m) => {
+                path.push(m.ptr());
+                write!(f, "{{")?;
+                for (i, (k, v)) in m.entries.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "\"{k}\": ")?;
+                    v.fmt_in(f, path)?;
+                }
+                write!(f, "}}")?;
+            }
+            v => return write!(f, "{v}"),
+        }
+        path.pop();
+        Ok(())
+    }
+}
+
+impl PartialEq for Val {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_in(other, &mut Vec::new())
+    }
+}
+
+impl std::fmt::Display for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Val::List(self.clone()).fmt_in(f, &mut Vec::new())
+    }
+}
+
+impl std::fmt::Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Val::Map(self.clone()).fmt_in(f, &mut Vec::new())
+    }
+}
+
+// Printing the contents keeps cyclic values from recursing forever
+impl std::fmt::Debug for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "List({self})")
+    }
+}
+
+impl std::fmt::Debug for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Map({self})")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        interpreter::Interpreter,
+        source::SourceMap,
+        test_util::{global, run},
+    };
+
+    fn fails(src: &str) -> bool {
+        let mut lox = Interpreter::builder().build();
+        lox.run(&SourceMap::new("test", src)).is_err()
+    }
+
+    #[test]
+    fn string_indexing() {
+        let env = run(r#"
+            let s = "héllo, wörld ✓";
+            let n = len(s);
+            let e = s[1];
+            let check = s[13];
+            let ascii = "abc"[2];
+            let rev = "";
+            for (let i = len(s) - 1; i >= 0; i = i - 1) rev = rev + s[i];"#);
+        assert_eq!(global(&env, "n"), Val::Number(14.0));
+        assert_eq!(global(&env, "e"), Val::String("é".into()));
+        assert_eq!(global(&env, "check"), Val::String("✓".into()));
+        assert_eq!(global(&env, "ascii"), Val::String("c".into()));
+        assert_eq!(global(&env, "rev"), Val::String("✓ dlröw ,olléh".into()));
+        assert!(fails(r#""abc"[3];"#));
+        assert!(fails(r#""é"[1];"#));
+        assert!(fails(r#""abc"[-1];"#));
+        assert!(fails(r#""abc"[0.5];"#));
+        assert!(fails(r#""abc"["a"];"#));
+        assert!(fails(r#"let s = "abc"; s[0] = "x";"#));
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_string_indexing() {
+        use std::time::Instant;
+
+        let mut lox = Interpreter::builder().build();
+        let text = "ü".repeat(1 << 19);
+        lox.define("text", Val::String(text.into()));
+        let start = Instant::now();
+        let src =
+            "let n = 0; for (let i = 0; i < len(text); i = i + 1) if (text[i] == \"ü\") n = n + 1;";
+        assert_eq!(lox.run(&SourceMap::new("bench", src)), Ok(()));
+        assert_eq!(global(&lox, "n"), Val::Number((1 << 19) as f64));
+        println!(
+            "indexing every character of a 1MB string: {:?}",
+            start.elapsed()
+        );
+    }
+
+    #[test]
+    fn literals_and_indexing() {
+        let env = run(r#"
+            let l = [1, "two", [3]];
+            let m = {"a": 1, "b": [2]};
+            let x = l[2][0];
+            let y = m["b"][0];
+            l[0] = 10;
+            m["c"] = 3;
+            let n = len(l) + len(m);"#);
+        assert_eq!(global(&env, "x"), Val::Number(3.0));
+        assert_eq!(global(&env, "y"), Val::Number(2.0));
+        assert_eq!(global(&env, "n"), Val::Number(6.0));
+        assert_eq!(global(&env, "l").to_string(), r#"[10, "two", [3]]"#);
+        assert_eq!(
+            global(&env, "m").to_string(),
+            r#"{"a": 1, "b": [2], "c": 3}"#
+        );
+    }
+
+    #[test]
+    fn deep_equality() {
+        let env = run(r#"
+            let a = [1, {"k": [2, 3]}, "s"];
+            let b = [1, {"k": [2, 3]}, "s"];
+            let c = [1, {"k": [2, 4]}, "s"];
+            let eq = a == b;
+            let ne = a != c;
+            let same_ab = same(a, b);
+            let same_aa = same(a, a);
+            let nan = [0/0] == [0/0];"#);
+        assert_eq!(global(&env, "eq"), Val::Boolean(true));
+        assert_eq!(global(&env, "ne"), Val::Boolean(true));
+        assert_eq!(global(&env, "same_ab"), Val::Boolean(false));
+        assert_eq!(global(&env, "same_aa"), Val::Boolean(true));
+        assert_eq!(global(&env, "nan"), Val::Boolean(false));
+    }
+
+    #[test]
+    fn cyclic_values() {
+        let env = run(r#"
+            let a = [1]; push(a, a);
+            let b = [1]; push(b, b);
+            let c = [2]; push(c, c);
+            let m = {}; m["self"] = m;
+            let ab = a == b;
+            let ac = a == c;"#);
+        assert_eq!(global(&env, "ab"), Val::Boolean(true));
+        assert_eq!(global(&env, "ac"), Val::Boolean(false));
+        assert_eq!(global(&env, "a").to_string(), "[1, [...]]");
+        assert_eq!(global(&env, "m").to_string(), r#"{"self": {...}}"#);
+        assert!(fails("let a = []; push(a, a); clone(a);"));
+    }
+
+    #[test]
+    fn aliasing_and_clone() {
+        let env = run(r#"
+            let a = [[1]];
+            let alias = a;
+            let copy = clone(a);
+            push(alias[0], 2);
+            let seen = len(a[0]);
+            let copied = len(copy[0]);"#);
+        assert_eq!(global(&env, "seen"), Val::Number(2.0));
+        assert_eq!(global(&env, "copied"), Val::Number(1.0));
+    }
+
+    #[test]
+    fn frozen() {
+        let env = run(r#"
+            let m = {"l": [1]};
+            let f = freeze(m);
+            m["x"] = 1;
+            let seen = has(f, "x");
+            let eq = f == m;"#);
+        assert_eq!(global(&env, "seen"), Val::Boolean(true));
+        assert_eq!(global(&env, "eq"), Val::Boolean(true));
+        assert!(fails(r#"let f = freeze({"a": 1}); f["a"] = 2;"#));
+        assert!(fails(r#"let f = freeze({"l": [1]}); push(f["l"], 2);"#));
+        assert!(fails(r#"let f = freeze([1]); push(f, 2);"#));
+    }
+
+    #[test]
+    fn bad_index() {
+        assert!(fails("[1][1];"));
+        assert!(fails("[1][0.5];"));
+        assert!(fails(r#"[1]["a"];"#));
+        assert!(fails(r#"{"a": 1}[1];"#));
+        assert!(fails(r#"{"a": 1}["b"];"#));
+        assert!(fails("1[0];"));
+    }
+}
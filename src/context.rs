@@ -0,0 +1,230 @@
+use std::{
+    cell::RefCell,
+    io::{stdout, Write},
+    iter::once,
+    rc::Rc,
+};
+
+use crate::{
+    collections::{LastString, MAX_STRING_LEN},
+    error::RuntimeError,
+    import::Imports,
+    lexer::Loc,
+    source::{Located, SourceMap},
+    val::{Function, Val},
+};
+
+// How many calls deep a script may go by default. Every call takes several
+// native frames, so calls also fail once the stack runs short, whatever the
+// depth
+pub const MAX_CALL_DEPTH: usize = 256;
+
+// The native stack scripts are assumed to run on, that of a thread spawned
+// by `std`. Hosts with a larger one say so with `Builder::stack_size`
+pub const DEFAULT_STACK_SIZE: usize = 2 << 20;
+
+// Stack left to the host, to the frames above the outermost call and to
+// whatever runs between two calls
+const STACK_RESERVE: usize = 512 << 10;
+
+// What `+` does with a string and a number
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum StringPlusNumber {
+    #[default]
+    Error,
+    // Shows the number as `print` does and concatenates
+    Coerce,
+}
+
+// A call in progress: the value called and where from
+#[derive(Clone)]
+pub(crate) struct Frame {
+    pub callee: Val,
+    pub site: Located,
+}
+
+impl Frame {
+    // Natives have no location in the script, and are named as such
+    fn name(&self) -> (String, bool) {
+        match &self.callee {
+            Val::Func(Function::Native(name, ..)) => (format!("<native {name}>"), true),
+            Val::Func(f) => (f.name(), false),
+            Val::Class(c) => (c.name.clone(), false),
+            _ => ("<host fn>".to_string(), true),
+        }
+    }
+}
+
+// An output that keeps what is printed, for hosts that want it as text.
+// Its clones share what they keep
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    // What was printed since it was last taken
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.take()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// State of a running interpreter that evaluation needs besides the scopes
+pub struct Context {
+    // Where `print` writes to
+    out: Box<dyn Write>,
+    pub(crate) imports: Imports,
+    // The name of the source of the code being executed, and those of the
+    // code that is not run through `Interpreter::run`, such as the prelude,
+    // kept so that errors in it are shown against its own text
+    pub(crate) source: Rc<str>,
+    pub(crate) sources: Vec<SourceMap<'static>>,
+    // Where the runtime error being unwound happened, set by the innermost
+    // node with a location that it unwinds through
+    pub(crate) error_at: Option<Located>,
+    // The calls in progress, outermost first, and those the runtime error
+    // being unwound happened in, taken as it leaves the innermost of them
+    pub(crate) frames: Vec<Frame>,
+    pub(crate) trace: Option<Vec<Frame>>,
+    // Calls to user functions in progress, and how many there may be
+    pub(crate) depth: usize,
+    pub(crate) max_depth: usize,
+    // The size of the native stack, and where it stood on entering the
+    // outermost call in progress
+    pub(crate) stack_size: usize,
+    stack_base: usize,
+    pub(crate) string_plus_number: StringPlusNumber,
+    // Whether `/` and `%` by zero fail rather than give `inf` or `nan`
+    pub(crate) strict_math: bool,
+    // The offsets of the characters of the last string indexed
+    pub(crate) last_string: LastString,
+    // How long strings and lists may get
+    pub(crate) max_string_len: usize,
+    pub(crate) max_list_len: usize,
+    // Statements a run may execute, if limited, and how many are left
+    pub(crate) max_fuel: Option<u64>,
+    fuel: u64,
+    // Whether what `sandbox` disables is refused
+    pub(crate) sandboxed: bool,
+}
+
+impl Context {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            imports: Imports::default(),
+            source: Rc::default(),
+            sources: Vec::new(),
+            error_at: None,
+            frames: Vec::new(),
+            trace: None,
+            depth: 0,
+            max_depth: MAX_CALL_DEPTH,
+            stack_size: DEFAULT_STACK_SIZE,
+            stack_base: 0,
+            string_plus_number: StringPlusNumber::default(),
+            strict_math: false,
+            last_string: LastString::default(),
+            max_string_len: MAX_STRING_LEN,
+            max_list_len: usize::MAX,
+            max_fuel: None,
+            fuel: 0,
+            sandboxed: false,
+        }
+    }
+
+    // Each run starts with all of its fuel
+    pub(crate) fn refuel(&mut self) {
+        self.fuel = self.max_fuel.unwrap_or_default();
+    }
+
+    // Takes a statement's worth of fuel, if it is limited. Once out of it
+    // every statement fails, those of a `catch` too
+    pub(crate) fn burn(&mut self) -> Result<(), RuntimeError> {
+        let Some(max) = self.max_fuel else {
+            return Ok(());
+        };
+        if self.fuel == 0 {
+            return Err(RuntimeError::OutOfFuel { max });
+        }
+        self.fuel -= 1;
+        Ok(())
+    }
+
+    // Fails if what the sandbox disables is refused
+    pub(crate) fn allow(&self, name: &'static str) -> Result<(), RuntimeError> {
+        if self.sandboxed {
+            Err(RuntimeError::SandboxViolation(name))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Locates the runtime error being unwound at `loc` in the code being
+    // executed, unless a node it already unwound through did
+    pub(crate) fn fail_at(&mut self, loc: Option<Loc>) {
+        if self.error_at.is_none() {
+            self.error_at = loc.map(|loc| Located {
+                source: Rc::clone(&self.source),
+                loc,
+            });
+        }
+    }
+
+    // Each call the runtime error being unwound happened in, innermost
+    // first, with where in it execution was. Empty when it happened
+    // outside of any call
+    pub(crate) fn take_trace(&mut self) -> Vec<(String, Option<Located>)> {
+        let Some(frames) = self.trace.take() else {
+            return Vec::new();
+        };
+        let names = frames.iter().rev().map(Frame::name);
+        let names = names.chain(once(("<top level>".to_string(), false)));
+        let sites = frames.iter().rev().map(|f| Some(f.site.clone()));
+        let locs = once(self.error_at.clone()).chain(sites);
+        names
+            .zip(locs)
+            .map(|((name, native), loc)| (name, loc.filter(|_| !native)))
+            .collect()
+    }
+
+    // Forgets the calls of a run abandoned half way through, as by a panic
+    pub(crate) fn abandon(&mut self) {
+        self.depth = 0;
+        self.frames.clear();
+        self.trace = None;
+        self.error_at = None;
+    }
+
+    // Whether entering another call could overflow the native stack, by
+    // how much of it the calls in progress take. Stacks grow downwards on
+    // every platform Rust supports threads on
+    pub(crate) fn stack_exhausted(&mut self) -> bool {
+        let marker = 0u8;
+        let here = std::hint::black_box(&marker) as *const u8 as usize;
+        if self.depth == 0 {
+            self.stack_base = here;
+        }
+        self.stack_base.saturating_sub(here) > self.stack_size.saturating_sub(STACK_RESERVE)
+    }
+
+    // A closed or broken output is not an error in the script, so what
+    // cannot be written is dropped
+    pub fn print(&mut self, text: impl std::fmt::Display) {
+        let _ = writeln!(self.out, "{text}");
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new(Box::new(stdout()))
+    }
+}
@@ -1,63 +1,1237 @@
-use crate::lexer::{Loc, TokKind, Token};
+use std::{fmt, iter::once, process::ExitCode};
+
+use crate::{
+    collections::Map,
+    expr::Expr,
+    lexer::{Lexer, Loc, TokKind, Token},
+    source::{Located, SourceMap},
+    val::Val,
+};
+
+// Every way running a unit of code can fail, one variant per stage.
+// Stages that can find several problems at once report all of them
+#[derive(PartialEq, Debug)]
+pub enum LoxError {
+    Io { path: String },
+    Lex(Vec<LexError>),
+    Parse(Vec<ParserError>),
+    Resolve(Vec<ResolveError>),
+    // Warnings of lints set to `Level::Deny`, which compile no better
+    Denied(Vec<Warning>),
+    Runtime(RuntimeError),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum LexError {
+    Unexpected { c: char, loc: Loc },
+    // `loc` is where the string starts, and `len` how much of the input
+    // it took in
+    Unterminated { loc: Loc, len: usize },
+    UnterminatedComment { loc: Loc },
+}
 
 #[derive(PartialEq, Debug)]
 pub enum ParserError {
-    Expected { exp: TokKind, fnd: Option<Token> },
-    TooManyParams { loc: Loc },
-    TooManyArgs { loc: Loc },
-    InvalidAsgn { loc: Loc },
-    Unmatched { open: Token, hint: Option<Loc> },
-    Unexpected { tok: Token },
-    EOF,
-}
-
-fn message_at_location(src: &str, loc: &Loc, msg: &str) {
-    eprintln!(
-        "{:>4} | {}",
+    Expected {
+        exp: TokKind,
+        fnd: Option<Token>,
+    },
+    // A statement followed by another on a later line, where `after` is
+    // the start of the last token of the first one
+    MissingSemicolon {
+        after: Loc,
+    },
+    TooManyParams {
+        loc: Loc,
+    },
+    // `function` is the name of the function the parameters are of
+    DuplicateParam {
+        name: String,
+        function: String,
+        loc: Loc,
+    },
+    ParamShadowsFn {
+        name: String,
+        loc: Loc,
+    },
+    MissingDefault {
+        name: String,
+        loc: Loc,
+    },
+    RestNotLast {
+        name: String,
+        loc: Loc,
+    },
+    TooManyArgs {
+        loc: Loc,
+    },
+    // Constructs nested more than `max` deep, at the first token past them
+    TooDeep {
+        max: usize,
+        loc: Loc,
+    },
+    InvalidAsgn {
+        loc: Loc,
+    },
+    // A keyword where a name was expected
+    ReservedWord {
+        kw: TokKind,
+        loc: Loc,
+    },
+    // Arms of a `match` only take literals and `_`
+    InvalidPattern {
+        loc: Loc,
+    },
+    // A `Warning::ChainedCmp` under strict mode
+    ChainedCmp {
+        expr: Box<Expr>,
+        fix: Box<Expr>,
+        loc: Loc,
+    },
+    Unmatched {
+        open: Token,
+        hint: Option<Loc>,
+    },
+    Unexpected {
+        tok: Token,
+    },
+    BadNumber {
+        text: String,
+        loc: Loc,
+    },
+    // The lexer stopped the parse, see `Parser::parse_lexed`
+    Lex(LexError),
+    Eof,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ResolveError {
+    // At the new declaration, `prev` at the one it clashes with
+    Redeclared {
+        name: String,
+        loc: Loc,
+        prev: Loc,
+    },
+    ReadInInitializer {
+        name: String,
+        loc: Loc,
+    },
+    TopLevelReturn {
+        loc: Loc,
+    },
+    ThisOutsideClass {
+        loc: Loc,
+    },
+    ThisInStatic {
+        loc: Loc,
+    },
+    ReturnFromInit {
+        loc: Loc,
+    },
+    SuperOutsideSubclass {
+        loc: Loc,
+    },
+    // At the superclass
+    InheritsItself {
+        name: String,
+        loc: Loc,
+    },
+    // A `break` or `continue`, named by `keyword`
+    OutsideLoop {
+        keyword: &'static str,
+        loc: Loc,
+    },
+    UnknownLabel {
+        name: String,
+        loc: Loc,
+    },
+    // At the `(` of a call, `decl` at the name of the function called, which
+    // natives have none of. As with `RuntimeError::Arity`, `max` is
+    // `usize::MAX` for functions with a rest parameter
+    Arity {
+        name: String,
+        min: usize,
+        max: usize,
+        got: usize,
+        loc: Loc,
+        decl: Option<Loc>,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum RuntimeError {
+    UndefinedVariable {
+        name: String,
+    },
+    UndefinedProperty {
+        name: String,
+    },
+    TypeMismatch {
+        msg: &'static str,
+    },
+    // A binary operator applied to operands of the wrong types, named
+    // `lhs` and `rhs`
+    Operands {
+        msg: &'static str,
+        lhs: &'static str,
+        rhs: &'static str,
+    },
+    // `+` with a string and something other than a string, each operand
+    // with its type and value, shown as in containers
+    Concat {
+        lhs: (&'static str, String),
+        rhs: (&'static str, String),
+    },
+    NotCallable,
+    // `callee` is the name of what was called, qualified for methods, and
+    // `decl` where it is declared. Host callables have neither, nor do
+    // natives have a declaration.
+    // The number of arguments taken is between `min` and `max`, which is
+    // `usize::MAX` for functions with a rest parameter
+    Arity {
+        callee: Option<String>,
+        min: usize,
+        max: usize,
+        got: usize,
+        decl: Option<Located>,
+    },
+    // More than 255 arguments once lists are spread into them
+    TooManyArgs {
+        got: usize,
+    },
+    IndexOutOfRange {
+        idx: f64,
+        len: usize,
+    },
+    UndefinedKey {
+        key: String,
+    },
+    Frozen {
+        kind: &'static str,
+    },
+    CyclicClone,
+    TopLevelReturn,
+    ParseErrors,
+    // A string built past `max` bytes
+    StringTooLong {
+        max: usize,
+    },
+    // A list pushed to past `max` items
+    ListTooLong {
+        max: usize,
+    },
+    // A run that executed more than `max` statements
+    OutOfFuel {
+        max: u64,
+    },
+    // Something a sandboxed script may not do, named as in `sandbox`
+    SandboxViolation(&'static str),
+    // A panic while running sandboxed code, such as in a host callable,
+    // with its message
+    Panicked {
+        message: String,
+    },
+    // A list destructured by `let` with fewer items than names
+    TooFewItems {
+        expected: usize,
+        got: usize,
+    },
+    // An `assert` whose condition does not hold, with its message
+    AssertionFailed {
+        message: Option<String>,
+    },
+    // The file could not be read or did not compile, which was reported
+    // with its own diagnostics
+    ImportFailed {
+        path: String,
+    },
+    // Every file in the cycle, from the first one to import itself again
+    ImportCycle {
+        chain: Vec<String>,
+    },
+    // Too many calls in progress, on entering the function `name`
+    StackOverflow {
+        name: String,
+    },
+    // `/` or `%` by zero under strict math, with its left operand
+    DivisionByZero {
+        op: &'static str,
+        lhs: f64,
+    },
+    // A value thrown by `throw` that no `try` caught
+    Thrown {
+        value: Val,
+    },
+    // Raised by a script through the `raise` native
+    User {
+        kind: String,
+        message: String,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Warning {
+    // At the operator of the expression, or where it starts if it has none
+    DiscardedTail {
+        func: String,
+        expr: Expr,
+        loc: Loc,
+    },
+    // At the first use of the variable in the function
+    LoopCapture {
+        name: String,
+        loc: Loc,
+    },
+    // Located as `DiscardedTail`
+    UnusedResult {
+        expr: Expr,
+        asgn: bool,
+        loc: Loc,
+    },
+    // The condition of an `if` or a `while` is an assignment, at its target
+    AsgnInCondition {
+        expr: Expr,
+        loc: Loc,
+    },
+    // `a < b < c`, at the second operator. `fix` compares `b` twice instead
+    ChainedCmp {
+        expr: Expr,
+        fix: Expr,
+        loc: Loc,
+    },
+    // A local never read nor assigned to
+    UnusedVariable {
+        name: String,
+        loc: Loc,
+        param: bool,
+    },
+    // A local assigned to but never read, at the last assignment and with
+    // `decl` where it is declared
+    UnreadAssignment {
+        name: String,
+        loc: Loc,
+        decl: Loc,
+    },
+    // A function that no code reachable from the top level reads
+    UnusedFunction {
+        name: String,
+        loc: Loc,
+    },
+    // A statement after one that always jumps away, at the first such
+    // statement and with `jump` where it jumps
+    Unreachable {
+        loc: Loc,
+        jump: Loc,
+    },
+    // A `loop` with neither a `break` nor a `return` out of it
+    EndlessLoop,
+    // An arm of a `match` that an earlier one shadows
+    DuplicateArm {
+        pattern: Expr,
+    },
+    NumberOverflow {
+        text: String,
+        loc: Loc,
+    },
+    NumberPrecision {
+        text: String,
+        loc: Loc,
+    },
+    // A function over one of the limits given to `lox lint`
+    Metric {
+        func: String,
+        metric: &'static str,
+        value: usize,
+        max: usize,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+// The names lints are allowed or denied by, one per kind of `Warning`
+pub const LINTS: &[&str] = &[
+    "discarded_tail",
+    "loop_capture",
+    "unused_result",
+    "asgn_in_condition",
+    "chained_cmp",
+    "unused_variable",
+    "unread_assignment",
+    "unused_function",
+    "unreachable",
+    "endless_loop",
+    "duplicate_arm",
+    "number_overflow",
+    "number_precision",
+    "metric",
+];
+
+// What is done with the warnings of a lint: nothing, report them, or
+// report them as errors, so that the code does not run
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Level {
+    Allow,
+    #[default]
+    Warn,
+    Deny,
+}
+
+// The level of each lint named in `LINTS`, and of all those not named
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Warnings {
+    pub default: Level,
+    lints: Vec<(&'static str, Level)>,
+}
+
+impl Warnings {
+    pub fn set(&mut self, lint: &str, level: Level) -> Result<(), String> {
+        let lint = LINTS
+            .iter()
+            .find(|l| **l == lint)
+            .ok_or_else(|| format!("unknown lint '{lint}'"))?;
+        self.lints.retain(|(l, _)| l != lint);
+        self.lints.push((lint, level));
+        Ok(())
+    }
+
+    pub fn level(&self, w: &Warning) -> Level {
+        self.lints
+            .iter()
+            .find(|(l, _)| *l == w.lint())
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+impl From<Vec<LexError>> for LoxError {
+    fn from(errs: Vec<LexError>) -> Self {
+        Self::Lex(errs)
+    }
+}
+
+impl From<ParserError> for LoxError {
+    fn from(err: ParserError) -> Self {
+        Self::Parse(vec![err])
+    }
+}
+
+impl From<Vec<ParserError>> for LoxError {
+    fn from(errs: Vec<ParserError>) -> Self {
+        Self::Parse(errs)
+    }
+}
+
+impl From<Vec<ResolveError>> for LoxError {
+    fn from(errs: Vec<ResolveError>) -> Self {
+        Self::Resolve(errs)
+    }
+}
+
+impl From<RuntimeError> for LoxError {
+    fn from(err: RuntimeError) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+impl LoxError {
+    pub fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    // The conventional exit codes from sysexits.h: EX_DATAERR when the code
+    // does not compile, EX_SOFTWARE when it fails while running and EX_IOERR
+    // when it cannot be read
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Self::Io { .. } => 74,
+            Self::Lex(_) | Self::Parse(_) | Self::Resolve(_) | Self::Denied(_) => 65,
+            Self::Runtime(_) => 70,
+        })
+    }
+
+    // One JSON object per diagnostic, for tools that consume them
+    pub fn to_json(&self, src: &SourceMap) -> Vec<String> {
+        let sev = self.severity();
+        match self {
+            Self::Io { .. } => vec![json_diagnostic(src, sev, "io", &self.to_string(), None)],
+            Self::Lex(errs) => errs
+                .iter()
+                .map(|e| json_diagnostic(src, sev, "lex", &e.to_string(), Some(e.loc())))
+                .collect(),
+            Self::Parse(errs) => errs
+                .iter()
+                .map(|e| json_diagnostic(src, sev, "parse", &e.to_string(), Some(e.loc(src))))
+                .collect(),
+            Self::Resolve(errs) => errs
+                .iter()
+                .map(|e| json_diagnostic(src, sev, "resolve", &e.to_string(), Some(e.loc())))
+                .collect(),
+            Self::Denied(warnings) => warnings.iter().map(|w| w.to_json_as(src, sev)).collect(),
+            Self::Runtime(e) => vec![json_diagnostic(src, sev, "runtime", &e.to_string(), None)],
+        }
+    }
+}
+
+impl Warning {
+    pub fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    // Its name in `LINTS`
+    pub fn lint(&self) -> &'static str {
+        LINTS[match self {
+            Self::DiscardedTail { .. } => 0,
+            Self::LoopCapture { .. } => 1,
+            Self::UnusedResult { .. } => 2,
+            Self::AsgnInCondition { .. } => 3,
+            Self::ChainedCmp { .. } => 4,
+            Self::UnusedVariable { .. } => 5,
+            Self::UnreadAssignment { .. } => 6,
+            Self::UnusedFunction { .. } => 7,
+            Self::Unreachable { .. } => 8,
+            Self::EndlessLoop => 9,
+            Self::DuplicateArm { .. } => 10,
+            Self::NumberOverflow { .. } => 11,
+            Self::NumberPrecision { .. } => 12,
+            Self::Metric { .. } => 13,
+        }]
+    }
+
+    // What `lox explain` knows it by
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::DiscardedTail { .. } => "W0001",
+            Self::LoopCapture { .. } => "W0002",
+            Self::UnusedResult { .. } => "W0003",
+            Self::AsgnInCondition { .. } => "W0004",
+            Self::ChainedCmp { .. } => "W0005",
+            Self::UnusedVariable { .. } => "W0006",
+            Self::UnreadAssignment { .. } => "W0007",
+            Self::UnusedFunction { .. } => "W0008",
+            Self::Unreachable { .. } => "W0009",
+            Self::EndlessLoop => "W0010",
+            Self::DuplicateArm { .. } => "W0011",
+            Self::NumberOverflow { .. } => "W0012",
+            Self::NumberPrecision { .. } => "W0013",
+            Self::Metric { .. } => "W0014",
+        }
+    }
+
+    pub fn loc(&self) -> Option<Loc> {
+        match self {
+            Self::DiscardedTail { loc, .. }
+            | Self::LoopCapture { loc, .. }
+            | Self::UnusedResult { loc, .. }
+            | Self::NumberOverflow { loc, .. }
+            | Self::NumberPrecision { loc, .. }
+            | Self::AsgnInCondition { loc, .. }
+            | Self::ChainedCmp { loc, .. }
+            | Self::UnusedVariable { loc, .. }
+            | Self::UnreadAssignment { loc, .. }
+            | Self::UnusedFunction { loc, .. }
+            | Self::Unreachable { loc, .. } => Some(*loc),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self, src: &SourceMap) -> String {
+        self.to_json_as(src, self.severity())
+    }
+
+    fn to_json_as(&self, src: &SourceMap, sev: Severity) -> String {
+        let stage = match self {
+            Self::NumberOverflow { .. } | Self::NumberPrecision { .. } => "lex",
+            Self::ChainedCmp { .. } => "parse",
+            Self::Metric { .. } => "lint",
+            _ => "resolve",
+        };
+        json_diagnostic(src, sev, stage, &self.to_string(), self.loc())
+    }
+}
+
+impl LexError {
+    pub fn loc(&self) -> Loc {
+        match self {
+            Self::Unexpected { loc, .. }
+            | Self::Unterminated { loc, .. }
+            | Self::UnterminatedComment { loc } => *loc,
+        }
+    }
+
+    // What `lox explain` knows it by
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unexpected { .. } => "E0001",
+            Self::Unterminated { .. } => "E0002",
+            Self::UnterminatedComment { .. } => "E0003",
+        }
+    }
+}
+
+impl ResolveError {
+    pub fn loc(&self) -> Loc {
+        use ResolveError::*;
+        match self {
+            Redeclared { loc, .. }
+            | ReadInInitializer { loc, .. }
+            | TopLevelReturn { loc }
+            | ThisOutsideClass { loc }
+            | ThisInStatic { loc }
+            | ReturnFromInit { loc }
+            | SuperOutsideSubclass { loc }
+            | InheritsItself { loc, .. }
+            | OutsideLoop { loc, .. }
+            | UnknownLabel { loc, .. }
+            | Arity { loc, .. } => *loc,
+        }
+    }
+
+    // What `lox explain` knows it by
+    pub fn code(&self) -> &'static str {
+        use ResolveError::*;
+        match self {
+            Redeclared { .. } => "E0201",
+            ReadInInitializer { .. } => "E0202",
+            TopLevelReturn { .. } => "E0203",
+            ThisOutsideClass { .. } => "E0204",
+            ThisInStatic { .. } => "E0205",
+            ReturnFromInit { .. } => "E0206",
+            SuperOutsideSubclass { .. } => "E0207",
+            InheritsItself { .. } => "E0208",
+            OutsideLoop { .. } => "E0209",
+            UnknownLabel { .. } => "E0210",
+            Arity { .. } => "E0211",
+        }
+    }
+}
+
+impl ParserError {
+    fn loc(&self, src: &SourceMap) -> Loc {
+        use ParserError::*;
+        match self {
+            Expected { fnd: Some(tok), .. } | Unmatched { open: tok, .. } | Unexpected { tok } => {
+                tok.loc
+            }
+            TooManyParams { loc }
+            | DuplicateParam { loc, .. }
+            | ParamShadowsFn { loc, .. }
+            | MissingDefault { loc, .. }
+            | RestNotLast { loc, .. }
+            | TooManyArgs { loc }
+            | TooDeep { loc, .. }
+            | InvalidAsgn { loc }
+            | ReservedWord { loc, .. }
+            | InvalidPattern { loc }
+            | ChainedCmp { loc, .. }
+            | BadNumber { loc, .. } => *loc,
+            Lex(e) => e.loc(),
+            MissingSemicolon { after } => token_end(src, *after),
+            Expected { fnd: None, .. } | Eof => src.eof_loc(),
+        }
+    }
+
+    // What `lox explain` knows it by
+    pub fn code(&self) -> &'static str {
+        use ParserError::*;
+        match self {
+            Expected { .. } => "E0101",
+            MissingSemicolon { .. } => "E0102",
+            TooManyParams { .. } => "E0103",
+            DuplicateParam { .. } => "E0104",
+            ParamShadowsFn { .. } => "E0105",
+            MissingDefault { .. } => "E0106",
+            RestNotLast { .. } => "E0107",
+            TooManyArgs { .. } => "E0108",
+            TooDeep { .. } => "E0109",
+            InvalidAsgn { .. } => "E0110",
+            ReservedWord { .. } => "E0111",
+            InvalidPattern { .. } => "E0112",
+            ChainedCmp { .. } => "E0113",
+            Unmatched { .. } => "E0114",
+            Unexpected { .. } => "E0115",
+            BadNumber { .. } => "E0116",
+            Lex(e) => e.code(),
+            Eof => "E0117",
+        }
+    }
+}
+
+// Where the token at `loc` ends, found by lexing it again
+fn token_end(src: &SourceMap, loc: Loc) -> Loc {
+    let mut lexer = Lexer::new(src.text()[loc.offset..].chars());
+    lexer.next();
+    src.offset_to_loc(loc.offset + lexer.loc().offset)
+        .unwrap_or(loc)
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_diagnostic(
+    src: &SourceMap,
+    sev: Severity,
+    stage: &str,
+    msg: &str,
+    loc: Option<Loc>,
+) -> String {
+    let sev = sev.name();
+    let (line, col, offset) = loc.map_or(("null".into(), "null".into(), "null".into()), |l| {
+        (l.row.to_string(), l.col.to_string(), l.offset.to_string())
+    });
+    format!(
+        r#"{{"severity": "{sev}", "stage": "{stage}", "message": {}, "file": {}, "line": {line}, "column": {col}, "offset": {offset}}}"#,
+        json_string(msg),
+        json_string(src.name()),
+    )
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path } => write!(f, "unable to open file: {path}"),
+            Self::Lex(errs) => write!(f, "{} lexical error(s)", errs.len()),
+            Self::Parse(errs) => write!(f, "{} syntax error(s)", errs.len()),
+            Self::Resolve(errs) => write!(f, "{} resolution error(s)", errs.len()),
+            Self::Denied(warnings) => write!(f, "{} denied warning(s)", warnings.len()),
+            Self::Runtime(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unexpected { c, .. } => write!(f, "unexpected character '{c}'"),
+            Self::Unterminated { loc, .. } => write!(f, "string starting at {loc} is never closed"),
+            Self::UnterminatedComment { .. } => write!(f, "unterminated block comment"),
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParserError::*;
+        match self {
+            Expected { exp, fnd: None } => write!(f, "expected {exp}, found end of file"),
+            Expected {
+                exp,
+                fnd: Some(tok),
+            } => write!(f, "syntax error: expected {exp}, found {}", tok.kind),
+            MissingSemicolon { .. } => write!(f, "syntax error: missing ';' after statement"),
+            TooManyParams { .. } => write!(f, "functions cannot take more than 255 parameters"),
+            DuplicateParam { name, function, .. } => {
+                write!(f, "duplicate parameter '{name}' in function '{function}'")
+            }
+            ParamShadowsFn { name, .. } => {
+                write!(f, "parameter '{name}' has the same name as its function")
+            }
+            MissingDefault { name, .. } => {
+                write!(f, "parameter '{name}' needs a default value")
+            }
+            RestNotLast { name, .. } => {
+                write!(f, "rest parameter '{name}' must be the last one")
+            }
+            TooManyArgs { .. } => write!(f, "functions cannot take more than 255 arguments"),
+            TooDeep { max, .. } => write!(f, "code cannot nest more than {max} levels deep"),
+            InvalidAsgn { .. } => write!(f, "invalid assignment target"),
+            ReservedWord { kw, .. } => {
+                write!(f, "{kw} is a reserved word and cannot be used as a name")
+            }
+            InvalidPattern { .. } => write!(f, "invalid pattern"),
+            ChainedCmp { expr, .. } => write!(f, "`{expr}` compares the result of a comparison"),
+            Unmatched { open, .. } => write!(f, "unmatched {}", open.kind),
+            Unexpected { tok } => write!(f, "unexpected token: {}", tok.kind),
+            BadNumber { text, .. } => write!(f, "invalid number literal '{text}'"),
+            Lex(e) => write!(f, "{e}"),
+            Eof => write!(f, "unexpected end of file"),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Redeclared { name, .. } => {
+                write!(f, "a variable named '{name}' already exists in this scope")
+            }
+            Self::ReadInInitializer { name, .. } => {
+                write!(
+                    f,
+                    "cannot read local variable '{name}' in its own initializer"
+                )
+            }
+            Self::TopLevelReturn { .. } => write!(f, "cannot return from top-level code"),
+            Self::ThisOutsideClass { .. } => write!(f, "cannot use 'this' outside of a class"),
+            Self::ThisInStatic { .. } => write!(f, "cannot use 'this' in a static method"),
+            Self::ReturnFromInit { .. } => write!(f, "cannot return a value from an initializer"),
+            Self::SuperOutsideSubclass { .. } => {
+                write!(f, "cannot use 'super' outside of a subclass method")
+            }
+            Self::InheritsItself { name, .. } => {
+                write!(f, "class '{name}' cannot inherit from itself")
+            }
+            Self::OutsideLoop { keyword, .. } => {
+                write!(f, "cannot use '{keyword}' outside of a loop")
+            }
+            Self::UnknownLabel { name, .. } => write!(f, "no enclosing loop is labeled '{name}'"),
+            Self::Arity {
+                name,
+                min,
+                max,
+                got,
+                ..
+            } => RuntimeError::Arity {
+                callee: Some(name.clone()),
+                min: *min,
+                max: *max,
+                got: *got,
+                decl: None,
+            }
+            .fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RuntimeError::*;
+        match self {
+            UndefinedVariable { name } => write!(f, "undefined variable '{name}'"),
+            UndefinedProperty { name } => write!(f, "undefined property '{name}'"),
+            TypeMismatch { msg } => write!(f, "{msg}"),
+            Operands { msg, lhs, rhs } => write!(f, "{msg}; found {lhs} and {rhs}"),
+            Concat { lhs, rhs } => write!(
+                f,
+                "can only concatenate string with string; found {} {} and {} {}",
+                lhs.0, lhs.1, rhs.0, rhs.1
+            ),
+            NotCallable => write!(f, "can only call functions"),
+            Arity {
+                callee,
+                min,
+                max,
+                got,
+                ..
+            } => {
+                if let Some(callee) = callee {
+                    write!(f, "{callee} ")?;
+                }
+                if *max == usize::MAX {
+                    write!(f, "expected at least {min} arguments, got {got}")
+                } else if min == max {
+                    write!(f, "expected {max} arguments, got {got}")
+                } else {
+                    write!(f, "expected {min} to {max} arguments, got {got}")
+                }
+            }
+            TooManyArgs { got } => {
+                write!(
+                    f,
+                    "functions cannot take more than 255 arguments, got {got}"
+                )
+            }
+            IndexOutOfRange { idx, len } => {
+                write!(f, "index {idx} out of range for length {len}")
+            }
+            UndefinedKey { key } => write!(f, "undefined key \"{key}\""),
+            Frozen { kind } => write!(f, "cannot modify a frozen {kind}"),
+            CyclicClone => write!(f, "cannot clone a value that contains itself"),
+            TopLevelReturn => write!(f, "cannot return from top-level code"),
+            ParseErrors => write!(f, "program contains parse errors"),
+            StringTooLong { max } => write!(f, "the string would be longer than {max} bytes"),
+            ListTooLong { max } => write!(f, "the list would be longer than {max} items"),
+            OutOfFuel { max } => write!(f, "ran out of fuel after {max} statements"),
+            SandboxViolation(name) => write!(f, "'{name}' is not allowed in the sandbox"),
+            Panicked { message } => write!(f, "the interpreter panicked: {message}"),
+            TooFewItems { expected, got } => {
+                write!(
+                    f,
+                    "expected at least {expected} items to destructure, got {got}"
+                )
+            }
+            AssertionFailed { message: None } => write!(f, "assertion failed"),
+            AssertionFailed {
+                message: Some(message),
+            } => write!(f, "assertion failed: {message}"),
+            ImportFailed { path } => write!(f, "cannot import '{path}'"),
+            ImportCycle { chain } => write!(f, "import cycle: {}", chain.join(" -> ")),
+            User { kind, message } => write!(f, "{kind}: {message}"),
+            StackOverflow { name } => write!(f, "stack overflow entering '{name}'"),
+            DivisionByZero { op, lhs } => {
+                let what = if *op == "%" { "remainder" } else { "division" };
+                if *lhs == 0.0 {
+                    write!(f, "{what} of zero by zero has no value")
+                } else {
+                    write!(f, "{what} of {lhs} by zero")
+                }
+            }
+            Thrown { value } => write!(f, "uncaught exception: {value}"),
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn kind(&self) -> &str {
+        use RuntimeError::*;
+        match self {
+            UndefinedVariable { .. } => "UndefinedVariable",
+            UndefinedProperty { .. } => "UndefinedProperty",
+            TypeMismatch { .. } | Operands { .. } | Concat { .. } => "TypeMismatch",
+            NotCallable => "NotCallable",
+            Arity { .. } => "Arity",
+            TooManyArgs { .. } => "TooManyArgs",
+            IndexOutOfRange { .. } => "IndexOutOfRange",
+            UndefinedKey { .. } => "UndefinedKey",
+            Frozen { .. } => "Frozen",
+            CyclicClone => "CyclicClone",
+            TopLevelReturn => "TopLevelReturn",
+            ParseErrors => "ParseErrors",
+            StringTooLong { .. } => "StringTooLong",
+            ListTooLong { .. } => "ListTooLong",
+            OutOfFuel { .. } => "OutOfFuel",
+            SandboxViolation(_) => "SandboxViolation",
+            Panicked { .. } => "Panicked",
+            TooFewItems { .. } => "TooFewItems",
+            AssertionFailed { .. } => "AssertionFailed",
+            ImportFailed { .. } => "ImportFailed",
+            ImportCycle { .. } => "ImportCycle",
+            User { kind, .. } => kind,
+            StackOverflow { .. } => "StackOverflow",
+            DivisionByZero { .. } => "DivisionByZero",
+            Thrown { .. } => "Thrown",
+        }
+    }
+
+    // What `lox explain` knows it by. Those of the same `kind` share it
+    pub fn code(&self) -> &'static str {
+        use RuntimeError::*;
+        match self {
+            UndefinedVariable { .. } => "E0301",
+            UndefinedProperty { .. } => "E0302",
+            TypeMismatch { .. } | Operands { .. } | Concat { .. } => "E0303",
+            NotCallable => "E0304",
+            Arity { .. } => "E0305",
+            TooManyArgs { .. } => "E0306",
+            IndexOutOfRange { .. } => "E0307",
+            UndefinedKey { .. } => "E0308",
+            Frozen { .. } => "E0309",
+            CyclicClone => "E0310",
+            TopLevelReturn => "E0311",
+            ParseErrors => "E0312",
+            StringTooLong { .. } => "E0313",
+            ListTooLong { .. } => "E0314",
+            OutOfFuel { .. } => "E0315",
+            SandboxViolation(_) => "E0316",
+            Panicked { .. } => "E0317",
+            TooFewItems { .. } => "E0318",
+            AssertionFailed { .. } => "E0319",
+            ImportFailed { .. } => "E0320",
+            ImportCycle { .. } => "E0321",
+            StackOverflow { .. } => "E0322",
+            DivisionByZero { .. } => "E0323",
+            Thrown { .. } => "E0324",
+            User { .. } => "E0325",
+        }
+    }
+
+    // The value a script sees for this error: a map with its `kind`, its
+    // `message` and the `line` it happened on, nil when not known
+    pub fn to_val(&self, loc: Option<Loc>) -> Val {
+        // What was thrown is caught as is
+        if let Self::Thrown { value } = self {
+            return value.clone();
+        }
+        let message = match self {
+            Self::User { message, .. } => message.clone(),
+            e => e.to_string(),
+        };
+        let fields = [
+            ("kind", Val::String(self.kind().into())),
+            ("message", Val::String(message.into())),
+            ("line", loc.map_or(Val::Nil, |l| Val::Number(l.row as f64))),
+        ];
+        Val::Map(Map::new(
+            fields.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+        ))
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DiscardedTail { func, .. } => {
+                write!(
+                    f,
+                    "the value of the last statement in '{func}' is discarded"
+                )
+            }
+            Self::LoopCapture { name, .. } => write!(
+                f,
+                "function declared in a loop captures the loop variable '{name}'"
+            ),
+            Self::UnusedResult { expr, .. } => write!(f, "the result of `{expr}` is unused"),
+            Self::AsgnInCondition { expr, .. } => {
+                write!(f, "the condition `{expr}` is an assignment")
+            }
+            Self::ChainedCmp { expr, .. } => {
+                write!(f, "`{expr}` compares the result of a comparison")
+            }
+            Self::UnusedVariable { name, param, .. } => {
+                let kind = if *param { "parameter" } else { "variable" };
+                write!(f, "unused {kind} '{name}'")
+            }
+            Self::UnreadAssignment { name, .. } => {
+                write!(f, "value assigned to '{name}' is never read")
+            }
+            Self::UnusedFunction { name, .. } => write!(f, "function '{name}' is never used"),
+            Self::Unreachable { .. } => write!(f, "unreachable code"),
+            Self::EndlessLoop => write!(f, "nothing breaks out of this `loop`"),
+            Self::DuplicateArm { pattern } => {
+                write!(f, "`{pattern}` is matched by more than one arm")
+            }
+            Self::NumberOverflow { text, .. } => {
+                write!(
+                    f,
+                    "number literal '{text}' is too large and becomes infinity"
+                )
+            }
+            Self::NumberPrecision { text, .. } => {
+                write!(f, "number literal '{text}' cannot be represented exactly")
+            }
+            Self::Metric {
+                func,
+                metric,
+                value,
+                max,
+            } => write!(
+                f,
+                "'{func}' has a {metric} of {value}, over the limit of {max}"
+            ),
+        }
+    }
+}
+
+// The line of a diagnostic with `msg` under the character at `loc`
+fn render_at_location(src: &SourceMap, loc: &Loc, msg: &str) -> String {
+    let line = src
+        .display_line(loc.row)
+        .expect("Errors should be reported on an existing line");
+    format!(
+        "  --> {}:{loc}\n{:>4} | {line}\n       {}{msg}\n",
+        src.name(),
         loc.row,
-        src.lines()
-            .nth(loc.row)
-            .expect("Errors should be reported on an existing line")
-    );
-    eprintln!("       {}{msg}", " ".repeat(loc.col))
+        " ".repeat(src.display_col(*loc))
+    )
+}
+
+fn message_at_location(src: &SourceMap, loc: &Loc, msg: &str) {
+    eprint!("{}", render_at_location(src, loc, msg))
 }
 
 pub trait Report {
-    fn report(&self, code: &str);
+    fn report(&self, src: &SourceMap);
+}
+
+impl Report for LoxError {
+    fn report(&self, code: &SourceMap) {
+        match self {
+            Self::Io { .. } => eprintln!("error[lox]: {self}"),
+            Self::Runtime(e) => eprintln!("error[{}]: {e}", e.code()),
+            Self::Lex(errs) => errs.iter().for_each(|e| e.report(code)),
+            Self::Parse(errs) => errs.iter().for_each(|e| e.report(code)),
+            Self::Resolve(errs) => errs.iter().for_each(|e| e.report(code)),
+            Self::Denied(warnings) => {
+                for w in warnings {
+                    w.report_as(code, Severity::Error);
+                    eprintln!("       note: the lint '{}' is denied", w.lint());
+                }
+            }
+        }
+    }
+}
+
+impl LoxError {
+    // The line that closes the diagnostics of code that does not compile
+    pub fn summary(&self) -> Option<String> {
+        let n = match self {
+            Self::Lex(errs) => errs.len(),
+            Self::Parse(errs) => errs.len(),
+            Self::Resolve(errs) => errs.len(),
+            Self::Denied(warnings) => warnings.len(),
+            Self::Io { .. } | Self::Runtime(_) => return None,
+        };
+        let s = if n == 1 { "" } else { "s" };
+        Some(format!("error[lox]: aborting due to {n} previous error{s}"))
+    }
+}
+
+// The most calls a trace shows
+const MAX_TRACE: usize = 16;
+
+impl RuntimeError {
+    // Runtime errors are located by the interpreter that ran into them,
+    // see `Context::error_at`, which also traces the calls they happened
+    // in. A location is shown against the source it is in: `code`, which
+    // for the REPL holds the earlier lines too, or one of those `kept`,
+    // such as the prelude. Only its name is shown for any other
+    pub fn report_at(
+        &self,
+        code: &SourceMap,
+        kept: &[SourceMap],
+        at: Option<&Located>,
+        trace: &[(String, Option<Located>)],
+    ) {
+        eprint!("{}", self.render_at(code, kept, at, trace));
+    }
+
+    pub fn render_at(
+        &self,
+        code: &SourceMap,
+        kept: &[SourceMap],
+        at: Option<&Located>,
+        trace: &[(String, Option<Located>)],
+    ) -> String {
+        let show = |at: &Located, msg: &str| {
+            let src = once(code).chain(kept).find(|s| s.name() == &*at.source);
+            let fits = |src: &&SourceMap| {
+                src.line_text(at.loc.row)
+                    .is_some_and(|t| at.loc.col < t.chars().count())
+            };
+            match src.filter(fits) {
+                Some(src) => render_at_location(src, &at.loc, msg),
+                None => format!("  --> {}:{}\n", at.source, at.loc),
+            }
+        };
+        let mut out = format!("error[{}]: {self}\n", self.code());
+        match (self, at) {
+            (
+                Self::Arity {
+                    decl: Some(decl), ..
+                },
+                Some(at),
+            ) => {
+                out += &show(at, "^ called here...");
+                out += "...\n";
+                out += &show(decl, "^ ... but declared here");
+            }
+            (_, Some(at)) => out += &show(at, "^ here"),
+            (_, None) => {}
+        }
+        // Runaway recursion is shown by its innermost calls and where it
+        // started
+        let shown = |i: usize| i < MAX_TRACE - 1 || i == trace.len() - 1;
+        for (i, (name, loc)) in trace.iter().enumerate() {
+            if !shown(i) {
+                if i == MAX_TRACE - 1 {
+                    let hidden = trace.len() - MAX_TRACE;
+                    out += &format!("       ... {hidden} more calls\n");
+                }
+                continue;
+            }
+            match loc {
+                Some(at) => out += &format!("       at {name} ({}:{})\n", at.source, at.loc),
+                None => out += &format!("       at {name}\n"),
+            }
+        }
+        out
+    }
+}
+
+impl Report for LexError {
+    fn report(&self, code: &SourceMap) {
+        eprintln!("error[{}]: {self}", self.code());
+        match self {
+            Self::Unterminated { loc, len } => {
+                message_at_location(code, loc, "^ opened here");
+                eprintln!("       note: the string took in the remaining {len} characters");
+            }
+            _ => message_at_location(code, &self.loc(), "^"),
+        }
+    }
 }
 
 impl Report for ParserError {
-    fn report(&self, code: &str) {
+    fn report(&self, code: &SourceMap) {
         use ParserError::*;
-        eprint!("error[lox]: ");
+        eprintln!("error[{}]: {self}", self.code());
 
         match self {
-            Expected { exp, fnd: None } => {
-                eprintln!("expected {exp}, found end of file");
-                let (lineno, line) = code.lines().enumerate().last().unwrap();
-                eprintln!("{lineno:>4} | {line}");
-                eprintln!("       {}^ EOF found here", " ".repeat(line.len()));
+            Expected { fnd: None, .. } | Eof => {
+                message_at_location(code, &code.eof_loc(), "^ EOF found here");
             }
             Expected {
-                exp,
-                fnd: Some(tok),
+                exp: TokKind::LBrace,
+                fnd:
+                    Some(
+                        tok @ Token {
+                            kind: TokKind::Arrow,
+                            ..
+                        },
+                    ),
             } => {
-                eprintln!("syntax error: expected {exp}, found {}", tok.kind);
                 message_at_location(code, &tok.loc, "^ here");
+                eprintln!("       help: a function with a block body takes no `=>`, remove it");
+            }
+            Expected { fnd: Some(tok), .. } => message_at_location(code, &tok.loc, "^ here"),
+            MissingSemicolon { .. } => {
+                message_at_location(code, &self.loc(code), "^ help: add ';' here");
             }
             TooManyParams { loc } => {
                 message_at_location(code, loc, "^ this is the 256th parameter");
             }
+            DuplicateParam { loc, .. } => {
+                message_at_location(code, loc, "^ already used in this parameter list");
+            }
+            ParamShadowsFn { loc, .. } => {
+                message_at_location(code, loc, "^ the function could not refer to itself");
+            }
+            MissingDefault { loc, .. } => {
+                message_at_location(code, loc, "^ it comes after a parameter with one");
+            }
+            RestNotLast { loc, .. } => {
+                message_at_location(code, loc, "^ it takes every argument left");
+            }
             TooManyArgs { loc } => {
-                eprintln!("functions cannot take more than 255 arguments");
                 message_at_location(code, loc, "^ this is the 256th argument");
             }
+            TooDeep { loc, .. } => message_at_location(code, loc, "^ too deep"),
             InvalidAsgn { loc } => {
-                eprintln!("invalid assignment target");
                 message_at_location(code, loc, "^ only variables may be assigned a value");
             }
+            ReservedWord { loc, .. } => message_at_location(code, loc, "^ reserved word"),
+            InvalidPattern { loc } => {
+                message_at_location(code, loc, "^ only literals and `_` may be matched");
+            }
+            ChainedCmp { fix, loc, .. } => {
+                message_at_location(code, loc, "^ comparisons do not chain");
+                eprintln!("       help: did you mean `{fix}`?");
+            }
             Unmatched { open, hint } => {
-                eprintln!("unmatched {}", open.kind);
                 if let Some(hint) = hint {
                     message_at_location(code, &open.loc, "^ unclosed delimiter here...");
                     eprintln!("...");
@@ -66,16 +1240,405 @@ impl Report for ParserError {
                     message_at_location(code, &open.loc, "^ unclosed delimiter here");
                 }
             }
-            Unexpected { tok } => {
-                eprintln!("unexpected token: {}", tok.kind);
-                message_at_location(code, &tok.loc, "^");
+            Unexpected { tok } => message_at_location(code, &tok.loc, "^"),
+            BadNumber { loc, .. } => message_at_location(code, loc, "^"),
+            Lex(e) => message_at_location(code, &e.loc(), "^"),
+        }
+    }
+}
+
+impl Report for ResolveError {
+    fn report(&self, code: &SourceMap) {
+        eprintln!("error[{}]: {self}", self.code());
+        match self {
+            Self::Redeclared { loc, prev, .. } if loc != prev => {
+                message_at_location(code, loc, "^ declared again here...");
+                eprintln!("...");
+                message_at_location(code, prev, "^ ... after it was declared here");
             }
-            EOF => {
-                eprintln!("unexpected end of file");
-                let (lineno, line) = code.lines().enumerate().last().unwrap();
-                eprintln!("{lineno:>4} | {line}");
-                eprintln!("       {}^ EOF found here", " ".repeat(line.len()));
+            Self::Arity {
+                loc,
+                decl: Some(decl),
+                ..
+            } => {
+                message_at_location(code, loc, "^ called here...");
+                eprintln!("...");
+                message_at_location(code, decl, "^ ... but declared here");
             }
+            _ => message_at_location(code, &self.loc(), "^"),
         }
     }
 }
+
+impl Report for Warning {
+    fn report(&self, code: &SourceMap) {
+        self.report_as(code, Severity::Warning);
+    }
+}
+
+impl Warning {
+    // Denied warnings are reported as errors
+    fn report_as(&self, code: &SourceMap, sev: Severity) {
+        use Warning::*;
+        eprintln!("{}[{}]: {self}", sev.name(), self.code());
+
+        match self {
+            DiscardedTail { expr, loc, .. } => {
+                message_at_location(code, loc, "^");
+                eprintln!("       help: did you mean `return {expr};`?");
+            }
+            LoopCapture { name, loc } => {
+                message_at_location(code, loc, "^ captured here");
+                eprintln!("       note: all iterations share '{name}', so the function sees its latest value");
+                eprintln!("       help: copy it into a variable declared inside the loop body");
+            }
+            UnusedResult { expr, asgn, loc } => {
+                message_at_location(code, loc, "^");
+                if let (true, Expr::Eq(lhs, rhs, _)) = (asgn, expr) {
+                    eprintln!("       help: did you mean `{lhs} = {rhs};`?");
+                }
+            }
+            NumberOverflow { loc, .. } => message_at_location(code, loc, "^"),
+            NumberPrecision { text, loc } => {
+                message_at_location(code, loc, "^");
+                let x: f64 = text.parse().unwrap_or_default();
+                eprintln!("       note: it is the same number as {x}");
+            }
+            AsgnInCondition { expr, loc } => {
+                message_at_location(code, loc, "^");
+                if let Expr::Asgn(var, value) = expr {
+                    eprintln!("       help: did you mean `{} == {value}`?", var.name);
+                }
+                eprintln!("       note: add `// lox-allow` to the line if it is intended");
+            }
+            ChainedCmp { fix, loc, .. } => {
+                message_at_location(code, loc, "^ comparisons do not chain");
+                eprintln!("       help: did you mean `{fix}`?");
+            }
+            UnusedVariable { name, loc, .. } => {
+                message_at_location(code, loc, "^");
+                eprintln!("       help: if this is intended, name it '_{name}'");
+            }
+            UnreadAssignment { name, loc, decl } => {
+                message_at_location(code, loc, "^ assigned here...");
+                eprintln!("...");
+                message_at_location(
+                    code,
+                    decl,
+                    "^ ... to a variable declared here, which is never read",
+                );
+                eprintln!("       help: if this is intended, name it '_{name}'");
+            }
+            UnusedFunction { name, loc } => {
+                message_at_location(code, loc, "^");
+                eprintln!("       note: nothing run from the top level calls it");
+                eprintln!("       help: if this is intended, name it '_{name}'");
+            }
+            Unreachable { loc, jump } => {
+                message_at_location(code, loc, "^ this never runs...");
+                eprintln!("...");
+                message_at_location(code, jump, "^ ... as this always jumps away");
+            }
+            EndlessLoop => eprintln!("       note: it only ends with an error"),
+            DuplicateArm { .. } => eprintln!("       note: only the first of them ever runs"),
+            Metric { .. } => eprintln!("       help: consider splitting it into smaller functions"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    fn run(src: &str) -> Result<(), LoxError> {
+        Interpreter::builder()
+            .prelude(false)
+            .build()
+            .run(&SourceMap::new("test", src))
+    }
+
+    #[test]
+    fn exit_codes() {
+        let code = |src| run(src).unwrap_err().exit_code();
+        assert_eq!(code("let s = \"open;"), ExitCode::from(65));
+        assert_eq!(code("let = 1;"), ExitCode::from(65));
+        assert_eq!(code("return 1;"), ExitCode::from(65));
+        assert_eq!(code("1 + nil;"), ExitCode::from(70));
+        assert_eq!(code("assert false;"), ExitCode::from(70));
+        let io = LoxError::Io {
+            path: "x".to_string(),
+        };
+        assert_eq!(io.exit_code(), ExitCode::from(74));
+    }
+
+    #[test]
+    fn lex_errors_aggregate() {
+        assert_eq!(
+            run("let a = #;\nlet b = @;\nprint \"open;"),
+            Err(LoxError::Lex(vec![
+                LexError::Unexpected {
+                    c: '#',
+                    loc: Loc {
+                        row: 0,
+                        col: 8,
+                        offset: 8
+                    }
+                },
+                LexError::Unexpected {
+                    c: '@',
+                    loc: Loc {
+                        row: 1,
+                        col: 8,
+                        offset: 19
+                    }
+                },
+                LexError::Unterminated {
+                    loc: Loc {
+                        row: 2,
+                        col: 6,
+                        offset: 28
+                    },
+                    len: 5
+                },
+            ]))
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let err = run("let a = 1;\nlet s = \"one\ntwo;\nprint s;").unwrap_err();
+        let LoxError::Lex(errs) = err else {
+            panic!("expected a lexical error")
+        };
+        assert_eq!(
+            errs,
+            vec![LexError::Unterminated {
+                loc: Loc {
+                    row: 1,
+                    col: 8,
+                    offset: 19
+                },
+                len: 17
+            }]
+        );
+        assert_eq!(
+            errs[0].to_string(),
+            "string starting at 1:8 is never closed"
+        );
+    }
+
+    #[test]
+    fn unterminated_comment() {
+        assert_eq!(
+            run("let a = 1; /* closed */\n/* open"),
+            Err(LoxError::Lex(vec![LexError::UnterminatedComment {
+                loc: Loc {
+                    row: 1,
+                    col: 0,
+                    offset: 24
+                }
+            }]))
+        );
+    }
+
+    #[test]
+    fn resolve_errors_aggregate() {
+        let at = |col| Loc {
+            row: 0,
+            col,
+            offset: col,
+        };
+        assert_eq!(
+            run("{ let a; let a; } return; { let b = b; }"),
+            Err(LoxError::Resolve(vec![
+                ResolveError::Redeclared {
+                    name: "a".to_string(),
+                    loc: at(13),
+                    prev: at(6),
+                },
+                ResolveError::TopLevelReturn { loc: at(18) },
+                ResolveError::ReadInInitializer {
+                    name: "b".to_string(),
+                    loc: at(36),
+                },
+            ]))
+        );
+    }
+
+    #[test]
+    fn runtime_error() {
+        assert_eq!(
+            run("let a = 1; a();"),
+            Err(LoxError::Runtime(RuntimeError::NotCallable))
+        );
+        // Called through another name, so that the resolver does not check
+        // the number of arguments first
+        let msg = |src| run(src).unwrap_err().to_string();
+        assert_eq!(
+            msg("fn f(a, b = 1) {} let g = f; g();"),
+            "f expected 1 to 2 arguments, got 0"
+        );
+        assert_eq!(
+            msg("fn f(a, b = 1) {} let g = f; g(1, 2, 3);"),
+            "f expected 1 to 2 arguments, got 3"
+        );
+        assert_eq!(
+            msg("fn f(a) {} let g = f; g();"),
+            "f expected 1 arguments, got 0"
+        );
+        assert_eq!(
+            msg("fn f(a, ...b) {} let g = f; g();"),
+            "f expected at least 1 arguments, got 0"
+        );
+        // Natives are named as they are defined
+        assert_eq!(
+            msg("let c = clock; c(1);"),
+            "clock expected 0 arguments, got 1"
+        );
+    }
+
+    #[test]
+    fn error_values() {
+        let e = RuntimeError::TypeMismatch {
+            msg: "operand must be a number",
+        };
+        assert_eq!(
+            e.to_val(None).to_string(),
+            r#"{"kind": "TypeMismatch", "line": nil, "message": "operand must be a number"}"#
+        );
+    }
+
+    #[test]
+    fn raised_errors() {
+        let err = run(r#"raise("Parse", "bad input");"#).unwrap_err();
+        let LoxError::Runtime(e) = err else {
+            panic!("expected a runtime error")
+        };
+        assert_eq!(e.to_string(), "Parse: bad input");
+        assert_eq!(
+            e.to_val(None).to_string(),
+            r#"{"kind": "Parse", "line": nil, "message": "bad input"}"#
+        );
+        assert!(run(r#"raise(1, "a");"#).is_err());
+    }
+
+    #[test]
+    fn caret_after_tabs() {
+        let src = SourceMap::new("test", "\tlet x = ;");
+        assert_eq!(
+            render_at_location(
+                &src,
+                &Loc {
+                    row: 0,
+                    col: 9,
+                    offset: 9
+                },
+                "^"
+            ),
+            "  --> test:0:9\n   0 |     let x = ;\n                   ^\n"
+        );
+        let src = SourceMap::new("test", " \t\tx = ;").tab_width(8);
+        assert_eq!(
+            render_at_location(
+                &src,
+                &Loc {
+                    row: 0,
+                    col: 3,
+                    offset: 3
+                },
+                "^"
+            ),
+            format!(
+                "  --> test:0:3\n   0 | {}x = ;\n{}^\n",
+                " ".repeat(16),
+                " ".repeat(7 + 16)
+            )
+        );
+    }
+
+    #[test]
+    fn parser_messages() {
+        let msg = |src| match run(src) {
+            Err(LoxError::Parse(errs)) => errs[0].to_string(),
+            res => panic!("expected a syntax error, got {res:?}"),
+        };
+        assert_eq!(
+            msg("{ let a = 1 }"),
+            "syntax error: expected ';', found '}'"
+        );
+        assert_eq!(
+            msg("class (a) {}"),
+            "syntax error: expected an identifier, found '('"
+        );
+        assert_eq!(msg("fn (a) {}"), "expected ';', found end of file");
+        assert_eq!(
+            msg("let a = 1 b;"),
+            "syntax error: expected ';', found identifier 'b'"
+        );
+        assert_eq!(
+            msg("print 1 \"s\";"),
+            "syntax error: expected ';', found string \"s\""
+        );
+        assert_eq!(
+            msg("print 1 2;"),
+            "syntax error: expected ';', found number 2"
+        );
+        assert_eq!(
+            msg("let = 1;"),
+            "syntax error: expected an identifier, found '='"
+        );
+        assert_eq!(msg("print );"), "unexpected token: ')'");
+        assert_eq!(msg("print (1;"), "unmatched '('");
+        assert_eq!(
+            msg("fn f(a = 1, b) {}"),
+            "parameter 'b' needs a default value"
+        );
+        assert_eq!(
+            msg("fn f(...a, b) {}"),
+            "rest parameter 'a' must be the last one"
+        );
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let src = SourceMap::new("test", "let s = \"a b\"\nprint s;");
+        let Err(LoxError::Parse(errs)) = run(src.text()) else {
+            panic!("expected a syntax error");
+        };
+        let [e] = &errs[..] else {
+            panic!("expected one error, got {errs:?}");
+        };
+        // Right after the string
+        let end = e.loc(&src);
+        assert_eq!((end.row, end.col), (0, 13));
+        assert_eq!(
+            render_at_location(&src, &end, "^ help: add ';' here"),
+            "  --> test:0:13\n   0 | let s = \"a b\"\n                    ^ help: add ';' here\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let src = SourceMap::new("a \"b\".lox", "let x = ;");
+        let err = run(src.text()).unwrap_err();
+        assert_eq!(
+            err.to_json(&src),
+            vec![
+                r#"{"severity": "error", "stage": "parse", "message": "unexpected token: ';'", "file": "a \"b\".lox", "line": 0, "column": 8, "offset": 8}"#
+            ]
+        );
+        let w = Warning::LoopCapture {
+            name: "i".to_string(),
+            loc: Loc {
+                row: 0,
+                col: 4,
+                offset: 4,
+            },
+        };
+        assert!(w.to_json(&src).starts_with(r#"{"severity": "warning""#));
+        assert!(w
+            .to_json(&src)
+            .ends_with(r#""line": 0, "column": 4, "offset": 4}"#));
+    }
+}
@@ -0,0 +1,407 @@
+// What `lox explain` says about each diagnostic code, see the `code` of
+// each kind of error and of `Warning`. Codes are never reused, so that
+// those in old output keep their meaning: a diagnostic that goes away
+// keeps its explanation, and a new one takes the next free code of its
+// stage. Errors reading a file have no code, as they are not about code
+
+// The explanation of `code`, in either case
+pub fn explain(code: &str) -> Option<&'static str> {
+    Some(match code.to_ascii_uppercase().as_str() {
+        // Lexing
+        "E0001" => {
+            "\
+A character that no token starts with, such as `@` or `$`, outside of a
+string or a comment."
+        }
+        "E0002" => {
+            "\
+A string whose closing `\"` never comes. Strings may span lines, so the
+error shows where the string starts, and everything after it was taken
+in as part of it."
+        }
+        "E0003" => {
+            "\
+A block comment whose closing `*/` never comes. Block comments nest, so
+each `/*` inside one needs its own `*/`."
+        }
+
+        // Parsing
+        "E0101" => {
+            "\
+A token other than the one the grammar requires at this point, such as a
+missing `)` or `{`. The error names what was expected and what was
+found."
+        }
+        "E0102" => {
+            "\
+A statement that is not ended by `;` before the next one starts on a
+later line:
+
+    let a = 1
+    print a;"
+        }
+        "E0103" => {
+            "\
+A function declared with more than 255 parameters."
+        }
+        "E0104" => {
+            "\
+Two parameters of the same function share a name, so the second would
+hide the first:
+
+    fn f(a, a) {}"
+        }
+        "E0105" => {
+            "\
+A parameter named as its function, which would hide the function from
+its own body, so that it could not call itself."
+        }
+        "E0106" => {
+            "\
+A parameter without a default value after one with a default. Arguments
+fill parameters from the left, so every parameter after the first with
+a default needs one too:
+
+    fn f(a = 1, b) {}"
+        }
+        "E0107" => {
+            "\
+A rest parameter, `...name`, that is not the last parameter. It takes
+every argument left, so none can follow it."
+        }
+        "E0108" => {
+            "\
+A call with more than 255 arguments."
+        }
+        "E0109" => {
+            "\
+Code nested deeper than the limit, such as parentheses inside one
+another. Each link of a chain of operators or calls counts too. There is
+a limit only under the sandbox, where code nested this deep would
+otherwise overflow the native stack."
+        }
+        "E0110" => {
+            "\
+The left side of `=` is not something that can be assigned to. Only
+variables, properties, such as `a.b`, and indexing, such as `a[0]`, can
+be:
+
+    1 = 2;"
+        }
+        "E0111" => {
+            "\
+A keyword used as a name, such as of a variable, a parameter or a
+function."
+        }
+        "E0112" => {
+            "\
+An arm of a `match` whose pattern is not a literal, a negative number
+or `_`."
+        }
+        "E0113" => {
+            "\
+A comparison of the result of a comparison, such as `a < b < c`, under
+`--strict`. It compares `a < b`, which is `true` or `false`, to `c`.
+Write `a < b and b < c` to test that `b` lies between the two."
+        }
+        "E0114" => {
+            "\
+An opening parenthesis, bracket or brace that is never closed. The error
+shows where it was opened, and where it was likely meant to be closed."
+        }
+        "E0115" => {
+            "\
+A token that no expression can start with, such as `)` or `;` where a
+value was expected."
+        }
+        "E0116" => {
+            "\
+A number literal that is not a valid number, such as `1.2.3`. An `_`
+may only separate two digits, as in `1_000`."
+        }
+        "E0117" => {
+            "\
+The file ends in the middle of a statement."
+        }
+
+        // Resolving
+        "E0201" => {
+            "\
+A variable declared twice in the same local scope. Declaring it again
+in an inner block hides the outer one instead."
+        }
+        "E0202" => {
+            "\
+A local variable read in its own initializer, where it is not yet
+defined:
+
+    {
+      let a = a + 1;
+    }"
+        }
+        "E0203" => {
+            "\
+A `return` outside of any function."
+        }
+        "E0204" => {
+            "\
+`this` outside of the methods of a class, where there is no instance
+for it to be."
+        }
+        "E0205" => {
+            "\
+`this` in a static method, which is called on the class rather than on
+an instance."
+        }
+        "E0206" => {
+            "\
+A `return` with a value in `init`. The initializer always gives the
+instance, so it may only `return;` early."
+        }
+        "E0207" => {
+            "\
+`super` outside of the methods of a class with a superclass."
+        }
+        "E0208" => {
+            "\
+A class named as its own superclass:
+
+    class A < A {}"
+        }
+        "E0209" => {
+            "\
+A `break` or `continue` outside of any loop."
+        }
+        "E0210" => {
+            "\
+A `break` or `continue` naming a label that no enclosing loop has."
+        }
+        "E0211" => {
+            "\
+A call with a number of arguments that the function declared in the
+same unit of code cannot take. The error shows the declaration as well."
+        }
+
+        // Running
+        "E0301" => {
+            "\
+A variable read or assigned to that was never declared. Globals must be
+declared before the code using them runs."
+        }
+        "E0302" => {
+            "\
+A property read that the instance does not have, nor any method of its
+class or of the classes it inherits from."
+        }
+        "E0303" => {
+            "\
+A value of the wrong type for what is done with it, such as `-` on a
+string or `+` on a string and a number. `--coerce-strings` lets `+` make
+a string of a number added to a string."
+        }
+        "E0304" => {
+            "\
+A call to a value that is not a function nor a class."
+        }
+        "E0305" => {
+            "\
+A call with a number of arguments that the function cannot take. The
+error shows the declaration of the function when it has one."
+        }
+        "E0306" => {
+            "\
+A call with more than 255 arguments once spread lists are expanded."
+        }
+        "E0307" => {
+            "\
+An index past the end of a list or a string, or one that is negative or
+not a whole number."
+        }
+        "E0308" => {
+            "\
+A key read from a map that has no entry for it. `has` tells whether it
+does first."
+        }
+        "E0309" => {
+            "\
+A change to a list or a map made immutable by `freeze`."
+        }
+        "E0310" => {
+            "\
+A deep copy with `clone` of a list or a map that contains itself, which
+would never end."
+        }
+        "E0311" => {
+            "\
+A `return` outside of any function, in a program that was not
+resolved, such as one built by a tool."
+        }
+        "E0312" => {
+            "\
+A program that did not parse, run anyway, such as by a tool. Running
+stops at the first statement that failed to parse."
+        }
+        "E0313" => {
+            "\
+A string that would grow past the longest one allowed, 1 GiB, or 1 MiB
+under the sandbox."
+        }
+        "E0314" => {
+            "\
+A list that would grow past the most items allowed under the sandbox."
+        }
+        "E0315" => {
+            "\
+A run that executed more statements than the sandbox allows, such as in
+a loop that never ends. Catching the error does not give any back."
+        }
+        "E0316" => {
+            "\
+Something that the sandbox does not allow at all, such as `import`."
+        }
+        "E0317" => {
+            "\
+A sandboxed run that panicked, such as in a callable of the host. It is
+a bug in the host or the interpreter rather than in the script."
+        }
+        "E0318" => {
+            "\
+A list destructured by `let` with fewer items than names:
+
+    let [a, b] = [1];"
+        }
+        "E0319" => {
+            "\
+An `assert` whose condition does not hold."
+        }
+        "E0320" => {
+            "\
+A file that `import` could not read, or that failed to compile or run.
+Its own diagnostics were reported before this error."
+        }
+        "E0321" => {
+            "\
+Files that import each other in a cycle. The error lists every file in
+it."
+        }
+        "E0322" => {
+            "\
+Too many calls in progress at once, usually from a recursion that does
+not end. `--max-call-depth` raises the limit."
+        }
+        "E0323" => {
+            "\
+A division or a remainder by zero under `--strict-math`, which would
+otherwise give `inf` or `nan`."
+        }
+        "E0324" => {
+            "\
+A value thrown by `throw` that no `try` caught."
+        }
+        "E0325" => {
+            "\
+An error raised by a script with `raise`, of the kind it gave."
+        }
+
+        // Warnings, in the order of `LINTS`
+        "W0001" => {
+            "\
+A function whose last statement is an expression whose value is thrown
+away. It looks like a `return` was left out:
+
+    fn double(x) { x * 2; }"
+        }
+        "W0002" => {
+            "\
+A function declared in a loop that captures the loop variable. Every
+iteration shares the variable, so the function sees its latest value
+rather than the one of its iteration. Copy it into a variable declared
+inside the body."
+        }
+        "W0003" => {
+            "\
+An expression statement whose value is unused, such as a comparison
+written where an assignment was meant:
+
+    a == 1;"
+        }
+        "W0004" => {
+            "\
+An assignment as the condition of an `if` or a `while`, where a
+comparison was likely meant."
+        }
+        "W0005" => {
+            "\
+A comparison of the result of a comparison, such as `a < b < c`. It
+compares `a < b`, which is `true` or `false`, to `c`. Write
+`a < b and b < c` to test that `b` lies between the two."
+        }
+        "W0006" => {
+            "\
+A local variable or parameter that is never read nor assigned to.
+Those whose name starts with `_` are not reported."
+        }
+        "W0007" => {
+            "\
+A value assigned to a local variable that is never read afterwards."
+        }
+        "W0008" => {
+            "\
+A function that no code reachable from the top level calls or reads."
+        }
+        "W0009" => {
+            "\
+A statement after one that always jumps away, such as `return`, `break`
+or `throw`, which can never run."
+        }
+        "W0010" => {
+            "\
+A `loop` with neither a `break` nor a `return` out of it, which runs
+forever."
+        }
+        "W0011" => {
+            "\
+An arm of a `match` whose pattern an earlier arm already matches, so
+that it never runs."
+        }
+        "W0012" => {
+            "\
+A number literal too large for a number, which becomes infinity."
+        }
+        "W0013" => {
+            "\
+A number literal with more digits than a number can hold, which is
+rounded to the nearest number that can be."
+        }
+        "W0014" => {
+            "\
+A function over one of the limits given to `lox lint`, such as
+`--max-complexity`."
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::explain;
+
+    // Each stage numbers its codes from 1 with no gaps
+    #[test]
+    fn codes_contiguous() {
+        for (prefix, first, count) in [
+            ("E", 1, 3),
+            ("E", 101, 17),
+            ("E", 201, 11),
+            ("E", 301, 25),
+            ("W", 1, 14),
+        ] {
+            for n in first..first + count {
+                assert!(explain(&format!("{prefix}{n:04}")).is_some(), "{n}");
+            }
+            assert_eq!(explain(&format!("{prefix}{:04}", first + count)), None);
+        }
+        assert_eq!(explain("e0101"), explain("E0101"));
+        assert_eq!(explain("lox"), None);
+    }
+}
@@ -1,116 +1,1001 @@
 use std::rc::Rc;
 
+use crate::collections;
+use crate::context::{Context, Frame, StringPlusNumber::Coerce};
+use crate::error::RuntimeError;
+use crate::lexer::Loc;
 use crate::scope::Scope;
-use crate::val::{Callable, Val};
+use crate::source::Located;
+use crate::stmt::{FunDecl, Stmt};
+use crate::val::{call_value, checked_callee, Function, Val};
+
+// Where a node is in the source. Every node that can fail at runtime has
+// one, at its operator unless noted. Locations take no part in comparisons,
+// so trees parsed from different text compare equal
+#[derive(Debug, Clone, Copy, Default)]
+pub struct At(pub Loc);
+
+impl PartialEq for At {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Expr {
     // A variant for grouping is not necessary,
     // as long as the parser handles `Paren`s correctly
     Asgn(Variable, Box<Expr>),
-    Call(Box<Expr>, Vec<Expr>),
+    // `a, b` evaluates both and yields `b`
+    Seq(Box<Expr>, Box<Expr>),
+    // At the `(`
+    Call(Box<Expr>, Vec<Arg>, At),
+    // `cond ? then : else`, which evaluates only the branch taken
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
     And(Box<Expr>, Box<Expr>),
     Or(Box<Expr>, Box<Expr>),
-    Eq(Box<Expr>, Box<Expr>),
-    Ne(Box<Expr>, Box<Expr>),
-    Gt(Box<Expr>, Box<Expr>),
-    Ge(Box<Expr>, Box<Expr>),
-    Lt(Box<Expr>, Box<Expr>),
-    Le(Box<Expr>, Box<Expr>),
-    Add(Box<Expr>, Box<Expr>),
-    Sub(Box<Expr>, Box<Expr>),
-    Mul(Box<Expr>, Box<Expr>),
-    Div(Box<Expr>, Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>, At),
+    Ne(Box<Expr>, Box<Expr>, At),
+    Gt(Box<Expr>, Box<Expr>, At),
+    Ge(Box<Expr>, Box<Expr>, At),
+    Lt(Box<Expr>, Box<Expr>, At),
+    Le(Box<Expr>, Box<Expr>, At),
+    // Bitwise operators work on the integers held by numbers
+    BitOr(Box<Expr>, Box<Expr>, At),
+    BitXor(Box<Expr>, Box<Expr>, At),
+    BitAnd(Box<Expr>, Box<Expr>, At),
+    Shl(Box<Expr>, Box<Expr>, At),
+    Shr(Box<Expr>, Box<Expr>, At),
+    Add(Box<Expr>, Box<Expr>, At),
+    Sub(Box<Expr>, Box<Expr>, At),
+    Mul(Box<Expr>, Box<Expr>, At),
+    Div(Box<Expr>, Box<Expr>, At),
+    // The remainder of a truncating division, so it takes the sign of the
+    // dividend
+    Rem(Box<Expr>, Box<Expr>, At),
+    // Binds tighter than the unary operators, so `-2 ** 2` is -4
+    Pow(Box<Expr>, Box<Expr>, At),
     Not(Box<Expr>),
-    Opp(Box<Expr>),
+    Opp(Box<Expr>, At),
     Lit(Val),
     Var(Variable),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>, At),
+    // At the `[`
+    Index(Box<Expr>, Box<Expr>, At),
+    SetIndex(Box<Expr>, Box<Expr>, Box<Expr>, At),
+    // At the name of the property
+    Get(Box<Expr>, String, At),
+    Set(Box<Expr>, String, Box<Expr>, At),
+    This(Variable),
+    // Resolved like a variable named `super`
+    Super(Variable, String),
+    // `fn (params) { body }`, a function without a name
+    Lambda(Rc<FunDecl>),
+}
+
+// An argument of a call, `x`, or `...xs` for the items of a list
+#[derive(PartialEq, Debug, Clone)]
+pub enum Arg {
+    Positional(Expr),
+    Spread(Expr),
+}
+
+impl Arg {
+    pub fn expr(&self) -> &Expr {
+        match self {
+            Self::Positional(e) | Self::Spread(e) => e,
+        }
+    }
+
+    pub fn expr_mut(&mut self) -> &mut Expr {
+        match self {
+            Self::Positional(e) | Self::Spread(e) => e,
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Variable {
-    pub name: Box<str>,
+    pub name: Rc<str>,
     pub depth: isize,
+    pub loc: At,
 }
 
 impl Variable {
-    pub fn new(name: String) -> Self {
+    pub fn new(name: impl Into<Rc<str>>) -> Self {
         Self {
-            name: name.into_boxed_str(),
+            name: name.into(),
             depth: -1,
+            loc: At::default(),
+        }
+    }
+
+    pub fn at(name: impl Into<Rc<str>>, loc: Loc) -> Self {
+        Self {
+            loc: At(loc),
+            ..Self::new(name)
         }
     }
 }
 
+// SEMANTICS: evaluation order
+//
+// Every sub-expression is evaluated exactly once, strictly left-to-right:
+// - binary operators evaluate their left operand before their right one;
+// - `and`/`or` evaluate their left operand, and the right one only when the
+//   left one does not decide the result on its own;
+// - calls evaluate the callee first, then every argument from first to last,
+//   and only then check the arity and perform the call;
+// - assignments evaluate the value before binding it, so `a = b = c`
+//   evaluates `c`, binds `b` and finally binds `a`;
+// - indexing evaluates the object before the index, and assigning to an
+//   index evaluates the object, then the index and then the value;
+// - assigning to a property evaluates the object before the value;
+// - list and map literals evaluate their elements in source order, the key
+//   of each entry before its value.
+// Any transformation of the tree (constant folding, a different backend, ...)
+// must preserve this order, since it is observable through side effects.
+
+// SEMANTICS: operator overloading
+//
+// When the built-in meaning of an operator does not apply and its left
+// operand is an instance, the operator calls a method of that instance with
+// the right operand as its only argument:
+//
+//   a + b    a.plus(b)     the result is returned as is
+//   a - b    a.minus(b)    (same)
+//   a * b    a.times(b)    (same)
+//   a < b    a.less(b)     the result is converted to a boolean
+//   a == b   a.equals(b)   (same), and `a != b` negates it
+//
+// Only methods count, not fields holding functions. Without the method,
+// `+`, `-`, `*` and `<` fail as they would for any other value, and `==`
+// compares identity. The right operand is never looked at, so `2 * v` fails
+// even if `v` has `times`. Equality of lists and maps compares the instances
+// in them by identity, without calling `equals`.
+
+// Calls the method overloading an operator, if `lhs` has one
+fn overloaded(
+    ctx: &mut Context,
+    method: &str,
+    lhs: &Val,
+    rhs: &Val,
+) -> Option<Result<Val, RuntimeError>> {
+    match lhs {
+        Val::Instance(i) => Some(call_value(ctx, &i.method(method)?, vec![rhs.clone()])),
+        _ => None,
+    }
+}
+
+// What `==` means, shared with the arms of a `match`
+pub(crate) fn equals(ctx: &mut Context, x: &Val, y: &Val) -> Result<bool, RuntimeError> {
+    match overloaded(ctx, "equals", x, y) {
+        Some(res) => Ok(res?.is_truthy()),
+        None => Ok(x == y),
+    }
+}
+
 macro_rules! try_numeric {
-    ($sc:expr, $lhs:ident $op:tt $rhs:ident => $var:tt) => {{
-        let (x, y) = ($lhs.eval(Rc::clone(&$sc))?, $rhs.eval($sc)?);
+    ($ctx:ident, $sc:expr, $lhs:ident $op:tt $rhs:ident => $var:tt $(, $method:literal)?) => {{
+        let (x, y) = ($lhs.eval($ctx, Rc::clone(&$sc))?, $rhs.eval($ctx, $sc)?);
         match (&x, &y) {
             (Val::Number(x), Val::Number(y)) => Ok(Val::$var(x $op y)),
-            (Val::Number(_), _) => Err(()),
-                    (_, _) => Err(()),
+            $((x, y) if let Some(res) = overloaded($ctx, $method, x, y) => {
+                res.map(|v| try_numeric!(@convert $var v))
+            })?
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "operands must be numbers",
+            }),
         }
     }};
+    (@convert Boolean $v:ident) => { Val::Boolean($v.is_truthy()) };
+    (@convert Number $v:ident) => { $v };
+}
+
+// The integer a number holds, for the bitwise operators. Anything that
+// does not fit an i64 exactly is rejected rather than rounded
+fn integer(v: &Val) -> Result<i64, RuntimeError> {
+    match *v {
+        Val::Number(x) if x.fract() == 0.0 && x.abs() < 2f64.powi(63) => Ok(x as i64),
+        _ => Err(RuntimeError::TypeMismatch {
+            msg: "operands must be integers",
+        }),
+    }
+}
+
+fn concat(ctx: &Context, s: &str, t: &str) -> Result<Val, RuntimeError> {
+    collections::check_string_len(ctx, s.len().checked_add(t.len()))?;
+    Ok(Val::String(format!("{s}{t}").into()))
+}
+
+// `s * n` and `n * s` repeat a string a whole number of times
+fn repeat(ctx: &Context, s: &str, n: f64) -> Result<Val, RuntimeError> {
+    let count = match n {
+        n if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        _ => {
+            return Err(RuntimeError::TypeMismatch {
+                msg: "a string can only be repeated a whole, non-negative number of times",
+            })
+        }
+    };
+    collections::check_string_len(ctx, s.len().checked_mul(count))?;
+    Ok(Val::String(s.repeat(count).into()))
 }
 
 impl Expr {
-    pub fn eval(&self, scope: Rc<Scope>) -> Result<Val, ()> {
+    pub fn eval(&self, ctx: &mut Context, scope: Rc<Scope>) -> Result<Val, RuntimeError> {
+        let res = self.eval_here(ctx, scope);
+        if res.is_err() {
+            ctx.fail_at(self.loc());
+        }
+        res
+    }
+
+    // Where an error evaluating this node is reported
+    pub fn loc(&self) -> Option<Loc> {
+        use Expr::*;
+        match self {
+            Call(.., at)
+            | Eq(.., at)
+            | Ne(.., at)
+            | Gt(.., at)
+            | Ge(.., at)
+            | Lt(.., at)
+            | Le(.., at)
+            | BitOr(.., at)
+            | BitXor(.., at)
+            | BitAnd(.., at)
+            | Shl(.., at)
+            | Shr(.., at)
+            | Add(.., at)
+            | Sub(.., at)
+            | Mul(.., at)
+            | Div(.., at)
+            | Rem(.., at)
+            | Pow(.., at)
+            | Opp(_, at)
+            | Map(_, at)
+            | Index(.., at)
+            | SetIndex(.., at)
+            | Get(.., at)
+            | Set(.., at) => Some(at.0),
+            Asgn(var, _) | Var(var) | This(var) | Super(var, _) => Some(var.loc.0),
+            Seq(..) | Cond(..) | And(..) | Or(..) | Not(_) | Lit(_) | List(_) | Lambda(_) => None,
+        }
+    }
+
+    fn eval_here(&self, ctx: &mut Context, scope: Rc<Scope>) -> Result<Val, RuntimeError> {
         use Expr::*;
         match self {
             Asgn(var, expr) => {
-                let val = expr.eval(Rc::clone(&scope))?;
+                let val = expr.eval(ctx, Rc::clone(&scope))?;
                 scope.asgn(var, val.clone())?;
                 Ok(val)
             }
-            Call(callee, args) => match callee.eval(Rc::clone(&scope))? {
-                Val::Func(f) => f.call(
-                    args.iter()
-                        .map(|a| a.eval(Rc::clone(&scope)))
-                        .collect::<Result<Vec<_>, _>>()?,
-                ),
-                _ => {
-                    println!("Type is not callable");
-                    Err(())
+            Seq(lhs, rhs) => {
+                lhs.eval(ctx, Rc::clone(&scope))?;
+                rhs.eval(ctx, scope)
+            }
+            Call(callee, args, at) => {
+                let callee = callee.eval(ctx, Rc::clone(&scope))?;
+                let mut vals = Vec::with_capacity(args.len());
+                for arg in args {
+                    let val = arg.expr().eval(ctx, Rc::clone(&scope))?;
+                    match (arg, val) {
+                        (Arg::Positional(_), val) => vals.push(val),
+                        (Arg::Spread(_), Val::List(list)) => {
+                            for i in 0..list.len() {
+                                vals.push(list.get(&Val::Number(i as f64))?);
+                            }
+                        }
+                        _ => {
+                            return Err(RuntimeError::TypeMismatch {
+                                msg: "can only spread lists",
+                            })
+                        }
+                    }
                 }
+                // The parser limits the arguments written, this limits
+                // those spread
+                if vals.len() > 255 {
+                    return Err(RuntimeError::TooManyArgs { got: vals.len() });
+                }
+                let f = checked_callee(&callee, vals.len())?;
+                ctx.frames.push(Frame {
+                    callee: callee.clone(),
+                    site: Located {
+                        source: Rc::clone(&ctx.source),
+                        loc: at.0,
+                    },
+                });
+                let res = f.call(ctx, vals);
+                // The trace is taken in the innermost call the error leaves
+                if res.is_err() && ctx.trace.is_none() {
+                    ctx.trace = Some(ctx.frames.clone());
+                }
+                ctx.frames.pop();
+                res
+            }
+            Cond(cond, then_e, else_e) => {
+                if cond.eval(ctx, Rc::clone(&scope))?.is_truthy() {
+                    then_e.eval(ctx, scope)
+                } else {
+                    else_e.eval(ctx, scope)
+                }
+            }
+            And(lhs, rhs) => match lhs.eval(ctx, Rc::clone(&scope))? {
+                b if !b.is_truthy() => Ok(b),
+                _ => rhs.eval(ctx, scope),
             },
-            And(lhs, rhs) => match lhs.eval(Rc::clone(&scope))? {
-                b @ (Val::Nil | Val::Boolean(false)) => Ok(b),
-                _ => rhs.eval(scope),
-            },
-            Or(lhs, rhs) => match lhs.eval(Rc::clone(&scope))? {
-                Val::Nil | Val::Boolean(false) => rhs.eval(scope),
-                b => Ok(b),
+            Or(lhs, rhs) => match lhs.eval(ctx, Rc::clone(&scope))? {
+                b if b.is_truthy() => Ok(b),
+                _ => rhs.eval(ctx, scope),
             },
-            Eq(lhs, rhs) => {
-                let (x, y) = (lhs.eval(Rc::clone(&scope))?, rhs.eval(scope)?);
-                Ok(Val::Boolean(x == y))
-            }
-            Ne(lhs, rhs) => {
-                let (x, y) = (lhs.eval(Rc::clone(&scope))?, rhs.eval(scope)?);
-                Ok(Val::Boolean(x != y))
-            }
-            Gt(lhs, rhs) => try_numeric!(scope, lhs >  rhs => Boolean),
-            Ge(lhs, rhs) => try_numeric!(scope, lhs >= rhs => Boolean),
-            Lt(lhs, rhs) => try_numeric!(scope, lhs <  rhs => Boolean),
-            Le(lhs, rhs) => try_numeric!(scope, lhs <= rhs => Boolean),
-            Add(lhs, rhs) => match (lhs.eval(Rc::clone(&scope))?, rhs.eval(scope)?) {
+            Eq(lhs, rhs, _) | Ne(lhs, rhs, _) => {
+                let (x, y) = (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?);
+                let eq = equals(ctx, &x, &y)?;
+                Ok(Val::Boolean(eq == matches!(self, Eq(..))))
+            }
+            // Numbers, and strings lexicographically, by their bytes
+            Gt(lhs, rhs, _) | Ge(lhs, rhs, _) | Lt(lhs, rhs, _) | Le(lhs, rhs, _) => {
+                let (x, y) = (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?);
+                let ord = match (&x, &y) {
+                    (Val::Number(a), Val::Number(b)) => a.partial_cmp(b),
+                    (Val::String(s), Val::String(t)) => Some(s.cmp(t)),
+                    (x, y) if let (Lt(..), Some(res)) = (self, overloaded(ctx, "less", x, y)) => {
+                        return res.map(|v| Val::Boolean(v.is_truthy()));
+                    }
+                    (x, y) => {
+                        return Err(RuntimeError::Operands {
+                            msg: "operands must be two numbers or two strings",
+                            lhs: x.type_name(),
+                            rhs: y.type_name(),
+                        })
+                    }
+                };
+                use std::cmp::Ordering::*;
+                Ok(Val::Boolean(match self {
+                    Gt(..) => ord == Some(Greater),
+                    Ge(..) => matches!(ord, Some(Greater | Equal)),
+                    Lt(..) => ord == Some(Less),
+                    _ => matches!(ord, Some(Less | Equal)),
+                }))
+            }
+            BitOr(lhs, rhs, _)
+            | BitXor(lhs, rhs, _)
+            | BitAnd(lhs, rhs, _)
+            | Shl(lhs, rhs, _)
+            | Shr(lhs, rhs, _) => {
+                let (x, y) = (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?);
+                let (x, y) = (integer(&x)?, integer(&y)?);
+                let shift = || {
+                    u32::try_from(y)
+                        .ok()
+                        .filter(|&n| n < 64)
+                        .ok_or(RuntimeError::TypeMismatch {
+                            msg: "shift amount must be from 0 to 63",
+                        })
+                };
+                let res = match self {
+                    BitOr(..) => x | y,
+                    BitXor(..) => x ^ y,
+                    BitAnd(..) => x & y,
+                    Shl(..) => x << shift()?,
+                    _ => x >> shift()?,
+                };
+                Ok(Val::Number(res as f64))
+            }
+            Add(lhs, rhs, _) => match (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?) {
                 (Val::Number(x), Val::Number(y)) => Ok(Val::Number(x + y)),
-                (Val::String(s), Val::String(t)) => Ok(Val::String(format!("{s}{t}").into())),
-                _ => Err(()),
+                (Val::String(s), Val::String(t)) => concat(ctx, &s, &t),
+                (Val::String(s), Val::Number(x)) if ctx.string_plus_number == Coerce => {
+                    concat(ctx, &s, &x.to_string())
+                }
+                (Val::Number(x), Val::String(s)) if ctx.string_plus_number == Coerce => {
+                    concat(ctx, &x.to_string(), &s)
+                }
+                (x, y) if let Some(res) = overloaded(ctx, "plus", &x, &y) => res,
+                (x, y) if matches!(x, Val::String(_)) || matches!(y, Val::String(_)) => {
+                    // Strings quoted, as containers show them
+                    let shown = |v: &Val| match v {
+                        Val::String(s) => format!("\"{s}\""),
+                        v => v.to_string(),
+                    };
+                    Err(RuntimeError::Concat {
+                        lhs: (x.type_name(), shown(&x)),
+                        rhs: (y.type_name(), shown(&y)),
+                    })
+                }
+                (x, y) => Err(RuntimeError::Operands {
+                    msg: "operands must be two numbers or two strings",
+                    lhs: x.type_name(),
+                    rhs: y.type_name(),
+                }),
             },
-            Sub(lhs, rhs) => try_numeric!(scope, lhs - rhs => Number),
-            Mul(lhs, rhs) => try_numeric!(scope, lhs * rhs => Number),
-            Div(lhs, rhs) => try_numeric!(scope, lhs / rhs => Number),
-            Not(arg) => match arg.eval(scope)? {
-                Val::Nil | Val::Boolean(false) => Ok(Val::Boolean(true)),
-                _ => Ok(Val::Boolean(true)),
+            Sub(lhs, rhs, _) => try_numeric!(ctx, scope, lhs - rhs => Number, "minus"),
+            Mul(lhs, rhs, _) => match (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?) {
+                (Val::Number(x), Val::Number(y)) => Ok(Val::Number(x * y)),
+                (Val::String(s), Val::Number(n)) | (Val::Number(n), Val::String(s)) => {
+                    repeat(ctx, &s, n)
+                }
+                (x, y) if let Some(res) = overloaded(ctx, "times", &x, &y) => res,
+                _ => Err(RuntimeError::TypeMismatch {
+                    msg: "operands must be numbers",
+                }),
             },
-            Opp(arg) => match arg.eval(scope)? {
+            Div(lhs, rhs, _) | Rem(lhs, rhs, _) if ctx.strict_math => {
+                let (x, y) = (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?);
+                let op = if matches!(self, Div(..)) { "/" } else { "%" };
+                match (x, y) {
+                    (Val::Number(x), Val::Number(0.0)) => {
+                        Err(RuntimeError::DivisionByZero { op, lhs: x })
+                    }
+                    (Val::Number(x), Val::Number(y)) if op == "/" => Ok(Val::Number(x / y)),
+                    (Val::Number(x), Val::Number(y)) => Ok(Val::Number(x % y)),
+                    _ => Err(RuntimeError::TypeMismatch {
+                        msg: "operands must be numbers",
+                    }),
+                }
+            }
+            Div(lhs, rhs, _) => try_numeric!(ctx, scope, lhs / rhs => Number),
+            Rem(lhs, rhs, _) => try_numeric!(ctx, scope, lhs % rhs => Number),
+            Pow(lhs, rhs, _) => match (lhs.eval(ctx, Rc::clone(&scope))?, rhs.eval(ctx, scope)?) {
+                (Val::Number(x), Val::Number(y)) => Ok(Val::Number(x.powf(y))),
+                _ => Err(RuntimeError::TypeMismatch {
+                    msg: "operands must be numbers",
+                }),
+            },
+            Not(arg) => Ok(Val::Boolean(!arg.eval(ctx, scope)?.is_truthy())),
+            Opp(arg, _) => match arg.eval(ctx, scope)? {
                 Val::Number(x) => Ok(Val::Number(-x)),
-                _ => Err(()),
+                _ => Err(RuntimeError::TypeMismatch {
+                    msg: "operand must be a number",
+                }),
             },
             Lit(v) => Ok(v.clone()),
             Var(var) => scope.get(var),
+            List(items) => Ok(Val::List(collections::List::new(
+                items
+                    .iter()
+                    .map(|e| e.eval(ctx, Rc::clone(&scope)))
+                    .collect::<Result<_, _>>()?,
+            ))),
+            Map(entries, _) => {
+                let map = collections::Map::new(Default::default());
+                for (k, v) in entries {
+                    let key = k.eval(ctx, Rc::clone(&scope))?;
+                    map.set(&key, v.eval(ctx, Rc::clone(&scope))?)?;
+                }
+                Ok(Val::Map(map))
+            }
+            Index(obj, idx, _) => {
+                match (obj.eval(ctx, Rc::clone(&scope))?, idx.eval(ctx, scope)?) {
+                    (Val::List(l), i) => l.get(&i),
+                    (Val::Map(m), k) => m.get(&k),
+                    (Val::String(s), i) => collections::char_at(ctx, &s, &i),
+                    _ => Err(RuntimeError::TypeMismatch {
+                        msg: "only lists, maps and strings can be indexed",
+                    }),
+                }
+            }
+            SetIndex(obj, idx, value, _) => {
+                let (obj, idx) = (
+                    obj.eval(ctx, Rc::clone(&scope))?,
+                    idx.eval(ctx, Rc::clone(&scope))?,
+                );
+                let val = value.eval(ctx, scope)?;
+                match obj {
+                    Val::List(l) => l.set(&idx, val.clone())?,
+                    Val::Map(m) => m.set(&idx, val.clone())?,
+                    Val::String(_) => {
+                        return Err(RuntimeError::TypeMismatch {
+                            msg: "strings cannot be modified",
+                        })
+                    }
+                    _ => {
+                        return Err(RuntimeError::TypeMismatch {
+                            msg: "only lists and maps can be indexed",
+                        })
+                    }
+                }
+                Ok(val)
+            }
+            Get(obj, name, _) => match obj.eval(ctx, scope)? {
+                Val::Instance(i) => i.get(name),
+                Val::Class(c) => c.get(name),
+                _ => Err(RuntimeError::TypeMismatch {
+                    msg: "only instances have properties",
+                }),
+            },
+            Set(obj, name, value, _) => match obj.eval(ctx, Rc::clone(&scope))? {
+                Val::Instance(i) => {
+                    let val = value.eval(ctx, scope)?;
+                    i.set(name, val.clone());
+                    Ok(val)
+                }
+                _ => Err(RuntimeError::TypeMismatch {
+                    msg: "only instances have fields",
+                }),
+            },
+            This(var) => scope.get(var),
+            Super(var, name) => {
+                // Methods bind `this` one scope inside the one binding `super`
+                let this = Variable {
+                    name: "this".into(),
+                    depth: var.depth - 1,
+                    loc: var.loc,
+                };
+                match (scope.get(var)?, scope.get(&this)?) {
+                    (Val::Class(superclass), Val::Instance(receiver)) => {
+                        receiver.bind(&superclass, name)
+                    }
+                    _ => unreachable!("the resolver only allows `super` in subclass methods"),
+                }
+            }
+            Lambda(decl) => Ok(Val::Func(Function::UserDef(Rc::clone(decl), scope))),
         }
     }
 }
+
+impl Expr {
+    // Binding power of the grammar level that produces this expression
+    fn prec(&self) -> u8 {
+        use Expr::*;
+        match self {
+            Seq(..) => 0,
+            Asgn(..) | SetIndex(..) | Set(..) => 1,
+            Cond(..) => 2,
+            And(..) | Or(..) => 3,
+            Eq(..) | Ne(..) | Gt(..) | Ge(..) | Lt(..) | Le(..) => 4,
+            BitOr(..) => 5,
+            BitXor(..) => 6,
+            BitAnd(..) => 7,
+            Shl(..) | Shr(..) => 8,
+            Add(..) | Sub(..) => 9,
+            Mul(..) | Div(..) | Rem(..) => 10,
+            Not(_) | Opp(..) => 11,
+            Pow(..) => 12,
+            Call(..) | Index(..) | Get(..) => 13,
+            Lit(_) | Var(_) | List(_) | Map(..) | This(_) | Super(..) | Lambda(_) => 14,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter<'_>, min_prec: u8) -> std::fmt::Result {
+        if self.prec() < min_prec {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Expr::*;
+        let prec = self.prec();
+        let (lhs, op, rhs) = match self {
+            Asgn(var, value) => {
+                write!(f, "{} = ", var.name)?;
+                return value.fmt_operand(f, prec);
+            }
+            // Wherever commas separate items, a sequence needs parentheses
+            Seq(lhs, rhs) => {
+                lhs.fmt_operand(f, prec)?;
+                write!(f, ", ")?;
+                return rhs.fmt_operand(f, prec + 1);
+            }
+            // Right associative, and the middle is delimited by `?` and `:`
+            Cond(cond, then_e, else_e) => {
+                cond.fmt_operand(f, prec + 1)?;
+                write!(f, " ? {then_e} : ")?;
+                return else_e.fmt_operand(f, prec);
+            }
+            // Right associative, with a unary operand on the right
+            Pow(lhs, rhs, _) => {
+                lhs.fmt_operand(f, prec + 1)?;
+                write!(f, " ** ")?;
+                return rhs.fmt_operand(f, prec - 1);
+            }
+            Call(callee, args, _) => {
+                callee.fmt_operand(f, prec)?;
+                write!(f, "(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if let Arg::Spread(_) = arg {
+                        write!(f, "...")?;
+                    }
+                    arg.expr().fmt_operand(f, 1)?;
+                }
+                return write!(f, ")");
+            }
+            Not(arg) => {
+                write!(f, "!")?;
+                return arg.fmt_operand(f, prec);
+            }
+            // `--x` would be one token for most readers, if not for the lexer
+            Opp(arg, _) if matches!(**arg, Opp(..)) => return write!(f, "-({arg})"),
+            Opp(arg, _) => {
+                write!(f, "-")?;
+                return arg.fmt_operand(f, prec);
+            }
+            Lit(Val::String(s)) => return write!(f, "\"{s}\""),
+            // Numbers print in the shortest form that parses back to the same
+            // value, which for the infinity of an overflowing literal is the
+            // smallest integer literal that overflows too
+            Lit(Val::Number(x)) if *x == f64::INFINITY => {
+                return write!(f, "1{}", "0".repeat(f64::MAX_10_EXP as usize + 1))
+            }
+            Lit(v) => return write!(f, "{v}"),
+            Var(var) => return write!(f, "{}", var.name),
+            This(_) => return write!(f, "this"),
+            Super(_, name) => return write!(f, "super.{name}"),
+            // On a single line, whatever the statements in the body
+            Lambda(decl) => {
+                write!(f, "fn ({}) {{", decl.fmt_params())?;
+                let Stmt::Block(stmts) = &decl.body else {
+                    unreachable!("function bodies are blocks")
+                };
+                for s in stmts {
+                    write!(f, " {s}")?;
+                }
+                let space = if stmts.is_empty() { "" } else { " " };
+                return write!(f, "{space}}}");
+            }
+            List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_operand(f, 1)?;
+                }
+                return write!(f, "]");
+            }
+            Map(entries, _) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    k.fmt_operand(f, 1)?;
+                    write!(f, ": ")?;
+                    v.fmt_operand(f, 1)?;
+                }
+                return write!(f, "}}");
+            }
+            Index(obj, idx, _) => {
+                obj.fmt_operand(f, prec)?;
+                write!(f, "[")?;
+                idx.fmt_operand(f, 1)?;
+                return write!(f, "]");
+            }
+            SetIndex(obj, idx, value, _) => {
+                obj.fmt_operand(f, 13)?;
+                write!(f, "[")?;
+                idx.fmt_operand(f, 1)?;
+                write!(f, "] = ")?;
+                return value.fmt_operand(f, prec);
+            }
+            Get(obj, name, _) => {
+                obj.fmt_operand(f, prec)?;
+                return write!(f, ".{name}");
+            }
+            Set(obj, name, value, _) => {
+                obj.fmt_operand(f, 13)?;
+                write!(f, ".{name} = ")?;
+                return value.fmt_operand(f, prec);
+            }
+            And(lhs, rhs) => (lhs, "and", rhs),
+            Or(lhs, rhs) => (lhs, "or", rhs),
+            Eq(lhs, rhs, _) => (lhs, "==", rhs),
+            Ne(lhs, rhs, _) => (lhs, "!=", rhs),
+            Gt(lhs, rhs, _) => (lhs, ">", rhs),
+            Ge(lhs, rhs, _) => (lhs, ">=", rhs),
+            Lt(lhs, rhs, _) => (lhs, "<", rhs),
+            Le(lhs, rhs, _) => (lhs, "<=", rhs),
+            BitOr(lhs, rhs, _) => (lhs, "|", rhs),
+            BitXor(lhs, rhs, _) => (lhs, "^", rhs),
+            BitAnd(lhs, rhs, _) => (lhs, "&", rhs),
+            Shl(lhs, rhs, _) => (lhs, "<<", rhs),
+            Shr(lhs, rhs, _) => (lhs, ">>", rhs),
+            Add(lhs, rhs, _) => (lhs, "+", rhs),
+            Sub(lhs, rhs, _) => (lhs, "-", rhs),
+            Mul(lhs, rhs, _) => (lhs, "*", rhs),
+            Div(lhs, rhs, _) => (lhs, "/", rhs),
+            Rem(lhs, rhs, _) => (lhs, "%", rhs),
+        };
+        // Binary operators are left associative
+        lhs.fmt_operand(f, prec)?;
+        write!(f, " {op} ")?;
+        rhs.fmt_operand(f, prec + 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{interpreter::Interpreter, test_util::global};
+
+    // `note` appends its tag to the global `log` and passes its value through,
+    // so the final contents of `log` reveal the order of evaluation.
+    fn run(src: &str) -> Interpreter {
+        crate::test_util::run(&format!(
+            r#"let log = ""; fn note(tag, v) {{ log = log + tag; return v; }} {src}"#
+        ))
+    }
+
+    fn log(src: &str) -> Val {
+        global(&run(src), "log")
+    }
+
+    #[test]
+    fn call_args_left_to_right() {
+        assert_eq!(
+            log(r#"fn f(a, b, c) { return a; } f(note("a", 1), note("b", 2), note("c", 3));"#),
+            Val::String("abc".into())
+        );
+    }
+
+    #[test]
+    fn callee_before_args() {
+        assert_eq!(
+            log(r#"fn f(a, b) { return a; }
+                   fn pick() { log = log + "f"; return f; }
+                   pick()(note("a", 1), note("b", 2));"#),
+            Val::String("fab".into())
+        );
+    }
+
+    #[test]
+    fn nested_calls() {
+        let env = run(r#"fn f(a, b, c) { return a + b + c; }
+                         let r = f(note("a", 1), f(note("b", 2), note("c", 3), note("d", 4)), note("e", 5));"#);
+        assert_eq!(global(&env, "log"), Val::String("abcde".into()));
+        assert_eq!(global(&env, "r"), Val::Number(15.0));
+    }
+
+    #[test]
+    fn binary_operands() {
+        assert_eq!(
+            log(r#"note("a", 1) + note("b", 2) * note("c", 3) < note("d", 4) - note("e", 5);"#),
+            Val::String("abcde".into())
+        );
+        assert_eq!(
+            log(r#"note("a", 1) == note("b", 1);"#),
+            Val::String("ab".into())
+        );
+    }
+
+    #[test]
+    fn chained_assignment() {
+        let env = run(r#"let a; let b; a = b = note("v", 1) + note("w", 2);"#);
+        assert_eq!(global(&env, "log"), Val::String("vw".into()));
+        assert_eq!(global(&env, "a"), Val::Number(3.0));
+        assert_eq!(global(&env, "b"), Val::Number(3.0));
+    }
+
+    #[test]
+    fn assignment_value_before_binding() {
+        let env = run(r#"let x = 1; x = note("a", x + 1);"#);
+        assert_eq!(global(&env, "x"), Val::Number(2.0));
+        assert_eq!(global(&env, "log"), Val::String("a".into()));
+    }
+
+    #[test]
+    fn bitwise() {
+        use crate::{error::LoxError, source::SourceMap};
+
+        let env = crate::test_util::run(
+            "let a = 12 & 10; let b = 12 | 3; let c = 12 ^ 10; let d = 1 << 2 + 1 == 8;
+             let e = -16 >> 2; let f = true and 1 | 2; let g = 1 << 62;",
+        );
+        assert_eq!(global(&env, "a"), Val::Number(8.0));
+        assert_eq!(global(&env, "b"), Val::Number(15.0));
+        assert_eq!(global(&env, "c"), Val::Number(6.0));
+        assert_eq!(global(&env, "d"), Val::Boolean(true));
+        assert_eq!(global(&env, "e"), Val::Number(-4.0));
+        assert_eq!(global(&env, "f"), Val::Number(3.0));
+        assert_eq!(global(&env, "g"), Val::Number(2f64.powi(62)));
+
+        // Not truncated, a fraction is an error
+        for (src, msg) in [
+            ("2.5 & 1;", "operands must be integers"),
+            ("\"1\" | 1;", "operands must be integers"),
+            ("1 << 64;", "shift amount must be from 0 to 63"),
+            ("1 >> -1;", "shift amount must be from 0 to 63"),
+        ] {
+            let mut lox = Interpreter::builder().build();
+            assert_eq!(
+                lox.run(&SourceMap::new("test", src)),
+                Err(LoxError::Runtime(RuntimeError::TypeMismatch { msg })),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn power() {
+        use crate::{error::LoxError, source::SourceMap};
+
+        let env = crate::test_util::run("let a = 2 ** 3 ** 2; let b = -2 ** 2; let c = 4 ** -0.5;");
+        assert_eq!(global(&env, "a"), Val::Number(512.0));
+        assert_eq!(global(&env, "b"), Val::Number(-4.0));
+        assert_eq!(global(&env, "c"), Val::Number(0.5));
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "2 ** \"2\";")),
+            Err(LoxError::Runtime(RuntimeError::TypeMismatch {
+                msg: "operands must be numbers"
+            }))
+        );
+    }
+
+    #[test]
+    fn string_repetition() {
+        use crate::{error::LoxError, source::SourceMap};
+
+        let env = crate::test_util::run(
+            r#"let a = "-" * 3; let b = 2 * "ab"; let c = "x" * 0; let d = "" * 5;"#,
+        );
+        assert_eq!(global(&env, "a"), Val::String("---".into()));
+        assert_eq!(global(&env, "b"), Val::String("abab".into()));
+        assert_eq!(global(&env, "c"), Val::String("".into()));
+        assert_eq!(global(&env, "d"), Val::String("".into()));
+
+        let mut lox = Interpreter::builder().build();
+        let msg = "a string can only be repeated a whole, non-negative number of times";
+        for src in [r#""a" * 1.5;"#, r#"-1 * "a";"#, r#""a" * (0 / 0);"#] {
+            assert_eq!(
+                lox.run(&SourceMap::new("test", src)),
+                Err(LoxError::Runtime(RuntimeError::TypeMismatch { msg })),
+                "{src}"
+            );
+        }
+        assert!(lox.run(&SourceMap::new("test", r#""a" * "b";"#)).is_err());
+
+        // Too long to allocate, or just too long
+        for src in [r#""ab" * 1e15;"#, r#"1e10 * "ab";"#, r#""a" * 2e9;"#] {
+            assert_eq!(
+                lox.run(&SourceMap::new("test", src)),
+                Err(LoxError::Runtime(RuntimeError::StringTooLong {
+                    max: collections::MAX_STRING_LEN
+                })),
+                "{src}"
+            );
+        }
+        let env = crate::test_util::run(r#"let s = "abc" * 1000000;"#);
+        let Val::String(s) = global(&env, "s") else {
+            panic!("expected a string")
+        };
+        assert_eq!(s.len(), 3_000_000);
+    }
+
+    #[test]
+    fn remainder() {
+        use crate::{error::LoxError, source::SourceMap};
+
+        let env = crate::test_util::run(
+            "let a = 7 % 3; let b = -7 % 3; let c = 7 % -3; let d = 5.5 % 2;",
+        );
+        assert_eq!(global(&env, "a"), Val::Number(1.0));
+        assert_eq!(global(&env, "b"), Val::Number(-1.0));
+        assert_eq!(global(&env, "c"), Val::Number(1.0));
+        assert_eq!(global(&env, "d"), Val::Number(1.5));
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "\"7\" % 2;")),
+            Err(LoxError::Runtime(RuntimeError::TypeMismatch {
+                msg: "operands must be numbers"
+            }))
+        );
+    }
+
+    #[test]
+    fn string_comparison() {
+        use crate::{error::LoxError, source::SourceMap};
+
+        let env = crate::test_util::run(
+            r#"let a = "ab" <= "ab" and "ab" >= "ab" and !("ab" < "ab");
+               let b = "ab" < "abc" and "abc" > "ab";
+               let c = "B" < "a" and "a" > "Z";
+               let d = "b" > "abc";"#,
+        );
+        for name in ["a", "b", "c", "d"] {
+            assert_eq!(global(&env, name), Val::Boolean(true), "{name}");
+        }
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "\"1\" < 2;")),
+            Err(LoxError::Runtime(RuntimeError::Operands {
+                msg: "operands must be two numbers or two strings",
+                lhs: "string",
+                rhs: "number"
+            }))
+        );
+        assert_eq!(
+            RuntimeError::Operands {
+                msg: "operands must be two numbers or two strings",
+                lhs: "nil",
+                rhs: "string"
+            }
+            .to_string(),
+            "operands must be two numbers or two strings; found nil and string"
+        );
+    }
+
+    #[test]
+    fn string_plus_number() {
+        use crate::{context::StringPlusNumber, error::LoxError, source::SourceMap};
+
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.run(&SourceMap::new("test", r#""count: " + 3;"#)),
+            Err(LoxError::Runtime(RuntimeError::Concat {
+                lhs: ("string", "\"count: \"".into()),
+                rhs: ("number", "3".into())
+            }))
+        );
+        let Err(LoxError::Runtime(e)) = lox.run(&SourceMap::new("test", r#"1.5 + "a";"#)) else {
+            panic!("expected a runtime error")
+        };
+        assert_eq!(
+            e.to_string(),
+            r#"can only concatenate string with string; found number 1.5 and string "a""#
+        );
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "1 + nil;")),
+            Err(LoxError::Runtime(RuntimeError::Operands {
+                msg: "operands must be two numbers or two strings",
+                lhs: "number",
+                rhs: "nil"
+            }))
+        );
+
+        let mut lox = Interpreter::builder()
+            .string_plus_number(StringPlusNumber::Coerce)
+            .build();
+        let src = r#"let a = "n=" + 3; let b = 0.5 + "x"; let c = "" + -2;"#;
+        assert_eq!(lox.run(&SourceMap::new("test", src)), Ok(()));
+        assert_eq!(global(&lox, "a"), Val::String("n=3".into()));
+        assert_eq!(global(&lox, "b"), Val::String("0.5x".into()));
+        assert_eq!(global(&lox, "c"), Val::String("-2".into()));
+        // Only numbers are made strings
+        assert!(lox.run(&SourceMap::new("test", r#""a" + nil;"#)).is_err());
+    }
+
+    #[test]
+    fn conditional_branch_taken() {
+        let env = run(r#"let a = note("c", true) ? note("t", 1) : note("e", 2);
+                         let b = note("c", nil) ? note("t", 1) : note("e", 2);"#);
+        assert_eq!(global(&env, "log"), Val::String("ctce".into()));
+        assert_eq!(global(&env, "a"), Val::Number(1.0));
+        assert_eq!(global(&env, "b"), Val::Number(2.0));
+    }
+
+    #[test]
+    fn short_circuit() {
+        assert_eq!(
+            log(r#"note("a", false) and note("b", true);"#),
+            Val::String("a".into())
+        );
+        assert_eq!(
+            log(r#"note("a", true) and note("b", true);"#),
+            Val::String("ab".into())
+        );
+        assert_eq!(
+            log(r#"note("a", true) or note("b", true);"#),
+            Val::String("a".into())
+        );
+        assert_eq!(
+            log(r#"note("a", nil) or note("b", true);"#),
+            Val::String("ab".into())
+        );
+        assert_eq!(
+            log(r#"note("a", nil) or note("b", false) or note("c", 1) or note("d", 2);"#),
+            Val::String("abc".into())
+        );
+    }
+}
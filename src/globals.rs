@@ -1,6 +1,11 @@
 use std::collections::HashMap;
 
-use crate::val::{Function, Val};
+use crate::{
+    collections::char_len,
+    context::Context,
+    error::RuntimeError,
+    val::{Function, NativeFn, Val},
+};
 
 #[macro_export]
 macro_rules! global_maker {
@@ -37,12 +42,201 @@ macro_rules! global_maker {
 
 pub fn globals() -> HashMap<String, Val> {
     let mut g = HashMap::new();
-    fn clock(_: Vec<Val>) -> Val {
+    fn clock(_: &mut Context, _: Vec<Val>) -> Result<Val, RuntimeError> {
         use std::time::{SystemTime, UNIX_EPOCH};
         let now = SystemTime::now();
         let since_epoch = now.duration_since(UNIX_EPOCH).expect("time went backwards");
-        Val::Number(since_epoch.as_secs_f64())
+        Ok(Val::Number(since_epoch.as_secs_f64()))
     }
-    g.insert("clock".to_string(), Val::Func(Function::Native(0, clock)));
+    fn len(ctx: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        match &args[0] {
+            Val::List(l) => Ok(Val::Number(l.len() as f64)),
+            Val::Map(m) => Ok(Val::Number(m.len() as f64)),
+            Val::String(s) => Ok(Val::Number(char_len(ctx, s) as f64)),
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "only lists, maps and strings have a length",
+            }),
+        }
+    }
+    fn push(ctx: &mut Context, mut args: Vec<Val>) -> Result<Val, RuntimeError> {
+        let val = args.pop().unwrap();
+        match &args[0] {
+            Val::List(l) if l.len() >= ctx.max_list_len => Err(RuntimeError::ListTooLong {
+                max: ctx.max_list_len,
+            }),
+            Val::List(l) => l.push(val).map(|_| Val::Nil),
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "can only push to lists",
+            }),
+        }
+    }
+    fn has(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        match &args[0] {
+            Val::Map(m) => m.has(&args[1]).map(Val::Boolean),
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "can only look up keys in maps",
+            }),
+        }
+    }
+    // `==` compares contents, this tells whether two values are the same
+    // list or map
+    fn same(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        Ok(Val::Boolean(match (&args[0], &args[1]) {
+            (Val::List(a), Val::List(b)) => a.same(b),
+            (Val::Map(a), Val::Map(b)) => a.same(b),
+            (a, b) => a == b,
+        }))
+    }
+    fn clone(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        args[0].deep_clone()
+    }
+    fn freeze(_: &mut Context, mut args: Vec<Val>) -> Result<Val, RuntimeError> {
+        Ok(args.pop().unwrap().freeze())
+    }
+    // The argument of the natives that test numbers
+    fn number(args: &[Val]) -> Result<f64, RuntimeError> {
+        match args[0] {
+            Val::Number(x) => Ok(x),
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "can only test numbers",
+            }),
+        }
+    }
+    // `x != x` tells the same, if less clearly
+    fn is_nan(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        Ok(Val::Boolean(number(&args)?.is_nan()))
+    }
+    fn is_finite(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        Ok(Val::Boolean(number(&args)?.is_finite()))
+    }
+    fn is_integer(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        let x = number(&args)?;
+        Ok(Val::Boolean(x.is_finite() && x.fract() == 0.0))
+    }
+    // Fails with an error of the given kind, see `RuntimeError::to_val`
+    fn raise(_: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        match (&args[0], &args[1]) {
+            (Val::String(kind), Val::String(message)) => Err(RuntimeError::User {
+                kind: kind.to_string(),
+                message: message.to_string(),
+            }),
+            _ => Err(RuntimeError::TypeMismatch {
+                msg: "the kind and message of an error must be strings",
+            }),
+        }
+    }
+    // Prints the signature of a function and what it does
+    fn help(ctx: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        let (signature, doc) = match &args[0] {
+            Val::Func(Function::Native(_, _, f)) => {
+                match NATIVES.iter().find(|n| std::ptr::fn_addr_eq(n.2, *f)) {
+                    Some((name, _, _, params, about)) => {
+                        (format!("fn {name}({params})"), Some(*about))
+                    }
+                    // Defined by the host
+                    None => ("<native fn>".to_string(), None),
+                }
+            }
+            Val::Func(
+                Function::UserDef(decl, _)
+                | Function::Bound { method: decl, .. }
+                | Function::Static { method: decl, .. },
+            ) => (
+                format!("fn {}({})", decl.name, decl.fmt_params()),
+                decl.doc.as_deref(),
+            ),
+            _ => {
+                return Err(RuntimeError::TypeMismatch {
+                    msg: "can only describe functions",
+                })
+            }
+        };
+        ctx.print(signature);
+        for line in doc.unwrap_or("no documentation").lines() {
+            if line.is_empty() {
+                ctx.print("");
+            } else {
+                ctx.print(format_args!("    {line}"));
+            }
+        }
+        Ok(Val::Nil)
+    }
+    // Along with the parameters and the description shown by `help`
+    const NATIVES: [(&str, u8, NativeFn, &str, &str); 12] = [
+        ("clock", 0, clock, "", "The seconds since the Unix epoch"),
+        (
+            "len",
+            1,
+            len,
+            "value",
+            "The number of items of a list or map, or of characters of a string",
+        ),
+        (
+            "push",
+            2,
+            push,
+            "list, value",
+            "Adds a value at the end of a list",
+        ),
+        ("has", 2, has, "map, key", "Whether a map has a key"),
+        (
+            "same",
+            2,
+            same,
+            "a, b",
+            "Whether two lists or maps are the same one, rather than equal",
+        ),
+        (
+            "clone",
+            1,
+            clone,
+            "value",
+            "A copy of a value, down to the lists and maps in it",
+        ),
+        (
+            "freeze",
+            1,
+            freeze,
+            "value",
+            "Makes a list or map immutable",
+        ),
+        ("isNan", 1, is_nan, "x", "Whether a number is NaN"),
+        (
+            "isFinite",
+            1,
+            is_finite,
+            "x",
+            "Whether a number is neither infinite nor NaN",
+        ),
+        (
+            "isInteger",
+            1,
+            is_integer,
+            "x",
+            "Whether a number is finite and has no fractional part",
+        ),
+        (
+            "raise",
+            2,
+            raise,
+            "kind, message",
+            "Fails with an error of the given kind",
+        ),
+        (
+            "help",
+            1,
+            help,
+            "function",
+            "Prints the parameters and the documentation of a function",
+        ),
+    ];
+    for (name, arity, f, _, _) in NATIVES {
+        g.insert(
+            name.to_string(),
+            Val::Func(Function::Native(name, arity, f)),
+        );
+    }
+    g.insert("INFINITY".to_string(), Val::Number(f64::INFINITY));
+    g.insert("NAN".to_string(), Val::Number(f64::NAN));
     g
 }
@@ -0,0 +1,96 @@
+// `import "file.lox";` runs another file on the global scope. A file runs
+// once per interpreter however many files import it, so that importing
+// the same helpers from several places does not define them twice
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    context::Context,
+    error::{Report, RuntimeError, Warnings},
+    interpreter::{compile, read_source, Session},
+    scope::Scope,
+    source::SourceMap,
+};
+
+#[derive(Default)]
+pub struct Imports {
+    // What imported files are compiled with
+    pub jlox: bool,
+    pub strict: bool,
+    pub warnings: Warnings,
+    // Canonical paths of the files imported and run so far
+    seen: HashSet<PathBuf>,
+    // The files being run, each imported by the one before it, along with
+    // the path diagnostics name them by
+    running: Vec<(PathBuf, String)>,
+}
+
+impl Imports {
+    // Paths are relative to the importing file, or to the current directory
+    // outside of one, as in the REPL
+    fn locate(&self, path: &str) -> String {
+        match self.running.last() {
+            Some((_, importer)) => {
+                let dir = Path::new(importer).parent().unwrap_or(Path::new(""));
+                dir.join(path).to_string_lossy().into_owned()
+            }
+            None => path.to_string(),
+        }
+    }
+
+    // While a file is loaded by the host rather than imported, so that what
+    // it imports is found relative to it
+    pub fn enter(&mut self, path: &str) {
+        let canonical = Path::new(path).canonicalize();
+        let canonical = canonical.unwrap_or_else(|_| PathBuf::from(path));
+        self.running.push((canonical, path.to_string()));
+    }
+
+    pub fn leave(&mut self) {
+        self.running.pop();
+    }
+}
+
+pub fn import(ctx: &mut Context, path: &str, globals: Rc<Scope>) -> Result<(), RuntimeError> {
+    let name = ctx.imports.locate(path);
+    let failed = || RuntimeError::ImportFailed { path: name.clone() };
+    let canonical = Path::new(&name).canonicalize().map_err(|_| failed())?;
+
+    let running = &ctx.imports.running;
+    if let Some(i) = running.iter().position(|(p, _)| *p == canonical) {
+        let mut chain: Vec<_> = running[i..].iter().map(|(_, n)| n.clone()).collect();
+        chain.push(name);
+        return Err(RuntimeError::ImportCycle { chain });
+    }
+    // A file that failed is run again on the next import, once fixed
+    if ctx.imports.seen.contains(&canonical) {
+        return Ok(());
+    }
+
+    let text = read_source(&name).map_err(|_| failed())?;
+    let src = SourceMap::new(&name, &text);
+    // The importing file is reported on by its caller, so the diagnostics
+    // of this one are reported here, where its text is at hand
+    let mut session = Session::new(ctx.imports.strict, false, true);
+    let prog =
+        compile(&src, ctx.imports.jlox, &ctx.imports.warnings, &mut session).map_err(|e| {
+            e.report(&src);
+            failed()
+        })?;
+    // Its runtime errors are located in it, and shown against its text
+    ctx.sources.retain(|s| s.name() != name);
+    ctx.sources.push(src.into_owned());
+    ctx.imports.running.push((canonical.clone(), name.clone()));
+    let importer = std::mem::replace(&mut ctx.source, name.into());
+    let res = prog.exec(ctx, globals);
+    ctx.source = importer;
+    ctx.imports.running.pop();
+    if res.is_ok() {
+        ctx.imports.seen.insert(canonical);
+    }
+    res
+}
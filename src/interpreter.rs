@@ -0,0 +1,952 @@
+use std::{
+    collections::HashSet,
+    fs::read_to_string,
+    io::Write,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+};
+
+use crate::{
+    collections::List,
+    context::{Context, StringPlusNumber, DEFAULT_STACK_SIZE, MAX_CALL_DEPTH},
+    error::{Level, LoxError, ParserError, Report, RuntimeError, Warning, Warnings},
+    globals,
+    lexer::Lexer,
+    parser::RecursiveDescent,
+    prog::Prog,
+    resolver::Resolver,
+    sandbox,
+    scope::Scope,
+    source::SourceMap,
+    val::Val,
+};
+
+const PRELUDE: &str = include_str!("prelude.lox");
+
+pub fn read_source(path: &str) -> Result<String, LoxError> {
+    read_to_string(path).map_err(|_| LoxError::Io {
+        path: path.to_string(),
+    })
+}
+
+// What compiling a unit of code leaves for the next, so that a REPL
+// session does not start over on every submission
+pub(crate) struct Session {
+    interned: HashSet<Rc<str>>,
+    resolver: Resolver,
+    strict: bool,
+    repl: bool,
+    // How deeply code may nest, see `sandbox`
+    max_nesting: usize,
+    // How many tokens the last unit had, to tell that only it was lexed
+    tokens: usize,
+}
+
+impl Session {
+    pub(crate) fn new(strict: bool, repl: bool, library: bool) -> Self {
+        Self {
+            interned: HashSet::new(),
+            resolver: Resolver::new(strict, repl).library(library),
+            strict,
+            repl,
+            max_nesting: usize::MAX,
+            tokens: 0,
+        }
+    }
+}
+
+// Compiles the unit of code last appended to `src`. Shared with the files
+// a program imports, which like the prelude are compiled as a `library`
+pub(crate) fn compile(
+    src: &SourceMap,
+    jlox: bool,
+    levels: &Warnings,
+    session: &mut Session,
+) -> Result<Prog, LoxError> {
+    let (strict, repl) = (session.strict, session.repl);
+    let (text, start) = src.unit();
+    let interned = std::mem::take(&mut session.interned);
+    let mut lexer = Lexer::new(text.chars()).jlox(jlox).resume(start, interned);
+    let (tokens, errors) = lexer.scan_all();
+    session.interned = lexer.take_interned();
+    session.tokens = tokens.len();
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    let warnings = lexer.take_warnings();
+    let docs = lexer.take_docs();
+    // Warnings on a line with a `// lox-allow` comment are left out
+    let allowed = |w: &Warning| w.loc().is_some_and(|l| lexer.allowed().contains(&l.row));
+    let (mut emitted, mut denied) = (0, Vec::new());
+    let mut report = |w: Warning| match levels.level(&w) {
+        _ if allowed(&w) => {}
+        Level::Allow => {}
+        Level::Warn => {
+            w.report(src);
+            emitted += 1;
+        }
+        Level::Deny => denied.push(w),
+    };
+    warnings.into_iter().for_each(&mut report);
+
+    let (mut prog, mut errors, warnings) =
+        RecursiveDescent::<Prog>::parse_with_max_nesting(tokens.into_iter(), session.max_nesting);
+    for w in warnings {
+        match w {
+            // Strict mode does not let comparisons look chained
+            Warning::ChainedCmp { expr, fix, loc } if strict && !allowed(&w) => {
+                errors.push(ParserError::ChainedCmp {
+                    expr: Box::new(expr),
+                    fix: Box::new(fix),
+                    loc,
+                })
+            }
+            w => report(w),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    session.resolver.docs(docs);
+    session.resolver.source(src.name());
+    for w in session.resolver.resolve(&mut prog)? {
+        report(w);
+    }
+    if emitted > 0 && !repl {
+        let s = if emitted == 1 { "" } else { "s" };
+        eprintln!("warning[lox]: {emitted} warning{s} emitted");
+    }
+    if !denied.is_empty() {
+        return Err(LoxError::Denied(denied));
+    }
+    Ok(prog)
+}
+
+pub struct Interpreter {
+    globals: Rc<Scope>,
+    ctx: Context,
+    strict: bool,
+    repl: bool,
+    jlox: bool,
+    warnings: Warnings,
+    session: Session,
+}
+
+pub struct Builder {
+    prelude: bool,
+    strict: bool,
+    repl: bool,
+    jlox: bool,
+    warnings: Warnings,
+    max_call_depth: usize,
+    stack_size: usize,
+    string_plus_number: StringPlusNumber,
+    strict_math: bool,
+    sandbox: bool,
+    args: Vec<String>,
+    out: Option<Box<dyn Write>>,
+}
+
+impl Builder {
+    pub fn prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Code run interactively evaluates bare expressions for their value
+    pub fn repl(mut self, repl: bool) -> Self {
+        self.repl = repl;
+        self
+    }
+
+    // Accepts `var` and `fun` as in the reference dialect, jlox
+    pub fn jlox(mut self, jlox: bool) -> Self {
+        self.jlox = jlox;
+        self
+    }
+
+    // Which warnings are reported, and which keep code from running
+    pub fn warnings(mut self, warnings: Warnings) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    // How many calls deep a script may go before it fails with a stack
+    // overflow
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    // How large the native stack of the thread running scripts is, so that
+    // deep calls fail before they overflow it
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = bytes;
+        self
+    }
+
+    // Whether `+` with a string and a number fails, the default, or makes a
+    // string of the number
+    pub fn string_plus_number(mut self, mode: StringPlusNumber) -> Self {
+        self.string_plus_number = mode;
+        self
+    }
+
+    // Makes `/` and `%` by zero fail instead of giving `inf` or `nan`
+    pub fn strict_math(mut self, strict_math: bool) -> Self {
+        self.strict_math = strict_math;
+        self
+    }
+
+    // Runs scripts under the limits of `sandbox`, without what it disables
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    // Command line arguments for the script, available as the global `args`
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    // Where `print` writes to, stdout by default
+    pub fn output(mut self, out: Box<dyn Write>) -> Self {
+        self.out = Some(out);
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut globals = globals::globals();
+        let args = self.args.into_iter().map(|a| Val::String(a.into()));
+        globals.insert("args".to_string(), Val::List(List::new(args.collect())));
+        let mut ctx = self.out.map_or_else(Context::default, Context::new);
+        ctx.imports.jlox = self.jlox;
+        ctx.imports.strict = self.strict;
+        ctx.imports.warnings = self.warnings.clone();
+        ctx.max_depth = self.max_call_depth;
+        ctx.stack_size = self.stack_size;
+        ctx.string_plus_number = self.string_plus_number;
+        ctx.strict_math = self.strict_math;
+        let mut session = Session::new(self.strict, self.repl, false);
+        if self.sandbox {
+            ctx.sandboxed = true;
+            session.max_nesting = sandbox::MAX_NESTING;
+            ctx.max_depth = ctx.max_depth.min(sandbox::MAX_CALL_DEPTH);
+            ctx.max_fuel = Some(sandbox::FUEL);
+            ctx.max_string_len = ctx.max_string_len.min(sandbox::MAX_STRING_LEN);
+            ctx.max_list_len = ctx.max_list_len.min(sandbox::MAX_LIST_LEN);
+        }
+        let mut lox = Interpreter {
+            globals: Scope::new_global(globals),
+            ctx,
+            strict: self.strict,
+            repl: self.repl,
+            jlox: self.jlox,
+            warnings: self.warnings,
+            session,
+        };
+        if self.prelude {
+            lox.ctx.refuel();
+            // The prelude is part of the interpreter, so it must always work
+            let prelude = SourceMap::new("<prelude>", PRELUDE);
+            let mut session = Session::new(lox.strict, lox.repl, true);
+            lox.ctx.source = prelude.name().into();
+            let res = compile(&prelude, lox.jlox, &lox.warnings, &mut session)
+                .and_then(|prog| Ok(prog.exec(&mut lox.ctx, Rc::clone(&lox.globals))?));
+            if res.is_err() {
+                panic!("the prelude failed to load, this is a bug in the interpreter");
+            }
+            lox.ctx.sources.push(prelude);
+        }
+        lox
+    }
+}
+
+impl Interpreter {
+    pub fn builder() -> Builder {
+        Builder {
+            prelude: true,
+            strict: false,
+            repl: false,
+            jlox: false,
+            warnings: Warnings::default(),
+            max_call_depth: MAX_CALL_DEPTH,
+            stack_size: DEFAULT_STACK_SIZE,
+            string_plus_number: StringPlusNumber::default(),
+            strict_math: false,
+            sandbox: false,
+            args: Vec::new(),
+            out: None,
+        }
+    }
+
+    // For code that is not trusted, see `sandbox`
+    pub fn sandboxed() -> Builder {
+        Self::builder().sandbox(true)
+    }
+
+    // Runs a source file as a single unit of code. What it imports is found
+    // relative to it
+    pub fn load(&mut self, path: &str) -> Result<(), LoxError> {
+        let src = read_source(path).inspect_err(|e| eprintln!("error[lox]: {e}"))?;
+        self.ctx.imports.enter(path);
+        let res = self.run(&SourceMap::new(path, &src));
+        self.ctx.imports.leave();
+        res
+    }
+
+    // Runs a whole unit of code on the global scope, reporting every
+    // diagnostic. Nothing is executed unless all of the unit compiles,
+    // so a broken unit leaves the globals untouched
+    pub fn run(&mut self, src: &SourceMap) -> Result<(), LoxError> {
+        let res = if self.ctx.sandboxed {
+            self.exec_guarded(src)
+        } else {
+            self.exec(src)
+        };
+        if let Err(e) = &res {
+            match e {
+                LoxError::Runtime(e) => {
+                    let trace = self.ctx.take_trace();
+                    let at = self.ctx.error_at.take();
+                    e.report_at(src, &self.ctx.sources, at.as_ref(), &trace);
+                }
+                e => e.report(src),
+            }
+            if let Some(summary) = e.summary().filter(|_| !self.repl) {
+                eprintln!("{summary}");
+            }
+        }
+        res
+    }
+
+    // Compiles and executes a unit of code, leaving any runtime error to be
+    // reported from the context
+    fn exec(&mut self, src: &SourceMap) -> Result<(), LoxError> {
+        self.ctx.error_at = None;
+        self.ctx.refuel();
+        let prog = self.compile(src)?;
+        self.ctx.source = src.name().into();
+        Ok(prog.exec(&mut self.ctx, Rc::clone(&self.globals))?)
+    }
+
+    // Fails the run rather than the host when it panics, as a host callable
+    // may. The globals are left as the run left them
+    fn exec_guarded(&mut self, src: &SourceMap) -> Result<(), LoxError> {
+        let res = panic::catch_unwind(AssertUnwindSafe(|| self.exec(src)));
+        res.unwrap_or_else(|payload| {
+            self.ctx.abandon();
+            let message = match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(message) => message.to_string(),
+                    Err(_) => "unknown cause".to_string(),
+                },
+            };
+            Err(RuntimeError::Panicked { message }.into())
+        })
+    }
+
+    // Lexes, parses and resolves the unit of code last appended to `src`.
+    // Warnings are reported here, errors are left to the caller
+    pub fn compile(&mut self, src: &SourceMap) -> Result<Prog, LoxError> {
+        compile(src, self.jlox, &self.warnings, &mut self.session)
+    }
+
+    // Lets the host read what a program left in the globals
+    pub fn global(&self, name: &str) -> Option<Val> {
+        let var = crate::expr::Variable::new(name.to_string());
+        self.globals.get(&var).ok()
+    }
+
+    // Lets the host provide values to programs, such as its own callables
+    pub fn define(&mut self, name: &str, val: Val) {
+        self.globals.def(name, val);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::Capture, error::RuntimeError, val::Val};
+
+    fn run(lox: &mut Interpreter, src: &str) {
+        assert_eq!(lox.run(&SourceMap::new("test", src)), Ok(()));
+    }
+
+    // What a program prints
+    fn printed(src: &str) -> String {
+        printed_by(Interpreter::builder(), src)
+    }
+
+    fn printed_by(lox: Builder, src: &str) -> String {
+        let out = Capture::default();
+        let mut lox = lox.output(Box::new(out.clone())).build();
+        run(&mut lox, src);
+        out.take()
+    }
+
+    #[test]
+    fn resolve_errors_stop_the_run() {
+        let out = Capture::default();
+        let mut lox = Interpreter::builder().output(Box::new(out.clone())).build();
+        let src = "print 1; { let a; let a; } return;";
+        let res = lox.run(&SourceMap::new("test", src));
+        assert!(matches!(res, Err(LoxError::Resolve(e)) if e.len() == 2));
+        assert!(out.take().is_empty());
+    }
+
+    #[test]
+    fn jlox_keywords() {
+        let src = "var a = 1; fun f(x) { return x + a; } print f(2);";
+        assert_eq!(printed_by(Interpreter::builder().jlox(true), src), "3\n");
+        let mut lox = Interpreter::builder().build();
+        let res = lox.run(&SourceMap::new("test", src));
+        assert!(matches!(res, Err(LoxError::Parse(_))));
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(printed("print (1, 2);"), "2\n");
+        let src = "fn f(a, b) { print a; print b; } let n = 0; f(1, (n = 2, 3)); print n;";
+        assert_eq!(printed(src), "1\n3\n2\n");
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let src = "let i = 0; let n = 1; while (i < 5) { i += 1; n *= 2; } n -= 2; n /= 5; print i; print n;";
+        assert_eq!(printed(src), "5\n6\n");
+    }
+
+    #[test]
+    fn asgn_in_condition() {
+        let src = "let x; if (x = 2) print x; while (x = nil) print 1; print x;";
+        assert_eq!(printed(src), "2\nnil\n");
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        let src = "print 1 < 2 == true; print 2 >= 3 != false;";
+        assert_eq!(printed(src), "true\nfalse\n");
+        let mut lox = Interpreter::builder().strict(true).build();
+        let res = lox.run(&SourceMap::new("test", src));
+        assert!(matches!(
+            res.unwrap_err(),
+            LoxError::Parse(e) if matches!(e[..], [ParserError::ChainedCmp { .. }, ParserError::ChainedCmp { .. }])
+        ));
+    }
+
+    // What `run` reports for a program that fails at runtime
+    fn runtime_error(src: &str) -> String {
+        runtime_error_with(Interpreter::builder(), src)
+    }
+
+    fn runtime_error_with(lox: Builder, src: &str) -> String {
+        let src = SourceMap::new("test", src);
+        let mut lox = lox.build();
+        let Err(LoxError::Runtime(e)) = lox.exec(&src) else {
+            panic!("expected a runtime error")
+        };
+        let trace = lox.ctx.take_trace();
+        e.render_at(&src, &lox.ctx.sources, lox.ctx.error_at.as_ref(), &trace)
+    }
+
+    #[test]
+    fn runtime_error_location() {
+        // At the operator inside the function, not at the call
+        assert_eq!(
+            runtime_error("fn f(x) {\n  return x - 1;\n}\nprint f(\"a\");"),
+            "\
+error[E0303]: operands must be numbers
+  --> test:1:11
+   1 |   return x - 1;
+                  ^ here
+       at f (test:1:11)
+       at <top level> (test:3:7)
+"
+        );
+
+        // Where it was caught, as the `line` of the error
+        let src = "let e;\ntry {\n  nil();\n} catch (err) {\n  e = err;\n}\nprint e[\"line\"];";
+        assert_eq!(printed(src), "2\n");
+    }
+
+    // Against the prelude, not against the line of the script that has the
+    // same number
+    #[test]
+    fn prelude_error_location() {
+        let src =
+            "let a = 1;\nlet b = 2;\nlet c = 3;\nlet the_quick_brown_fox = 2;\nprint abs(\"x\");";
+        let rendered = runtime_error(src);
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "error[E0303]: operands must be two numbers or two strings; found string and number",
+                "  --> <prelude>:4:10",
+                "   4 |     if (x < 0) return -x;",
+                "                 ^ here",
+                "       at abs (<prelude>:4:10)",
+                "       at <top level> (test:4:9)",
+            ]
+        );
+    }
+
+    // In the imported file, named as the importing one found it
+    #[test]
+    fn import_error_location() {
+        let lib = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/imports/lib");
+        let rendered = runtime_error(&format!("import \"{lib}/fails.lox\";"));
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "error[E0303]: operands must be numbers",
+                &format!("  --> {lib}/fails.lox:2:13"),
+                "   2 |     return x / 2;",
+                "                    ^ here",
+                &format!("       at half ({lib}/fails.lox:2:13)"),
+                &format!("       at <top level> ({lib}/fails.lox:5:10)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let src = "print 1 / 0; print -1 % 0; print 0 / 0;";
+        assert_eq!(printed(src), "inf\nNaN\nNaN\n");
+
+        let strict = || Interpreter::builder().strict_math(true);
+        let mut lox = strict().build();
+        for (src, msg) in [
+            ("1 / 0;", "division of 1 by zero"),
+            ("-2.5 / -0;", "division of -2.5 by zero"),
+            ("0 / 0;", "division of zero by zero has no value"),
+            ("7 % 0;", "remainder of 7 by zero"),
+            ("0 % 0;", "remainder of zero by zero has no value"),
+        ] {
+            let Err(LoxError::Runtime(e)) = lox.run(&SourceMap::new("test", src)) else {
+                panic!("expected a runtime error for {src}")
+            };
+            assert_eq!(e.to_string(), msg);
+        }
+        let src = "let a = 7 / 2; let b = 7 % 2;";
+        assert_eq!(lox.run(&SourceMap::new("test", src)), Ok(()));
+        assert_eq!(lox.global("a"), Some(Val::Number(3.5)));
+        assert_eq!(lox.global("b"), Some(Val::Number(1.0)));
+
+        // Out of the function it happened in
+        let src = "fn avg(sum, n) {\n  return sum / n;\n}\nprint avg(10, 0);";
+        assert_eq!(
+            runtime_error_with(strict(), src),
+            "\
+error[E0323]: division of 10 by zero
+  --> test:1:13
+   1 |   return sum / n;
+                    ^ here
+       at avg (test:1:13)
+       at <top level> (test:3:9)
+"
+        );
+    }
+
+    #[test]
+    fn arity_error_location() {
+        // Through another name, or the resolver would catch it
+        let src = "fn add(a, b) {\n  return a + b;\n}\nlet f = add;\nf(1);";
+        assert_eq!(
+            runtime_error(src),
+            "\
+error[E0305]: add expected 2 arguments, got 1
+  --> test:4:1
+   4 | f(1);
+        ^ called here...
+...
+  --> test:0:3
+   0 | fn add(a, b) {
+          ^ ... but declared here
+"
+        );
+        // Each in its own source
+        let rendered = runtime_error("let f = max;\nf(1);");
+        assert_eq!(
+            rendered.lines().skip(4).collect::<Vec<_>>(),
+            [
+                "...",
+                "  --> <prelude>:8:3",
+                "   8 | fn max(a, b) {",
+                "          ^ ... but declared here",
+            ]
+        );
+    }
+
+    #[test]
+    fn stack_trace() {
+        let src = "\
+fn c(x) {
+  return 1 + x;
+}
+fn b(x) { return c(x); }
+fn a() {
+  let x = b(\"a\");
+  return x;
+}
+print a();";
+        let trace: Vec<_> = runtime_error(src)
+            .lines()
+            .skip(4)
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            trace,
+            [
+                "       at c (test:1:11)",
+                "       at b (test:3:18)",
+                "       at a (test:5:11)",
+                "       at <top level> (test:8:7)",
+            ]
+        );
+
+        // Natives have no location, and the calls of a caught error are
+        // left behind with it
+        let src = "\
+fn f() { try { len(1); } catch (e) {} len(nil); }
+f();";
+        let rendered = runtime_error(src);
+        let trace: Vec<_> = rendered.lines().skip(4).collect();
+        assert_eq!(
+            trace,
+            [
+                "       at <native len>",
+                "       at f (test:0:41)",
+                "       at <top level> (test:1:1)",
+            ]
+        );
+        // Each call in its own source
+        let src = "fn bad(i) {\n  return i + nil;\n}\ntimes(2, bad);";
+        let rendered = runtime_error(src);
+        let trace: Vec<_> = rendered.lines().skip(4).collect();
+        assert_eq!(
+            trace,
+            [
+                "       at bad (test:1:11)",
+                "       at times (<prelude>:19:39)",
+                "       at <top level> (test:3:5)",
+            ]
+        );
+        // Outside of any call there is nothing to trace
+        assert_eq!(runtime_error("print -nil;").lines().count(), 4);
+    }
+
+    #[test]
+    fn call_depth() {
+        let src = "fn f(n) { if (n > 0) return f(n - 1); return n; }";
+        let mut lox = Interpreter::builder().max_call_depth(10).build();
+        run(&mut lox, src);
+        // The outermost call counts too
+        run(&mut lox, "let x = f(9);");
+        let res = lox.run(&SourceMap::new("test", "f(10);"));
+        assert_eq!(
+            res,
+            Err(LoxError::Runtime(RuntimeError::StackOverflow {
+                name: "f".to_string()
+            }))
+        );
+        // Unwinding leaves no calls behind
+        run(&mut lox, "let y = f(9);");
+    }
+
+    #[test]
+    fn default_call_depth() {
+        // Deeper than the stack of a test thread holds in debug builds
+        let thread = std::thread::Builder::new().stack_size(64 << 20);
+        let test = thread.spawn(|| {
+            let mut lox = Interpreter::builder().stack_size(64 << 20).build();
+            let src = "fn f(n) { if (n > 0) return f(n - 1); return n; } let x = f(250);";
+            run(&mut lox, src);
+            let err = lox.run(&SourceMap::new("test", "fn g() { g(); } g();"));
+            let err = err.unwrap_err();
+            assert_eq!(err.exit_code(), std::process::ExitCode::from(70));
+            assert_eq!(err.to_string(), "stack overflow entering 'g'");
+        });
+        test.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn stack_guard() {
+        // On a thread of the default size, however deep calls may go
+        let test = std::thread::spawn(|| {
+            let mut lox = Interpreter::builder().max_call_depth(1 << 20).build();
+            run(&mut lox, "class A { m() { return this.m(); } }");
+            for (src, name) in [("fn g() { g(); } g();", "g"), ("A().m();", "m")] {
+                let err = lox.run(&SourceMap::new("test", src)).unwrap_err();
+                assert_eq!(err.to_string(), format!("stack overflow entering '{name}'"));
+            }
+            // The stack is measured afresh for every outermost call
+            run(
+                &mut lox,
+                "fn f(n) { if (n > 0) return f(n - 1); return n; } f(5);",
+            );
+        });
+        test.join().unwrap();
+    }
+
+    #[test]
+    fn lint_levels() {
+        let src = "fn f() { let unused = 1; } f(); print 1;";
+        let outcome = |warnings| {
+            let out = Capture::default();
+            let lox = Interpreter::builder().output(Box::new(out.clone()));
+            let res = lox
+                .warnings(warnings)
+                .build()
+                .run(&SourceMap::new("test", src));
+            (res, out.take())
+        };
+        let (res, out) = outcome(Warnings::default());
+        assert_eq!((res, &out[..]), (Ok(()), "1\n"));
+
+        let mut deny = Warnings::default();
+        deny.default = Level::Deny;
+        let (res, out) = outcome(deny.clone());
+        assert!(out.is_empty());
+        let err = res.unwrap_err();
+        assert_eq!(err.exit_code(), std::process::ExitCode::from(65));
+        assert!(
+            matches!(&err, LoxError::Denied(w) if matches!(w[..], [Warning::UnusedVariable { .. }]))
+        );
+
+        // An allowed lint is neither reported nor denied
+        deny.set("unused_variable", Level::Allow).unwrap();
+        assert_eq!(outcome(deny).0, Ok(()));
+    }
+
+    #[test]
+    fn allowed_lines() {
+        let mut deny = Warnings::default();
+        deny.default = Level::Deny;
+        // Strict for the warning on the tail of `f`
+        let run = |src: &str| {
+            let lox = Interpreter::builder().strict(true);
+            let mut lox = lox.warnings(deny.clone()).build();
+            lox.run(&SourceMap::new("test", src))
+        };
+        // Denied, unless allowed on its line
+        let allowed = |src: &str| {
+            assert!(matches!(run(src), Err(LoxError::Denied(_))), "{src}");
+            assert_eq!(run(&format!("{src} // lox-allow")), Ok(()), "{src}");
+        };
+        allowed("fn f(r) { r * r; }");
+        allowed("for (let i = 0; i < 1; i += 1) { fn f() { return i; } }");
+        allowed("let x; x == 5;");
+    }
+
+    #[test]
+    fn help() {
+        let src = "\
+/// Greets someone.
+///
+/// Says hi by default.
+fn greet(name, greeting = \"hi\", ...rest) {}
+// Not documentation
+fn plain() {}
+help(greet);
+help(plain);
+help(len);";
+        assert_eq!(
+            printed(src),
+            "\
+fn greet(name, greeting = \"hi\", ...rest)
+    Greets someone.
+
+    Says hi by default.
+fn plain()
+    no documentation
+fn len(value)
+    The number of items of a list or map, or of characters of a string
+"
+        );
+    }
+
+    #[test]
+    fn number_checks() {
+        let src = "\
+print isNan(NAN); print isNan(INFINITY); print isNan(0 / 0); print isNan(1);
+print isFinite(1.5); print isFinite(-INFINITY); print isFinite(NAN);
+print isInteger(-3); print isInteger(2.5); print isInteger(INFINITY); print isInteger(NAN);";
+        assert_eq!(
+            printed(src),
+            "true\nfalse\ntrue\nfalse\ntrue\nfalse\nfalse\ntrue\nfalse\nfalse\nfalse\n"
+        );
+        let mut lox = Interpreter::builder().build();
+        for src in ["isNan(\"NaN\");", "isFinite(nil);", "isInteger([1]);"] {
+            assert_eq!(
+                lox.run(&SourceMap::new("test", src)),
+                Err(LoxError::Runtime(RuntimeError::TypeMismatch {
+                    msg: "can only test numbers"
+                })),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn nan_equality() {
+        // NaN equals nothing, not even itself or a list holding it
+        let src = "\
+let x = NAN;
+print x == x; print x != x; print NAN == NAN; print [NAN] == [NAN];
+print INFINITY == INFINITY; print x == nil;";
+        assert_eq!(printed(src), "false\ntrue\nfalse\nfalse\ntrue\nfalse\n");
+        assert_ne!(Val::Number(f64::NAN), Val::Number(f64::NAN));
+        assert_eq!(Val::Number(0.0), Val::Number(-0.0));
+    }
+
+    #[test]
+    fn leading_dot_numbers() {
+        assert_eq!(printed("print .5 + .25;"), "0.75\n");
+        let mut lox = Interpreter::builder().build();
+        let res = lox.run(&SourceMap::new("test", "let foo = 1; print foo.5;"));
+        assert!(matches!(res, Err(LoxError::Parse(_))));
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(printed("print 1_000 + 1;"), "1001\n");
+        let mut lox = Interpreter::builder().build();
+        let res = lox.run(&SourceMap::new("test", "print 1__000;"));
+        assert!(matches!(res, Err(LoxError::Parse(_))));
+    }
+
+    #[test]
+    fn prelude() {
+        let mut lox = Interpreter::builder().build();
+        run(
+            &mut lox,
+            "let a = abs(-2); let b = max(1, 3); let c = min(1, 3);",
+        );
+        assert_eq!(lox.global("a"), Some(Val::Number(2.0)));
+        assert_eq!(lox.global("b"), Some(Val::Number(3.0)));
+        assert_eq!(lox.global("c"), Some(Val::Number(1.0)));
+    }
+
+    #[test]
+    fn script_args() {
+        let lox = Interpreter::builder()
+            .args(vec!["a".to_string(), "b".to_string()])
+            .build();
+        assert_eq!(lox.global("args").unwrap().to_string(), r#"["a", "b"]"#);
+    }
+
+    #[test]
+    fn no_prelude() {
+        let lox = Interpreter::builder().prelude(false).build();
+        assert_eq!(lox.global("abs"), None);
+        assert!(matches!(lox.global("clock"), Some(Val::Func(_))));
+    }
+
+    #[test]
+    fn load_broken_file() {
+        let dir = std::env::temp_dir().join(format!("lox-load-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("defs.lox");
+        let path = path.to_str().unwrap();
+
+        let mut lox = Interpreter::builder().build();
+        std::fs::write(path, "let a = 1; fn f() { return a; } let b = ;").unwrap();
+        assert!(matches!(lox.load(path), Err(LoxError::Parse(_))));
+        assert_eq!(lox.global("a"), None);
+        assert_eq!(lox.global("f"), None);
+
+        std::fs::write(path, "let a = 1; fn f() { return a; } let b = f();").unwrap();
+        assert_eq!(lox.load(path), Ok(()));
+        assert_eq!(lox.global("b"), Some(Val::Number(1.0)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.load("/nonexistent/file.lox"),
+            Err(LoxError::Io {
+                path: "/nonexistent/file.lox".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn shadow_prelude() {
+        let mut lox = Interpreter::builder().build();
+        run(&mut lox, "fn max(a, b) { return 0; } let m = max(1, 2);");
+        assert_eq!(lox.global("m"), Some(Val::Number(0.0)));
+    }
+
+    // Submitted as the REPL does, appending each line to the session
+    fn submit(lox: &mut Interpreter, src: &mut SourceMap, line: &str) {
+        src.append(line);
+        assert_eq!(lox.run(src), Ok(()));
+    }
+
+    fn submit_lines(lox: &mut Interpreter, from: usize, to: usize) -> std::time::Duration {
+        let mut src = SourceMap::new("<stdin>", "");
+        let start = std::time::Instant::now();
+        for i in from..to {
+            let line = format!("let v{i} = {i}; fn f{i}(x) {{ return x + v{i}; }}\n");
+            submit(lox, &mut src, &line);
+        }
+        start.elapsed()
+    }
+
+    #[test]
+    fn repl_session_accumulates() {
+        let mut lox = Interpreter::builder().repl(true).build();
+        submit_lines(&mut lox, 0, 500);
+        run(
+            &mut lox,
+            "let total = 0; for (let i = 0; i < 1; i = i + 1) total = f0(1) + f499(1);",
+        );
+        assert_eq!(lox.global("v250"), Some(Val::Number(250.0)));
+        assert_eq!(lox.global("total"), Some(Val::Number(501.0)));
+    }
+
+    // Only the new line is lexed, and the strings of earlier ones are not
+    // interned again, however long the session
+    #[test]
+    fn repl_lexes_each_line_once() {
+        let mut lox = Interpreter::builder().repl(true).build();
+        let mut src = SourceMap::new("<stdin>", "");
+        submit(&mut lox, &mut src, "let x = 0;\n");
+        let interned = lox.session.interned.len();
+        for i in 1..500 {
+            submit(
+                &mut lox,
+                &mut src,
+                &format!("fn f{i}(a) {{ return a + x; }}\n"),
+            );
+            assert_eq!(lox.session.tokens, 12);
+            // `f{i}` and `a` the first time
+            assert_eq!(lox.session.interned.len(), interned + i + 1);
+        }
+        assert_eq!(src.unit().1.row, 499);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_repl_submissions() {
+        let mut lox = Interpreter::builder().repl(true).build();
+        let elapsed = submit_lines(&mut lox, 0, 1000);
+        println!(
+            "1000 REPL submissions: {elapsed:?} ({:?} per line)",
+            elapsed / 1000
+        );
+    }
+}
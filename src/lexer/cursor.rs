@@ -1,9 +1,19 @@
 use std::iter::Peekable;
 
+// Rows and columns count from 0, and columns count characters. `offset`
+// is in bytes from the start of the source, for tools that slice it
 #[derive(Default, PartialEq, Debug, Clone, Copy)]
 pub struct Loc {
     pub row: usize,
     pub col: usize,
+    pub offset: usize,
+}
+
+impl Loc {
+    // The text from this location up to `end`
+    pub fn slice<'a>(&self, end: &Loc, src: &'a str) -> &'a str {
+        &src[self.offset..end.offset]
+    }
 }
 
 impl std::fmt::Display for Loc {
@@ -22,6 +32,7 @@ where
     col: usize,
     row: usize,
     bol: usize,
+    offset: usize,
 }
 
 impl<I> Cursor<I>
@@ -35,9 +46,18 @@ where
             col: 0,
             row: 0,
             bol: 0,
+            offset: 0,
         }
     }
 
+    // Counts locations from `start`, for input that goes on a longer text
+    pub fn at(mut self, start: Loc) -> Self {
+        self.row = start.row;
+        self.col = start.col;
+        self.offset = start.offset;
+        self
+    }
+
     pub fn next_if(&mut self, f: impl FnOnce(char) -> bool) -> Option<char> {
         // Use self.next since it updates loc information
         if self.src.peek().copied().is_some_and(f) {
@@ -55,16 +75,16 @@ where
         self.src.peek().copied()
     }
 
-    pub fn peek_snd(&mut self) -> Option<char> {
-        let mut it = self.src.clone();
-        it.next();
-        it.next()
+    // The character `n` places after the next one
+    pub fn peek_nth(&mut self, n: usize) -> Option<char> {
+        self.src.clone().nth(n)
     }
 
     pub fn loc(&self) -> Loc {
         Loc {
             row: self.row,
             col: self.col - self.bol,
+            offset: self.offset,
         }
     }
 }
@@ -77,13 +97,13 @@ where
     type Item = char;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.src.next().map(|x| {
+        self.src.next().inspect(|&x| {
             self.col += 1;
+            self.offset += x.len_utf8();
             if x == '\n' {
                 self.row += 1;
                 self.bol = self.col;
             }
-            x
         })
     }
 }
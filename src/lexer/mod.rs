@@ -1,26 +1,43 @@
-use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::error::{LexError, Warning};
 
 mod cursor;
 use cursor::Cursor;
 pub use cursor::Loc;
 
-lazy_static! {
-    static ref KEYWORDS: HashMap<&'static str, TokKind> = HashMap::from([
+thread_local! {
+    // Per thread, since tokens hold reference counted text
+    static KEYWORDS: HashMap<&'static str, TokKind> = HashMap::from([
         ("and", TokKind::And),
+        ("assert", TokKind::Assert),
+        ("break", TokKind::Break),
+        ("catch", TokKind::Catch),
         ("class", TokKind::Class),
+        ("continue", TokKind::Continue),
         ("else", TokKind::Else),
         ("false", TokKind::False),
         ("fn", TokKind::Fn),
         ("for", TokKind::For),
         ("if", TokKind::If),
+        ("import", TokKind::Import),
+        ("in", TokKind::In),
         ("let", TokKind::Let),
+        ("loop", TokKind::Loop),
+        ("match", TokKind::Match),
         ("nil", TokKind::Nil),
         ("or", TokKind::Or),
         ("print", TokKind::Print),
         ("return", TokKind::Return),
+        ("static", TokKind::Static),
+        ("super", TokKind::Super),
         ("this", TokKind::This),
+        ("throw", TokKind::Throw),
         ("true", TokKind::True),
+        ("try", TokKind::Try),
         ("while", TokKind::While),
     ]);
 }
@@ -36,45 +53,76 @@ impl std::fmt::Display for TokKind {
         use TokKind::*;
         match &self {
             And => write!(f, "'and'"),
+            Assert => write!(f, "'assert'"),
+            Break => write!(f, "'break'"),
+            Catch => write!(f, "'catch'"),
             Class => write!(f, "'class'"),
+            Continue => write!(f, "'continue'"),
             Else => write!(f, "'else'"),
             False => write!(f, "'false'"),
             Fn => write!(f, "'fn'"),
             For => write!(f, "'for'"),
             If => write!(f, "'if'"),
+            Import => write!(f, "'import'"),
+            In => write!(f, "'in'"),
             Let => write!(f, "'let'"),
+            Loop => write!(f, "'loop'"),
+            Match => write!(f, "'match'"),
             Nil => write!(f, "'nil'"),
             Or => write!(f, "'or'"),
             Print => write!(f, "'print'"),
             Return => write!(f, "'return'"),
+            Static => write!(f, "'static'"),
+            Super => write!(f, "'super'"),
             This => write!(f, "'this'"),
+            Throw => write!(f, "'throw'"),
             True => write!(f, "'true'"),
+            Try => write!(f, "'try'"),
             While => write!(f, "'while'"),
             LParen => write!(f, "'('"),
             RParen => write!(f, "')'"),
             LBrace => write!(f, "'{{'"),
             RBrace => write!(f, "'}}'"),
+            LBracket => write!(f, "'['"),
+            RBracket => write!(f, "']'"),
+            Colon => write!(f, "':'"),
+            Question => write!(f, "'?'"),
             Comma => write!(f, "','"),
             Dot => write!(f, "'.'"),
             Minus => write!(f, "'-'"),
             Plus => write!(f, "'+'"),
             Semicolon => write!(f, "';'"),
             Star => write!(f, "'*'"),
+            Percent => write!(f, "'%'"),
+            Amp => write!(f, "'&'"),
+            Pipe => write!(f, "'|'"),
+            Caret => write!(f, "'^'"),
             Bang => write!(f, "'!'"),
             Equal => write!(f, "'='"),
             Less => write!(f, "'<'"),
             Greater => write!(f, "'>'"),
             Slash => write!(f, "'/'"),
             BangEqual => write!(f, "'!='"),
+            PlusEqual => write!(f, "'+='"),
+            MinusEqual => write!(f, "'-='"),
+            StarEqual => write!(f, "'*='"),
+            SlashEqual => write!(f, "'/='"),
+            StarStar => write!(f, "'**'"),
+            LessLess => write!(f, "'<<'"),
+            GreaterGreater => write!(f, "'>>'"),
+            Arrow => write!(f, "'=>'"),
+            DotDotDot => write!(f, "'...'"),
             EqualEqual => write!(f, "'=='"),
             LessEqual => write!(f, "'<='"),
             GreaterEqual => write!(f, "'>='"),
-            Str(_) => write!(f, "a string literal"),
-            Number(_) => write!(f, "a number literal"),
-            Ident(_) => write!(f, "an identifier"),
+            // With the lexeme, unless it is a placeholder in what was expected
+            Str(s) if s.is_empty() => write!(f, "a string"),
+            Str(s) => write!(f, "string \"{s}\""),
+            Number(x) => write!(f, "number {x}"),
+            BadNumber(text) => write!(f, "invalid number '{text}'"),
+            Ident(name) if name.is_empty() => write!(f, "an identifier"),
+            Ident(name) => write!(f, "identifier '{name}'"),
             Comment => write!(f, "a comment"),
-            Unexpected => write!(f, "an invalid symbol"),
-            Unterminated => write!(f, "an unterminated string literal"),
         }
     }
 }
@@ -83,31 +131,51 @@ impl std::fmt::Display for TokKind {
 pub enum TokKind {
     // Keywords
     And,
+    Assert,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
     Fn,
     For,
     If,
+    Import,
+    In,
     Let,
+    Loop,
+    Match,
     Nil,
     Or,
     Print,
     Return,
+    Static,
+    Super,
     This,
+    Throw,
     True,
+    Try,
     While,
     // Single-character symbols
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
+    Colon,
+    Question,
     Comma,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Star,
+    Percent,
+    Amp,
+    Pipe,
+    Caret,
 
     Bang,
     Equal,
@@ -119,15 +187,30 @@ pub enum TokKind {
     EqualEqual,
     LessEqual,
     GreaterEqual,
-    // Literals
-    Str(String),
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    StarStar,
+    LessLess,
+    GreaterGreater,
+    Arrow,
+    // Three-character symbols
+    DotDotDot,
+    // Literals. Every occurrence of the same text shares its allocation
+    Str(Rc<str>),
     Number(f64),
+    BadNumber(String),
 
-    Ident(String),
+    Ident(Rc<str>),
 
     Comment,
-    Unexpected,
-    Unterminated,
+}
+
+impl TokKind {
+    pub fn is_keyword(&self) -> bool {
+        KEYWORDS.with(|kws| kws.values().any(|kw| kw == self))
+    }
 }
 
 #[derive(Clone)]
@@ -140,6 +223,16 @@ where
     // Used to construct literals and identifiers
     // and to avoid repeated allocations
     buf: String,
+    // Every identifier and string seen so far
+    interned: HashSet<Rc<str>>,
+    warnings: Vec<Warning>,
+    // The lines with a `// lox-allow` comment, whose warnings are not
+    // reported
+    allowed: Vec<usize>,
+    // The text of each run of `///` comments on consecutive lines, along
+    // with the line of the last one
+    docs: Vec<(usize, String)>,
+    jlox: bool,
 }
 
 impl<I> Lexer<I>
@@ -153,7 +246,106 @@ where
         Self {
             cursor: Cursor::new(src),
             buf: String::with_capacity(Self::BUF_CAP),
+            interned: HashSet::new(),
+            warnings: Vec::new(),
+            allowed: Vec::new(),
+            docs: Vec::new(),
+            jlox: false,
+        }
+    }
+
+    // Also takes the `var` and `fun` of the reference dialect, jlox, as
+    // `let` and `fn`
+    pub fn jlox(mut self, jlox: bool) -> Self {
+        self.jlox = jlox;
+        self
+    }
+
+    // For input that goes on the text lexed before, as in the REPL: tokens
+    // are located from `start`, and the strings interned then are reused
+    pub fn resume(mut self, start: Loc, interned: HashSet<Rc<str>>) -> Self {
+        self.cursor = self.cursor.at(start);
+        self.interned = interned;
+        self
+    }
+
+    // Every identifier and string seen so far, to resume from
+    pub fn take_interned(&mut self) -> HashSet<Rc<str>> {
+        std::mem::take(&mut self.interned)
+    }
+
+    // Where the input read so far ends, right after the last token
+    pub fn loc(&self) -> Loc {
+        self.cursor.loc()
+    }
+
+    // Every token of the rest of the input, with the lexical errors set
+    // apart in the order they appear. Warnings are left to be taken
+    pub fn scan_all(&mut self) -> (Vec<Token>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for t in self.by_ref() {
+            match t {
+                Ok(t) => tokens.push(t),
+                Err(e) => errors.push(e),
+            }
+        }
+        (tokens, errors)
+    }
+
+    // Warnings about the tokens produced so far
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // The lines seen so far where warnings are allowed
+    pub fn allowed(&self) -> &[usize] {
+        &self.allowed
+    }
+
+    pub fn take_docs(&mut self) -> Vec<(usize, String)> {
+        std::mem::take(&mut self.docs)
+    }
+
+    // Adds a line of a `///` comment to the run on the lines just above
+    fn doc_line(&mut self, row: usize) {
+        let text = &self.buf[1..];
+        let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+        match self.docs.last_mut() {
+            Some((last, doc)) if *last + 1 == row => {
+                *last = row;
+                doc.push('\n');
+                doc.push_str(text);
+            }
+            _ => self.docs.push((row, text.to_string())),
+        }
+    }
+
+    // A literal that does not denote the number it spells out. Every integer
+    // up to 2^53 is exact, and past it the integer part must survive the
+    // conversion, as the fractional part is expected to be approximate.
+    // So is a number written with an exponent
+    fn check_number(text: &str, x: f64, loc: Loc) -> Option<Warning> {
+        let text = text.to_string();
+        if x.is_infinite() {
+            Some(Warning::NumberOverflow { text, loc })
+        } else if x >= 2f64.powi(53) && !text.contains(['e', 'E']) {
+            let int = text.split('.').next().unwrap_or_default().replace('_', "");
+            (int.trim_start_matches('0') != x.to_string())
+                .then_some(Warning::NumberPrecision { text, loc })
+        } else {
+            None
+        }
+    }
+
+    // The contents of `buf`, allocated only the first time they are seen
+    fn intern_buf(&mut self) -> Rc<str> {
+        if let Some(s) = self.interned.get(self.buf.as_str()) {
+            return Rc::clone(s);
         }
+        let s: Rc<str> = self.buf.as_str().into();
+        self.interned.insert(Rc::clone(&s));
+        s
     }
 
     fn buf_while(&mut self, mut f: impl FnMut(char) -> bool) {
@@ -162,22 +354,128 @@ where
         }
     }
 
-    fn next_raw(&mut self) -> Option<Token> {
+    // Literals too large for an f64 become infinity, as Rust's parser does.
+    // Digits may be separated by single underscores, which are dropped
+    fn number(text: &str) -> TokKind {
+        let digit = |c: Option<&u8>| c.is_some_and(u8::is_ascii_digit);
+        let b = text.as_bytes();
+        let separated = (0..b.len())
+            .filter(|&i| b[i] == b'_')
+            .all(|i| i > 0 && digit(b.get(i - 1)) && digit(b.get(i + 1)));
+        match text.replace('_', "").parse() {
+            Ok(x) if separated => TokKind::Number(x),
+            _ => TokKind::BadNumber(text.to_string()),
+        }
+    }
+
+    // A number literal starting at `first`, which is either a digit or the
+    // `.` of a literal without an integer part
+    fn number_literal(&mut self, first: char, loc: Loc) -> TokKind {
+        self.buf.clear();
+        self.buf.push(first);
+        // Misplaced underscores are taken in, to report the whole
+        // literal as invalid
+        self.buf_while(|c| c.is_ascii_digit() || c == '_');
+        if first != '.'
+            && self.cursor.peek().is_some_and(|c| c == '.')
+            && self
+                .cursor
+                .peek_nth(1)
+                .is_some_and(|c| c.is_ascii_digit() || c == '_')
+        {
+            self.cursor.next();
+            self.buf.push('.');
+            self.buf_while(|c| c.is_ascii_digit() || c == '_')
+        }
+        // Without digits, the `e` starts an identifier instead
+        let sign = matches!(self.cursor.peek_nth(1), Some('+' | '-')) as usize;
+        if self.cursor.peek().is_some_and(|c| c == 'e' || c == 'E')
+            && self
+                .cursor
+                .peek_nth(1 + sign)
+                .is_some_and(|c| c.is_ascii_digit())
+        {
+            for _ in 0..=sign {
+                self.buf.extend(self.cursor.next());
+            }
+            self.buf_while(|c| c.is_ascii_digit() || c == '_')
+        }
+        let kind = Self::number(&self.buf);
+        if let TokKind::Number(x) = kind {
+            self.warnings.extend(Self::check_number(&self.buf, x, loc));
+        }
+        kind
+    }
+
+    // Block comments nest, so every `/*` in one must be closed as well
+    fn block_comment(&mut self) -> bool {
+        let mut depth = 1;
+        while let Some(c) = self.cursor.next() {
+            match c {
+                '*' if self.cursor.next_if(|c| c == '/').is_some() => depth -= 1,
+                '/' if self.cursor.next_if(|c| c == '*').is_some() => depth += 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn next_raw(&mut self) -> Option<Result<Token, LexError>> {
         self.cursor.eat_while(char::is_whitespace);
 
         use TokKind::*;
         let loc = self.cursor.loc();
-        let kind = self.cursor.next().map(|c| match c {
+        let kind = match self.cursor.next()? {
             '(' => LParen,
             ')' => RParen,
             '{' => LBrace,
             '}' => RBrace,
+            '[' => LBracket,
+            ']' => RBracket,
+            ':' => Colon,
+            '?' => Question,
             ',' => Comma,
+            // A bare `.` is left for property access
+            '.' if self.cursor.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                self.number_literal('.', loc)
+            }
+            '.' if self.cursor.peek() == Some('.') && self.cursor.peek_nth(1) == Some('.') => {
+                self.cursor.next();
+                self.cursor.next();
+                DotDotDot
+            }
             '.' => Dot,
-            '-' => Minus,
-            '+' => Plus,
+            '-' => {
+                if self.cursor.next_if(|c| c == '=').is_some() {
+                    MinusEqual
+                } else {
+                    Minus
+                }
+            }
+            '+' => {
+                if self.cursor.next_if(|c| c == '=').is_some() {
+                    PlusEqual
+                } else {
+                    Plus
+                }
+            }
             ';' => Semicolon,
-            '*' => Star,
+            '*' => {
+                if self.cursor.next_if(|c| c == '=').is_some() {
+                    StarEqual
+                } else if self.cursor.next_if(|c| c == '*').is_some() {
+                    StarStar
+                } else {
+                    Star
+                }
+            }
+            '%' => Percent,
+            '&' => Amp,
+            '|' => Pipe,
+            '^' => Caret,
             '!' => {
                 if self.cursor.next_if(|c| c == '=').is_some() {
                     BangEqual
@@ -188,6 +486,8 @@ where
             '=' => {
                 if self.cursor.next_if(|c| c == '=').is_some() {
                     EqualEqual
+                } else if self.cursor.next_if(|c| c == '>').is_some() {
+                    Arrow
                 } else {
                     Equal
                 }
@@ -195,6 +495,8 @@ where
             '<' => {
                 if self.cursor.next_if(|c| c == '=').is_some() {
                     LessEqual
+                } else if self.cursor.next_if(|c| c == '<').is_some() {
+                    LessLess
                 } else {
                     Less
                 }
@@ -202,14 +504,33 @@ where
             '>' => {
                 if self.cursor.next_if(|c| c == '=').is_some() {
                     GreaterEqual
+                } else if self.cursor.next_if(|c| c == '>').is_some() {
+                    GreaterGreater
                 } else {
                     Greater
                 }
             }
             '/' => {
                 if self.cursor.next_if(|c| c == '/').is_some() {
-                    self.cursor.eat_while(|c| c != '\n');
+                    self.buf.clear();
+                    self.buf_while(|c| c != '\n');
+                    // Possibly followed by the reason, `// lox-allow: ...`
+                    let rest = self.buf.trim().strip_prefix("lox-allow");
+                    if rest.is_some_and(|r| r.is_empty() || r.starts_with([':', ' '])) {
+                        self.allowed.push(loc.row);
+                    }
+                    // `////` is an ordinary comment, as in rulers of slashes
+                    if self.buf.starts_with('/') && !self.buf.starts_with("//") {
+                        self.doc_line(loc.row);
+                    }
                     Comment
+                } else if self.cursor.next_if(|c| c == '*').is_some() {
+                    if !self.block_comment() {
+                        return Some(Err(LexError::UnterminatedComment { loc }));
+                    }
+                    Comment
+                } else if self.cursor.next_if(|c| c == '=').is_some() {
+                    SlashEqual
                 } else {
                     Slash
                 }
@@ -218,36 +539,28 @@ where
                 self.buf.clear();
                 self.buf_while(|c| c != '"');
                 if self.cursor.next_if(|c| c == '"').is_some() {
-                    Str(self.buf.to_string())
+                    Str(self.intern_buf())
                 } else {
-                    Unterminated
+                    let len = self.buf.chars().count();
+                    return Some(Err(LexError::Unterminated { loc, len }));
                 }
             }
             x if x.is_ascii_alphabetic() || x == '_' => {
                 self.buf.clear();
                 self.buf.push(x);
                 self.buf_while(|c| c.is_ascii_alphanumeric() || c == '_');
-                KEYWORDS
-                    .get(&*self.buf)
-                    .map_or_else(|| Ident(self.buf.to_string()), |kw| kw.clone())
-            }
-            x if x.is_ascii_digit() => {
-                self.buf.clear();
-                self.buf.push(x);
-                self.buf_while(|c| c.is_ascii_digit());
-                if self.cursor.peek().is_some_and(|c| c == '.')
-                    && (self.cursor.peek_snd().is_some_and(|c| c.is_ascii_digit()))
-                {
-                    self.cursor.next();
-                    self.buf.push('.');
-                    self.buf_while(|c| c.is_ascii_digit())
+                match KEYWORDS.with(|kws| kws.get(&*self.buf).cloned()) {
+                    Some(kw) => kw,
+                    None if self.jlox && self.buf == "var" => Let,
+                    None if self.jlox && self.buf == "fun" => Fn,
+                    None => Ident(self.intern_buf()),
                 }
-                Number(self.buf.parse().unwrap())
             }
-            _ => Unexpected,
-        });
+            x if x.is_ascii_digit() => self.number_literal(x, loc),
+            c => return Some(Err(LexError::Unexpected { c, loc })),
+        };
 
-        kind.map(|kind| Token { kind, loc })
+        Some(Ok(Token { kind, loc }))
     }
 }
 
@@ -256,10 +569,16 @@ where
     I: Iterator<Item = char>,
     I: Clone,
 {
-    type Item = Token;
+    type Item = Result<Token, LexError>;
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(t) = self.next_raw() {
-            if t.kind != TokKind::Comment {
+            if !matches!(
+                t,
+                Ok(Token {
+                    kind: TokKind::Comment,
+                    ..
+                })
+            ) {
                 return Some(t);
             }
         }
@@ -272,50 +591,155 @@ mod test {
     use super::*;
 
     macro_rules! tok {
-        ($k:tt($a:expr), $r:expr, $c:expr) => {
+        ($k:tt($a:expr), $r:expr, $c:expr, $o:expr) => {
             Token {
                 kind: TokKind::$k($a),
-                loc: Loc { row: $r, col: $c },
+                loc: Loc {
+                    row: $r,
+                    col: $c,
+                    offset: $o,
+                },
             }
         };
-        ($k:tt , $r:expr, $c:expr) => {
+        ($k:tt , $r:expr, $c:expr, $o:expr) => {
             Token {
                 kind: TokKind::$k,
-                loc: Loc { row: $r, col: $c },
+                loc: Loc {
+                    row: $r,
+                    col: $c,
+                    offset: $o,
+                },
             }
         };
     }
 
     #[test]
     fn punctuation() {
-        let mut l = Lexer::new("(){};,+-*!===<=>=!=<>/.".chars());
-        assert_eq!(l.next(), Some(tok!(LParen, 0, 0)));
-        assert_eq!(l.next(), Some(tok!(RParen, 0, 1)));
-        assert_eq!(l.next(), Some(tok!(LBrace, 0, 2)));
-        assert_eq!(l.next(), Some(tok!(RBrace, 0, 3)));
-        assert_eq!(l.next(), Some(tok!(Semicolon, 0, 4)));
-        assert_eq!(l.next(), Some(tok!(Comma, 0, 5)));
-        assert_eq!(l.next(), Some(tok!(Plus, 0, 6)));
-        assert_eq!(l.next(), Some(tok!(Minus, 0, 7)));
-        assert_eq!(l.next(), Some(tok!(Star, 0, 8)));
-        assert_eq!(l.next(), Some(tok!(BangEqual, 0, 9)));
-        assert_eq!(l.next(), Some(tok!(EqualEqual, 0, 11)));
-        assert_eq!(l.next(), Some(tok!(LessEqual, 0, 13)));
-        assert_eq!(l.next(), Some(tok!(GreaterEqual, 0, 15)));
-        assert_eq!(l.next(), Some(tok!(BangEqual, 0, 17)));
-        assert_eq!(l.next(), Some(tok!(Less, 0, 19)));
-        assert_eq!(l.next(), Some(tok!(Greater, 0, 20)));
-        assert_eq!(l.next(), Some(tok!(Slash, 0, 21)));
-        assert_eq!(l.next(), Some(tok!(Dot, 0, 22)));
+        let mut l = Lexer::new("(){};,+-*!===<=>=!=<>/.%".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(LParen, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(RParen, 0, 1, 1))));
+        assert_eq!(l.next(), Some(Ok(tok!(LBrace, 0, 2, 2))));
+        assert_eq!(l.next(), Some(Ok(tok!(RBrace, 0, 3, 3))));
+        assert_eq!(l.next(), Some(Ok(tok!(Semicolon, 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Comma, 0, 5, 5))));
+        assert_eq!(l.next(), Some(Ok(tok!(Plus, 0, 6, 6))));
+        assert_eq!(l.next(), Some(Ok(tok!(Minus, 0, 7, 7))));
+        assert_eq!(l.next(), Some(Ok(tok!(Star, 0, 8, 8))));
+        assert_eq!(l.next(), Some(Ok(tok!(BangEqual, 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(EqualEqual, 0, 11, 11))));
+        assert_eq!(l.next(), Some(Ok(tok!(LessEqual, 0, 13, 13))));
+        assert_eq!(l.next(), Some(Ok(tok!(GreaterEqual, 0, 15, 15))));
+        assert_eq!(l.next(), Some(Ok(tok!(BangEqual, 0, 17, 17))));
+        assert_eq!(l.next(), Some(Ok(tok!(Less, 0, 19, 19))));
+        assert_eq!(l.next(), Some(Ok(tok!(Greater, 0, 20, 20))));
+        assert_eq!(l.next(), Some(Ok(tok!(Slash, 0, 21, 21))));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 22, 22))));
+        assert_eq!(l.next(), Some(Ok(tok!(Percent, 0, 23, 23))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let mut l = Lexer::new("+= -= *= /= + = ** * *".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(PlusEqual, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(MinusEqual, 0, 3, 3))));
+        assert_eq!(l.next(), Some(Ok(tok!(StarEqual, 0, 6, 6))));
+        assert_eq!(l.next(), Some(Ok(tok!(SlashEqual, 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(Plus, 0, 12, 12))));
+        assert_eq!(l.next(), Some(Ok(tok!(Equal, 0, 14, 14))));
+        assert_eq!(l.next(), Some(Ok(tok!(StarStar, 0, 16, 16))));
+        assert_eq!(l.next(), Some(Ok(tok!(Star, 0, 19, 19))));
+        assert_eq!(l.next(), Some(Ok(tok!(Star, 0, 21, 21))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn resume() {
+        let mut first = Lexer::new("let x;\n".chars());
+        let Some(Ok(Token {
+            kind: TokKind::Ident(x),
+            ..
+        })) = first.nth(1)
+        else {
+            panic!("expected an identifier")
+        };
+        let start = Loc {
+            row: 1,
+            col: 0,
+            offset: 7,
+        };
+        let mut l = Lexer::new("\n x".chars()).resume(start, first.take_interned());
+        let Some(Ok(Token {
+            kind: TokKind::Ident(again),
+            loc,
+        })) = l.next()
+        else {
+            panic!("expected an identifier")
+        };
+        assert!(Rc::ptr_eq(&x, &again));
+        assert_eq!((loc.row, loc.col, loc.offset), (2, 1, 9));
+    }
+
+    #[test]
+    fn arrow() {
+        let mut l = Lexer::new("=> ==> = >".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Arrow, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(EqualEqual, 0, 3, 3))));
+        assert_eq!(l.next(), Some(Ok(tok!(Greater, 0, 5, 5))));
+        assert_eq!(l.next(), Some(Ok(tok!(Equal, 0, 7, 7))));
+        assert_eq!(l.next(), Some(Ok(tok!(Greater, 0, 9, 9))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn dot_dot_dot() {
+        let mut l = Lexer::new("...rest .. .".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(DotDotDot, 0, 0, 0))));
+        assert!(matches!(
+            l.next(),
+            Some(Ok(Token {
+                kind: TokKind::Ident(_),
+                ..
+            }))
+        ));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 8, 8))));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 11, 11))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn bitwise() {
+        let mut l = Lexer::new("& | ^ << >> <<= >>= and".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Amp, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Pipe, 0, 2, 2))));
+        assert_eq!(l.next(), Some(Ok(tok!(Caret, 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(LessLess, 0, 6, 6))));
+        assert_eq!(l.next(), Some(Ok(tok!(GreaterGreater, 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(LessLess, 0, 12, 12))));
+        assert_eq!(l.next(), Some(Ok(tok!(Equal, 0, 14, 14))));
+        assert_eq!(l.next(), Some(Ok(tok!(GreaterGreater, 0, 16, 16))));
+        assert_eq!(l.next(), Some(Ok(tok!(Equal, 0, 18, 18))));
+        assert_eq!(l.next(), Some(Ok(tok!(And, 0, 20, 20))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn brackets() {
+        let mut l = Lexer::new("[]:?".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(LBracket, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(RBracket, 0, 1, 1))));
+        assert_eq!(l.next(), Some(Ok(tok!(Colon, 0, 2, 2))));
+        assert_eq!(l.next(), Some(Ok(tok!(Question, 0, 3, 3))));
         assert_eq!(l.next(), None);
     }
 
     #[test]
     fn strings() {
         let mut l = Lexer::new(r#"  "string"  ""  "msg" "#.chars());
-        assert_eq!(l.next(), Some(tok!(Str("string".to_string()), 0, 2)));
-        assert_eq!(l.next(), Some(tok!(Str("".to_string()), 0, 12)));
-        assert_eq!(l.next(), Some(tok!(Str("msg".to_string()), 0, 16)));
+        assert_eq!(l.next(), Some(Ok(tok!(Str("string".into()), 0, 2, 2))));
+        assert_eq!(l.next(), Some(Ok(tok!(Str("".into()), 0, 12, 12))));
+        assert_eq!(l.next(), Some(Ok(tok!(Str("msg".into()), 0, 16, 16))));
         assert_eq!(l.next(), None)
     }
 
@@ -326,22 +750,21 @@ mod test {
     abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"
                 .chars(),
         );
-        assert_eq!(l.next(), Some(tok!(Ident("andy".to_string()), 0, 0)));
-        assert_eq!(l.next(), Some(tok!(Ident("formless".to_string()), 0, 5)));
-        assert_eq!(l.next(), Some(tok!(Ident("fo".to_string()), 0, 14)));
-        assert_eq!(l.next(), Some(tok!(Ident("_".to_string()), 0, 17)));
-        assert_eq!(l.next(), Some(tok!(Ident("_123".to_string()), 0, 19)));
-        assert_eq!(l.next(), Some(tok!(Ident("_abc".to_string()), 0, 24)));
-        assert_eq!(l.next(), Some(tok!(Ident("ab123".to_string()), 0, 29)));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("andy".into()), 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("formless".into()), 0, 5, 5))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("fo".into()), 0, 14, 14))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("_".into()), 0, 17, 17))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("_123".into()), 0, 19, 19))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("_abc".into()), 0, 24, 24))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("ab123".into()), 0, 29, 29))));
         assert_eq!(
             l.next(),
-            Some(tok!(
-                Ident(
-                    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_".to_string()
-                ),
+            Some(Ok(tok!(
+                Ident("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_".into()),
                 1,
-                4
-            ))
+                4,
+                39
+            )))
         );
         assert_eq!(l.next(), None);
     }
@@ -349,22 +772,166 @@ mod test {
     #[test]
     fn numbers() {
         let mut l = Lexer::new("123 123.456 .456 123.".chars());
-        assert_eq!(l.next(), Some(tok!(Number(123.0), 0, 0)));
-        assert_eq!(l.next(), Some(tok!(Number(123.456), 0, 4)));
-        assert_eq!(l.next(), Some(tok!(Dot, 0, 12)));
-        assert_eq!(l.next(), Some(tok!(Number(456.0), 0, 13)));
-        assert_eq!(l.next(), Some(tok!(Number(123.0), 0, 17)));
-        assert_eq!(l.next(), Some(tok!(Dot, 0, 20)));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(123.0), 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(123.456), 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(0.456), 0, 12, 12))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(123.0), 0, 17, 17))));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 20, 20))));
+        assert_eq!(l.next(), None);
+
+        let mut l = Lexer::new(".5e2 a.b ._5".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Number(50.0), 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("a".into()), 0, 5, 5))));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 6, 6))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("b".into()), 0, 7, 7))));
+        assert_eq!(l.next(), Some(Ok(tok!(Dot, 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("_5".into()), 0, 10, 10))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn exponents() {
+        let mut l = Lexer::new("1e9 2.5e-3 6.02E23 1e+2 7e0".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Number(1e9), 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(2.5e-3), 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(6.02e23), 0, 11, 11))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(100.0), 0, 19, 19))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(7.0), 0, 24, 24))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn incomplete_exponents() {
+        let mut l = Lexer::new("1e 1e+ 2E-x 3ex".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Number(1.0), 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("e".into()), 0, 1, 1))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(1.0), 0, 3, 3))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("e".into()), 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Plus, 0, 5, 5))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(2.0), 0, 7, 7))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("E".into()), 0, 8, 8))));
+        assert_eq!(l.next(), Some(Ok(tok!(Minus, 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("x".into()), 0, 10, 10))));
+        assert_eq!(l.next(), Some(Ok(tok!(Number(3.0), 0, 12, 12))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("ex".into()), 0, 13, 13))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn number_overflow() {
+        let huge = format!("1{}", "0".repeat(400));
+        let mut l = Lexer::new(huge.chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Number(f64::INFINITY), 0, 0, 0))));
         assert_eq!(l.next(), None);
     }
 
+    #[test]
+    fn allowed_lines() {
+        let src = "if (x = 1) {} // lox-allow\n// lox-allow: below\nx = 2; // lox-allowed\n/* lox-allow */";
+        let mut l = Lexer::new(src.chars());
+        l.by_ref().for_each(drop);
+        assert_eq!(l.allowed(), [0, 1]);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let src =
+            "/// Adds.\n///\n///   x + y\nfn f() {}\n// not a doc\n//// nor this\n\n///last  ";
+        let mut l = Lexer::new(src.chars());
+        l.by_ref().for_each(drop);
+        let docs = vec![(2, "Adds.\n\n  x + y".to_string()), (7, "last".to_string())];
+        assert_eq!(l.take_docs(), docs);
+    }
+
+    #[test]
+    fn number_warnings() {
+        let huge = format!("1{}", "0".repeat(400));
+        let src = format!(
+            "1 + {huge};\n9007199254740993 + 9007199254740992 + 10000000000000000000000;\n1e22 + 1e400;"
+        );
+        let mut l = Lexer::new(src.chars());
+        l.by_ref().for_each(drop);
+        assert_eq!(
+            l.take_warnings(),
+            vec![
+                Warning::NumberOverflow {
+                    text: huge,
+                    loc: Loc {
+                        row: 0,
+                        col: 4,
+                        offset: 4
+                    }
+                },
+                Warning::NumberPrecision {
+                    text: "9007199254740993".to_string(),
+                    loc: Loc {
+                        row: 1,
+                        col: 0,
+                        offset: 407
+                    }
+                },
+                Warning::NumberOverflow {
+                    text: "1e400".to_string(),
+                    loc: Loc {
+                        row: 2,
+                        col: 7,
+                        offset: 477
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_number() {
+        type L<'a> = Lexer<std::str::Chars<'a>>;
+        assert_eq!(L::number("12.5"), TokKind::Number(12.5));
+        assert_eq!(L::number("1.2.3"), TokKind::BadNumber("1.2.3".to_string()));
+        assert_eq!(L::number(""), TokKind::BadNumber("".to_string()));
+    }
+
+    #[test]
+    fn digit_separators() {
+        let bad = |s: &str| Some(Ok(tok!(BadNumber(s.to_string()), 0, 0, 0)));
+        assert_eq!(
+            Lexer::new("1_000_000".chars()).next(),
+            Some(Ok(tok!(Number(1e6), 0, 0, 0)))
+        );
+        assert_eq!(
+            Lexer::new("12.345_678".chars()).next(),
+            Some(Ok(tok!(Number(12.345678), 0, 0, 0)))
+        );
+        assert_eq!(
+            Lexer::new("1_0e1_0".chars()).next(),
+            Some(Ok(tok!(Number(1e11), 0, 0, 0)))
+        );
+        assert_eq!(
+            Lexer::new("1_0".chars()).next(),
+            Some(Ok(tok!(Number(10.0), 0, 0, 0)))
+        );
+        assert_eq!(Lexer::new("1__0".chars()).next(), bad("1__0"));
+        assert_eq!(Lexer::new("1_".chars()).next(), bad("1_"));
+        assert_eq!(Lexer::new("1._5".chars()).next(), bad("1._5"));
+        assert_eq!(Lexer::new("1_.5".chars()).next(), bad("1_.5"));
+        assert_eq!(Lexer::new("1.5_".chars()).next(), bad("1.5_"));
+        assert_eq!(
+            Lexer::new("1e_5".chars()).next(),
+            Some(Ok(tok!(Number(1.0), 0, 0, 0)))
+        );
+        // An identifier, as it has always been
+        assert_eq!(
+            Lexer::new("_1".chars()).next(),
+            Some(Ok(tok!(Ident("_1".into()), 0, 0, 0)))
+        );
+    }
+
     #[test]
     fn whitespace() {
         let mut l = Lexer::new("space    tabs\t\t\t\tnewline\n\n\nend\r\n".chars());
-        assert_eq!(l.next(), Some(tok!(Ident("space".to_string()), 0, 0)));
-        assert_eq!(l.next(), Some(tok!(Ident("tabs".to_string()), 0, 9)));
-        assert_eq!(l.next(), Some(tok!(Ident("newline".to_string()), 0, 17)));
-        assert_eq!(l.next(), Some(tok!(Ident("end".to_string()), 3, 0)));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("space".into()), 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("tabs".into()), 0, 9, 9))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("newline".into()), 0, 17, 17))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("end".into()), 3, 0, 27))));
         assert_eq!(l.next(), None);
     }
 
@@ -375,24 +942,45 @@ mod test {
 let nil or print return this true while"
                 .chars(),
         );
-        assert_eq!(l.next(), Some(tok!(And, 0, 0)));
-        assert_eq!(l.next(), Some(tok!(Class, 0, 4)));
-        assert_eq!(l.next(), Some(tok!(Else, 0, 10)));
-        assert_eq!(l.next(), Some(tok!(False, 0, 15)));
-        assert_eq!(l.next(), Some(tok!(Fn, 0, 21)));
-        assert_eq!(l.next(), Some(tok!(For, 0, 24)));
-        assert_eq!(l.next(), Some(tok!(If, 0, 28)));
-        assert_eq!(l.next(), Some(tok!(Let, 1, 0)));
-        assert_eq!(l.next(), Some(tok!(Nil, 1, 4)));
-        assert_eq!(l.next(), Some(tok!(Or, 1, 8)));
-        assert_eq!(l.next(), Some(tok!(Print, 1, 11)));
-        assert_eq!(l.next(), Some(tok!(Return, 1, 17)));
-        assert_eq!(l.next(), Some(tok!(This, 1, 24)));
-        assert_eq!(l.next(), Some(tok!(True, 1, 29)));
-        assert_eq!(l.next(), Some(tok!(While, 1, 34)));
+        assert_eq!(l.next(), Some(Ok(tok!(And, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Class, 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Else, 0, 10, 10))));
+        assert_eq!(l.next(), Some(Ok(tok!(False, 0, 15, 15))));
+        assert_eq!(l.next(), Some(Ok(tok!(Fn, 0, 21, 21))));
+        assert_eq!(l.next(), Some(Ok(tok!(For, 0, 24, 24))));
+        assert_eq!(l.next(), Some(Ok(tok!(If, 0, 28, 28))));
+        assert_eq!(l.next(), Some(Ok(tok!(Let, 1, 0, 31))));
+        assert_eq!(l.next(), Some(Ok(tok!(Nil, 1, 4, 35))));
+        assert_eq!(l.next(), Some(Ok(tok!(Or, 1, 8, 39))));
+        assert_eq!(l.next(), Some(Ok(tok!(Print, 1, 11, 42))));
+        assert_eq!(l.next(), Some(Ok(tok!(Return, 1, 17, 48))));
+        assert_eq!(l.next(), Some(Ok(tok!(This, 1, 24, 55))));
+        assert_eq!(l.next(), Some(Ok(tok!(True, 1, 29, 60))));
+        assert_eq!(l.next(), Some(Ok(tok!(While, 1, 34, 65))));
+        assert_eq!(l.next(), None);
+    }
+
+    #[test]
+    fn loop_keywords() {
+        let mut l = Lexer::new("break continue breaks loop match".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Break, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Continue, 0, 6, 6))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("breaks".into()), 0, 15, 15))));
+        assert_eq!(l.next(), Some(Ok(tok!(Loop, 0, 22, 22))));
+        assert_eq!(l.next(), Some(Ok(tok!(Match, 0, 27, 27))));
         assert_eq!(l.next(), None);
     }
 
+    #[test]
+    fn jlox_keywords() {
+        let mut l = Lexer::new("var fun vars".chars()).jlox(true);
+        assert_eq!(l.next(), Some(Ok(tok!(Let, 0, 0, 0))));
+        assert_eq!(l.next(), Some(Ok(tok!(Fn, 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("vars".into()), 0, 8, 8))));
+        let mut l = Lexer::new("var".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("var".into()), 0, 0, 0))));
+    }
+
     #[test]
     fn comments() {
         let mut l1 = Lexer::new(
@@ -400,20 +988,160 @@ let nil or print return this true while"
         );
         let mut l2 = l1.clone();
 
-        assert_eq!(l1.next_raw(), Some(tok!(Ident("foo".to_string()), 0, 0)));
-        assert_eq!(l1.next_raw(), Some(tok!(Comment, 1, 0)));
-        assert_eq!(l1.next_raw(), Some(tok!(Ident("bar".to_string()), 2, 0)));
-        assert_eq!(l1.next_raw(), Some(tok!(Comment, 2, 4)));
-        assert_eq!(l1.next_raw(), Some(tok!(Comment, 3, 0)));
-        assert_eq!(l1.next_raw(), Some(tok!(Ident("end".to_string()), 4, 0)));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Ident("foo".into()), 0, 0, 0))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 1, 0, 4))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Ident("bar".into()), 2, 0, 25))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 2, 4, 29))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 3, 0, 48))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Ident("end".into()), 4, 0, 65))));
         assert_eq!(l1.next_raw(), None);
 
-        assert_eq!(l2.next(), Some(tok!(Ident("foo".to_string()), 0, 0)));
-        assert_eq!(l2.next(), Some(tok!(Ident("bar".to_string()), 2, 0)));
-        assert_eq!(l2.next(), Some(tok!(Ident("end".to_string()), 4, 0)));
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("foo".into()), 0, 0, 0))));
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("bar".into()), 2, 0, 25))));
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("end".into()), 4, 0, 65))));
         assert_eq!(l2.next(), None);
     }
 
+    #[test]
+    fn block_comments() {
+        let mut l1 =
+            Lexer::new("foo /* a /* b */ c */ bar\n/* one\n   two */ baz /**/ /***/ end".chars());
+        let mut l2 = l1.clone();
+
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Ident("foo".into()), 0, 0, 0))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 0, 4, 4))));
+        assert_eq!(
+            l1.next_raw(),
+            Some(Ok(tok!(Ident("bar".into()), 0, 22, 22)))
+        );
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 1, 0, 26))));
+        assert_eq!(
+            l1.next_raw(),
+            Some(Ok(tok!(Ident("baz".into()), 2, 10, 43)))
+        );
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 2, 14, 47))));
+        assert_eq!(l1.next_raw(), Some(Ok(tok!(Comment, 2, 19, 52))));
+        assert_eq!(
+            l1.next_raw(),
+            Some(Ok(tok!(Ident("end".into()), 2, 25, 58)))
+        );
+        assert_eq!(l1.next_raw(), None);
+
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("foo".into()), 0, 0, 0))));
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("bar".into()), 0, 22, 22))));
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("baz".into()), 2, 10, 43))));
+        assert_eq!(l2.next(), Some(Ok(tok!(Ident("end".into()), 2, 25, 58))));
+        assert_eq!(l2.next(), None);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let mut l = Lexer::new("a\n  /* open /* closed */\n".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("a".into()), 0, 0, 0))));
+        assert_eq!(
+            l.next(),
+            Some(Err(LexError::UnterminatedComment {
+                loc: Loc {
+                    row: 1,
+                    col: 2,
+                    offset: 4
+                }
+            }))
+        );
+        assert_eq!(l.next(), None);
+        let mut l = Lexer::new("/* a */ */".chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Star, 0, 8, 8))));
+        assert_eq!(l.next(), Some(Ok(tok!(Slash, 0, 9, 9))));
+    }
+
+    #[test]
+    fn scan_all() {
+        use TokKind::*;
+
+        let (tokens, errors) =
+            Lexer::new("let a = #1;\nlet b = @ $ 2;\nprint \"open;\nend".chars()).scan_all();
+        let kinds: Vec<_> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Let,
+                Ident("a".into()),
+                Equal,
+                Number(1.0),
+                Semicolon,
+                Let,
+                Ident("b".into()),
+                Equal,
+                Number(2.0),
+                Semicolon,
+                Print
+            ]
+        );
+        assert_eq!(
+            errors,
+            [
+                LexError::Unexpected {
+                    c: '#',
+                    loc: Loc {
+                        row: 0,
+                        col: 8,
+                        offset: 8
+                    }
+                },
+                LexError::Unexpected {
+                    c: '@',
+                    loc: Loc {
+                        row: 1,
+                        col: 8,
+                        offset: 20
+                    }
+                },
+                LexError::Unexpected {
+                    c: '$',
+                    loc: Loc {
+                        row: 1,
+                        col: 10,
+                        offset: 22
+                    }
+                },
+                LexError::Unterminated {
+                    loc: Loc {
+                        row: 2,
+                        col: 6,
+                        offset: 33
+                    },
+                    len: 9
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn byte_offsets() {
+        let src = "print \"ñandú 🦤\";\n\"é\" @";
+        let mut l = Lexer::new(src.chars());
+        assert_eq!(l.next(), Some(Ok(tok!(Print, 0, 0, 0))));
+        let s = l.next().unwrap().unwrap();
+        assert_eq!(s, tok!(Str("ñandú 🦤".into()), 0, 6, 6));
+        let semi = l.next().unwrap().unwrap();
+        // 5 bytes more than characters on the first line, 1 on the second
+        assert_eq!(semi, tok!(Semicolon, 0, 15, 20));
+        assert_eq!(s.loc.slice(&semi.loc, src), "\"ñandú 🦤\"");
+        assert_eq!(l.next(), Some(Ok(tok!(Str("é".into()), 1, 0, 22))));
+        assert_eq!(
+            l.next(),
+            Some(Err(LexError::Unexpected {
+                c: '@',
+                loc: Loc {
+                    row: 1,
+                    col: 4,
+                    offset: 27
+                }
+            }))
+        );
+        assert_eq!(l.next(), None);
+    }
+
     #[test]
     fn errors() {
         let mut l = Lexer::new(
@@ -421,11 +1149,32 @@ let nil or print return this true while"
 true and 1 == 1 "#
                 .chars(),
         );
-        assert_eq!(l.next(), Some(tok!(Ident("foo".to_string()), 0, 1)));
-        assert_eq!(l.next(), Some(tok!(LParen, 0, 4)));
-        assert_eq!(l.next(), Some(tok!(Ident("bar".to_string()), 0, 5)));
-        assert_eq!(l.next(), Some(tok!(Unexpected, 0, 9)));
-        assert_eq!(l.next(), Some(tok!(RParen, 0, 11)));
-        assert_eq!(l.next(), Some(tok!(Unterminated, 0, 13)));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("foo".into()), 0, 1, 1))));
+        assert_eq!(l.next(), Some(Ok(tok!(LParen, 0, 4, 4))));
+        assert_eq!(l.next(), Some(Ok(tok!(Ident("bar".into()), 0, 5, 5))));
+        assert_eq!(
+            l.next(),
+            Some(Err(LexError::Unexpected {
+                c: '@',
+                loc: Loc {
+                    row: 0,
+                    col: 9,
+                    offset: 9
+                }
+            }))
+        );
+        assert_eq!(l.next(), Some(Ok(tok!(RParen, 0, 11, 11))));
+        assert_eq!(
+            l.next(),
+            Some(Err(LexError::Unterminated {
+                loc: Loc {
+                    row: 0,
+                    col: 13,
+                    offset: 13
+                },
+                len: 23
+            }))
+        );
+        assert_eq!(l.next(), None);
     }
 }
@@ -0,0 +1,39 @@
+pub mod class;
+pub mod collections;
+pub mod context;
+pub mod error;
+pub mod explain;
+pub mod expr;
+mod globals;
+mod import;
+pub mod interpreter;
+pub mod lexer;
+pub mod metrics;
+pub mod parser;
+pub mod prog;
+mod resolver;
+pub mod sandbox;
+mod scope;
+pub mod sexpr;
+pub mod source;
+pub mod stmt;
+pub mod symbols;
+pub mod val;
+
+pub use interpreter::{Builder, Interpreter};
+
+#[cfg(test)]
+mod test_util {
+    use crate::{interpreter::Interpreter, source::SourceMap, val::Val};
+
+    // Runs a whole program, which must succeed, and returns the interpreter
+    pub fn run(src: &str) -> Interpreter {
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(lox.run(&SourceMap::new("test", src)), Ok(()));
+        lox
+    }
+
+    pub fn global(lox: &Interpreter, name: &str) -> Val {
+        lox.global(name).unwrap()
+    }
+}
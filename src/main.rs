@@ -1,96 +1,162 @@
-#![feature(is_some_and)]
+use std::{env, process::ExitCode, thread};
 
-use std::{
-    env,
-    fs::read_to_string,
-    io::{stdin, stdout, Write},
-    process::ExitCode,
-    rc::Rc,
+use cli::{Cli, Command, Metrics};
+use lox::{
+    context::{StringPlusNumber, MAX_CALL_DEPTH},
+    error::{LoxError, Report},
+    explain,
+    interpreter::read_source,
+    lexer::Lexer,
+    metrics::{self, Thresholds},
+    parser::RecursiveDescent,
+    prog::Prog,
+    source::SourceMap,
+    Builder, Interpreter,
 };
 
-use lexer::Lexer;
-use parser::{Parser, RecursiveDescent};
-use resolver::Resolver;
-use scope::Scope;
+mod cli;
+mod repl;
 
-use crate::error::Report;
-
-mod error;
-mod expr;
-mod globals;
-mod lexer;
-mod parser;
-mod prog;
-mod resolver;
-mod scope;
-mod stmt;
-mod val;
-
-fn usage(prog: String) -> ExitCode {
-    eprintln!("Usage: {prog} [script]");
+fn usage(err: &str) -> ExitCode {
+    eprintln!("error: {err}\n\n{}", cli::USAGE);
     ExitCode::from(64)
 }
 
-fn run_file(path: &str) -> ExitCode {
-    let src = match read_to_string(path) {
-        Ok(src) => src,
-        Err(_) => {
-            println!("ERROR: unable to open file: {path}");
-            return ExitCode::from(74);
+fn run_file(path: &str, lox: Builder) -> ExitCode {
+    lox.build()
+        .load(path)
+        .map_or_else(|e| e.exit_code(), |_| ExitCode::SUCCESS)
+}
+
+// Reads a script and hands it to `f`, reporting whatever goes wrong
+fn with_script(path: &str, f: impl FnOnce(&SourceMap) -> Result<(), LoxError>) -> ExitCode {
+    let text = match read_source(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error[lox]: {e}");
+            return e.exit_code();
         }
     };
-    run(&src, Scope::new_global(globals::globals())).unwrap_or(ExitCode::from(0))
+    let src = SourceMap::new(path, &text);
+    match f(&src) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.report(&src);
+            if let Some(summary) = e.summary() {
+                eprintln!("{summary}");
+            }
+            e.exit_code()
+        }
+    }
 }
 
-fn repl() -> ExitCode {
-    let stdin = stdin();
-    let mut input = String::with_capacity(64);
-
-    let env = Scope::new_global(globals::globals());
+// Compiles a script without running it
+fn check(src: &SourceMap, lox: Builder) -> Result<Prog, LoxError> {
+    lox.prelude(false).build().compile(src)
+}
 
-    loop {
-        input.clear();
-        print!("> ");
-        stdout().flush().expect("Error flushing stdout");
-        stdin
-            .read_line(&mut input)
-            .expect("Error reading from stdin");
+// Compiles a script with every warning enabled, and measures its functions
+fn lint(
+    src: &SourceMap,
+    lox: Builder,
+    metrics: Option<Metrics>,
+    limits: &Thresholds,
+) -> Result<(), LoxError> {
+    let funcs = metrics::metrics(&check(src, lox.strict(true))?);
+    match metrics {
+        Some(Metrics::Table) => print!("{}", metrics::table(&funcs)),
+        Some(Metrics::Json) => funcs.iter().for_each(|f| println!("{}", f.to_json())),
+        None => {}
+    }
+    for w in funcs.iter().flat_map(|f| f.check(limits)) {
+        w.report(src);
+    }
+    Ok(())
+}
 
-        if let Some(_e) = run(&input, Rc::clone(&env)) {
-            // return e;
-        }
+fn fmt(src: &SourceMap, jlox: bool) -> Result<(), LoxError> {
+    let (tokens, errors) = Lexer::new(src.text().chars()).jlox(jlox).scan_all();
+    if !errors.is_empty() {
+        return Err(errors.into());
     }
+    let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(tokens.into_iter());
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    print!("{prog}");
+    Ok(())
 }
 
-fn run(src: &str, env: Rc<Scope>) -> Option<ExitCode> {
-    let mut lexer = Lexer::new(src.chars()).peekable();
-    let mut prog = match RecursiveDescent::<prog::Prog>::parse(&mut lexer) {
-        Ok(p) => p,
-        Err(e) => {
-            e.report(src);
-            return Some(ExitCode::from(65));
+fn tokens(src: &SourceMap, jlox: bool) -> Result<(), LoxError> {
+    for t in Lexer::new(src.text().chars()).jlox(jlox) {
+        match t {
+            Ok(t) => println!("{}\t{:?}", t.loc, t.kind),
+            Err(e) => println!("{}\terror: {e}", e.loc()),
         }
-    };
+    }
+    Ok(())
+}
 
-    let mut r = Resolver::new();
-    r.resolve(&mut prog);
+fn ast(src: &SourceMap, lox: Builder) -> Result<(), LoxError> {
+    for s in check(src, lox)?.stmts {
+        println!("{s:#?}");
+    }
+    Ok(())
+}
 
-    match prog.exec(env) {
-        Ok(_) => None,
-        e => {
-            println!("runtime error\t{e:?}");
-            Some(ExitCode::from(70))
+fn explain(code: &str) -> ExitCode {
+    match explain::explain(code) {
+        Some(text) => {
+            println!("{}\n\n{text}", code.to_ascii_uppercase());
+            ExitCode::SUCCESS
         }
+        None => usage(&format!("no diagnostic has the code '{code}'")),
     }
 }
 
+// Scripts run on a thread of their own, with a stack that holds
+// `MAX_CALL_DEPTH` calls even in debug builds, whose frames are largest
+const STACK_SIZE: usize = 64 << 20;
+
 fn main() -> ExitCode {
-    let mut args = env::args();
-    let prog = args.next().expect("Program name must always be present");
-    let args: Vec<_> = args.collect();
-    match &args[..] {
-        [] => repl(),
-        [script] => run_file(script),
-        _ => usage(prog),
+    let lox = thread::Builder::new().stack_size(STACK_SIZE).spawn(run_cli);
+    let lox = lox.expect("the interpreter thread could not be started");
+    lox.join().unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+fn run_cli() -> ExitCode {
+    let cli = match Cli::parse(env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => return usage(&e),
+    };
+    let lox = Interpreter::builder()
+        .strict(cli.strict)
+        .prelude(cli.prelude)
+        .jlox(cli.jlox)
+        .string_plus_number(if cli.coerce_strings {
+            StringPlusNumber::Coerce
+        } else {
+            StringPlusNumber::Error
+        })
+        .strict_math(cli.strict_math)
+        .sandbox(cli.sandbox)
+        .warnings(cli.warnings)
+        .max_call_depth(cli.max_call_depth.unwrap_or(MAX_CALL_DEPTH))
+        .stack_size(STACK_SIZE);
+    match cli.command {
+        Command::Run { script, args } => run_file(&script, lox.args(args)),
+        Command::Repl => repl::repl(lox, cli.max_input.unwrap_or(repl::MAX_INPUT)),
+        Command::Fmt(script) => with_script(&script, |src| fmt(src, cli.jlox)),
+        Command::Lint(script) => {
+            with_script(&script, |src| lint(src, lox, cli.metrics, &cli.limits))
+        }
+        Command::Check(script) => with_script(&script, |src| check(src, lox).map(|_| ())),
+        Command::Tokens(script) => with_script(&script, |src| tokens(src, cli.jlox)),
+        Command::Ast(script) => with_script(&script, |src| ast(src, lox)),
+        Command::Explain(code) => explain(&code),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            ExitCode::SUCCESS
+        }
     }
 }
@@ -0,0 +1,379 @@
+// Per-function metrics of a resolved program, reported by `lox lint`
+//
+// Every function is measured on its own body: a function declared inside
+// it counts as a single statement and is measured separately. `for` loops
+// are measured after they are desugared into a `while`.
+
+use std::collections::HashSet;
+
+use crate::{
+    error::{json_string, Warning},
+    expr::{Expr, Variable},
+    prog::Prog,
+    stmt::{FunDecl, Stmt},
+};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunMetrics {
+    // `Class.method` for methods
+    pub name: String,
+    pub statements: usize,
+    // How deeply `if`s and `while`s nest, an `else if` does not nest deeper
+    pub depth: usize,
+    pub params: usize,
+    // Distinct variables of enclosing functions it refers to
+    pub captures: usize,
+    // 1 + branches + loops + `and`/`or`
+    pub complexity: usize,
+}
+
+// Limits over which `lox lint` warns
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    pub complexity: Option<usize>,
+    pub depth: Option<usize>,
+    pub params: Option<usize>,
+}
+
+#[derive(Default)]
+struct Counter<'a> {
+    statements: usize,
+    depth: usize,
+    max_depth: usize,
+    // Scopes entered since the one of the parameters
+    level: usize,
+    captures: HashSet<&'a str>,
+    complexity: usize,
+    // Functions declared in the body, measured after it
+    nested: Vec<(String, &'a FunDecl)>,
+}
+
+impl<'a> Counter<'a> {
+    fn stmt(&mut self, s: &'a Stmt) {
+        match s {
+            Stmt::Block(stmts) => {
+                self.level += 1;
+                stmts.iter().for_each(|s| self.stmt(s));
+                self.level -= 1;
+                return;
+            }
+            Stmt::Expr(e, _)
+            | Stmt::Print(e, _)
+            | Stmt::Return(Some(e), _)
+            | Stmt::Decl(_, Some(e), _)
+            | Stmt::DeclPattern(_, _, e, _) => self.expr(e),
+            Stmt::Assert(cond, message, _) => {
+                self.expr(cond);
+                if let Some(message) = message {
+                    self.expr(message);
+                }
+            }
+            Stmt::Let(decls) => {
+                for d in decls {
+                    if let Stmt::Decl(_, Some(e), _) | Stmt::DeclPattern(_, _, e, _) = d {
+                        self.expr(e);
+                    }
+                }
+            }
+            Stmt::If(..) => self.branch(s),
+            // Each arm but the default is a branch, like an `else if`
+            Stmt::Match(value, arms, default, _) => {
+                self.complexity += arms.len();
+                self.expr(value);
+                arms.iter().for_each(|(_, arm)| self.nested_stmt(arm));
+                if let Some(arm) = default {
+                    self.nested_stmt(arm);
+                }
+            }
+            Stmt::Loop(body, _, _) => {
+                self.complexity += 1;
+                self.nested_stmt(body);
+            }
+            Stmt::Throw(value, _) => self.expr(value),
+            // The handler is a branch taken on errors
+            Stmt::Try(body, _, handler, _) => {
+                self.complexity += 1;
+                self.nested_stmt(body);
+                self.level += 1;
+                self.nested_stmt(handler);
+                self.level -= 1;
+            }
+            Stmt::ForIn(_, iterable, body, _, _) => {
+                self.complexity += 1;
+                self.expr(iterable);
+                self.level += 1;
+                self.nested_stmt(body);
+                self.level -= 1;
+            }
+            Stmt::While(cond, body, inc, _, _) => {
+                self.complexity += 1;
+                self.expr(cond);
+                self.nested_stmt(body);
+                if let Some(inc) = inc {
+                    self.expr(inc);
+                }
+            }
+            Stmt::Func(decl) => self.nested.push((decl.name.clone(), decl)),
+            Stmt::Class(decl) => {
+                for (_, init) in &decl.fields {
+                    self.expr(init);
+                }
+                for m in decl.statics.iter().chain(&decl.methods) {
+                    self.nested.push((format!("{}.{}", decl.name, m.name), m));
+                }
+            }
+            Stmt::Return(None, _)
+            | Stmt::Decl(_, None, _)
+            | Stmt::Break(_, _)
+            | Stmt::Continue(_, _)
+            | Stmt::Import(_, _)
+            | Stmt::Error(_) => {}
+        }
+        self.statements += 1;
+    }
+
+    // An `if` and the chain of `else if`s that follows it
+    fn branch(&mut self, s: &'a Stmt) {
+        let Stmt::If(cond, then_b, else_b, _) = s else {
+            return self.nested_stmt(s);
+        };
+        self.complexity += 1;
+        self.expr(cond);
+        self.nested_stmt(then_b);
+        if let Some(else_b) = else_b {
+            if matches!(**else_b, Stmt::If(..)) {
+                self.statements += 1;
+            }
+            self.branch(else_b);
+        }
+    }
+
+    fn nested_stmt(&mut self, s: &'a Stmt) {
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+        self.stmt(s);
+        self.depth -= 1;
+    }
+
+    fn expr(&mut self, e: &'a Expr) {
+        use Expr::*;
+        match e {
+            Asgn(var, value) => {
+                self.var(var);
+                self.expr(value);
+            }
+            Var(var) => self.var(var),
+            Call(callee, args, _) => {
+                self.expr(callee);
+                args.iter().for_each(|a| self.expr(a.expr()));
+            }
+            Cond(cond, then_e, else_e) => {
+                self.complexity += 1;
+                self.expr(cond);
+                self.expr(then_e);
+                self.expr(else_e);
+            }
+            And(lhs, rhs) | Or(lhs, rhs) => {
+                self.complexity += 1;
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Seq(lhs, rhs)
+            | Eq(lhs, rhs, _)
+            | Ne(lhs, rhs, _)
+            | Gt(lhs, rhs, _)
+            | Ge(lhs, rhs, _)
+            | Lt(lhs, rhs, _)
+            | Le(lhs, rhs, _)
+            | BitOr(lhs, rhs, _)
+            | BitXor(lhs, rhs, _)
+            | BitAnd(lhs, rhs, _)
+            | Shl(lhs, rhs, _)
+            | Shr(lhs, rhs, _)
+            | Add(lhs, rhs, _)
+            | Sub(lhs, rhs, _)
+            | Mul(lhs, rhs, _)
+            | Div(lhs, rhs, _)
+            | Rem(lhs, rhs, _)
+            | Pow(lhs, rhs, _)
+            | Index(lhs, rhs, _) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Not(arg) | Opp(arg, _) | Get(arg, _, _) => self.expr(arg),
+            SetIndex(obj, idx, value, _) => {
+                self.expr(obj);
+                self.expr(idx);
+                self.expr(value);
+            }
+            Set(obj, _, value, _) => {
+                self.expr(obj);
+                self.expr(value);
+            }
+            List(items) => items.iter().for_each(|i| self.expr(i)),
+            Map(entries, _) => entries.iter().for_each(|(k, v)| {
+                self.expr(k);
+                self.expr(v);
+            }),
+            Lambda(decl) => self.nested.push((decl.name.clone(), decl)),
+            // `this` and `super` are bound around every method
+            Lit(_) | This(_) | Super(..) => {}
+        }
+    }
+
+    // The resolver counts scopes outwards from the use, so anything further
+    // than the parameters belongs to an enclosing function. Globals are not
+    // resolved at all
+    fn var(&mut self, var: &'a Variable) {
+        if var.depth > self.level as isize {
+            self.captures.insert(&var.name);
+        }
+    }
+}
+
+pub fn metrics(prog: &Prog) -> Vec<FunMetrics> {
+    let mut top = Counter::default();
+    prog.stmts.iter().for_each(|s| top.stmt(s));
+    let mut out = Vec::new();
+    let mut pending = top.nested;
+    pending.reverse();
+    while let Some((name, decl)) = pending.pop() {
+        let mut c = Counter::default();
+        decl.defaults.iter().for_each(|e| c.expr(e));
+        c.stmt(&decl.body);
+        out.push(FunMetrics {
+            name,
+            statements: c.statements,
+            depth: c.max_depth,
+            params: decl.params.len() + decl.rest.is_some() as usize,
+            captures: c.captures.len(),
+            complexity: c.complexity + 1,
+        });
+        pending.extend(c.nested.into_iter().rev());
+    }
+    out
+}
+
+impl FunMetrics {
+    pub fn check(&self, limits: &Thresholds) -> Vec<Warning> {
+        [
+            ("complexity", self.complexity, limits.complexity),
+            ("nesting depth", self.depth, limits.depth),
+            ("parameter count", self.params, limits.params),
+        ]
+        .into_iter()
+        .filter_map(|(metric, value, max)| {
+            let max = max.filter(|&max| value > max)?;
+            Some(Warning::Metric {
+                func: self.name.clone(),
+                metric,
+                value,
+                max,
+            })
+        })
+        .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"function": {}, "statements": {}, "depth": {}, "params": {}, "captures": {}, "complexity": {}}}"#,
+            json_string(&self.name),
+            self.statements,
+            self.depth,
+            self.params,
+            self.captures,
+            self.complexity,
+        )
+    }
+}
+
+// An aligned table with a row per function
+pub fn table(funcs: &[FunMetrics]) -> String {
+    const COLUMNS: [&str; 5] = ["statements", "depth", "params", "captures", "complexity"];
+    let width = funcs
+        .iter()
+        .map(|f| f.name.chars().count())
+        .chain(["function".len()])
+        .max()
+        .unwrap_or_default();
+    let mut out = format!("{:width$}", "function");
+    for c in COLUMNS {
+        out += &format!("  {c}");
+    }
+    out.push('\n');
+    for f in funcs {
+        let values = [f.statements, f.depth, f.params, f.captures, f.complexity];
+        out += &format!("{:width$}", f.name);
+        for (c, v) in COLUMNS.iter().zip(values) {
+            out += &format!("  {v:>w$}", w = c.len());
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aligned_table() {
+        let funcs = [
+            FunMetrics {
+                name: "f".to_string(),
+                statements: 3,
+                depth: 0,
+                params: 2,
+                captures: 0,
+                complexity: 1,
+            },
+            FunMetrics {
+                name: "Point.norm".to_string(),
+                statements: 12,
+                depth: 2,
+                params: 0,
+                captures: 1,
+                complexity: 16,
+            },
+        ];
+        assert_eq!(
+            table(&funcs),
+            "\
+function    statements  depth  params  captures  complexity
+f                    3      0       2         0           1
+Point.norm          12      2       0         1          16
+"
+        );
+        assert_eq!(
+            funcs[1].to_json(),
+            r#"{"function": "Point.norm", "statements": 12, "depth": 2, "params": 0, "captures": 1, "complexity": 16}"#
+        );
+    }
+
+    #[test]
+    fn thresholds() {
+        let f = FunMetrics {
+            name: "f".to_string(),
+            statements: 12,
+            depth: 3,
+            params: 2,
+            captures: 0,
+            complexity: 16,
+        };
+        let limits = Thresholds {
+            complexity: Some(15),
+            depth: Some(3),
+            params: None,
+        };
+        assert_eq!(
+            f.check(&limits),
+            vec![Warning::Metric {
+                func: "f".to_string(),
+                metric: "complexity",
+                value: 16,
+                max: 15,
+            }]
+        );
+        assert_eq!(f.check(&Thresholds::default()), vec![]);
+    }
+}
@@ -1,7 +1,6 @@
-use std::iter::Peekable;
-
 use crate::{
-    error::ParserError,
+    error::{LexError, ParserError, Warning},
+    expr::{At, Expr},
     lexer::{
         Loc,
         TokKind::{self, *},
@@ -9,17 +8,73 @@ use crate::{
     },
 };
 
+// Parses `src` with the parser `$parser`, both from a lexer and from the
+// tokens it scans, which must give the same result
+#[cfg(test)]
+macro_rules! parse {
+    ($parser:ty, $src:expr) => {{
+        use crate::{lexer::Lexer, parser::TokenCursor};
+        let src: &str = $src;
+        let streamed = <$parser>::parse_lexed(Lexer::new(src.chars()));
+        let (tokens, errors) = Lexer::new(src.chars()).scan_all();
+        if errors.is_empty() {
+            let collected = <$parser>::parse(&mut TokenCursor::new(&tokens));
+            assert_eq!(collected, streamed, "{src}");
+        }
+        streamed
+    }};
+}
+
+mod pratt;
 mod rec_desc;
+mod stream;
+pub use pratt::Pratt;
 pub use rec_desc::RecursiveDescent;
+pub use stream::{Lookahead, Nesting, TokenCursor, TokenStream};
 
 pub trait Parser<Output> {
-    fn parse(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Output, ParserError>;
+    fn parse(lexer: &mut impl TokenStream) -> Result<Output, ParserError>;
+
+    // Parses straight from a lexer. The parse sees the input end at the
+    // first lexical error, which is reported in place of whatever it found
+    fn parse_lexed(
+        lexer: impl Iterator<Item = Result<Token, LexError>>,
+    ) -> Result<Output, ParserError> {
+        let mut tokens = UntilError { lexer, error: None };
+        let res = Self::parse(&mut Lookahead::new(tokens.by_ref()));
+        match tokens.error {
+            Some(e) => Err(ParserError::Lex(e)),
+            None => res,
+        }
+    }
+}
+
+// The tokens of a lexer up to its first error, which is kept aside
+struct UntilError<I> {
+    lexer: I,
+    error: Option<LexError>,
 }
 
-fn consume(
-    lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    expected: TokKind,
-) -> Result<Token, ParserError> {
+impl<I> Iterator for UntilError<I>
+where
+    I: Iterator<Item = Result<Token, LexError>>,
+{
+    type Item = Token;
+    fn next(&mut self) -> Option<Token> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.lexer.next()? {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
+
+fn consume(lexer: &mut impl TokenStream, expected: TokKind) -> Result<Token, ParserError> {
     if let Some(t) = lexer.next_if(|t| t.kind == expected) {
         Ok(t)
     } else {
@@ -30,15 +85,100 @@ fn consume(
     }
 }
 
-fn consume_ident(
-    lexer: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<(String, Loc), ParserError> {
+// The `;` that ends a statement. When the next token is on a later line,
+// it is most likely the start of the next statement, which is left to be
+// parsed as such
+fn end_stmt(lexer: &mut impl TokenStream) -> Result<Token, ParserError> {
+    let last = lexer.last_loc();
+    match (lexer.peek(), last) {
+        (Some(t), Some(after)) if t.kind != Semicolon && t.loc.row > after.row => {
+            Err(ParserError::MissingSemicolon { after })
+        }
+        _ => consume(lexer, Semicolon),
+    }
+}
+
+// Parses with `f` one construct deeper. Every recursion of the parsers
+// goes through here, so that no input can run them out of stack, and what
+// `f` went deeper by is undone, even if it failed
+fn nested<S: TokenStream, T>(
+    lexer: &mut S,
+    f: impl FnOnce(&mut S) -> Result<T, ParserError>,
+) -> Result<T, ParserError> {
+    let depth = lexer.nesting().depth;
+    deepen(lexer)?;
+    let res = f(lexer);
+    lexer.nesting().depth = depth;
+    res
+}
+
+// Goes one construct deeper, failing at the next token past the deepest
+// allowed. The loops that chain operators and calls also go through here,
+// since each link nests what came before it one deeper in the tree
+fn deepen(lexer: &mut impl TokenStream) -> Result<(), ParserError> {
+    let Nesting { depth, max } = *lexer.nesting();
+    if depth == max {
+        if let Some(t) = lexer.peek() {
+            return Err(ParserError::TooDeep { max, loc: t.loc });
+        }
+    }
+    lexer.nesting().depth += 1;
+    Ok(())
+}
+
+// The comparison `op` makes between `lhs` and `rhs`
+fn comparison(op: &Token, lhs: Expr, rhs: Expr) -> Expr {
+    let (lhs, rhs, at) = (Box::new(lhs), Box::new(rhs), At(op.loc));
+    match op.kind {
+        BangEqual => Expr::Ne(lhs, rhs, at),
+        EqualEqual => Expr::Eq(lhs, rhs, at),
+        Less => Expr::Lt(lhs, rhs, at),
+        Greater => Expr::Gt(lhs, rhs, at),
+        LessEqual => Expr::Le(lhs, rhs, at),
+        GreaterEqual => Expr::Ge(lhs, rhs, at),
+        _ => unreachable!("not a comparison operator"),
+    }
+}
+
+// `a < b < c` compares the boolean `a < b` to `c`, which is hardly ever
+// what was meant. `lhs` is a comparison that `op` follows without
+// parentheses between them. Equalities alone are left alone, since
+// `a == b == c` is how booleans are compared
+fn check_chain(lexer: &mut impl TokenStream, lhs: &Expr, op: &Token, rhs: &Expr) {
+    use Expr::*;
+    let (Eq(_, middle, _)
+    | Ne(_, middle, _)
+    | Lt(_, middle, _)
+    | Gt(_, middle, _)
+    | Le(_, middle, _)
+    | Ge(_, middle, _)) = lhs
+    else {
+        return;
+    };
+    let ordering = matches!(op.kind, Less | Greater | LessEqual | GreaterEqual);
+    if !ordering && !matches!(lhs, Lt(..) | Gt(..) | Le(..) | Ge(..)) {
+        return;
+    }
+    let second = comparison(op, (**middle).clone(), rhs.clone());
+    lexer.warn(Warning::ChainedCmp {
+        expr: comparison(op, lhs.clone(), rhs.clone()),
+        fix: And(Box::new(lhs.clone()), Box::new(second)),
+        loc: op.loc,
+    });
+}
+
+fn consume_ident(lexer: &mut impl TokenStream) -> Result<(String, Loc), ParserError> {
     if let Some(Token {
         kind: Ident(name),
         loc,
     }) = lexer.next_if(|t| matches!(t.kind, Ident(_)))
     {
-        Ok((name, loc))
+        Ok((name.to_string(), loc))
+    } else if let Some(kw) = lexer.next_if(|t| t.kind.is_keyword()) {
+        Err(ParserError::ReservedWord {
+            kw: kw.kind,
+            loc: kw.loc,
+        })
     } else {
         Err(ParserError::Expected {
             exp: Ident(Default::default()),
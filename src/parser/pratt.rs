@@ -0,0 +1,331 @@
+use crate::{
+    error::ParserError,
+    expr::{Arg, At, Expr},
+    lexer::{Loc, TokKind, TokKind::*, Token},
+};
+
+use super::{check_chain, consume, consume_ident, Parser, RecursiveDescent, TokenStream};
+
+// Parses expressions by precedence climbing over a table of binding powers,
+// into the same trees as `RecursiveDescent`. Literals, names and anonymous
+// functions are left to `RecursiveDescent`, whose statement parser reads
+// the bodies of the functions
+pub struct Pratt;
+
+// The binding powers of the infix operators, on their left and on their
+// right. Left associative operators bind tighter on their right, right
+// associative ones on their left
+fn infix_bp(kind: &TokKind) -> Option<(u8, u8)> {
+    Some(match kind {
+        Comma => (1, 2),
+        Equal | PlusEqual | MinusEqual | StarEqual | SlashEqual => (4, 3),
+        Question => (6, 5),
+        And | Or => (7, 8),
+        BangEqual | EqualEqual | Less | Greater | LessEqual | GreaterEqual => (CMP_BP, 10),
+        Pipe => (11, 12),
+        Caret => (13, 14),
+        Amp => (15, 16),
+        LessLess | GreaterGreater => (17, 18),
+        Plus | Minus => (19, 20),
+        Star | Slash | Percent => (21, 22),
+        // Tighter than the unary operators on its left but not on its right,
+        // where any unary operator may start the exponent
+        StarStar => (25, 24),
+        _ => return None,
+    })
+}
+
+// The comparisons, which do not chain
+const CMP_BP: u8 = 9;
+
+// `!` and `-`, which take everything up to the next binary operator but `**`
+const PREFIX_BP: u8 = 23;
+
+// Where commas separate items, such as arguments, each is parsed from here
+const ASGN_BP: u8 = 3;
+
+impl Parser<Expr> for Pratt {
+    fn parse(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        Self::parse_bp(lexer, 0)
+    }
+}
+
+impl Pratt {
+    // An expression of the operators that bind at least as tightly as `min_bp`
+    fn parse_bp(lexer: &mut impl TokenStream, min_bp: u8) -> Result<Expr, ParserError> {
+        let start = lexer.peek().map(|t| t.loc);
+        let mut lhs = Self::parse_prefix(lexer)?;
+        // Comparisons in a row, the operands of the others bind tighter
+        let mut cmps = 0;
+
+        while let Some((l_bp, r_bp)) = lexer.peek().and_then(|t| infix_bp(&t.kind)) {
+            if l_bp < min_bp {
+                break;
+            }
+            let op = lexer.next().expect("Already peeked");
+            let target_loc = start.expect("Already parsed using this token");
+            lhs = match &op.kind {
+                Equal | PlusEqual | MinusEqual | StarEqual | SlashEqual => {
+                    Self::parse_asgn(lexer, lhs, op, target_loc)?
+                }
+                // The middle can be any expression since it is delimited by
+                // `?` and `:`
+                Question => {
+                    let then_e = Self::parse_bp(lexer, ASGN_BP)?;
+                    consume(lexer, Colon)?;
+                    let else_e = Self::parse_bp(lexer, r_bp)?;
+                    Expr::Cond(Box::new(lhs), Box::new(then_e), Box::new(else_e))
+                }
+                kind => {
+                    let rhs = Self::parse_bp(lexer, r_bp)?;
+                    if l_bp == CMP_BP {
+                        if cmps == 1 {
+                            check_chain(lexer, &lhs, &op, &rhs);
+                        }
+                        cmps += 1;
+                    } else {
+                        cmps = 0;
+                    }
+                    let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+                    let at = At(op.loc);
+                    match kind {
+                        Comma => Expr::Seq(lhs, rhs),
+                        And => Expr::And(lhs, rhs),
+                        Or => Expr::Or(lhs, rhs),
+                        BangEqual => Expr::Ne(lhs, rhs, at),
+                        EqualEqual => Expr::Eq(lhs, rhs, at),
+                        Less => Expr::Lt(lhs, rhs, at),
+                        Greater => Expr::Gt(lhs, rhs, at),
+                        LessEqual => Expr::Le(lhs, rhs, at),
+                        GreaterEqual => Expr::Ge(lhs, rhs, at),
+                        Pipe => Expr::BitOr(lhs, rhs, at),
+                        Caret => Expr::BitXor(lhs, rhs, at),
+                        Amp => Expr::BitAnd(lhs, rhs, at),
+                        LessLess => Expr::Shl(lhs, rhs, at),
+                        GreaterGreater => Expr::Shr(lhs, rhs, at),
+                        Plus => Expr::Add(lhs, rhs, at),
+                        Minus => Expr::Sub(lhs, rhs, at),
+                        Star => Expr::Mul(lhs, rhs, at),
+                        Slash => Expr::Div(lhs, rhs, at),
+                        Percent => Expr::Rem(lhs, rhs, at),
+                        StarStar => Expr::Pow(lhs, rhs, at),
+                        _ => unreachable!(),
+                    }
+                }
+            };
+        }
+        Ok(lhs)
+    }
+
+    // `x += e` is `x = x + e`. Only variables can be targets of those, so
+    // that nothing is evaluated twice
+    fn parse_asgn(
+        lexer: &mut impl TokenStream,
+        target: Expr,
+        op: Token,
+        target_loc: Loc,
+    ) -> Result<Expr, ParserError> {
+        // Checked before the value is parsed, which may fail too
+        let valid = match op.kind {
+            Equal => matches!(target, Expr::Var(_) | Expr::Index(..) | Expr::Get(..)),
+            _ => matches!(target, Expr::Var(_)),
+        };
+        if !valid {
+            return Err(ParserError::InvalidAsgn { loc: target_loc });
+        }
+        let value = Box::new(Self::parse_bp(lexer, ASGN_BP)?);
+        Ok(match (op.kind, target) {
+            (Equal, Expr::Var(var)) => Expr::Asgn(var, value),
+            (Equal, Expr::Index(obj, idx, at)) => Expr::SetIndex(obj, idx, value, at),
+            (Equal, Expr::Get(obj, name, at)) => Expr::Set(obj, name, value, at),
+            (kind, Expr::Var(var)) => {
+                let current = Box::new(Expr::Var(var.clone()));
+                let at = At(op.loc);
+                let value = match kind {
+                    PlusEqual => Expr::Add(current, value, at),
+                    MinusEqual => Expr::Sub(current, value, at),
+                    StarEqual => Expr::Mul(current, value, at),
+                    SlashEqual => Expr::Div(current, value, at),
+                    _ => unreachable!(),
+                };
+                Expr::Asgn(var, Box::new(value))
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    fn parse_item(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        Self::parse_bp(lexer, ASGN_BP)
+    }
+
+    fn parse_arg(lexer: &mut impl TokenStream) -> Result<Arg, ParserError> {
+        if lexer.next_if(|t| t.kind == DotDotDot).is_some() {
+            Ok(Arg::Spread(Self::parse_item(lexer)?))
+        } else {
+            Ok(Arg::Positional(Self::parse_item(lexer)?))
+        }
+    }
+
+    // A unary operator or an operand, with the calls, indexing and
+    // properties that follow it
+    fn parse_prefix(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        if let Some(op) = lexer.next_if(|t| matches!(t.kind, Bang | Minus)) {
+            let arg = Box::new(Self::parse_bp(lexer, PREFIX_BP)?);
+            return Ok(match op.kind {
+                Bang => Expr::Not(arg),
+                _ => Expr::Opp(arg, At(op.loc)),
+            });
+        }
+        let Some(open) = lexer.next_if(|t| matches!(t.kind, LParen | LBracket | LBrace)) else {
+            let operand = RecursiveDescent::<Expr>::parse_primary(lexer)?;
+            return Self::parse_postfix(lexer, operand);
+        };
+        let (expr, close) = match open.kind {
+            LParen => (Self::parse_bp(lexer, 0)?, RParen),
+            LBracket => {
+                let items = RecursiveDescent::parse_args(lexer, RBracket, Self::parse_item)?;
+                (Expr::List(items), RBracket)
+            }
+            _ => {
+                let entries = RecursiveDescent::parse_entries(lexer, Self::parse_item)?;
+                (Expr::Map(entries, At(open.loc)), RBrace)
+            }
+        };
+        let closing = lexer.next();
+        if !closing.as_ref().is_some_and(|t| t.kind == close) {
+            return Err(ParserError::Unmatched {
+                open,
+                hint: closing.map(|t| t.loc),
+            });
+        }
+        Self::parse_postfix(lexer, expr)
+    }
+
+    fn parse_postfix(lexer: &mut impl TokenStream, mut callee: Expr) -> Result<Expr, ParserError> {
+        while let Some(open) = lexer.next_if(|t| matches!(t.kind, LParen | LBracket | Dot)) {
+            let close = match open.kind {
+                Dot => {
+                    let (name, loc) = consume_ident(lexer)?;
+                    callee = Expr::Get(Box::new(callee), name, At(loc));
+                    continue;
+                }
+                LParen => {
+                    let args = RecursiveDescent::parse_args(lexer, RParen, Self::parse_arg)?;
+                    callee = Expr::Call(Box::new(callee), args, At(open.loc));
+                    RParen
+                }
+                _ => {
+                    let idx = Self::parse_item(lexer)?;
+                    callee = Expr::Index(Box::new(callee), Box::new(idx), At(open.loc));
+                    RBracket
+                }
+            };
+            if lexer.next_if(|t| t.kind == close).is_none() {
+                return Err(ParserError::Unmatched {
+                    open,
+                    hint: lexer.peek().map(|t| t.loc),
+                });
+            }
+        }
+        Ok(callee)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{error::Warning, lexer::Lexer, parser::Lookahead};
+
+    // Every expression parsed in the tests of `RecursiveDescent`, and a few
+    // more around the operators whose precedence is unusual
+    const FIXTURES: &[&str] = &[
+        "6 + hello + 8 ;",
+        "6 + 3 + 8",
+        "a - b - c * d",
+        "a = 1, b = 2, 3",
+        "f(1, (2, 3))",
+        "a ? b : c ? d : e",
+        "x = c ? 1 : 2",
+        "c ? 1 ; 2",
+        "1 << 2 + 1 == 8",
+        "1 | 2 ^ 3 & 4 >> 5",
+        "2 ** 3 ** 2",
+        "-2 ** -2 * 3",
+        "a += b -= 2",
+        "x *= 3",
+        "x = 1 += 2",
+        "a.b /= 2",
+        "1 + 4 % 3 * 2",
+        "a = b = 3",
+        "x + (3 + 8)",
+        "x = true and 0 != 2 + 6 / -!false",
+        "-!false / 6 + 2 != 0 and true",
+        "2 + - 6 / ",
+        "(2 + 3",
+        "a + b = c",
+        "a + b = )",
+        "f(fn (a, b) { return a; })",
+        "f(fn (x) => fn (y) => x = y, 1)",
+        "fn (x) => { x }",
+        "fn () {}() + 1",
+        "f(a, b,)",
+        "f(,)",
+        "[1, 2,][0] = {\"a\": [], 2: {}}",
+        "a.b.c(d)[e] = f ? g : h",
+        "a ? b = 1 : c",
+        "a ? b : c = d",
+        "a or b and c ? x, y : z",
+        "-a.b(c) ** -d ** 2 * e",
+        "!x ** 2 ** -y",
+        "super.m(this.x, this)",
+        "a[1",
+        "f(1",
+        "1e999 + 0x",
+        "((a))(b)(c)",
+        "x, y = 1, z += 2",
+        "f(...xs, 1, ...[a, b] + c)",
+    ];
+
+    #[test]
+    fn same_trees() {
+        for src in FIXTURES {
+            assert_eq!(
+                parse!(Pratt, src),
+                parse!(RecursiveDescent<Expr>, src),
+                "{src}"
+            );
+        }
+    }
+
+    fn warnings<P: Parser<Expr>>(src: &str) -> Vec<Warning> {
+        let mut lexer = Lookahead::new(Lexer::new(src.chars()).map(Result::unwrap));
+        P::parse(&mut lexer).unwrap();
+        lexer.take_warnings()
+    }
+
+    #[test]
+    fn same_warnings() {
+        for src in [
+            "a < b <= c",
+            "(a < b) == c",
+            "a == b == c != d",
+            "a < b + c < d < e",
+            "a < b and c >= d == e",
+            "x = a > b, c ? d == e : f <= g != h",
+        ] {
+            assert_eq!(
+                warnings::<Pratt>(src),
+                warnings::<RecursiveDescent<Expr>>(src),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn same_locations() {
+        let src = "a = -b[0] + c.d(e) * 2";
+        let rd = parse!(RecursiveDescent<Expr>, src).unwrap();
+        let pratt = parse!(Pratt, src).unwrap();
+        assert_eq!(format!("{pratt:?}"), format!("{rd:?}"));
+    }
+}
@@ -1,20 +1,30 @@
-use std::iter::Peekable;
+use std::{cell::Cell, rc::Rc};
 
 use crate::{
-    error::ParserError,
-    expr::{Expr, Variable},
-    lexer::{TokKind::*, Token},
+    error::{ParserError, Warning},
+    expr::{Arg, At, Expr, Variable},
+    lexer::{Loc, TokKind, TokKind::*, Token},
     prog::Prog,
-    stmt::Stmt,
+    stmt::{ClassDecl, FunDecl, Stmt},
     val::Val,
 };
 
-use super::{consume, consume_ident, Parser};
+use super::{
+    check_chain, comparison, consume, consume_ident, deepen, end_stmt, nested, Lookahead, Parser,
+    TokenStream,
+};
 
 pub struct RecursiveDescent<T>(std::marker::PhantomData<T>);
 
+// What anonymous functions are called in diagnostics. Not an identifier,
+// so no parameter can shadow it
+const LAMBDA_NAME: &str = "<anonymous>";
+
+// The names, the defaults of the last of them and the `...rest` parameter
+type Params = (Vec<(String, At)>, Vec<Expr>, Option<(String, At)>);
+
 impl Parser<Prog> for RecursiveDescent<Prog> {
-    fn parse(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Prog, ParserError> {
+    fn parse(lexer: &mut impl TokenStream) -> Result<Prog, ParserError> {
         let mut program = Prog::new();
 
         while lexer.peek().is_some() {
@@ -24,35 +34,165 @@ impl Parser<Prog> for RecursiveDescent<Prog> {
     }
 }
 
+impl RecursiveDescent<Prog> {
+    // Parses as much of a program as possible. Each top level statement that
+    // fails to parse is replaced by `Stmt::Error` and parsing resumes at the
+    // start of the next one, so that tools get a tree even for broken code
+    // and every independent error is reported at once
+    pub fn parse_with_recovery(
+        tokens: impl Iterator<Item = Token>,
+    ) -> (Prog, Vec<ParserError>, Vec<Warning>) {
+        Self::parse_with_max_nesting(tokens, usize::MAX)
+    }
+
+    // Fails on constructs nested more than `max_nesting` deep, for input
+    // that is not trusted
+    pub fn parse_with_max_nesting(
+        tokens: impl Iterator<Item = Token>,
+        max_nesting: usize,
+    ) -> (Prog, Vec<ParserError>, Vec<Warning>) {
+        // How many braces are open in what has been read, peeked tokens
+        // included. A stray `}` does not make it negative
+        let depth = Cell::new(0usize);
+        let lexer = Lookahead::new(tokens.inspect(|t| match t.kind {
+            LBrace => depth.set(depth.get() + 1),
+            RBrace => depth.set(depth.get().saturating_sub(1)),
+            _ => {}
+        }));
+        let lexer = &mut lexer.max_nesting(max_nesting);
+        let mut program = Prog::new();
+        let mut errors = Vec::new();
+
+        while let Some(start) = lexer.peek().map(|t| t.loc) {
+            match RecursiveDescent::<Stmt>::parse(lexer) {
+                Ok(stmt) => program.stmts.push(stmt),
+                // The next statement is already next, unless the `;` is
+                // missing in a block, whose rest is skipped as usual. The
+                // brace peeked is not read yet
+                Err(e @ ParserError::MissingSemicolon { .. }) => {
+                    let open = match lexer.peek().map(|t| &t.kind) {
+                        Some(LBrace) => depth.get() - 1,
+                        Some(RBrace) => depth.get() + 1,
+                        _ => depth.get(),
+                    };
+                    errors.push(e);
+                    program.stmts.push(Stmt::Error(start));
+                    if open > 0 {
+                        Self::synchronize(lexer, start, &depth);
+                    }
+                }
+                Err(e) => {
+                    errors.push(e);
+                    program.stmts.push(Stmt::Error(start));
+                    Self::synchronize(lexer, start, &depth);
+                }
+            }
+        }
+        (program, errors, lexer.take_warnings())
+    }
+
+    // Skips to what looks like the start of the next top level statement:
+    // past a `;` or a `}` outside of any block, or up to a keyword that
+    // begins a statement there. Skipping the rest of an enclosing block
+    // keeps its closing brace from being reported as another error, and
+    // the `;` after it when the block was part of an expression
+    fn synchronize(lexer: &mut impl TokenStream, start: Loc, depth: &Cell<usize>) {
+        // Always make progress, even if the statement failed on its first token
+        lexer.next_if(|t| t.loc == start);
+        while let Some(t) = lexer.peek() {
+            // Not a brace, so `depth` does not count it
+            if depth.get() == 0
+                && matches!(
+                    t.kind,
+                    Class
+                        | Fn
+                        | Let
+                        | For
+                        | If
+                        | While
+                        | Loop
+                        | Match
+                        | Print
+                        | Return
+                        | Break
+                        | Continue
+                        | Import
+                        | Assert
+                        | Throw
+                        | Try
+                )
+            {
+                return;
+            }
+            // Nothing is peeked anymore, so `depth` is exact
+            match lexer.next() {
+                Some(t) if depth.get() == 0 && t.kind == Semicolon => return,
+                Some(t) if depth.get() == 0 && t.kind == RBrace => {
+                    lexer.next_if(|t| t.kind == Semicolon);
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
 impl Parser<Stmt> for RecursiveDescent<Stmt> {
-    fn parse(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Stmt, ParserError> {
-        match lexer
-            .next_if(|t| matches!(t.kind, Let | Fn))
-            .map(|t| t.kind)
-        {
-            Some(Let) => Self::parse_var_decl(lexer),
-            Some(Fn) => Self::parse_fun_decl(lexer),
-            _ => Self::parse_stmt(lexer),
+    fn parse(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        match lexer.next_if(|t| matches!(t.kind, Let | Fn | Class)) {
+            Some(Token { kind: Let, .. }) => Self::parse_var_decl(lexer),
+            Some(Token { kind: Fn, loc }) if lexer.peek().is_some_and(|t| t.kind == LParen) => {
+                Self::parse_lambda_stmt(lexer, loc)
+            }
+            Some(Token { kind: Fn, .. }) => Self::parse_fun_decl(lexer),
+            Some(_) => Self::parse_class_decl(lexer),
+            None => Self::parse_stmt(lexer),
         }
-        .map_err(|e| {
-            // Self::sync(lexer);
-            e
-        })
     }
 }
 
 impl RecursiveDescent<Stmt> {
-    fn parse_stmt(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Stmt, ParserError> {
-        let stmt = if let Some(tok) =
-            lexer.next_if(|t| matches!(t.kind, LBrace | Print | If | While | For | Return))
-        {
+    fn parse_stmt(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        nested(lexer, Self::parse_stmt_here)
+    }
+
+    fn parse_stmt_here(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        let stmt = if let Some(tok) = lexer.next_if(|t| {
+            matches!(
+                t.kind,
+                LBrace
+                    | Print
+                    | If
+                    | While
+                    | Loop
+                    | For
+                    | Match
+                    | Return
+                    | Break
+                    | Continue
+                    | Import
+                    | Assert
+                    | Throw
+                    | Try
+            )
+        }) {
             match tok.kind {
                 LBrace => {
                     let block = Self::parse_block(lexer)?;
                     consume(lexer, RBrace)?;
                     block
                 }
-                Print => Stmt::Print(RecursiveDescent::parse(lexer)?),
+                Print => Stmt::Print(RecursiveDescent::parse(lexer)?, At(tok.loc)),
+                // The comma ends the condition, so it cannot be a sequence
+                Assert => {
+                    let cond = RecursiveDescent::<Expr>::parse_asgn(lexer)?;
+                    let message = if lexer.next_if(|t| t.kind == Comma).is_some() {
+                        Some(RecursiveDescent::<Expr>::parse_asgn(lexer)?)
+                    } else {
+                        None
+                    };
+                    Stmt::Assert(cond, message, At(tok.loc))
+                }
                 If => {
                     consume(lexer, LParen)?;
                     let cond = RecursiveDescent::parse(lexer)?;
@@ -63,32 +203,70 @@ impl RecursiveDescent<Stmt> {
                     } else {
                         None
                     };
-                    Stmt::If(cond, Box::new(body), otherwise.map(Box::new))
+                    Stmt::If(cond, Box::new(body), otherwise.map(Box::new), At(tok.loc))
                 }
-                While => {
-                    consume(lexer, LParen)?;
-                    let cond = RecursiveDescent::parse(lexer)?;
-                    consume(lexer, RParen)?;
-                    let body = Self::parse_stmt(lexer)?;
-                    Stmt::While(cond, Box::new(body))
-                }
-                For => Self::parse_for(lexer)?,
+                While => Self::parse_while(lexer, None, tok.loc)?,
+                Loop => Self::parse_loop(lexer, None, tok.loc)?,
+                Match => Self::parse_match(lexer, tok.loc)?,
+                For => Self::parse_for(lexer, None, tok.loc)?,
                 Return => {
-                    if lexer.peek().is_some_and(|t| t.kind == Semicolon) {
-                        Stmt::Return(None)
+                    let value = if lexer.peek().is_some_and(|t| t.kind == Semicolon) {
+                        None
                     } else {
-                        Stmt::Return(Some(RecursiveDescent::parse(lexer)?))
-                    }
+                        Some(RecursiveDescent::parse(lexer)?)
+                    };
+                    Stmt::Return(value, At(tok.loc))
                 }
+                Throw => Stmt::Throw(RecursiveDescent::parse(lexer)?, At(tok.loc)),
+                Try => Self::parse_try(lexer)?,
+                Break => Stmt::Break(Self::parse_label(lexer)?, At(tok.loc)),
+                Continue => Stmt::Continue(Self::parse_label(lexer)?, At(tok.loc)),
+                Import => match lexer.next_if(|t| matches!(t.kind, Str(_))) {
+                    Some(Token {
+                        kind: Str(path), ..
+                    }) => Stmt::Import(path.to_string(), At(tok.loc)),
+                    _ => {
+                        return Err(ParserError::Expected {
+                            exp: Str(Default::default()),
+                            fnd: lexer.peek().cloned(),
+                        })
+                    }
+                },
                 _ => unreachable!(),
             }
         } else {
-            Stmt::Expr(RecursiveDescent::parse(lexer)?)
+            let start = lexer.peek().ok_or(ParserError::Eof)?.loc;
+            match RecursiveDescent::parse(lexer)? {
+                // A label, which is only allowed on loops
+                Expr::Var(var) if lexer.next_if(|t| t.kind == Colon).is_some() => {
+                    let label = Some(var.name.to_string());
+                    match lexer.next_if(|t| matches!(t.kind, While | Loop | For)) {
+                        Some(Token { kind: While, loc }) => Self::parse_while(lexer, label, loc)?,
+                        Some(Token { kind: Loop, loc }) => Self::parse_loop(lexer, label, loc)?,
+                        Some(Token { loc, .. }) => Self::parse_for(lexer, label, loc)?,
+                        None => {
+                            return Err(ParserError::Expected {
+                                exp: While,
+                                fnd: lexer.peek().cloned(),
+                            })
+                        }
+                    }
+                }
+                expr => Stmt::Expr(expr, At(start)),
+            }
         };
 
         match stmt {
-            Stmt::Expr(_) | Stmt::Decl(_, _) | Stmt::Print(_) | Stmt::Return(_) => {
-                consume(lexer, Semicolon)?;
+            Stmt::Expr(..)
+            | Stmt::Decl(..)
+            | Stmt::Print(..)
+            | Stmt::Return(_, _)
+            | Stmt::Break(_, _)
+            | Stmt::Continue(_, _)
+            | Stmt::Import(_, _)
+            | Stmt::Assert(..)
+            | Stmt::Throw(..) => {
+                end_stmt(lexer)?;
             }
             _ => {}
         }
@@ -96,30 +274,129 @@ impl RecursiveDescent<Stmt> {
         Ok(stmt)
     }
 
-    fn parse_var_decl(
-        lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    ) -> Result<Stmt, ParserError> {
-        let (name, _) = consume_ident(lexer)?;
+    fn parse_var_decl(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        let mut decls = Vec::new();
+        loop {
+            if let Some(open) = lexer.next_if(|t| t.kind == LBracket) {
+                decls.push(Self::parse_list_pattern(lexer, open.loc)?);
+                if lexer.next_if(|t| t.kind == Comma).is_none() {
+                    break;
+                }
+                continue;
+            }
+            let (name, loc) = consume_ident(lexer)?;
+            // Like an argument, so that in `let a = 1, b = 2;` the comma
+            // starts another binding rather than an assignment to `b`
+            let init = if lexer.next_if(|t| t.kind == Equal).is_some() {
+                Some(RecursiveDescent::<Expr>::parse_asgn(lexer)?)
+            } else {
+                None
+            };
+            decls.push(Stmt::Decl(name, init, At(loc)));
+            if lexer.next_if(|t| t.kind == Comma).is_none() {
+                break;
+            }
+        }
+
+        end_stmt(lexer)?;
 
-        let init = if lexer.next_if(|t| t.kind == Equal).is_some() {
-            Some(RecursiveDescent::<Expr>::parse(lexer)?)
+        Ok(if decls.len() == 1 {
+            decls.pop().unwrap()
+        } else {
+            Stmt::Let(decls)
+        })
+    }
+
+    // `[a, b, ...rest] = xs`, after the `[`. A pattern always has a value,
+    // and its rest comes last
+    fn parse_list_pattern(lexer: &mut impl TokenStream, loc: Loc) -> Result<Stmt, ParserError> {
+        let mut names = Vec::new();
+        let mut rest = None;
+        while lexer.peek().is_some_and(|t| t.kind != RBracket) {
+            if lexer.next_if(|t| t.kind == DotDotDot).is_some() {
+                rest = Some(consume_ident(lexer)?.0);
+                break;
+            }
+            names.push(consume_ident(lexer)?.0);
+            if lexer.next_if(|t| t.kind == Comma).is_none() {
+                break;
+            }
+        }
+        consume(lexer, RBracket)?;
+        consume(lexer, Equal)?;
+        let init = RecursiveDescent::<Expr>::parse_asgn(lexer)?;
+        Ok(Stmt::DeclPattern(names, rest, init, At(loc)))
+    }
+
+    fn parse_fun_decl(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        Ok(Stmt::Func(Rc::new(Self::parse_function(lexer)?)))
+    }
+
+    // An expression statement that starts with an anonymous function. The
+    // `fn` is already read, so only calls, indexing and properties can
+    // follow the function here. Anything else needs the statement to be
+    // wrapped in parentheses
+    fn parse_lambda_stmt(lexer: &mut impl TokenStream, loc: Loc) -> Result<Stmt, ParserError> {
+        let lambda = RecursiveDescent::<Expr>::parse_lambda(lexer, loc)?;
+        let expr = RecursiveDescent::<Expr>::parse_postfix(lexer, lambda)?;
+        end_stmt(lexer)?;
+        Ok(Stmt::Expr(expr, At(loc)))
+    }
+
+    // Methods are written like functions, without the `fn` keyword
+    fn parse_class_decl(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        let (name, loc) = consume_ident(lexer)?;
+
+        let superclass = if lexer.next_if(|t| t.kind == Less).is_some() {
+            let (name, loc) = consume_ident(lexer)?;
+            Some(Variable::at(name, loc))
         } else {
             None
         };
 
-        consume(lexer, Semicolon)?;
+        consume(lexer, LBrace)?;
 
-        Ok(Stmt::Decl(name, init))
+        let (mut methods, mut statics, mut fields) = (Vec::new(), Vec::new(), Vec::new());
+        while lexer.peek().is_some_and(|t| t.kind != RBrace) {
+            if lexer.next_if(|t| t.kind == Static).is_none() {
+                methods.push(Rc::new(Self::parse_function(lexer)?));
+                continue;
+            }
+            let (name, loc) = consume_ident(lexer)?;
+            if lexer.next_if(|t| t.kind == Equal).is_some() {
+                fields.push((name, RecursiveDescent::<Expr>::parse_asgn(lexer)?));
+                consume(lexer, Semicolon)?;
+            } else {
+                statics.push(Rc::new(Self::parse_function_rest(lexer, name, loc)?));
+            }
+        }
+
+        consume(lexer, RBrace)?;
+
+        Ok(Stmt::Class(ClassDecl {
+            name,
+            superclass,
+            methods,
+            statics,
+            fields,
+            loc: At(loc),
+        }))
     }
 
-    fn parse_fun_decl(
-        lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    ) -> Result<Stmt, ParserError> {
-        let (name, _) = consume_ident(lexer)?;
+    fn parse_function(lexer: &mut impl TokenStream) -> Result<FunDecl, ParserError> {
+        let (name, loc) = consume_ident(lexer)?;
+        Self::parse_function_rest(lexer, name, loc)
+    }
 
+    // Everything after the name of a function
+    fn parse_function_rest(
+        lexer: &mut impl TokenStream,
+        name: String,
+        loc: Loc,
+    ) -> Result<FunDecl, ParserError> {
         consume(lexer, LParen)?;
 
-        let params = Self::parse_params(lexer)?;
+        let (params, defaults, rest) = Self::parse_params(lexer, &name)?;
 
         consume(lexer, RParen)?;
 
@@ -129,29 +406,67 @@ impl RecursiveDescent<Stmt> {
 
         consume(lexer, RBrace)?;
 
-        Ok(Stmt::Func(name, params, Box::new(body)))
+        Ok(FunDecl {
+            name,
+            params,
+            defaults,
+            rest,
+            body,
+            loc: At(loc),
+            doc: None,
+            source: Rc::default(),
+        })
     }
 
-    fn parse_params(
-        lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    ) -> Result<Vec<String>, ParserError> {
+    // Parameters with a default must all come after those without one
+    fn parse_params(lexer: &mut impl TokenStream, fn_name: &str) -> Result<Params, ParserError> {
         let mut params = Vec::new();
+        let mut defaults = Vec::new();
+        let mut rest = None;
         if lexer.peek().is_some_and(|t| t.kind != RParen) {
-            let (name, _) = consume_ident(lexer)?;
-            params.push(name);
-
-            while lexer.next_if(|t| t.kind == Comma).is_some() {
+            loop {
+                let is_rest = lexer.next_if(|t| t.kind == DotDotDot).is_some();
                 let (name, loc) = consume_ident(lexer)?;
-                params.push(name);
+                if params.iter().any(|(p, _)| *p == name) {
+                    return Err(ParserError::DuplicateParam {
+                        name,
+                        function: fn_name.to_string(),
+                        loc,
+                    });
+                }
+                if name == fn_name {
+                    return Err(ParserError::ParamShadowsFn { name, loc });
+                }
+                if is_rest {
+                    if lexer.next_if(|t| t.kind == Comma).is_some()
+                        && lexer.peek().is_some_and(|t| t.kind != RParen)
+                    {
+                        return Err(ParserError::RestNotLast { name, loc });
+                    }
+                    rest = Some((name, At(loc)));
+                    break;
+                }
+                if lexer.next_if(|t| t.kind == Equal).is_some() {
+                    defaults.push(RecursiveDescent::<Expr>::parse_asgn(lexer)?);
+                } else if !defaults.is_empty() {
+                    return Err(ParserError::MissingDefault { name, loc });
+                }
+                params.push((name, At(loc)));
                 if params.len() > 255 {
                     return Err(ParserError::TooManyParams { loc });
                 }
+                // A trailing comma is allowed before the `)`
+                if lexer.next_if(|t| t.kind == Comma).is_none()
+                    || lexer.peek().is_some_and(|t| t.kind == RParen)
+                {
+                    break;
+                }
             }
         }
-        Ok(params)
+        Ok((params, defaults, rest))
     }
 
-    fn parse_block(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Stmt, ParserError> {
+    fn parse_block(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
         let mut block = Vec::new();
         while lexer.peek().is_some_and(|t| t.kind != RBrace) {
             block.push(RecursiveDescent::parse(lexer)?);
@@ -159,7 +474,101 @@ impl RecursiveDescent<Stmt> {
         Ok(Stmt::Block(block))
     }
 
-    fn parse_for(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Stmt, ParserError> {
+    // `try { ... } catch (e) { ... }`, after the `try`
+    fn parse_try(lexer: &mut impl TokenStream) -> Result<Stmt, ParserError> {
+        consume(lexer, LBrace)?;
+        let body = Self::parse_block(lexer)?;
+        consume(lexer, RBrace)?;
+        consume(lexer, Catch)?;
+        consume(lexer, LParen)?;
+        let (name, loc) = consume_ident(lexer)?;
+        consume(lexer, RParen)?;
+        consume(lexer, LBrace)?;
+        let handler = Self::parse_block(lexer)?;
+        consume(lexer, RBrace)?;
+        Ok(Stmt::Try(Box::new(body), name, Box::new(handler), At(loc)))
+    }
+
+    // The label a `break` or `continue` jumps to, if any
+    fn parse_label(lexer: &mut impl TokenStream) -> Result<Option<String>, ParserError> {
+        if lexer.peek().is_some_and(|t| matches!(t.kind, Ident(_))) {
+            Ok(Some(consume_ident(lexer)?.0))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_while(
+        lexer: &mut impl TokenStream,
+        label: Option<String>,
+        loc: Loc,
+    ) -> Result<Stmt, ParserError> {
+        consume(lexer, LParen)?;
+        let cond = RecursiveDescent::parse(lexer)?;
+        consume(lexer, RParen)?;
+        let body = Self::parse_stmt(lexer)?;
+        Ok(Stmt::While(cond, Box::new(body), None, label, At(loc)))
+    }
+
+    // The body must be a block, a `loop` without one would read like a
+    // statement that runs once
+    fn parse_loop(
+        lexer: &mut impl TokenStream,
+        label: Option<String>,
+        loc: Loc,
+    ) -> Result<Stmt, ParserError> {
+        consume(lexer, LBrace)?;
+        let body = Self::parse_block(lexer)?;
+        consume(lexer, RBrace)?;
+        Ok(Stmt::Loop(Box::new(body), label, At(loc)))
+    }
+
+    // Each arm is a statement, ended by its own `;` or `}`, and may be
+    // followed by a comma. The `_` arm must be the last
+    fn parse_match(lexer: &mut impl TokenStream, loc: Loc) -> Result<Stmt, ParserError> {
+        consume(lexer, LParen)?;
+        let value = RecursiveDescent::parse(lexer)?;
+        consume(lexer, RParen)?;
+        consume(lexer, LBrace)?;
+        let (mut arms, mut default) = (Vec::new(), None);
+        while default.is_none() && lexer.peek().is_some_and(|t| t.kind != RBrace) {
+            let pattern = Self::parse_pattern(lexer)?;
+            consume(lexer, Arrow)?;
+            let arm = Self::parse_stmt(lexer)?;
+            lexer.next_if(|t| t.kind == Comma);
+            match pattern {
+                Some(pattern) => arms.push((pattern, arm)),
+                None => default = Some(Box::new(arm)),
+            }
+        }
+        consume(lexer, RBrace)?;
+        Ok(Stmt::Match(value, arms, default, At(loc)))
+    }
+
+    // A literal, or `None` for `_`
+    fn parse_pattern(lexer: &mut impl TokenStream) -> Result<Option<Val>, ParserError> {
+        let loc = lexer.peek().ok_or(ParserError::Eof)?.loc;
+        let negative = lexer.next_if(|t| t.kind == Minus).is_some();
+        let tok = lexer.next().ok_or(ParserError::Eof)?;
+        match tok.kind {
+            Number(x) if negative => Ok(Some(Val::Number(-x))),
+            Number(x) => Ok(Some(Val::Number(x))),
+            _ if negative => Err(ParserError::InvalidPattern { loc }),
+            Str(s) => Ok(Some(Val::String(s))),
+            True => Ok(Some(Val::Boolean(true))),
+            False => Ok(Some(Val::Boolean(false))),
+            Nil => Ok(Some(Val::Nil)),
+            Ident(name) if &*name == "_" => Ok(None),
+            BadNumber(text) => Err(ParserError::BadNumber { text, loc }),
+            _ => Err(ParserError::InvalidPattern { loc }),
+        }
+    }
+
+    fn parse_for(
+        lexer: &mut impl TokenStream,
+        label: Option<String>,
+        loc: Loc,
+    ) -> Result<Stmt, ParserError> {
         consume(lexer, LParen)?;
         // parse init
         let init = if let Some(t) = lexer.next_if(|t| matches!(t.kind, Let | Semicolon)) {
@@ -169,39 +578,39 @@ impl RecursiveDescent<Stmt> {
                 _ => unreachable!(),
             }
         } else {
-            let expr = RecursiveDescent::parse(lexer)?;
+            let start = lexer.peek().ok_or(ParserError::Eof)?.loc;
+            let expr = RecursiveDescent::<Expr>::parse_asgn(lexer)?;
+            // `for (x in xs)` starts like an initializer that is a variable
+            if let Expr::Var(var) = &expr {
+                if lexer.next_if(|t| t.kind == In).is_some() {
+                    let iterable = RecursiveDescent::<Expr>::parse(lexer)?;
+                    consume(lexer, RParen)?;
+                    let body = Self::parse_stmt(lexer)?;
+                    let name = var.name.to_string();
+                    return Ok(Stmt::ForIn(name, iterable, Box::new(body), label, At(loc)));
+                }
+            }
             consume(lexer, Semicolon)?;
-            Some(Stmt::Expr(expr))
+            Some(Stmt::Expr(expr, At(start)))
         };
         // parse cond
         let cond = if lexer.peek().is_some_and(|t| t.kind == Semicolon) {
             Expr::Lit(Val::Boolean(true))
         } else {
-            RecursiveDescent::parse(lexer)?
+            RecursiveDescent::<Expr>::parse_asgn(lexer)?
         };
         consume(lexer, Semicolon)?;
         // parse increment
         let increment = if lexer.peek().is_some_and(|t| t.kind == RParen) {
             None
         } else {
-            Some(RecursiveDescent::parse(lexer)?)
+            Some(RecursiveDescent::<Expr>::parse_asgn(lexer)?)
         };
         consume(lexer, RParen)?;
         // parse body
         let body = Self::parse_stmt(lexer)?;
         // assemble loop
-        let body = if let Some(inc) = increment {
-            match body {
-                Stmt::Block(mut vec) => {
-                    vec.push(Stmt::Expr(inc));
-                    Stmt::Block(vec)
-                }
-                stmt => Stmt::Block(vec![stmt, Stmt::Expr(inc)]),
-            }
-        } else {
-            body
-        };
-        let desugared_loop = Stmt::While(cond, Box::new(body));
+        let desugared_loop = Stmt::While(cond, Box::new(body), increment, label, At(loc));
         Ok(if let Some(init) = init {
             Stmt::Block(vec![init, desugared_loop])
         } else {
@@ -211,49 +620,124 @@ impl RecursiveDescent<Stmt> {
 }
 
 /*
-* expr    -> asgn
-* asgn    -> IDENT "=" asgn | logic
+* expr    -> asgn ("," asgn)*
+* asgn    -> (IDENT | call "[" asgn "]" | call "." IDENT) "=" asgn
+*          | IDENT ("+=" | "-=" | "*=" | "/=") asgn | cond
+* cond    -> logic ("?" asgn ":" cond)?
 * logic   -> cmp | logic ("and" | "or") cmp
-* cmp     -> term | cmp ("==" | "!=" | "<" | "<=" | ">" | ">=") term
+* cmp     -> bitor | cmp ("==" | "!=" | "<" | "<=" | ">" | ">=") bitor
+* bitor   -> bitxor ("|" bitxor)*
+* bitxor  -> bitand ("^" bitand)*
+* bitand  -> shift ("&" shift)*
+* shift   -> term (("<<" | ">>") term)*
 * term    -> factor | term ("+" | "-") factor
-* factor  -> unary | factor ("*" | "/") unary
-* unary   -> ("!" | "-") unary | call
-* call    -> (call | primary) "(" args ")"
-* primary -> TRUE | FALSE | NIL | NUMBER | STRING | IDENT | "(" expr ")"
+* factor  -> unary | factor ("*" | "/" | "%") unary
+* unary   -> ("!" | "-") unary | pow
+* pow     -> call ("**" unary)?
+* call    -> (call | primary) ("(" args ")" | "[" asgn "]" | "." IDENT)
+* primary -> TRUE | FALSE | NIL | NUMBER | STRING | IDENT | THIS | "(" expr ")"
+*          | SUPER "." IDENT | FN "(" params ")" ("{" decl* "}" | "=>" asgn)
+*          | "[" args "]" | "{" entries "}"
 *
-* args -> expr ("," expr)* | EPSILON
+* params  -> IDENT ("," IDENT)* | EPSILON
+* args    -> asgn ("," asgn)* | EPSILON
+* entries -> asgn ":" asgn ("," asgn ":" asgn)* | EPSILON
 */
-
 impl Parser<Expr> for RecursiveDescent<Expr> {
-    fn parse(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
-        Self::parse_asgn(lexer)
+    fn parse(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        Self::parse_seq(lexer)
     }
 }
 
 impl RecursiveDescent<Expr> {
-    fn parse_asgn(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
+    fn parse_seq(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let mut lhs = Self::parse_asgn(lexer)?;
+
+        while lexer.next_if(|t| t.kind == Comma).is_some() {
+            let rhs = Self::parse_asgn(lexer)?;
+            lhs = Expr::Seq(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // Where commas separate items, such as arguments, each is parsed from
+    // here
+    fn parse_asgn(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        nested(lexer, Self::parse_asgn_here)
+    }
+
+    fn parse_asgn_here(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
         let target_loc = lexer.peek().map(|t| t.loc);
-        let target = Self::parse_log(lexer)?;
+        let target = Self::parse_cond(lexer)?;
+        let invalid = || ParserError::InvalidAsgn {
+            loc: target_loc.expect("Already parsed using this token"),
+        };
+
+        // `x += e` is `x = x + e`. Only variables can be targets, so that
+        // nothing is evaluated twice
+        if let Some(op) =
+            lexer.next_if(|t| matches!(t.kind, PlusEqual | MinusEqual | StarEqual | SlashEqual))
+        {
+            let Expr::Var(var) = target else {
+                return Err(invalid());
+            };
+            let current = Box::new(Expr::Var(var.clone()));
+            let value = Box::new(Self::parse_asgn(lexer)?);
+            let value = match op.kind {
+                PlusEqual => Expr::Add(current, value, At(op.loc)),
+                MinusEqual => Expr::Sub(current, value, At(op.loc)),
+                StarEqual => Expr::Mul(current, value, At(op.loc)),
+                SlashEqual => Expr::Div(current, value, At(op.loc)),
+                _ => unreachable!(),
+            };
+            return Ok(Expr::Asgn(var, Box::new(value)));
+        }
 
         if lexer.next_if(|t| t.kind == Equal).is_some() {
-            if let Expr::Var(var) = target {
-                let value = Self::parse_asgn(lexer)?;
-                Ok(Expr::Asgn(var, Box::new(value)))
-            } else {
+            match target {
+                Expr::Var(var) => {
+                    let value = Self::parse_asgn(lexer)?;
+                    Ok(Expr::Asgn(var, Box::new(value)))
+                }
+                Expr::Index(obj, idx, at) => {
+                    let value = Self::parse_asgn(lexer)?;
+                    Ok(Expr::SetIndex(obj, idx, Box::new(value), at))
+                }
+                Expr::Get(obj, name, at) => {
+                    let value = Self::parse_asgn(lexer)?;
+                    Ok(Expr::Set(obj, name, Box::new(value), at))
+                }
                 // println!("Invalid asignment target");
-                Err(ParserError::InvalidAsgn {
-                    loc: target_loc.expect("Already parsed using this token"),
-                })
+                _ => Err(invalid()),
             }
         } else {
             Ok(target)
         }
     }
 
-    fn parse_log(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
+    // Right associative, and the middle can be any expression since it is
+    // delimited by `?` and `:`
+    fn parse_cond(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let cond = Self::parse_log(lexer)?;
+        if lexer.next_if(|t| t.kind == Question).is_none() {
+            return Ok(cond);
+        }
+        let then_e = Self::parse_asgn(lexer)?;
+        consume(lexer, Colon)?;
+        let else_e = nested(lexer, Self::parse_cond)?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then_e),
+            Box::new(else_e),
+        ))
+    }
+
+    fn parse_log(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
         let mut lhs = Self::parse_cmp(lexer)?;
 
+        let depth = lexer.nesting().depth;
         while let Some(op) = lexer.next_if(|t| matches!(t.kind, And | Or)) {
+            deepen(lexer)?;
             let rhs = Self::parse_cmp(lexer)?;
 
             lhs = match op.kind {
@@ -262,85 +746,179 @@ impl RecursiveDescent<Expr> {
                 _ => unreachable!(),
             }
         }
+        lexer.nesting().depth = depth;
         Ok(lhs)
     }
 
-    fn parse_cmp(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
-        let mut lhs = Self::parse_term(lexer)?;
+    fn parse_cmp(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let mut lhs = Self::parse_bit_or(lexer)?;
+        let mut ops = 0;
 
+        let depth = lexer.nesting().depth;
         while let Some(op) = lexer.next_if(|t| {
             matches!(
                 t.kind,
                 BangEqual | EqualEqual | Less | Greater | LessEqual | GreaterEqual
             )
         }) {
+            deepen(lexer)?;
+            let rhs = Self::parse_bit_or(lexer)?;
+            // Once for the whole chain, at its second operator
+            if ops == 1 {
+                check_chain(lexer, &lhs, &op, &rhs);
+            }
+            ops += 1;
+            lhs = comparison(&op, lhs, rhs);
+        }
+        lexer.nesting().depth = depth;
+        Ok(lhs)
+    }
+
+    fn parse_bit_or(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let mut lhs = Self::parse_bit_xor(lexer)?;
+
+        let depth = lexer.nesting().depth;
+        while let Some(op) = lexer.next_if(|t| t.kind == Pipe) {
+            deepen(lexer)?;
+            let rhs = Self::parse_bit_xor(lexer)?;
+            lhs = Expr::BitOr(Box::new(lhs), Box::new(rhs), At(op.loc));
+        }
+        lexer.nesting().depth = depth;
+        Ok(lhs)
+    }
+
+    fn parse_bit_xor(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let mut lhs = Self::parse_bit_and(lexer)?;
+
+        let depth = lexer.nesting().depth;
+        while let Some(op) = lexer.next_if(|t| t.kind == Caret) {
+            deepen(lexer)?;
+            let rhs = Self::parse_bit_and(lexer)?;
+            lhs = Expr::BitXor(Box::new(lhs), Box::new(rhs), At(op.loc));
+        }
+        lexer.nesting().depth = depth;
+        Ok(lhs)
+    }
+
+    fn parse_bit_and(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let mut lhs = Self::parse_shift(lexer)?;
+
+        let depth = lexer.nesting().depth;
+        while let Some(op) = lexer.next_if(|t| t.kind == Amp) {
+            deepen(lexer)?;
+            let rhs = Self::parse_shift(lexer)?;
+            lhs = Expr::BitAnd(Box::new(lhs), Box::new(rhs), At(op.loc));
+        }
+        lexer.nesting().depth = depth;
+        Ok(lhs)
+    }
+
+    fn parse_shift(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let mut lhs = Self::parse_term(lexer)?;
+
+        let depth = lexer.nesting().depth;
+        while let Some(op) = lexer.next_if(|t| matches!(t.kind, LessLess | GreaterGreater)) {
+            deepen(lexer)?;
             let rhs = Self::parse_term(lexer)?;
 
             lhs = match op.kind {
-                BangEqual => Expr::Ne(Box::new(lhs), Box::new(rhs)),
-                EqualEqual => Expr::Eq(Box::new(lhs), Box::new(rhs)),
-                Less => Expr::Lt(Box::new(lhs), Box::new(rhs)),
-                Greater => Expr::Gt(Box::new(lhs), Box::new(rhs)),
-                LessEqual => Expr::Le(Box::new(lhs), Box::new(rhs)),
-                GreaterEqual => Expr::Ge(Box::new(lhs), Box::new(rhs)),
+                LessLess => Expr::Shl(Box::new(lhs), Box::new(rhs), At(op.loc)),
+                GreaterGreater => Expr::Shr(Box::new(lhs), Box::new(rhs), At(op.loc)),
                 _ => unreachable!(),
             }
         }
+        lexer.nesting().depth = depth;
         Ok(lhs)
     }
 
-    fn parse_term(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
+    fn parse_term(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
         let mut lhs = Self::parse_factor(lexer)?;
 
+        let depth = lexer.nesting().depth;
         while let Some(op) = lexer.next_if(|t| matches!(t.kind, Plus | Minus)) {
+            deepen(lexer)?;
             let rhs = Self::parse_factor(lexer)?;
 
             lhs = match op.kind {
-                Plus => Expr::Add(Box::new(lhs), Box::new(rhs)),
-                Minus => Expr::Sub(Box::new(lhs), Box::new(rhs)),
+                Plus => Expr::Add(Box::new(lhs), Box::new(rhs), At(op.loc)),
+                Minus => Expr::Sub(Box::new(lhs), Box::new(rhs), At(op.loc)),
                 _ => unreachable!(),
             }
         }
+        lexer.nesting().depth = depth;
         Ok(lhs)
     }
 
-    fn parse_factor(
-        lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    ) -> Result<Expr, ParserError> {
+    fn parse_factor(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
         let mut lhs = Self::parse_unary(lexer)?;
 
-        while let Some(op) = lexer.next_if(|t| matches!(t.kind, Star | Slash)) {
+        let depth = lexer.nesting().depth;
+        while let Some(op) = lexer.next_if(|t| matches!(t.kind, Star | Slash | Percent)) {
+            deepen(lexer)?;
             let rhs = Self::parse_unary(lexer)?;
 
             lhs = match op.kind {
-                Star => Expr::Mul(Box::new(lhs), Box::new(rhs)),
-                Slash => Expr::Div(Box::new(lhs), Box::new(rhs)),
+                Star => Expr::Mul(Box::new(lhs), Box::new(rhs), At(op.loc)),
+                Slash => Expr::Div(Box::new(lhs), Box::new(rhs), At(op.loc)),
+                Percent => Expr::Rem(Box::new(lhs), Box::new(rhs), At(op.loc)),
                 _ => unreachable!(),
             }
         }
+        lexer.nesting().depth = depth;
         Ok(lhs)
     }
 
-    fn parse_unary(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
+    fn parse_unary(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
         if let Some(op) = lexer.next_if(|t| matches!(t.kind, Bang | Minus)) {
-            let arg = Self::parse_unary(lexer)?;
+            let arg = nested(lexer, Self::parse_unary)?;
 
             Ok(match op.kind {
                 Bang => Expr::Not(Box::new(arg)),
-                Minus => Expr::Opp(Box::new(arg)),
+                Minus => Expr::Opp(Box::new(arg), At(op.loc)),
                 _ => unreachable!(),
             })
         } else {
-            Self::parse_call(lexer)
+            Self::parse_pow(lexer)
         }
     }
 
-    fn parse_call(lexer: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Expr, ParserError> {
-        let mut callee = Self::parse_primary(lexer)?;
-        while let Some(open) = lexer.next_if(|t| matches!(t.kind, LParen)) {
-            let args = Self::parse_args(lexer)?;
-            callee = Expr::Call(Box::new(callee), args);
-            if lexer.next_if(|t| matches!(t.kind, RParen)).is_none() {
+    // Right associative, and tighter than the unary operators on its left
+    // but not on its right, so `-2 ** -2` is `-(2 ** (-2))`
+    fn parse_pow(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let base = Self::parse_call(lexer)?;
+        if let Some(op) = lexer.next_if(|t| t.kind == StarStar) {
+            let exp = nested(lexer, Self::parse_unary)?;
+            Ok(Expr::Pow(Box::new(base), Box::new(exp), At(op.loc)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn parse_call(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
+        let callee = Self::parse_primary(lexer)?;
+        Self::parse_postfix(lexer, callee)
+    }
+
+    // The calls, indexing and properties that follow a primary
+    fn parse_postfix(lexer: &mut impl TokenStream, mut callee: Expr) -> Result<Expr, ParserError> {
+        let depth = lexer.nesting().depth;
+        while let Some(open) = lexer.next_if(|t| matches!(t.kind, LParen | LBracket | Dot)) {
+            deepen(lexer)?;
+            if open.kind == Dot {
+                let (name, loc) = consume_ident(lexer)?;
+                callee = Expr::Get(Box::new(callee), name, At(loc));
+                continue;
+            }
+            let close = if open.kind == LParen {
+                let args = Self::parse_args(lexer, RParen, Self::parse_arg)?;
+                callee = Expr::Call(Box::new(callee), args, At(open.loc));
+                RParen
+            } else {
+                let idx = Self::parse_asgn(lexer)?;
+                callee = Expr::Index(Box::new(callee), Box::new(idx), At(open.loc));
+                RBracket
+            };
+            if lexer.next_if(|t| t.kind == close).is_none() {
                 println!("Unmatched parenthesis");
                 return Err(ParserError::Unmatched {
                     open,
@@ -348,18 +926,69 @@ impl RecursiveDescent<Expr> {
                 });
             }
         }
+        lexer.nesting().depth = depth;
         Ok(callee)
     }
 
-    fn parse_args(
-        lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    ) -> Result<Vec<Expr>, ParserError> {
+    // Everything after the `fn` of an anonymous function. `fn (x) => e` is
+    // short for `fn (x) { return e; }`
+    fn parse_lambda(lexer: &mut impl TokenStream, loc: Loc) -> Result<Expr, ParserError> {
+        let name = LAMBDA_NAME.to_string();
+        consume(lexer, LParen)?;
+        let (params, defaults, rest) = RecursiveDescent::<Stmt>::parse_params(lexer, &name)?;
+        consume(lexer, RParen)?;
+        let body = if let Some(arrow) = lexer.next_if(|t| t.kind == Arrow) {
+            if lexer.peek().is_some_and(|t| t.kind == LBrace) {
+                // A block body takes no arrow
+                return Err(ParserError::Expected {
+                    exp: LBrace,
+                    fnd: Some(arrow),
+                });
+            }
+            let value = Self::parse_asgn(lexer)?;
+            Stmt::Block(vec![Stmt::Return(Some(value), At(arrow.loc))])
+        } else {
+            consume(lexer, LBrace)?;
+            let body = RecursiveDescent::<Stmt>::parse_block(lexer)?;
+            consume(lexer, RBrace)?;
+            body
+        };
+        Ok(Expr::Lambda(Rc::new(FunDecl {
+            name,
+            params,
+            defaults,
+            rest,
+            body,
+            loc: At(loc),
+            doc: None,
+            source: Rc::default(),
+        })))
+    }
+
+    fn parse_arg(lexer: &mut impl TokenStream) -> Result<Arg, ParserError> {
+        if lexer.next_if(|t| t.kind == DotDotDot).is_some() {
+            Ok(Arg::Spread(Self::parse_asgn(lexer)?))
+        } else {
+            Ok(Arg::Positional(Self::parse_asgn(lexer)?))
+        }
+    }
+
+    // The items are parsed by `item`, so that other expression parsers can
+    // share the rules for commas and the argument limit
+    pub(super) fn parse_args<S: TokenStream, T>(
+        lexer: &mut S,
+        close: TokKind,
+        item: fn(&mut S) -> Result<T, ParserError>,
+    ) -> Result<Vec<T>, ParserError> {
         let mut args = Vec::new();
-        if lexer.peek().is_some_and(|t| t.kind != RParen) {
-            args.push(Self::parse(lexer)?);
-            while lexer.next_if(|t| t.kind == Comma).is_some() {
+        if lexer.peek().is_some_and(|t| t.kind != close) {
+            args.push(item(lexer)?);
+            // A trailing comma is allowed before the closing token
+            while lexer.next_if(|t| t.kind == Comma).is_some()
+                && lexer.peek().is_some_and(|t| t.kind != close)
+            {
                 let arg_loc = lexer.peek().map(|t| t.loc);
-                args.push(Self::parse(lexer)?);
+                args.push(item(lexer)?);
                 if args.len() > 255 {
                     // println!("argument count (255) exceeded");
                     return Err(ParserError::TooManyArgs {
@@ -371,23 +1000,47 @@ impl RecursiveDescent<Expr> {
         Ok(args)
     }
 
-    fn parse_primary(
-        lexer: &mut Peekable<impl Iterator<Item = Token>>,
-    ) -> Result<Expr, ParserError> {
+    pub(super) fn parse_entries<S: TokenStream>(
+        lexer: &mut S,
+        item: fn(&mut S) -> Result<Expr, ParserError>,
+    ) -> Result<Vec<(Expr, Expr)>, ParserError> {
+        let mut entries = Vec::new();
+        if lexer.peek().is_some_and(|t| t.kind != RBrace) {
+            loop {
+                let key = item(lexer)?;
+                consume(lexer, Colon)?;
+                entries.push((key, item(lexer)?));
+                if lexer.next_if(|t| t.kind == Comma).is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    pub(super) fn parse_primary(lexer: &mut impl TokenStream) -> Result<Expr, ParserError> {
         match lexer.next() {
             None => {
                 // println!("EOF error");
-                Err(ParserError::EOF)
+                Err(ParserError::Eof)
             }
             Some(t) => match t.kind {
                 Nil => Ok(Expr::Lit(Val::Nil)),
                 True => Ok(Expr::Lit(Val::Boolean(true))),
                 False => Ok(Expr::Lit(Val::Boolean(false))),
                 Number(x) => Ok(Expr::Lit(Val::Number(x))),
-                Str(s) => Ok(Expr::Lit(Val::String(s.into()))),
-                Ident(s) => Ok(Expr::Var(Variable::new(s))),
+                BadNumber(text) => Err(ParserError::BadNumber { text, loc: t.loc }),
+                Str(s) => Ok(Expr::Lit(Val::String(s))),
+                Ident(s) => Ok(Expr::Var(Variable::at(s, t.loc))),
+                This => Ok(Expr::This(Variable::at("this", t.loc))),
+                Fn => Self::parse_lambda(lexer, t.loc),
+                Super => {
+                    consume(lexer, Dot)?;
+                    let (name, _) = consume_ident(lexer)?;
+                    Ok(Expr::Super(Variable::at("super", t.loc), name))
+                }
                 LParen => {
-                    let inner = Self::parse_log(lexer)?;
+                    let inner = Self::parse_seq(lexer)?;
                     let closing = lexer.next();
                     if closing.as_ref().is_some_and(|t| t.kind == RParen) {
                         Ok(inner)
@@ -399,6 +1052,28 @@ impl RecursiveDescent<Expr> {
                         })
                     }
                 }
+                LBracket | LBrace => {
+                    let (expr, close) = if t.kind == LBracket {
+                        (
+                            Expr::List(Self::parse_args(lexer, RBracket, Self::parse_asgn)?),
+                            RBracket,
+                        )
+                    } else {
+                        (
+                            Expr::Map(Self::parse_entries(lexer, Self::parse_asgn)?, At(t.loc)),
+                            RBrace,
+                        )
+                    };
+                    let closing = lexer.next();
+                    if closing.as_ref().is_some_and(|c| c.kind == close) {
+                        Ok(expr)
+                    } else {
+                        Err(ParserError::Unmatched {
+                            open: t,
+                            hint: closing.map(|t| t.loc),
+                        })
+                    }
+                }
                 _ => {
                     // println!("Unexpected Token: {x:?}");
                     Err(ParserError::Unexpected { tok: t })
@@ -410,147 +1085,786 @@ impl RecursiveDescent<Expr> {
 
 #[cfg(test)]
 mod test {
-    use crate::lexer::Lexer;
+    use crate::{
+        error::LexError,
+        lexer::{Lexer, Loc},
+        parser::TokenCursor,
+    };
 
     use super::*;
 
+    // Checks the tree of an expression against its S-expression
+    fn assert_parses(src: &str, sexpr: &str) {
+        let e: Expr = parse!(RecursiveDescent<_>, src).unwrap();
+        assert_eq!(e.to_sexpr(), sexpr, "{src}");
+    }
+
     #[test]
     fn trailing_chars() {
-        let mut l = Lexer::new("6 + hello + 8 ;".chars()).peekable();
+        let (tokens, _) = Lexer::new("6 + hello + 8 ;".chars()).scan_all();
+        let mut l = Lookahead::new(tokens.iter().cloned());
         let _: Expr = RecursiveDescent::parse(&mut l).unwrap();
-
         assert_ne!(l.next(), None);
+        let mut l = TokenCursor::new(&tokens);
+        let _: Expr = RecursiveDescent::parse(&mut l).unwrap();
+        assert_eq!(l.rest().len(), 1);
     }
 
     #[test]
     fn left_asoc() {
-        use Expr::*;
+        assert_parses("6 + 3 + 8", "(+ (+ 6 3) 8)");
+        assert_parses(
+            "a - b - c * d",
+            "(- (- (var a) (var b)) (* (var c) (var d)))",
+        );
+    }
 
-        let l = Lexer::new("6 + 3 + 8".chars());
-        let e = RecursiveDescent::parse(&mut l.peekable());
+    #[test]
+    fn sequence() {
+        use Expr::*;
 
+        let num = |x| Box::new(Lit(Val::Number(x)));
+        let e = parse!(RecursiveDescent<_>, "a = 1, b = 2, 3");
         assert_eq!(
             e,
-            Ok(Add(
-                Box::new(Add(
-                    Box::new(Lit(Val::Number(6.0))),
-                    Box::new(Lit(Val::Number(3.0))),
+            Ok(Seq(
+                Box::new(Seq(
+                    Box::new(Asgn(Variable::new("a"), num(1.0))),
+                    Box::new(Asgn(Variable::new("b"), num(2.0))),
                 )),
-                Box::new(Lit(Val::Number(8.0))),
+                num(3.0),
+            ))
+        );
+        let e = parse!(RecursiveDescent<_>, "f(1, (2, 3))");
+        assert_eq!(
+            e,
+            Ok(Call(
+                Box::new(Var(Variable::new("f"))),
+                vec![
+                    Arg::Positional(Lit(Val::Number(1.0))),
+                    Arg::Positional(Seq(num(2.0), num(3.0)))
+                ],
+                At::default()
             ))
         );
+        let e = parse!(RecursiveDescent<Stmt>, "let a = 1, b = 2;");
+        assert_eq!(
+            e,
+            Ok(Stmt::Let(vec![
+                Stmt::Decl("a".to_string(), Some(Lit(Val::Number(1.0))), At::default()),
+                Stmt::Decl("b".to_string(), Some(Lit(Val::Number(2.0))), At::default()),
+            ]))
+        );
+        // Nor do the clauses of a `for`
+        let e = parse!(RecursiveDescent<Stmt>, "for (;; i = 1, j = 2) {}");
+        assert!(matches!(e, Err(ParserError::Expected { exp: RParen, .. })));
     }
 
     #[test]
-    fn right_asoc() {
+    fn conditional() {
         use Expr::*;
 
-        let l = Lexer::new("a = b = 3".chars());
-        let e = RecursiveDescent::parse(&mut l.peekable());
-
+        let var = |name: &str| Box::new(Var(Variable::new(name)));
+        let e = parse!(RecursiveDescent<_>, "a ? b : c ? d : e");
+        assert_eq!(
+            e,
+            Ok(Cond(
+                var("a"),
+                var("b"),
+                Box::new(Cond(var("c"), var("d"), var("e")))
+            ))
+        );
+        let e = parse!(RecursiveDescent<_>, "x = c ? 1 : 2");
         assert_eq!(
             e,
             Ok(Asgn(
-                Variable::new("a".to_string()),
-                Box::new(Asgn(
-                    Variable::new("b".to_string()),
-                    Box::new(Lit(Val::Number(3.0))),
+                Variable::new("x"),
+                Box::new(Cond(
+                    var("c"),
+                    Box::new(Lit(Val::Number(1.0))),
+                    Box::new(Lit(Val::Number(2.0))),
                 )),
             ))
         );
+        let e = parse!(RecursiveDescent<Expr>, "c ? 1 ; 2");
+        assert_eq!(
+            e,
+            Err(ParserError::Expected {
+                exp: Colon,
+                fnd: Some(Token {
+                    kind: Semicolon,
+                    loc: Loc {
+                        row: 0,
+                        col: 6,
+                        offset: 6
+                    }
+                })
+            })
+        );
     }
 
     #[test]
-    fn grouping() {
+    fn bitwise_precedence() {
         use Expr::*;
 
-        let l = Lexer::new("x + (3 + 8)".chars());
-        let e = RecursiveDescent::parse(&mut l.peekable());
-
+        let num = |x| Box::new(Lit(Val::Number(x)));
+        let e = parse!(RecursiveDescent<_>, "1 << 2 + 1 == 8");
         assert_eq!(
             e,
-            Ok(Add(
-                Box::new(Var(Variable::new("x".to_string()))),
-                Box::new(Add(
-                    Box::new(Lit(Val::Number(3.0))),
-                    Box::new(Lit(Val::Number(8.0))),
+            Ok(Eq(
+                Box::new(Shl(
+                    num(1.0),
+                    Box::new(Add(num(2.0), num(1.0), At::default())),
+                    At::default()
+                )),
+                num(8.0),
+                At::default()
+            ))
+        );
+        let e = parse!(RecursiveDescent<_>, "1 | 2 ^ 3 & 4 >> 5");
+        assert_eq!(
+            e,
+            Ok(BitOr(
+                num(1.0),
+                Box::new(BitXor(
+                    num(2.0),
+                    Box::new(BitAnd(
+                        num(3.0),
+                        Box::new(Shr(num(4.0), num(5.0), At::default())),
+                        At::default()
+                    )),
+                    At::default(),
                 )),
+                At::default(),
             ))
         );
     }
 
     #[test]
-    fn prec_increasing() {
+    fn power() {
         use Expr::*;
 
-        let l = Lexer::new("x = true and 0 != 2 + 6 / -!false".chars());
-        let e = RecursiveDescent::parse(&mut l.peekable());
-
+        let num = |x| Box::new(Lit(Val::Number(x)));
+        let e = parse!(RecursiveDescent<_>, "2 ** 3 ** 2");
         assert_eq!(
             e,
-            Ok(Asgn(
-                Variable::new("x".to_string()),
-                Box::new(And(
-                    Box::new(Lit(Val::Boolean(true))),
-                    Box::new(Ne(
-                        Box::new(Lit(Val::Number(0.0))),
-                        Box::new(Add(
-                            Box::new(Lit(Val::Number(2.0))),
-                            Box::new(Div(
-                                Box::new(Lit(Val::Number(6.0))),
-                                Box::new(Opp(Box::new(Not(Box::new(Lit(Val::Boolean(false))))))),
-                            )),
-                        )),
+            Ok(Pow(
+                num(2.0),
+                Box::new(Pow(num(3.0), num(2.0), At::default())),
+                At::default()
+            ))
+        );
+        let e = parse!(RecursiveDescent<_>, "-2 ** -2 * 3");
+        assert_eq!(
+            e,
+            Ok(Mul(
+                Box::new(Opp(
+                    Box::new(Pow(
+                        num(2.0),
+                        Box::new(Opp(num(2.0), At::default())),
+                        At::default()
                     )),
-                ))
+                    At::default()
+                )),
+                num(3.0),
+                At::default()
             ))
         );
     }
 
     #[test]
-    fn prec_decreasing() {
+    fn compound_assignment() {
         use Expr::*;
 
-        let l = Lexer::new("-!false / 6 + 2 != 0 and true".chars());
-        let e = RecursiveDescent::parse(&mut l.peekable());
-
+        let var = |name: &str| Var(Variable::new(name));
+        let e = parse!(RecursiveDescent<_>, "a += b -= 2");
         assert_eq!(
             e,
-            Ok(And(
-                Box::new(Ne(
-                    Box::new(Add(
-                        Box::new(Div(
-                            Box::new(Opp(Box::new(Not(Box::new(Lit(Val::Boolean(false))))))),
-                            Box::new(Lit(Val::Number(6.0))),
+            Ok(Asgn(
+                Variable::new("a"),
+                Box::new(Add(
+                    Box::new(var("a")),
+                    Box::new(Asgn(
+                        Variable::new("b"),
+                        Box::new(Sub(
+                            Box::new(var("b")),
+                            Box::new(Lit(Val::Number(2.0))),
+                            At::default()
                         )),
-                        Box::new(Lit(Val::Number(2.0))),
                     )),
-                    Box::new(Lit(Val::Number(0.0))),
+                    At::default(),
+                )),
+            ))
+        );
+        let e = parse!(RecursiveDescent<Expr>, "x *= 3");
+        assert_eq!(
+            e,
+            Ok(Asgn(
+                Variable::new("x"),
+                Box::new(Mul(
+                    Box::new(var("x")),
+                    Box::new(Lit(Val::Number(3.0))),
+                    At::default()
                 )),
-                Box::new(Lit(Val::Boolean(true))),
             ))
         );
+        let e = parse!(RecursiveDescent<Expr>, "x = 1 += 2");
+        assert_eq!(
+            e,
+            Err(ParserError::InvalidAsgn {
+                loc: Loc {
+                    row: 0,
+                    col: 4,
+                    offset: 4
+                }
+            })
+        );
+        let e = parse!(RecursiveDescent<Expr>, "a.b /= 2");
+        assert!(matches!(e, Err(ParserError::InvalidAsgn { .. })));
+    }
+
+    #[test]
+    fn remainder_precedence() {
+        assert_parses("1 + 4 % 3 * 2", "(+ 1 (* (% 4 3) 2))");
+    }
+
+    #[test]
+    fn right_asoc() {
+        assert_parses("a = b = 3", "(= (var a) (= (var b) 3))");
+    }
+
+    #[test]
+    fn grouping() {
+        assert_parses("x + (3 + 8)", "(+ (var x) (+ 3 8))");
+    }
+
+    #[test]
+    fn prec_increasing() {
+        assert_parses(
+            "x = true and 0 != 2 + 6 / -!false",
+            "(= (var x) (and true (!= 0 (+ 2 (/ 6 (- (! false)))))))",
+        );
+    }
+
+    #[test]
+    fn prec_decreasing() {
+        assert_parses(
+            "-!false / 6 + 2 != 0 and true",
+            "(and (!= (+ (/ (- (! false)) 6) 2) 0) true)",
+        );
     }
 
     #[test]
     fn eof_error() {
-        let l = Lexer::new("2 + - 6 / ".chars());
-        let e = RecursiveDescent::<Expr>::parse(&mut l.peekable());
+        let e = parse!(RecursiveDescent<Expr>, "2 + - 6 / ");
 
         assert!(e.is_err());
     }
 
     #[test]
     fn unclosed_paren() {
-        let l = Lexer::new("2 + - (6 / 4".chars());
-        let e = RecursiveDescent::<Expr>::parse(&mut l.peekable());
+        let e = parse!(RecursiveDescent<Expr>, "2 + - (6 / 4");
         assert!(e.is_err());
     }
 
+    #[test]
+    fn duplicate_param() {
+        let e = parse!(RecursiveDescent<Stmt>, "fn f(a, b, a) { return a; }");
+        assert_eq!(
+            e,
+            Err(ParserError::DuplicateParam {
+                name: "a".to_string(),
+                function: "f".to_string(),
+                loc: Loc {
+                    row: 0,
+                    col: 11,
+                    offset: 11
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn param_shadows_fn() {
+        let e = parse!(RecursiveDescent<Stmt>, "fn f(f) { return f; }");
+        assert_eq!(
+            e,
+            Err(ParserError::ParamShadowsFn {
+                name: "f".to_string(),
+                loc: Loc {
+                    row: 0,
+                    col: 5,
+                    offset: 5
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn distinct_params() {
+        let e = parse!(RecursiveDescent<Stmt>, "fn f(a, b, c) { return a; }");
+        assert!(
+            matches!(e, Ok(Stmt::Func(decl)) if decl.name == "f" && decl.params.iter().map(|(p, _)| p).eq(["a", "b", "c"]))
+        );
+    }
+
     #[test]
     fn asgn_target_error() {
-        let l = Lexer::new("6 = 3 + 8".chars());
-        let e = RecursiveDescent::<Expr>::parse(&mut l.peekable());
+        let e = parse!(RecursiveDescent<Expr>, "6 = 3 + 8");
         assert!(e.is_err());
     }
+
+    #[test]
+    fn unexpected_character() {
+        let e = parse!(RecursiveDescent<Expr>, "1 + @ * 2").unwrap_err();
+        assert_eq!(
+            e,
+            ParserError::Lex(LexError::Unexpected {
+                c: '@',
+                loc: Loc {
+                    row: 0,
+                    col: 4,
+                    offset: 4
+                }
+            })
+        );
+        assert_eq!(e.to_string(), "unexpected character '@'");
+    }
+
+    // The parse ends at the first lexical error, and reports it in place
+    // of what the parser made of the end of input. An error found before
+    // the lexer gets there wins
+    #[test]
+    fn lex_error_in_program() {
+        let src = "let a = 1;\nprint a @ 2;";
+        let e = parse!(RecursiveDescent<Prog>, src).unwrap_err();
+        assert_eq!(
+            e,
+            ParserError::Lex(LexError::Unexpected {
+                c: '@',
+                loc: Loc {
+                    row: 1,
+                    col: 8,
+                    offset: 19
+                }
+            })
+        );
+        assert_eq!(e.to_string(), "unexpected character '@'");
+
+        let e = parse!(RecursiveDescent<Prog>, "print ;\n@");
+        assert!(matches!(e, Err(ParserError::Unexpected { .. })), "{e:?}");
+    }
+
+    #[test]
+    fn exponent_literal() {
+        assert_eq!(
+            parse!(RecursiveDescent<Stmt>, "let x = 1e3;"),
+            Ok(Stmt::Decl(
+                "x".to_string(),
+                Some(Expr::Lit(Val::Number(1000.0))),
+                At::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn recovery() {
+        let src = "let a = 1;\nprint a;\nlet = 2;\nfn f() { return a; }\nprint f();";
+        let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(prog.stmts.len(), 5);
+        assert_eq!(
+            prog.stmts[2],
+            Stmt::Error(Loc {
+                row: 2,
+                col: 0,
+                offset: 20
+            })
+        );
+        assert!(matches!(prog.stmts[0], Stmt::Decl(..)));
+        assert!(matches!(prog.stmts[1], Stmt::Print(..)));
+        assert!(matches!(prog.stmts[3], Stmt::Func(_)));
+        assert!(matches!(prog.stmts[4], Stmt::Print(..)));
+    }
+
+    #[test]
+    fn recovery_collects_every_error() {
+        let src = "print (1;\nlet b = 2;\nprint b +;\nlet = 3;\nlet c = 3;";
+        let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        assert_eq!(errors.len(), 3);
+        assert_eq!(prog.stmts.len(), 5);
+        assert!(matches!(prog.stmts[4], Stmt::Decl(..)));
+    }
+
+    #[test]
+    fn recovery_skips_enclosing_block() {
+        let src =
+            "fn f() {\n  let = 1;\n  if (a) { print 2; }\n}\nprint 3;\n{ print (4; }\nprint 5;";
+        let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!(prog.stmts.len(), 4);
+        assert!(matches!(prog.stmts[0], Stmt::Error(_)));
+        assert!(matches!(prog.stmts[1], Stmt::Print(..)));
+        assert!(matches!(prog.stmts[2], Stmt::Error(_)));
+        assert!(matches!(prog.stmts[3], Stmt::Print(..)));
+        let src = "let f = fn () => { return 1; };\nprint f;";
+        let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(prog.stmts[..], [Stmt::Error(_), Stmt::Print(..)]));
+        // Stray braces are an error each, or one when they follow each other
+        let src = "print 1; } print 2; } }";
+        let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!(prog.stmts.len(), 4);
+        assert!(matches!(prog.stmts[2], Stmt::Print(..)));
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let src = "let a = \"x\"\nprint a;\na = 2\nfn f() {\n  a = 3\n  print a;\n}\nprint 4;";
+        let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        let after = |row, col, offset| ParserError::MissingSemicolon {
+            after: Loc { row, col, offset },
+        };
+        assert_eq!(
+            errors,
+            vec![after(0, 8, 8), after(2, 4, 25), after(4, 6, 42)]
+        );
+        assert!(matches!(
+            prog.stmts[..],
+            [
+                Stmt::Error(_),
+                Stmt::Print(..),
+                Stmt::Error(_),
+                Stmt::Error(_),
+                Stmt::Print(..)
+            ]
+        ));
+        // On the same line, the next token is not taken for a statement
+        let e = parse!(RecursiveDescent<Stmt>, "print 1 print 2;");
+        assert!(matches!(
+            e,
+            Err(ParserError::Expected { exp: Semicolon, .. })
+        ));
+    }
+
+    #[test]
+    fn reserved_words() {
+        let reserved = |src| match parse!(RecursiveDescent<Stmt>, src) {
+            Err(ParserError::ReservedWord { kw, loc }) => (kw, loc.col),
+            res => panic!("{src}: {res:?}"),
+        };
+        assert_eq!(reserved("let class = 1;"), (Class, 4));
+        let e = parse!(RecursiveDescent<Stmt>, "let class = 1;").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "'class' is a reserved word and cannot be used as a name"
+        );
+        assert_eq!(reserved("fn for() {}"), (For, 3));
+        assert_eq!(reserved("fn f(a, while) {}"), (While, 8));
+        assert_eq!(reserved("fn f(...in) {}"), (In, 8));
+        assert_eq!(reserved("print a.this;"), (This, 8));
+        let e = parse!(RecursiveDescent<Stmt>, "let classy = 1;").unwrap();
+        assert_eq!(e.to_sexpr(), "(let classy 1)");
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        let src = "a < b <= c; (a < b) == c; a == b != c; x = 1 < 2 == 3 > 4;";
+        let (_, errors, warnings) = RecursiveDescent::<Prog>::parse_with_recovery(
+            Lexer::new(src.chars()).map(Result::unwrap),
+        );
+        assert_eq!(errors, vec![]);
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|w| match w {
+                Warning::ChainedCmp { expr, fix, loc } => {
+                    (expr.to_string(), fix.to_string(), loc.col)
+                }
+                w => panic!("unexpected warning {w}"),
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("a < b <= c".to_string(), "a < b and b <= c".to_string(), 6),
+                // Once per chain
+                ("1 < 2 == 3".to_string(), "1 < 2 and 2 == 3".to_string(), 49),
+            ]
+        );
+    }
+
+    #[test]
+    fn anonymous_function() {
+        let lambda = |params: &[&str], body| {
+            Expr::Lambda(Rc::new(FunDecl {
+                name: LAMBDA_NAME.to_string(),
+                params: params
+                    .iter()
+                    .map(|p| (p.to_string(), At::default()))
+                    .collect(),
+                defaults: vec![],
+                rest: None,
+                body: Stmt::Block(body),
+                loc: At::default(),
+                doc: None,
+                source: Rc::default(),
+            }))
+        };
+        let e = parse!(RecursiveDescent<_>, "f(fn (a, b) { return a; })");
+        assert_eq!(
+            e,
+            Ok(Expr::Call(
+                Box::new(Expr::Var(Variable::new("f"))),
+                vec![Arg::Positional(lambda(
+                    &["a", "b"],
+                    vec![Stmt::Return(
+                        Some(Expr::Var(Variable::new("a"))),
+                        At::default()
+                    )]
+                ))],
+                At::default()
+            ))
+        );
+        // At the start of a statement, `fn` and a `(` begin an expression
+        let s = parse!(RecursiveDescent<Stmt>, "fn () {}();");
+        assert_eq!(
+            s,
+            Ok(Stmt::Expr(
+                Expr::Call(Box::new(lambda(&[], vec![])), vec![], At::default()),
+                At::default()
+            ))
+        );
+        let s = parse!(RecursiveDescent<Stmt>, "fn f() {}");
+        assert!(matches!(s, Ok(Stmt::Func(f)) if f.name == "f"));
+        let s = parse!(RecursiveDescent<Stmt>, "fn () {}() + 1;");
+        assert!(matches!(
+            s,
+            Err(ParserError::Expected { exp: Semicolon, .. })
+        ));
+        let s = parse!(RecursiveDescent<Stmt>, "(fn () {}() + 1);");
+        assert!(matches!(s, Ok(Stmt::Expr(Expr::Add(..), _))));
+        // An arrow body is an argument, and nests to the right
+        let e = parse!(RecursiveDescent<_>, "f(fn (x) => fn (y) => x = y, 1)");
+        let y = Expr::Var(Variable::new("y"));
+        let inner = lambda(
+            &["y"],
+            vec![Stmt::Return(
+                Some(Expr::Asgn(Variable::new("x"), Box::new(y))),
+                At::default(),
+            )],
+        );
+        assert_eq!(
+            e,
+            Ok(Expr::Call(
+                Box::new(Expr::Var(Variable::new("f"))),
+                vec![
+                    Arg::Positional(lambda(
+                        &["x"],
+                        vec![Stmt::Return(Some(inner), At::default())]
+                    )),
+                    Arg::Positional(Expr::Lit(Val::Number(1.0)))
+                ],
+                At::default()
+            ))
+        );
+        let e = parse!(RecursiveDescent<Expr>, "fn (x) => { x }");
+        assert!(matches!(
+            e,
+            Err(ParserError::Expected {
+                exp: LBrace,
+                fnd: Some(Token { kind: Arrow, .. })
+            })
+        ));
+    }
+
+    #[test]
+    fn match_patterns() {
+        let s = parse!(
+            RecursiveDescent<Stmt>,
+            "match (x) {\n  1 => {}\n  y => {}\n}"
+        );
+        assert_eq!(
+            s,
+            Err(ParserError::InvalidPattern {
+                loc: Loc {
+                    row: 2,
+                    col: 2,
+                    offset: 24
+                }
+            })
+        );
+        let s = parse!(RecursiveDescent<Stmt>, "match (x) { -a => {} }");
+        assert!(matches!(
+            s,
+            Err(ParserError::InvalidPattern { loc }) if loc.col == 12
+        ));
+        // The default arm comes last
+        let s = parse!(RecursiveDescent<Stmt>, "match (x) { _ => {} 1 => {} }");
+        assert!(matches!(s, Err(ParserError::Expected { exp: RBrace, .. })));
+    }
+
+    #[test]
+    fn loop_labels() {
+        let s = parse!(
+            RecursiveDescent<Stmt>,
+            "outer: while (a) { continue outer; break; }"
+        );
+        assert_eq!(
+            s,
+            Ok(Stmt::While(
+                Expr::Var(Variable::new("a")),
+                Box::new(Stmt::Block(vec![
+                    Stmt::Continue(Some("outer".to_string()), At::default()),
+                    Stmt::Break(None, At::default()),
+                ])),
+                None,
+                Some("outer".to_string()),
+                At::default(),
+            ))
+        );
+        let s = parse!(
+            RecursiveDescent<Stmt>,
+            "l: for (; i < 3; i = i + 1) break l;"
+        );
+        assert!(matches!(s, Ok(Stmt::While(_, _, Some(_), Some(l), _)) if l == "l"));
+        let s = parse!(RecursiveDescent<Stmt>, "l: loop { break l; }");
+        assert!(matches!(s, Ok(Stmt::Loop(_, Some(l), _)) if l == "l"));
+        let s = parse!(RecursiveDescent<Stmt>, "loop break;");
+        assert!(matches!(s, Err(ParserError::Expected { exp: LBrace, .. })));
+        // Only loops can be labeled
+        let s = parse!(RecursiveDescent<Stmt>, "l: print 1;");
+        assert!(matches!(s, Err(ParserError::Expected { exp: While, .. })));
+        let s = parse!(RecursiveDescent<Stmt>, "while (a) break");
+        assert!(matches!(
+            s,
+            Err(ParserError::Expected { exp: Semicolon, .. })
+        ));
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let parse = |src: &str| parse!(RecursiveDescent<Stmt>, src);
+        assert!(parse("f(a, b,);").is_ok());
+        assert_eq!(parse("f(a, b,);"), parse("f(a, b);"));
+        assert_eq!(parse("fn f(a, b,) {}"), parse("fn f(a, b) {}"));
+        assert_eq!(
+            parse("fn f(a = 1, ...b,) {}"),
+            parse("fn f(a = 1, ...b) {}")
+        );
+        assert_eq!(
+            parse("let f = fn (a,) => a;"),
+            parse("let f = fn (a) => a;")
+        );
+        for src in ["f(,);", "f(a,,);", "f(a,,b);", "fn f(,) {}", "fn f(a,,) {}"] {
+            assert!(parse(src).is_err(), "{src}");
+        }
+
+        // The limits count arguments, not commas
+        let args = |n| vec!["x"; n].join(", ");
+        assert!(parse(&format!("f({},);", args(255))).is_ok());
+        let src = format!("f({},);", args(256));
+        assert!(matches!(
+            parse(&src),
+            Err(ParserError::TooManyArgs { loc }) if loc.offset == src.rfind('x').unwrap()
+        ));
+        let params = |n| {
+            (0..n)
+                .map(|i| format!("p{i}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        assert!(parse(&format!("fn f({},) {{}}", params(255))).is_ok());
+        let src = format!("fn f({},) {{}}", params(256));
+        assert!(matches!(
+            parse(&src),
+            Err(ParserError::TooManyParams { loc }) if loc.offset == src.find("p255").unwrap()
+        ));
+    }
+
+    #[test]
+    fn locations() {
+        let src = "let x = a +\n    f(b)[0];\nfor (;;) return -x.y;\nfor (let i;;) {}";
+        let prog = parse!(RecursiveDescent<Prog>, src).unwrap();
+        let at = |at: &At| (at.0.row, at.0.col);
+
+        let Stmt::Decl(_, Some(Expr::Add(a, index, plus)), _) = &prog.stmts[0] else {
+            panic!("expected an addition, got {:?}", prog.stmts[0])
+        };
+        assert_eq!(at(plus), (0, 10));
+        assert!(matches!(&**a, Expr::Var(a) if at(&a.loc) == (0, 8)));
+        let Expr::Index(call, _, bracket) = &**index else {
+            panic!("expected indexing, got {index:?}")
+        };
+        assert_eq!(at(bracket), (1, 8));
+        assert!(matches!(&**call, Expr::Call(_, _, paren) if at(paren) == (1, 5)));
+
+        // The loop a `for` desugars into is where the `for` is
+        let Stmt::While(_, body, _, _, keyword) = &prog.stmts[1] else {
+            panic!("expected a loop, got {:?}", prog.stmts[1])
+        };
+        assert_eq!(at(keyword), (2, 0));
+        let Stmt::Return(Some(Expr::Opp(get, minus)), ret) = &**body else {
+            panic!("expected a return, got {body:?}")
+        };
+        assert_eq!((at(ret), at(minus)), ((2, 9), (2, 16)));
+        assert!(matches!(&**get, Expr::Get(_, _, name) if at(name) == (2, 19)));
+        assert!(matches!(
+            &prog.stmts[2],
+            Stmt::Block(stmts) if matches!(&stmts[1], Stmt::While(.., keyword) if at(keyword) == (3, 0))
+        ));
+    }
+
+    #[test]
+    fn max_nesting() {
+        let parse = |src: &str| {
+            let tokens = Lexer::new(src.chars()).map(Result::unwrap);
+            RecursiveDescent::<Prog>::parse_with_max_nesting(tokens, 4).1
+        };
+        // The statement, its expression and one for each parenthesis
+        assert_eq!(parse("print ((1));\nprint ((1));"), []);
+        assert_eq!(
+            parse("print (((1)));\nprint ((1));"),
+            [ParserError::TooDeep {
+                max: 4,
+                loc: Loc {
+                    row: 0,
+                    col: 9,
+                    offset: 9
+                }
+            }]
+        );
+        // Each link of a chain nests what came before it
+        assert_eq!(parse("print 1 + 1 + 1;"), []);
+        assert_eq!(parse("print 1 + 1 + 1 + 1;").len(), 1);
+        assert_eq!(parse("print f()()();").len(), 1);
+    }
+
+    #[test]
+    fn import_path() {
+        let parse = |src: &str| parse!(RecursiveDescent<Prog>, src);
+        let prog = parse("import \"lib/a.lox\";").unwrap();
+        assert_eq!(
+            prog.stmts,
+            [Stmt::Import("lib/a.lox".to_string(), At::default())]
+        );
+        assert_eq!(prog.stmts[0].to_string(), "import \"lib/a.lox\";");
+        let e = parse("import a;").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "syntax error: expected a string, found identifier 'a'"
+        );
+    }
 }
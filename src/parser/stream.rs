@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+use crate::{
+    error::Warning,
+    lexer::{Loc, Token},
+};
+
+// Where the parsers read tokens from, looking as far ahead of the next
+// token as they need to
+pub trait TokenStream {
+    // The token `n` places after the next one, which is `peek_nth(0)`
+    fn peek_nth(&mut self, n: usize) -> Option<&Token>;
+
+    fn next(&mut self) -> Option<Token>;
+
+    // Where the last token read starts
+    fn last_loc(&self) -> Option<Loc>;
+
+    // Keeps a diagnostic about code that parses but is likely a mistake
+    fn warn(&mut self, w: Warning);
+
+    // How deep the parser is in what it reads
+    fn nesting(&mut self) -> &mut Nesting;
+
+    fn take_warnings(&mut self) -> Vec<Warning>;
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn next_if(&mut self, f: impl FnOnce(&Token) -> bool) -> Option<Token> {
+        if self.peek().is_some_and(f) {
+            self.next()
+        } else {
+            None
+        }
+    }
+}
+
+// How many constructs the parser is inside of, such as parentheses,
+// blocks or chained operators, and how many it may be. Unbounded by default
+#[derive(Clone, Copy)]
+pub struct Nesting {
+    pub depth: usize,
+    pub max: usize,
+}
+
+impl Default for Nesting {
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            max: usize::MAX,
+        }
+    }
+}
+
+// The tokens of an iterator, such as a lexer, pulled from it as they are
+// looked at
+pub struct Lookahead<I: Iterator<Item = Token>> {
+    tokens: I,
+    peeked: VecDeque<Token>,
+    last: Option<Loc>,
+    warnings: Vec<Warning>,
+    nesting: Nesting,
+}
+
+impl<I: Iterator<Item = Token>> Lookahead<I> {
+    pub fn new(tokens: I) -> Self {
+        Self {
+            tokens,
+            peeked: VecDeque::new(),
+            last: None,
+            warnings: Vec::new(),
+            nesting: Nesting::default(),
+        }
+    }
+
+    pub fn max_nesting(mut self, max: usize) -> Self {
+        self.nesting.max = max;
+        self
+    }
+}
+
+impl<I: Iterator<Item = Token>> TokenStream for Lookahead<I> {
+    fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        while self.peeked.len() <= n {
+            self.peeked.push_back(self.tokens.next()?);
+        }
+        self.peeked.get(n)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.peeked.pop_front().or_else(|| self.tokens.next())?;
+        self.last = Some(t.loc);
+        Some(t)
+    }
+
+    fn last_loc(&self) -> Option<Loc> {
+        self.last
+    }
+
+    fn warn(&mut self, w: Warning) {
+        self.warnings.push(w);
+    }
+
+    fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn nesting(&mut self) -> &mut Nesting {
+        &mut self.nesting
+    }
+}
+
+// Tokens collected beforehand, such as by `Lexer::scan_all`
+pub struct TokenCursor<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    warnings: Vec<Warning>,
+    nesting: Nesting,
+}
+
+impl<'a> TokenCursor<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            warnings: Vec::new(),
+            nesting: Nesting::default(),
+        }
+    }
+
+    // The tokens not read yet
+    pub fn rest(&self) -> &'a [Token] {
+        &self.tokens[self.pos..]
+    }
+}
+
+impl TokenStream for TokenCursor<'_> {
+    fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        Some(t)
+    }
+
+    fn last_loc(&self) -> Option<Loc> {
+        let last = self.pos.checked_sub(1)?;
+        Some(self.tokens[last].loc)
+    }
+
+    fn warn(&mut self, w: Warning) {
+        self.warnings.push(w);
+    }
+
+    fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn nesting(&mut self) -> &mut Nesting {
+        &mut self.nesting
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::{Lexer, TokKind::*};
+
+    fn check(stream: &mut impl TokenStream) {
+        assert_eq!(stream.peek_nth(2).map(|t| &t.kind), Some(&Number(1.0)));
+        assert_eq!(stream.peek_nth(9), None);
+        assert_eq!(stream.next_if(|t| t.kind == Equal), None);
+        assert_eq!(stream.last_loc(), None);
+        assert!(stream.next_if(|t| matches!(t.kind, Ident(_))).is_some());
+        assert_eq!(stream.last_loc().map(|l| l.col), Some(0));
+        assert_eq!(stream.peek().map(|t| &t.kind), Some(&Equal));
+        assert_eq!(stream.next().map(|t| t.kind), Some(Equal));
+    }
+
+    #[test]
+    fn lookahead() {
+        let (tokens, _) = Lexer::new("a = 1;".chars()).scan_all();
+        check(&mut Lookahead::new(tokens.clone().into_iter()));
+        let mut cursor = TokenCursor::new(&tokens);
+        check(&mut cursor);
+        assert_eq!(cursor.rest(), &tokens[2..]);
+    }
+}
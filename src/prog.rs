@@ -1,8 +1,14 @@
 use std::rc::Rc;
 
-use crate::{scope::Scope, stmt::Stmt};
+use crate::{
+    context::Context,
+    error::RuntimeError,
+    scope::Scope,
+    stmt::{Flow, Stmt},
+    val::{Function, Val},
+};
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug, Default)]
 pub struct Prog {
     pub stmts: Vec<Stmt>,
 }
@@ -12,10 +18,132 @@ impl Prog {
         Self { stmts: Vec::new() }
     }
 
-    pub fn exec(&self, scope: Rc<Scope>) -> Result<(), ()> {
+    // SEMANTICS: hoisting of global functions
+    //
+    // Every function declared at the top level of a program is defined before
+    // any of its statements runs, so a function can be called above the line
+    // that declares it. Only functions are hoisted:
+    // - a top level `let` is defined when its statement runs, and a function
+    //   that reads it before then fails with an undefined variable error;
+    // - when a name is declared more than once, the last declaration is the
+    //   one visible before the program starts. Each declaration still rebinds
+    //   the name when its statement runs, so from then on the code behaves as
+    //   if nothing had been hoisted.
+    pub fn exec(&self, ctx: &mut Context, scope: Rc<Scope>) -> Result<(), RuntimeError> {
+        // A partial tree from error recovery must not run at all
+        if self.stmts.iter().any(|s| matches!(s, Stmt::Error(_))) {
+            return Err(RuntimeError::ParseErrors);
+        }
+        for s in &self.stmts {
+            if let Stmt::Func(decl) = s {
+                let f = Function::UserDef(Rc::clone(decl), Rc::clone(&scope));
+                scope.def(&decl.name, Val::Func(f));
+            }
+        }
         for s in &self.stmts {
-            s.exec(Rc::clone(&scope))?;
+            // The resolver rejects top level returns and jumps outside of a
+            // loop, but if one ever slips through it must not be silently
+            // ignored
+            if s.exec(ctx, Rc::clone(&scope))? != Flow::Next {
+                return Err(RuntimeError::TopLevelReturn);
+            }
         }
         Ok(())
     }
 }
+
+// One statement per line, as `lox fmt` prints it
+impl std::fmt::Display for Prog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.stmts.iter().try_for_each(|s| writeln!(f, "{s}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        error::LoxError,
+        expr::{At, Expr},
+        globals::globals,
+        interpreter::Interpreter,
+        source::SourceMap,
+        test_util::{global, run},
+    };
+
+    #[test]
+    fn top_level_return() {
+        let prog = Prog {
+            stmts: vec![
+                Stmt::Return(Some(Expr::Lit(Val::Number(1.0))), At::default()),
+                Stmt::Print(Expr::Lit(Val::Nil), At::default()),
+            ],
+        };
+        assert_eq!(
+            prog.exec(&mut Context::default(), Scope::new_global(globals())),
+            Err(RuntimeError::TopLevelReturn)
+        );
+    }
+
+    #[test]
+    fn call_before_declaration() {
+        let env = run("let x = f(); fn f() { return 1; }");
+        assert_eq!(global(&env, "x"), Val::Number(1.0));
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let env = run(r#"
+            let r = even(10);
+            fn even(n) { if (n == 0) return true; return odd(n - 1); }
+            fn odd(n) { if (n == 0) return false; return even(n - 1); }"#);
+        assert_eq!(global(&env, "r"), Val::Boolean(true));
+    }
+
+    #[test]
+    fn let_is_not_hoisted() {
+        let mut lox = Interpreter::builder().build();
+        let src = SourceMap::new("test", "let r = f(); fn f() { return x; } let x = 1;");
+        assert_eq!(
+            lox.run(&src),
+            Err(LoxError::Runtime(RuntimeError::UndefinedVariable {
+                name: "x".to_string()
+            }))
+        );
+        assert_eq!(lox.global("r"), None);
+    }
+
+    #[test]
+    fn redefinition() {
+        let env = run(r#"
+            let a = f();
+            fn f() { return 1; }
+            let b = f();
+            fn f() { return 2; }
+            let c = f();"#);
+        assert_eq!(global(&env, "a"), Val::Number(2.0));
+        assert_eq!(global(&env, "b"), Val::Number(1.0));
+        assert_eq!(global(&env, "c"), Val::Number(2.0));
+    }
+
+    #[test]
+    fn partial_tree_does_not_run() {
+        let prog = Prog {
+            stmts: vec![
+                Stmt::Decl(
+                    "x".to_string(),
+                    Some(Expr::Lit(Val::Number(1.0))),
+                    At::default(),
+                ),
+                Stmt::Error(Default::default()),
+            ],
+        };
+        let globals = Scope::new_global(globals());
+        assert_eq!(
+            prog.exec(&mut Context::default(), Rc::clone(&globals)),
+            Err(RuntimeError::ParseErrors)
+        );
+        let x = crate::expr::Variable::new("x".to_string());
+        assert!(globals.get(&x).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+use lox::{source::SourceMap, Builder};
+
+// Submissions larger than this are rejected unless configured otherwise
+pub const MAX_INPUT: usize = 1 << 20;
+// Submissions larger than this may take a noticeable time to run
+const SLOW_INPUT: usize = 64 << 10;
+
+#[derive(Debug, PartialEq)]
+pub enum Submission {
+    Code(String),
+    Load(String),
+    Eof,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReplInputError {
+    TooLarge { len: usize, max: usize },
+    InvalidUtf8,
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for ReplInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { len, max } => write!(
+                f,
+                "input too large ({len} bytes, the limit is {max}), use :load to run a file"
+            ),
+            Self::InvalidUtf8 => write!(f, "input is not valid UTF-8, it was ignored"),
+            Self::Io(kind) => write!(f, "could not read input: {kind}"),
+        }
+    }
+}
+
+// Reads one line of input. A line over `max` bytes is consumed to its end
+// without being kept, so that the next read starts at the following line
+pub fn read_submission(input: &mut impl BufRead, max: usize) -> Result<Submission, ReplInputError> {
+    let mut line = Vec::new();
+    let mut len = 0;
+    loop {
+        let buf = match input.fill_buf() {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ReplInputError::Io(e.kind())),
+        };
+        if buf.is_empty() {
+            break;
+        }
+        let (chunk, done) = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => (&buf[..=i], true),
+            None => (buf, false),
+        };
+        len += chunk.len();
+        if len <= max {
+            line.extend_from_slice(chunk);
+        }
+        let n = chunk.len();
+        input.consume(n);
+        if done {
+            break;
+        }
+    }
+
+    if len == 0 {
+        return Ok(Submission::Eof);
+    }
+    if len > max {
+        return Err(ReplInputError::TooLarge { len, max });
+    }
+    let line = String::from_utf8(line).map_err(|_| ReplInputError::InvalidUtf8)?;
+    // `:load path` runs a file into the session
+    Ok(match line.trim().strip_prefix(":load ") {
+        Some(path) => Submission::Load(path.trim().to_string()),
+        None => Submission::Code(line),
+    })
+}
+
+pub fn repl(lox: Builder, max_input: usize) -> ExitCode {
+    let mut stdin = io::stdin().lock();
+    let mut lox = lox.repl(true).build();
+    // Submissions are lines of one text, numbered as such in diagnostics
+    let mut src = SourceMap::new("<stdin>", "");
+
+    loop {
+        print!("> ");
+        // A prompt that cannot be shown is no reason to stop
+        let _ = io::stdout().flush();
+
+        // Errors have already been reported, and the session goes on
+        let _ = match read_submission(&mut stdin, max_input) {
+            Ok(Submission::Eof) => {
+                println!();
+                return ExitCode::SUCCESS;
+            }
+            Ok(Submission::Load(path)) => lox.load(&path),
+            Ok(Submission::Code(code)) => {
+                if code.len() > SLOW_INPUT {
+                    eprintln!("parsing {} bytes…", code.len());
+                }
+                src.append(&code);
+                lox.run(&src)
+            }
+            Err(e) => {
+                eprintln!("error[lox]: {e}");
+                continue;
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_all(input: &[u8], max: usize) -> Vec<Result<Submission, ReplInputError>> {
+        let mut input = io::BufReader::with_capacity(4, input);
+        let mut subs = Vec::new();
+        loop {
+            match read_submission(&mut input, max) {
+                Ok(Submission::Eof) => return subs,
+                s => subs.push(s),
+            }
+        }
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            read_all(b"print 1;\n:load  a.lox \nlast", 100),
+            vec![
+                Ok(Submission::Code("print 1;\n".to_string())),
+                Ok(Submission::Load("a.lox".to_string())),
+                Ok(Submission::Code("last".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized() {
+        let long = format!("print \"{}\";\nprint 2;\n", "x".repeat(50));
+        assert_eq!(
+            read_all(long.as_bytes(), 20),
+            vec![
+                Err(ReplInputError::TooLarge { len: 60, max: 20 }),
+                Ok(Submission::Code("print 2;\n".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(
+            read_all(b"print \"\xff\xfe\";\nprint \"\xc3\xa9\";\n", 100),
+            vec![
+                Err(ReplInputError::InvalidUtf8),
+                Ok(Submission::Code("print \"é\";\n".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn eof() {
+        assert_eq!(read_all(b"", 100), vec![]);
+    }
+}
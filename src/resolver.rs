@@ -1,43 +1,328 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
 use crate::{
-    expr::{Expr, Variable},
+    error::{ResolveError, Warning},
+    expr::{Arg, At, Expr, Variable},
+    globals::globals,
+    lexer::Loc,
     prog::Prog,
-    stmt::Stmt,
+    stmt::{FunDecl, Stmt},
+    symbols::{DefId, DefKind, Definition, SymbolTable},
+    val::{Callable, Val},
 };
 
 #[derive(Clone, Copy, PartialEq)]
 enum FunctionType {
     None,
     Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+    // Inside a static method, which has neither `this` nor `super`
+    Static,
+}
+
+// A name declared in a local scope
+struct Binding {
+    defined: bool,
+    read: bool,
+    // Where it was last assigned to
+    assigned: Option<Loc>,
+    param: bool,
+    loc: Loc,
+    // Index in `functions` of the one declared by this name
+    func: Option<usize>,
+    def: Option<DefId>,
+}
+
+impl Binding {
+    fn new(loc: Loc) -> Self {
+        Self {
+            defined: false,
+            read: false,
+            assigned: None,
+            param: false,
+            loc,
+            func: None,
+            def: None,
+        }
+    }
+}
+
+// A function declared by `fn`, with the fewest and most arguments it takes
+struct FunctionUse {
+    name: String,
+    loc: Loc,
+    global: bool,
+    min: usize,
+    max: usize,
+    // Whether its name is assigned to, so it may end up naming another one
+    reassigned: bool,
+}
+
+// The callee of a call made by name, the function declared by `fn` that a
+// local name leads to or a global name
+enum Callee {
+    Local(usize),
+    Global(String),
 }
 
 pub struct Resolver {
-    scopes: Vec<HashMap<String, bool>>,
+    scopes: Vec<HashMap<String, Binding>>,
     curr_function: FunctionType,
+    curr_class: ClassType,
+    // Index in `scopes` of the parameters of the innermost function
+    function_scope: usize,
+    // Variables declared by the initializer of the enclosing `for` loops,
+    // along with the index of the scope that holds them and whether a
+    // capture of them was warned about in this loop
+    loop_vars: Vec<(String, usize, bool)>,
+    // Labels of the loops around the current statement, innermost last,
+    // and whether a `break` or `return` leaves them.
+    // Functions start with none, a jump cannot leave them
+    loops: Vec<(Option<String>, bool)>,
+    // Enables lints that are too noisy to be on by default
+    strict: bool,
+    // Bare expressions are how values get displayed in the REPL
+    repl: bool,
+    // The `///` comments of the program, by the line just below them
+    docs: HashMap<usize, String>,
+    // The name of the source of the next unit resolved
+    source: Rc<str>,
+    // Top-level functions are meant for later code, as with the prelude
+    library: bool,
+    // Every function declared by `fn`, and the ones read by each of them or,
+    // for `None`, by top-level code. Global names are looked up once the
+    // whole program is resolved, as a function may read those declared
+    // after it
+    functions: Vec<FunctionUse>,
+    reads: Vec<(Option<usize>, usize)>,
+    global_reads: Vec<(Option<usize>, String)>,
+    // Index in `functions` of the one whose body is being resolved
+    curr_use: Option<usize>,
+    // Calls by name, with the number of arguments and where they are made.
+    // They are checked once it is known whether the names are rebound
+    calls: Vec<(Callee, usize, Loc)>,
+    // Global names bound by anything but a single `fn`, and whether the
+    // program imports a file, which may bind any of them
+    rebound: Vec<String>,
+    imports: bool,
+    // Built only when asked for. Uses of globals are linked to their
+    // definitions once all of them are known
+    symbols: Option<SymbolTable>,
+    global_defs: Vec<DefId>,
+    global_refs: Vec<(Loc, String)>,
+    warnings: Vec<Warning>,
+    errors: Vec<ResolveError>,
 }
 
 impl Resolver {
-    pub fn new() -> Self {
+    pub fn new(strict: bool, repl: bool) -> Self {
         Self {
             scopes: Vec::new(),
             curr_function: FunctionType::None,
+            curr_class: ClassType::None,
+            function_scope: 0,
+            loop_vars: Vec::new(),
+            loops: Vec::new(),
+            strict,
+            repl,
+            docs: HashMap::new(),
+            source: Rc::default(),
+            library: false,
+            functions: Vec::new(),
+            reads: Vec::new(),
+            global_reads: Vec::new(),
+            curr_use: None,
+            calls: Vec::new(),
+            rebound: Vec::new(),
+            imports: false,
+            symbols: None,
+            global_defs: Vec::new(),
+            global_refs: Vec::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    fn declare(&mut self, var: &str) {
+    // The comments collected by `Lexer::take_docs`, given to the functions
+    // declared on the line below them in the next unit resolved
+    pub fn docs(&mut self, docs: Vec<(usize, String)>) {
+        self.docs = docs.into_iter().map(|(row, doc)| (row + 1, doc)).collect();
+    }
+
+    // What the functions of the next unit resolved name as their source
+    pub fn source(&mut self, name: &str) {
+        self.source = name.into();
+    }
+
+    pub fn symbols(mut self, symbols: bool) -> Self {
+        self.symbols = symbols.then(SymbolTable::default);
+        self
+    }
+
+    pub fn take_symbols(&mut self) -> Option<SymbolTable> {
+        self.symbols.take()
+    }
+
+    // For code whose top-level functions are called by code loaded after it,
+    // such as the prelude and imported files
+    pub fn library(mut self, library: bool) -> Self {
+        self.library = library;
+        self
+    }
+
+    fn declare(&mut self, var: &str, loc: Loc) {
+        // The body of a function is a block of its own, but its locals
+        // clash with the parameters all the same
+        let in_body = self.curr_function != FunctionType::None
+            && self.scopes.len() == self.function_scope + 2;
+        let params = in_body.then(|| &self.scopes[self.function_scope]);
+        if let Some(prev) = params.and_then(|ps| ps.get(var)) {
+            self.errors.push(ResolveError::Redeclared {
+                name: var.to_string(),
+                loc,
+                prev: prev.loc,
+            });
+        }
+        // Globals are defined, but not declared, in the symbol table
+        let def = if self.scopes.is_empty() {
+            None
+        } else {
+            self.definition(var, loc)
+        };
         if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(var) {
-                println!("Already a variable with this name in this scope");
-                todo!("resolver must return results");
+            if let Some(prev) = scope.get(var) {
+                self.errors.push(ResolveError::Redeclared {
+                    name: var.to_string(),
+                    loc,
+                    prev: prev.loc,
+                });
             }
-            scope.insert(var.to_string(), false);
+            scope.insert(
+                var.to_string(),
+                Binding {
+                    def,
+                    ..Binding::new(loc)
+                },
+            );
         }
     }
 
-    fn define(&mut self, var: &str) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(var.to_string(), true);
+    // Where a name is declared and defined at once, `loc` is where
+    fn define(&mut self, var: &str, loc: Loc) {
+        let declared = self.scopes.last().map(|s| s.contains_key(var));
+        let def = match declared {
+            Some(true) => None,
+            _ => self.definition(var, loc),
+        };
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope
+                    .entry(var.to_string())
+                    .or_insert_with(|| Binding {
+                        def,
+                        ..Binding::new(loc)
+                    })
+                    .defined = true
+            }
+            // Globals are only kept track of for the symbol table
+            None => self.global_defs.extend(def),
+        }
+    }
+
+    // A local function is declared ahead of the rest of its block, and
+    // only once
+    fn declare_function(&mut self, decl: &FunDecl) -> usize {
+        let declared = self.scopes.last().and_then(|s| s.get(&decl.name));
+        if let Some(id) = declared.and_then(|b| b.func) {
+            if self.functions[id].loc == decl.loc.0 {
+                return id;
+            }
+        }
+        let id = self.functions.len();
+        let global = self.scopes.is_empty();
+        if global
+            && self
+                .functions
+                .iter()
+                .any(|f| f.global && f.name == decl.name)
+        {
+            self.rebind(&decl.name);
+        }
+        self.functions.push(FunctionUse {
+            name: decl.name.clone(),
+            loc: decl.loc.0,
+            global,
+            min: decl.min_arity(),
+            max: decl.arity(),
+            reassigned: false,
+        });
+        self.define_item(&decl.name, decl.loc.0, DefKind::Func);
+        if let Some(b) = self.scopes.last_mut().and_then(|s| s.get_mut(&decl.name)) {
+            b.func = Some(id);
+        }
+        id
+    }
+
+    // Functions and classes, which are not reported when unused
+    fn define_item(&mut self, name: &str, loc: Loc, kind: DefKind) {
+        self.define(name, loc);
+        let def = match self.scopes.last_mut().and_then(|s| s.get_mut(name)) {
+            Some(b) => {
+                b.read = true;
+                b.def
+            }
+            None => self.global_defs.last().copied(),
+        };
+        self.set_kind(def, kind);
+    }
+
+    // Adds a definition to the symbol table, when building one. `this` and
+    // `super` are bound by the language rather than declared
+    fn definition(&mut self, name: &str, loc: Loc) -> Option<DefId> {
+        let table = self
+            .symbols
+            .as_mut()
+            .filter(|_| name != "this" && name != "super")?;
+        table.definitions.push(Definition {
+            name: name.to_string(),
+            loc,
+            kind: DefKind::Var,
+        });
+        Some(table.definitions.len() - 1)
+    }
+
+    fn set_kind(&mut self, def: Option<DefId>, kind: DefKind) {
+        if let (Some(table), Some(def)) = (&mut self.symbols, def) {
+            table.definitions[def].kind = kind;
+        }
+    }
+
+    // A global refers to the latest definition before it, or the first one
+    // after it
+    fn link_globals(&mut self) {
+        let Some(table) = &mut self.symbols else {
+            return;
+        };
+        for (loc, name) in std::mem::take(&mut self.global_refs) {
+            let mut defs = self
+                .global_defs
+                .iter()
+                .filter(|&&def| table.definitions[def].name == name);
+            let before = defs
+                .clone()
+                .take_while(|&&def| table.definitions[def].loc.offset <= loc.offset)
+                .last();
+            if let Some(&def) = before.or_else(|| defs.next()) {
+                table.references.push((loc, def));
+            }
         }
     }
 
@@ -45,121 +330,1321 @@ impl Resolver {
         self.scopes.push(HashMap::new());
     }
 
+    // Locals that are never read are reported, unless their name starts
+    // with `_`. Globals are not, as the REPL defines them for later. Those
+    // only assigned to are reported apart, at their last assignment
     fn end_scope(&mut self) {
-        self.scopes.pop();
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let mut unused: Vec<_> = scope
+            .into_iter()
+            .filter(|(name, b)| {
+                !b.read && !name.starts_with('_') && name != "this" && name != "super"
+            })
+            .collect();
+        unused.sort_by(|(n, a), (m, b)| (a.loc.offset, n).cmp(&(b.loc.offset, m)));
+        for (name, b) in unused {
+            self.warnings.push(match b.assigned {
+                Some(loc) => Warning::UnreadAssignment {
+                    name,
+                    loc,
+                    decl: b.loc,
+                },
+                None => Warning::UnusedVariable {
+                    name,
+                    loc: b.loc,
+                    param: b.param,
+                },
+            });
+        }
     }
 
-    pub fn resolve(&mut self, ast: &mut Prog) {
+    // Resolution goes on after an error, so that all of them are found.
+    // Nothing of a unit is kept once it is resolved, so the same resolver
+    // takes any number of them, as in the REPL, at the cost of each alone
+    pub fn resolve(&mut self, ast: &mut Prog) -> Result<Vec<Warning>, Vec<ResolveError>> {
         for s in &mut ast.stmts {
             self.resolve_stmt(s);
         }
+        self.check_unused_functions();
+        self.check_arities();
+        self.link_globals();
+        self.functions.clear();
+        self.rebound.clear();
+        self.imports = false;
+        self.docs.clear();
+        self.global_defs.clear();
+        if self.errors.is_empty() {
+            Ok(std::mem::take(&mut self.warnings))
+        } else {
+            self.warnings.clear();
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    // Functions are used when top-level code reaches them, through the
+    // functions it reads. Methods and anonymous functions count as part of
+    // the code around their declaration, so a class that is never used
+    // still keeps alive what its methods read
+    fn check_unused_functions(&mut self) {
+        let exempt =
+            |f: &FunctionUse| f.name.starts_with('_') || (f.global && (self.repl || self.library));
+        let mut used: Vec<bool> = self.functions.iter().map(exempt).collect();
+        let mut reads: Vec<_> = std::mem::take(&mut self.reads);
+        for (from, name) in std::mem::take(&mut self.global_reads) {
+            let declared = self.functions.iter().enumerate();
+            reads.extend(
+                declared
+                    .filter(|(_, f)| f.global && f.name == name)
+                    .map(|(i, _)| (from, i)),
+            );
+        }
+        let mut pending: Vec<usize> = (0..used.len()).filter(|&i| used[i]).collect();
+        pending.extend(
+            reads
+                .iter()
+                .filter(|(from, _)| from.is_none())
+                .map(|&(_, to)| to),
+        );
+        while let Some(f) = pending.pop() {
+            used[f] = true;
+            pending.extend(
+                reads
+                    .iter()
+                    .filter(|&&(from, to)| from == Some(f) && !used[to])
+                    .map(|&(_, to)| to),
+            );
+        }
+        for (f, _) in self.functions.iter().zip(used).filter(|(_, used)| !used) {
+            self.warnings.push(Warning::UnusedFunction {
+                name: f.name.clone(),
+                loc: f.loc,
+            });
+        }
+    }
+
+    // Calls by name to a function known to be the one called, that is
+    // declared by `fn` and never rebound, or a native one. Globals may be
+    // rebound by code the resolver does not see, as in the REPL, the files
+    // imported and those importing a library
+    fn check_arities(&mut self) {
+        // Only built when a call to a global is checked
+        let mut natives = None;
+        let checked_globals = !(self.repl || self.library || self.imports);
+        for (callee, got, loc) in std::mem::take(&mut self.calls) {
+            let (name, min, max, decl) = match callee {
+                Callee::Local(i) if !self.functions[i].reassigned => {
+                    let f = &self.functions[i];
+                    (f.name.clone(), f.min, f.max, Some(f.loc))
+                }
+                Callee::Global(name) if checked_globals && !self.rebound.contains(&name) => {
+                    let mut declared = self.functions.iter().filter(|f| f.global && f.name == name);
+                    match (
+                        declared.next(),
+                        natives.get_or_insert_with(globals).get(&name),
+                    ) {
+                        (Some(f), _) => (name, f.min, f.max, Some(f.loc)),
+                        (None, Some(Val::Func(f))) => (name, f.min_arity(), f.arity(), None),
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            if !(min..=max).contains(&got) {
+                self.errors.push(ResolveError::Arity {
+                    name,
+                    min,
+                    max,
+                    got,
+                    loc,
+                    decl,
+                });
+            }
+        }
+    }
+
+    // A function whose body ends in a bare value most likely meant to return it.
+    // Calls and assignments are exempt since they are evaluated for their effects
+    fn check_tail(&mut self, name: &str, body: &Stmt) {
+        if let Stmt::Block(stmts) = body {
+            if let Some(Stmt::Expr(e, at)) = stmts.last() {
+                if !matches!(e, Expr::Call(..) | Expr::Asgn(..)) {
+                    self.warnings.push(Warning::DiscardedTail {
+                        func: name.to_string(),
+                        expr: e.clone(),
+                        loc: e.loc().unwrap_or(at.0),
+                    });
+                }
+            }
+        }
+    }
+
+    // `if (x = 1)` is most likely a mistyped comparison
+    fn check_cond(&mut self, cond: &Expr) {
+        if let Expr::Asgn(var, _) = cond {
+            self.warnings.push(Warning::AsgnInCondition {
+                expr: cond.clone(),
+                loc: var.loc.0,
+            });
+        }
+    }
+
+    // An expression statement without side effects computes a value only to
+    // throw it away, and a comparison is most likely a mistyped assignment
+    fn check_unused(&mut self, e: &Expr, at: At) {
+        use Expr::*;
+        let loc = e.loc().unwrap_or(at.0);
+        match e {
+            Eq(lhs, _, _)
+            | Ne(lhs, _, _)
+            | Gt(lhs, _, _)
+            | Ge(lhs, _, _)
+            | Lt(lhs, _, _)
+            | Le(lhs, _, _) => self.warnings.push(Warning::UnusedResult {
+                expr: e.clone(),
+                asgn: matches!((e, &**lhs), (Eq(..), Var(_))),
+                loc,
+            }),
+            Lit(_) | Var(_) | List(_) | Map(..) | Index(..) | Get(..) | This(_) | Super(..) => {
+                self.warnings.push(Warning::UnusedResult {
+                    expr: e.clone(),
+                    asgn: false,
+                    loc,
+                })
+            }
+            _ => {}
+        }
     }
 
     fn resolve_stmt(&mut self, s: &mut Stmt) {
         match s {
             Stmt::Block(body) => {
                 self.begin_scope();
+                // This is the shape `for (let i = ...; ...)` desugars into
+                let loop_vars = match &body[..] {
+                    [Stmt::Decl(name, _, _), Stmt::While(..)] => vec![name.clone()],
+                    [Stmt::Let(decls), Stmt::While(..)] => decls
+                        .iter()
+                        .filter_map(|d| match d {
+                            Stmt::Decl(name, _, _) => Some(name.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                // Functions may call those declared after them in the block,
+                // as they run once all of them are. Variables stay in order
+                for s in body.iter() {
+                    if let Stmt::Func(decl) = s {
+                        self.declare_function(decl);
+                    }
+                }
+                // Reported at the first statement after the jump with a
+                // location, the jump itself if none has
+                let unreachable = body.iter().enumerate().find_map(|(i, s)| {
+                    let jump = diverges(s)?;
+                    let rest = &body[i + 1..];
+                    let loc = rest.iter().find_map(Stmt::start).unwrap_or(jump);
+                    (!rest.is_empty()).then_some(Warning::Unreachable { loc, jump })
+                });
                 for s in body {
-                    self.resolve_stmt(s);
+                    if !loop_vars.is_empty() && matches!(s, Stmt::While(..)) {
+                        let depth = self.scopes.len() - 1;
+                        let n = loop_vars.len();
+                        self.loop_vars
+                            .extend(loop_vars.iter().map(|name| (name.clone(), depth, false)));
+                        self.resolve_stmt(s);
+                        self.loop_vars.truncate(self.loop_vars.len() - n);
+                    } else {
+                        self.resolve_stmt(s);
+                    }
                 }
+                self.warnings.extend(unreachable);
                 self.end_scope();
             }
-            Stmt::Expr(expr) | Stmt::Print(expr) => self.resolve_expr(expr),
-            Stmt::Decl(var, init) => {
-                self.declare(var);
+            Stmt::Expr(expr, at) => {
+                if !self.repl {
+                    self.check_unused(expr, *at);
+                }
+                self.resolve_expr(expr)
+            }
+            Stmt::Print(expr, _) => self.resolve_expr(expr),
+            Stmt::Assert(cond, message, _) => {
+                self.resolve_expr(cond);
+                if let Some(message) = message {
+                    self.resolve_expr(message);
+                }
+            }
+            Stmt::Decl(var, init, at) => {
+                self.rebind(var);
+                self.declare(var, at.0);
                 if let Some(e) = init {
                     self.resolve_expr(e);
                 }
-                self.define(var);
+                self.define(var, at.0);
+            }
+            // The names are all at the `[`
+            Stmt::DeclPattern(vars, rest, init, at) => {
+                let vars: Vec<_> = vars.iter().chain(rest.iter()).collect();
+                for (i, var) in vars.iter().enumerate() {
+                    if self.scopes.is_empty() && vars[..i].contains(var) {
+                        self.errors.push(ResolveError::Redeclared {
+                            name: var.to_string(),
+                            loc: at.0,
+                            prev: at.0,
+                        });
+                    }
+                    self.rebind(var);
+                    self.declare(var, at.0);
+                }
+                self.resolve_expr(init);
+                vars.iter().for_each(|var| self.define(var, at.0));
             }
-            Stmt::If(cond, then_b, else_b) => {
+            Stmt::Let(decls) => {
+                let mut names: Vec<(String, Loc)> = Vec::new();
+                for d in decls {
+                    let declared: Vec<_> = match &*d {
+                        Stmt::Decl(name, _, at) => vec![(name.clone(), at.0)],
+                        Stmt::DeclPattern(vars, rest, _, at) => {
+                            vars.iter().chain(rest).map(|v| (v.clone(), at.0)).collect()
+                        }
+                        _ => vec![],
+                    };
+                    // Globals may be declared again, just not twice by the
+                    // same `let`. Locals are checked by `declare`, and names
+                    // repeated in a pattern by the pattern
+                    for (name, loc) in &declared {
+                        let prev = names.iter().find(|(n, _)| n == name);
+                        if let (true, Some(&(_, prev))) = (self.scopes.is_empty(), prev) {
+                            self.errors.push(ResolveError::Redeclared {
+                                name: name.clone(),
+                                loc: *loc,
+                                prev,
+                            });
+                        }
+                    }
+                    names.extend(declared);
+                    self.resolve_stmt(d);
+                }
+            }
+            Stmt::If(cond, then_b, else_b, _) => {
+                self.check_cond(cond);
                 self.resolve_expr(cond);
                 self.resolve_stmt(then_b);
                 if let Some(else_b) = else_b {
                     self.resolve_stmt(else_b);
                 }
             }
-            Stmt::While(cond, body) => {
+            Stmt::While(cond, body, inc, label, _) => {
+                self.check_cond(cond);
                 self.resolve_expr(cond);
+                self.loops.push((label.clone(), false));
                 self.resolve_stmt(body);
+                self.loops.pop();
+                if let Some(inc) = inc {
+                    self.resolve_expr(inc);
+                }
             }
-            Stmt::Func(name, params, body) => {
-                self.define(name);
-                let enclosing_function = self.curr_function;
-                self.curr_function = FunctionType::Function;
+            Stmt::ForIn(name, iterable, body, label, at) => {
+                self.resolve_expr(iterable);
+                // Each iteration has a scope of its own for the variable
                 self.begin_scope();
-                for p in params {
-                    self.define(p);
-                }
+                self.define(name, at.0);
+                self.loops.push((label.clone(), false));
                 self.resolve_stmt(body);
+                self.loops.pop();
+                self.end_scope();
+            }
+            Stmt::Break(label, at) => self.resolve_jump("break", label, at.0),
+            Stmt::Continue(label, at) => self.resolve_jump("continue", label, at.0),
+            Stmt::Throw(value, _) => self.resolve_expr(value),
+            Stmt::Try(body, name, handler, at) => {
+                self.resolve_stmt(body);
+                // The error is bound in a scope around the handler
+                self.begin_scope();
+                self.define(name, at.0);
+                self.resolve_stmt(handler);
                 self.end_scope();
-                self.curr_function = enclosing_function;
             }
-            Stmt::Return(ret) => {
+            // The imported file is resolved on its own when it runs
+            Stmt::Import(..) => self.imports = true,
+            Stmt::Func(decl) => {
+                let id = self.declare_function(decl);
+                let enclosing = self.curr_use.replace(id);
+                self.resolve_function(decl, FunctionType::Function);
+                self.curr_use = enclosing;
+            }
+            Stmt::Class(decl) => {
+                self.rebind(&decl.name);
+                self.declare(&decl.name, decl.loc.0);
+                self.define_item(&decl.name, decl.loc.0, DefKind::Class);
+                for (_, init) in &mut decl.fields {
+                    self.resolve_expr(init);
+                }
+                let enclosing_class = self.curr_class;
+                self.curr_class = ClassType::Class;
+                if let Some(superclass) = &mut decl.superclass {
+                    if *superclass.name == decl.name {
+                        self.errors.push(ResolveError::InheritsItself {
+                            name: decl.name.clone(),
+                            loc: superclass.loc.0,
+                        });
+                    }
+                    self.resolve_local(superclass, true);
+                    // Methods of a subclass close over a scope that binds
+                    // `super`, around the one that binds `this`
+                    self.curr_class = ClassType::Subclass;
+                    self.begin_scope();
+                    self.define("super", decl.loc.0);
+                }
+                let class_type = self.curr_class;
+                self.curr_class = ClassType::Static;
+                for m in &mut decl.statics {
+                    self.resolve_function(m, FunctionType::Method);
+                }
+                self.curr_class = class_type;
+                self.begin_scope();
+                self.define("this", decl.loc.0);
+                for m in &mut decl.methods {
+                    let kind = if m.name == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(m, kind);
+                }
+                self.end_scope();
+                if decl.superclass.is_some() {
+                    self.end_scope();
+                }
+                self.curr_class = enclosing_class;
+            }
+            Stmt::Match(value, arms, default, _) => {
+                self.resolve_expr(value);
+                let mut seen = Vec::new();
+                for (pattern, arm) in arms {
+                    // Only the first of the arms that match runs
+                    if seen.contains(&&*pattern) {
+                        self.warnings.push(Warning::DuplicateArm {
+                            pattern: Expr::Lit(pattern.clone()),
+                        });
+                    }
+                    seen.push(pattern);
+                    self.resolve_stmt(arm);
+                }
+                if let Some(arm) = default {
+                    self.resolve_stmt(arm);
+                }
+            }
+            Stmt::Loop(body, label, _) => {
+                self.loops.push((label.clone(), false));
+                self.resolve_stmt(body);
+                if let Some((_, false)) = self.loops.pop() {
+                    self.warnings.push(Warning::EndlessLoop);
+                }
+            }
+            Stmt::Return(ret, at) => {
+                for (_, exits) in &mut self.loops {
+                    *exits = true;
+                }
                 if self.curr_function == FunctionType::None {
-                    println!("Can't return from top-level code");
-                    todo!("resolver must return results");
+                    self.errors.push(ResolveError::TopLevelReturn { loc: at.0 });
+                }
+                // `init` always returns the instance
+                if self.curr_function == FunctionType::Initializer && ret.is_some() {
+                    self.errors.push(ResolveError::ReturnFromInit { loc: at.0 });
                 }
                 if let Some(expr) = ret {
                     self.resolve_expr(expr)
                 }
             }
+            // There is nothing to resolve, and it is never executed anyway
+            Stmt::Error(_) => {}
+        }
+    }
+
+    fn resolve_jump(&mut self, keyword: &'static str, label: &Option<String>, loc: Loc) {
+        let target = match label {
+            Some(_) => self.loops.iter().rposition(|(l, _)| l == label),
+            None => self.loops.len().checked_sub(1),
+        };
+        match (target, label) {
+            _ if self.loops.is_empty() => {
+                self.errors.push(ResolveError::OutsideLoop { keyword, loc })
+            }
+            // Breaking out of a loop leaves the ones inside it too
+            (Some(i), _) => {
+                for (_, exits) in &mut self.loops[i..] {
+                    *exits |= keyword == "break";
+                }
+            }
+            (None, Some(name)) => self.errors.push(ResolveError::UnknownLabel {
+                name: name.clone(),
+                loc,
+            }),
+            (None, None) => unreachable!("an unlabeled jump targets the innermost loop"),
         }
     }
 
+    fn resolve_function(&mut self, decl: &mut Rc<FunDecl>, kind: FunctionType) {
+        // Nothing else holds the declaration before it is executed,
+        // so this does not copy it
+        let FunDecl {
+            name,
+            params,
+            defaults,
+            rest,
+            body,
+            loc,
+            doc,
+            source,
+        } = Rc::make_mut(decl);
+        *doc = self.docs.remove(&loc.0.row);
+        *source = Rc::clone(&self.source);
+        if self.strict {
+            self.check_tail(name, body);
+        }
+        let enclosing_function = self.curr_function;
+        self.curr_function = kind;
+        let enclosing_scope = self.function_scope;
+        self.function_scope = self.scopes.len();
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        self.begin_scope();
+        let required = params.len() - defaults.len();
+        // The parser already rejects repeated parameters
+        for (p, at) in &params[..required] {
+            self.declare(p, at.0);
+            self.define(p, at.0);
+        }
+        // A default sees the parameters before it, but not its own
+        for ((p, at), default) in params[required..].iter().zip(defaults) {
+            self.declare(p, at.0);
+            self.resolve_expr(default);
+            self.define(p, at.0);
+        }
+        if let Some((rest, at)) = rest {
+            self.declare(rest, at.0);
+            self.define(rest, at.0);
+        }
+        let mut defs = Vec::new();
+        if let Some(scope) = self.scopes.last_mut() {
+            for b in scope.values_mut() {
+                b.param = true;
+                defs.push(b.def);
+            }
+        }
+        defs.into_iter()
+            .for_each(|def| self.set_kind(def, DefKind::Param));
+        self.resolve_stmt(body);
+        self.end_scope();
+        self.loops = enclosing_loops;
+        self.function_scope = enclosing_scope;
+        self.curr_function = enclosing_function;
+    }
+
     fn resolve_expr(&mut self, e: &mut Expr) {
         match e {
             Expr::Asgn(var, expr) => {
                 self.resolve_expr(expr);
-                self.resolve_local(var);
+                self.resolve_local(var, false);
             }
-            Expr::Call(callee, args) => {
+            Expr::Call(callee, args, at) => {
                 self.resolve_expr(callee);
+                // How many arguments a spread list makes is only known when
+                // the call is made
+                if let Expr::Var(var) = &**callee {
+                    if !args.iter().any(|a| matches!(a, Arg::Spread(_))) {
+                        self.record_call(var, args.len(), at.0);
+                    }
+                }
                 for a in args {
-                    self.resolve_expr(a)
+                    self.resolve_expr(a.expr_mut())
                 }
             }
+            Expr::Cond(cond, then_e, else_e) => {
+                self.resolve_expr(cond);
+                self.resolve_expr(then_e);
+                self.resolve_expr(else_e)
+            }
             Expr::And(lhs, rhs)
             | Expr::Or(lhs, rhs)
-            | Expr::Eq(lhs, rhs)
-            | Expr::Ne(lhs, rhs)
-            | Expr::Gt(lhs, rhs)
-            | Expr::Ge(lhs, rhs)
-            | Expr::Lt(lhs, rhs)
-            | Expr::Le(lhs, rhs)
-            | Expr::Add(lhs, rhs)
-            | Expr::Sub(lhs, rhs)
-            | Expr::Mul(lhs, rhs)
-            | Expr::Div(lhs, rhs) => {
+            | Expr::Seq(lhs, rhs)
+            | Expr::Eq(lhs, rhs, _)
+            | Expr::Ne(lhs, rhs, _)
+            | Expr::Gt(lhs, rhs, _)
+            | Expr::Ge(lhs, rhs, _)
+            | Expr::Lt(lhs, rhs, _)
+            | Expr::Le(lhs, rhs, _)
+            | Expr::BitOr(lhs, rhs, _)
+            | Expr::BitXor(lhs, rhs, _)
+            | Expr::BitAnd(lhs, rhs, _)
+            | Expr::Shl(lhs, rhs, _)
+            | Expr::Shr(lhs, rhs, _)
+            | Expr::Add(lhs, rhs, _)
+            | Expr::Sub(lhs, rhs, _)
+            | Expr::Mul(lhs, rhs, _)
+            | Expr::Div(lhs, rhs, _)
+            | Expr::Rem(lhs, rhs, _)
+            | Expr::Pow(lhs, rhs, _) => {
                 self.resolve_expr(lhs);
                 self.resolve_expr(rhs)
             }
-            Expr::Not(arg) | Expr::Opp(arg) => self.resolve_expr(arg),
+            Expr::Not(arg) | Expr::Opp(arg, _) => self.resolve_expr(arg),
+            Expr::Index(obj, idx, _) => {
+                self.resolve_expr(obj);
+                self.resolve_expr(idx)
+            }
+            Expr::SetIndex(obj, idx, value, _) => {
+                self.resolve_expr(obj);
+                self.resolve_expr(idx);
+                self.resolve_expr(value)
+            }
+            Expr::Get(obj, _, _) => self.resolve_expr(obj),
+            Expr::Set(obj, _, value, _) => {
+                self.resolve_expr(obj);
+                self.resolve_expr(value)
+            }
+            Expr::This(var) => {
+                let loc = var.loc.0;
+                match self.curr_class {
+                    ClassType::None => self.errors.push(ResolveError::ThisOutsideClass { loc }),
+                    ClassType::Static => self.errors.push(ResolveError::ThisInStatic { loc }),
+                    ClassType::Class | ClassType::Subclass => {}
+                }
+                self.resolve_local(var, true);
+            }
+            Expr::Super(var, _) => {
+                if self.curr_class != ClassType::Subclass {
+                    self.errors
+                        .push(ResolveError::SuperOutsideSubclass { loc: var.loc.0 });
+                }
+                self.resolve_local(var, true);
+            }
+            Expr::List(items) => {
+                for i in items {
+                    self.resolve_expr(i)
+                }
+            }
+            Expr::Map(entries, _) => {
+                for (k, v) in entries {
+                    self.resolve_expr(k);
+                    self.resolve_expr(v)
+                }
+            }
+            Expr::Lambda(decl) => self.resolve_function(decl, FunctionType::Function),
             Expr::Lit(_) => {}
             Expr::Var(var) => {
                 if self
                     .scopes
                     .last()
                     .and_then(|sc| sc.get(var.name.as_ref()))
-                    .is_some_and(|&val| !val)
+                    .is_some_and(|b| !b.defined)
                 {
-                    println!("Can't read local variable in its own initializer");
-                    todo!("resolver must return results");
+                    self.errors.push(ResolveError::ReadInInitializer {
+                        name: var.name.to_string(),
+                        loc: var.loc.0,
+                    });
                 }
-                self.resolve_local(var);
+                self.resolve_local(var, true);
             }
         }
     }
 
-    fn resolve_local(&mut self, var: &mut Variable) {
-        if let Some((i, _)) = self
+    // `read` is false for the target of an assignment
+    fn resolve_local(&mut self, var: &mut Variable, read: bool) {
+        if let Some((i, binding)) = self
             .scopes
-            .iter()
+            .iter_mut()
             .rev()
             .enumerate()
-            .find(|(_, scope)| scope.contains_key(&*var.name))
+            .find_map(|(i, scope)| Some((i, scope.get_mut(&*var.name)?)))
         {
+            if let (Some(table), Some(def)) = (&mut self.symbols, binding.def) {
+                table.references.push((var.loc.0, def));
+            }
+            if read {
+                binding.read = true;
+                if let Some(f) = binding.func {
+                    self.reads.push((self.curr_use, f));
+                }
+            } else {
+                binding.assigned = Some(var.loc.0);
+                if let Some(f) = binding.func {
+                    self.functions[f].reassigned = true;
+                }
+            }
             var.depth = i as isize;
+            self.check_loop_capture(var, self.scopes.len() - 1 - i);
+        } else {
+            if self.symbols.is_some() {
+                self.global_refs.push((var.loc.0, var.name.to_string()));
+            }
+            if read {
+                self.global_reads
+                    .push((self.curr_use, var.name.to_string()));
+            } else {
+                self.rebound.push(var.name.to_string());
+            }
+        }
+    }
+
+    // Declarations of globals, only top-level code makes them
+    fn rebind(&mut self, name: &str) {
+        if self.scopes.is_empty() {
+            self.rebound.push(name.to_string());
+        }
+    }
+
+    // `var` is already resolved, so its depth tells which scope holds it
+    fn record_call(&mut self, var: &Variable, args: usize, loc: Loc) {
+        let callee = match usize::try_from(var.depth) {
+            Ok(depth) => {
+                let scope = &self.scopes[self.scopes.len() - 1 - depth];
+                match scope.get(&*var.name).and_then(|b| b.func) {
+                    Some(f) => Callee::Local(f),
+                    None => return,
+                }
+            }
+            Err(_) => Callee::Global(var.name.to_string()),
+        };
+        self.calls.push((callee, args, loc));
+    }
+
+    // Every iteration of a desugared `for` shares the variable declared in its
+    // initializer, so closures created in different iterations all see its
+    // latest value rather than the one it had when they were created
+    fn check_loop_capture(&mut self, var: &Variable, scope: usize) {
+        let name = &*var.name;
+        if self.curr_function == FunctionType::None || scope >= self.function_scope {
+            return;
+        }
+        let mut loop_vars = self.loop_vars.iter_mut();
+        let loop_var = loop_vars.find(|(n, s, _)| n == name && *s == scope);
+        // Once per loop, at the first capture in it
+        if let Some((.., warned)) = loop_var.filter(|(.., warned)| !*warned) {
+            *warned = true;
+            self.warnings.push(Warning::LoopCapture {
+                name: name.to_string(),
+                loc: var.loc.0,
+            });
+        }
+    }
+}
+
+// Where the jump is that leaves a statement on every path through it, so
+// that nothing after it in a block ever runs
+fn diverges(s: &Stmt) -> Option<Loc> {
+    match s {
+        Stmt::Return(_, at) | Stmt::Break(_, at) | Stmt::Continue(_, at) | Stmt::Throw(_, at) => {
+            Some(at.0)
+        }
+        Stmt::Block(body) => body.iter().find_map(diverges),
+        Stmt::If(_, then_b, Some(else_b), _) => diverges(else_b).and(diverges(then_b)),
+        Stmt::Match(_, arms, Some(default), _)
+            if arms.iter().all(|(_, arm)| diverges(arm).is_some()) =>
+        {
+            diverges(default)
+        }
+        Stmt::Try(body, _, handler, _) => diverges(handler).and(diverges(body)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::{Parser, RecursiveDescent},
+    };
+
+    // Most tests declare functions only to lint their bodies, so those
+    // left unused are not reported here
+    fn warnings(src: &str, strict: bool) -> Vec<Warning> {
+        let mut prog = RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap();
+        let warnings = Resolver::new(strict, false).resolve(&mut prog).unwrap();
+        warnings
+            .into_iter()
+            .filter(|w| !matches!(w, Warning::UnusedFunction { .. }))
+            .collect()
+    }
+
+    fn unused_functions(src: &str) -> Vec<String> {
+        let mut prog = RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap();
+        let warnings = Resolver::new(false, false).resolve(&mut prog).unwrap();
+        warnings
+            .into_iter()
+            .filter_map(|w| match w {
+                Warning::UnusedFunction { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn errors(src: &str) -> Vec<ResolveError> {
+        let mut prog = RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap();
+        Resolver::new(false, false).resolve(&mut prog).unwrap_err()
+    }
+
+    fn repl_warnings(src: &str) -> Vec<Warning> {
+        let mut prog = RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap();
+        Resolver::new(false, true).resolve(&mut prog).unwrap()
+    }
+
+    fn resolved(src: &str) -> Vec<String> {
+        let mut prog = RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap();
+        Resolver::new(false, false).resolve(&mut prog).unwrap();
+        prog.stmts.iter().map(Stmt::to_sexpr).collect()
+    }
+
+    #[test]
+    fn depths() {
+        assert_eq!(
+            resolved("let g; fn f(a) { let b; { b = a + g; } return fn () => b; }"),
+            [
+                "(let g)",
+                "(fn f (a) (let b) (block (expr (= (var b 1) (+ (var a 2) (var g))))) \
+                 (return (fn () (return (var b 2)))))",
+            ]
+        );
+    }
+
+    #[test]
+    fn discarded_tail() {
+        let w = warnings("fn area(r) { 3.14 * r * r; }", true);
+        assert_eq!(w.len(), 1);
+        assert!(matches!(&w[0], Warning::DiscardedTail { func, expr, loc }
+            if func == "area" && expr.to_string() == "3.14 * r * r" && *loc == at(22)));
+    }
+
+    #[test]
+    fn discarded_tail_off_by_default() {
+        assert_eq!(warnings("fn area(r) { 3.14 * r * r; }", false), vec![]);
+    }
+
+    #[test]
+    fn tail_call_or_asgn() {
+        assert_eq!(warnings("fn f(g) { g(); }", true), vec![]);
+        assert_eq!(warnings("let x; fn f(v) { x = v; }", true), vec![]);
+    }
+
+    #[test]
+    fn loop_capture() {
+        let w = warnings(
+            "for (let i = 0; i < 3; i = i + 1) { fn f() { return i + i; } }",
+            false,
+        );
+        assert_eq!(
+            w,
+            vec![Warning::LoopCapture {
+                name: "i".to_string(),
+                loc: at(52),
+            }]
+        );
+    }
+
+    #[test]
+    fn loop_capture_per_loop() {
+        let src = "for (let i = 0; i < 3; i = i + 1) { fn f() { return i + i; } }
+for (let i = 0; i < 3; i = i + 1) { fn g() { return i; } }";
+        let w = warnings(src, false);
+        let locs: Vec<_> = w
+            .iter()
+            .map(|w| match w {
+                Warning::LoopCapture { name, loc } => (name.as_str(), loc.row, loc.col),
+                w => panic!("unexpected warning {w:?}"),
+            })
+            .collect();
+        assert_eq!(locs, [("i", 0, 52), ("i", 1, 52)]);
+    }
+
+    #[test]
+    fn loop_capture_nested_function() {
+        let w = warnings(
+            "for (let i = 0; i < 3; i = i + 1) { fn f() { fn g() { i = 1; } } }",
+            false,
+        );
+        assert_eq!(
+            w,
+            vec![Warning::LoopCapture {
+                name: "i".to_string(),
+                loc: at(54),
+            }]
+        );
+    }
+
+    #[test]
+    fn loop_no_capture() {
+        // Used directly by the loop body
+        assert_eq!(
+            warnings("for (let i = 0; i < 3; i = i + 1) { print i; }", false),
+            vec![]
+        );
+        // A fresh copy per iteration
+        assert_eq!(
+            warnings(
+                "for (let i = 0; i < 3; i = i + 1) { let j = i; fn f() { return j; } }",
+                false
+            ),
+            vec![]
+        );
+        // The function shadows the loop variable
+        assert_eq!(
+            warnings(
+                "for (let i = 0; i < 3; i = i + 1) { fn f(i) { return i; } }",
+                false
+            ),
+            vec![]
+        );
+        // Functions outside the loop
+        assert_eq!(
+            warnings(
+                "{ let i = 0; fn f() { return i; } for (; i < 3; i = i + 1) f(); }",
+                false
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn unused_comparison() {
+        let w = warnings("let x; x == 5;", false);
+        assert!(
+            matches!(&w[..], [Warning::UnusedResult { expr, asgn: true, loc }]
+            if expr.to_string() == "x == 5" && *loc == at(9))
+        );
+    }
+
+    #[test]
+    fn unused_call_comparison() {
+        let w = warnings("fn f() {} f() == 1; let x; x < 1;", false);
+        assert!(matches!(
+            &w[..],
+            [
+                Warning::UnusedResult { asgn: false, .. },
+                Warning::UnusedResult { asgn: false, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn unused_value() {
+        let w = warnings("let x; x; 1; \"a\";", false);
+        // Literals have no location of their own, so they are at the start
+        // of their statement
+        let locs: Vec<_> = w.iter().map(Warning::loc).collect();
+        assert_eq!(locs, [Some(at(7)), Some(at(10)), Some(at(13))]);
+    }
+
+    #[test]
+    fn used_values() {
+        assert_eq!(
+            warnings("let x; fn f() {} x = 5; f(); print x == 5;", false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn repl_unused_value() {
+        assert_eq!(repl_warnings("let x; x == 5; x;"), vec![]);
+    }
+
+    // As in the REPL, where one resolver takes every submission
+    #[test]
+    fn units_resolved_apart() {
+        let mut resolver = Resolver::new(false, false);
+        for i in 0..100 {
+            let src = format!("fn f{i}(a) {{ return a; }} let g = f{i}; f{i}(1);");
+            let mut prog = RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap();
+            assert_eq!(resolver.resolve(&mut prog), Ok(vec![]));
+            assert!(resolver.scopes.is_empty() && resolver.functions.is_empty());
+            assert!(resolver.calls.is_empty() && resolver.rebound.is_empty());
+            assert!(resolver.reads.is_empty() && resolver.global_reads.is_empty());
+        }
+        // A function of an earlier unit may have been rebound since
+        let mut prog = RecursiveDescent::parse_lexed(Lexer::new("f0();".chars())).unwrap();
+        assert_eq!(resolver.resolve(&mut prog), Ok(vec![]));
+    }
+
+    #[test]
+    fn tail_if() {
+        assert_eq!(warnings("fn f(x) { if (x) { x + 1; } }", true), vec![]);
+    }
+
+    // On the first line
+    fn at(col: usize) -> Loc {
+        Loc {
+            row: 0,
+            col,
+            offset: col,
+        }
+    }
+
+    #[test]
+    fn jump_targets() {
+        assert_eq!(
+            errors("a: while (true) { while (true) break b; }"),
+            vec![ResolveError::UnknownLabel {
+                name: "b".to_string(),
+                loc: at(31),
+            }]
+        );
+        // Labels are only in scope within their loop
+        assert_eq!(
+            errors("a: while (true) {} while (true) continue a;"),
+            vec![ResolveError::UnknownLabel {
+                name: "a".to_string(),
+                loc: at(32),
+            }]
+        );
+        assert_eq!(
+            errors("break;"),
+            vec![ResolveError::OutsideLoop {
+                keyword: "break",
+                loc: at(0),
+            }]
+        );
+        // A function does not see the loops around it
+        assert_eq!(
+            errors("a: while (true) { fn f() { continue a; } }"),
+            vec![ResolveError::OutsideLoop {
+                keyword: "continue",
+                loc: at(27),
+            }]
+        );
+        assert_eq!(
+            warnings(
+                "a: while (true) { b: for (;;) { break a; continue; } }",
+                false
+            )
+            .into_iter()
+            .filter(|w| !matches!(w, Warning::Unreachable { .. }))
+            .collect::<Vec<_>>(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn duplicate_arm() {
+        assert_eq!(
+            warnings(
+                r#"match (x) { 1 => {} "a" => {} 1.0 => {} _ => {} }"#,
+                false
+            ),
+            vec![Warning::DuplicateArm {
+                pattern: Expr::Lit(crate::val::Val::Number(1.0))
+            }]
+        );
+        assert_eq!(
+            warnings(r#"match (x) { 1 => {} "1" => {} }"#, false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn repeated_binding() {
+        let redeclared = |col, prev| {
+            vec![ResolveError::Redeclared {
+                name: "a".to_string(),
+                loc: at(col),
+                prev: at(prev),
+            }]
+        };
+        assert_eq!(errors("let a = 1, b, a = 2;"), redeclared(14, 4));
+        assert_eq!(errors("{ let a = 1, a = 2; }"), redeclared(13, 6));
+        // The names of a pattern are at its `[`
+        assert_eq!(errors("let [a, b, ...a] = [];"), redeclared(4, 4));
+        assert_eq!(errors("let [a] = [], [b, a] = [];"), redeclared(14, 4));
+        assert_eq!(errors("{ let [a, a] = []; }"), redeclared(6, 6));
+        // The same as separate statements
+        assert_eq!(warnings("let a = 1; let a = 2;", false), vec![]);
+    }
+
+    #[test]
+    fn collides_with_param() {
+        assert_eq!(
+            errors("fn f(a) { let a = 1; }"),
+            vec![ResolveError::Redeclared {
+                name: "a".to_string(),
+                loc: at(14),
+                prev: at(5),
+            }]
+        );
+        // Only the outermost block of the body clashes with them
+        assert_eq!(
+            warnings("fn f(a) { print a; { let a = 1; print a; } }", false),
+            vec![]
+        );
+        assert_eq!(
+            warnings("fn f(a, ...b) { let c = a + b; return c; }", false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn error_locations() {
+        let src = "fn f() {\n  let x = 1;\n  let x = 2;\n  let y = y;\n}";
+        assert_eq!(
+            errors(src),
+            vec![
+                ResolveError::Redeclared {
+                    name: "x".to_string(),
+                    loc: Loc {
+                        row: 2,
+                        col: 6,
+                        offset: 28
+                    },
+                    prev: Loc {
+                        row: 1,
+                        col: 6,
+                        offset: 15
+                    },
+                },
+                ResolveError::ReadInInitializer {
+                    name: "y".to_string(),
+                    loc: Loc {
+                        row: 3,
+                        col: 10,
+                        offset: 45
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn asgn_in_condition() {
+        let w = warnings("let done; while (done = true) break;", false);
+        let [Warning::AsgnInCondition { expr, loc }] = &w[..] else {
+            panic!("expected one warning, got {w:?}");
+        };
+        assert_eq!(expr.to_string(), "done = true");
+        assert_eq!((loc.row, loc.col), (0, 17));
+        assert_eq!(
+            warnings("let x; if (x = 1) {} else if (x = 2) {}", false).len(),
+            2
+        );
+        assert_eq!(
+            warnings("let x; if (x == 1) {} while ((x = 1) == 1) break;", false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn endless_loop() {
+        assert_eq!(
+            warnings("loop { print 1; }", false),
+            vec![Warning::EndlessLoop]
+        );
+        assert_eq!(
+            warnings("loop { loop { continue; } break; }", false),
+            vec![Warning::EndlessLoop]
+        );
+        // Nor does a `return` in a function declared inside it
+        assert_eq!(
+            warnings("loop { fn f() { loop { return; } } }", false),
+            vec![Warning::EndlessLoop]
+        );
+        assert_eq!(warnings("loop { if (true) break; }", false), vec![]);
+        assert_eq!(warnings("a: loop { loop { break a; } }", false), vec![]);
+        assert_eq!(
+            warnings("fn f() { loop { while (true) return; } }", false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn unused_variables() {
+        let unused = |name: &str, col, param| Warning::UnusedVariable {
+            name: name.to_string(),
+            loc: at(col),
+            param,
         };
+        assert_eq!(
+            warnings("{ let a = 1, b; }", false),
+            vec![unused("a", 6, false), unused("b", 13, false)]
+        );
+        assert_eq!(
+            warnings("fn f(a, _b) { return 1; }", false),
+            vec![unused("a", 5, true)]
+        );
+        assert_eq!(
+            warnings("fn add(a, b = 1, ...c) { return a; }", false),
+            vec![unused("b", 10, true), unused("c", 20, true)]
+        );
+        // Functions and classes are not reported when never called
+        assert_eq!(
+            warnings(
+                "{ let _a; let b = 1; fn g() {} class C {} print b; }",
+                false
+            ),
+            vec![]
+        );
+        assert_eq!(warnings("let a; fn f(x) { x = 1; }", false).len(), 1);
+    }
+
+    #[test]
+    fn unused_function() {
+        assert_eq!(unused_functions("fn f() {} fn g() {} g();"), ["f"]);
+        assert_eq!(
+            unused_functions("fn main() {} fn helper() {} fn other() { helper(); }"),
+            ["main", "helper", "other"]
+        );
+        // Reading a function is as good as calling it
+        assert!(unused_functions("fn f() {} let g = f; print g;").is_empty());
+        assert!(unused_functions("fn _f() {}").is_empty());
+    }
+
+    #[test]
+    fn unused_function_reachability() {
+        // Only calls from reachable code count, even those before the
+        // callee is declared
+        assert_eq!(
+            unused_functions("fn even(n) { odd(n); } fn odd(n) { even(n); }"),
+            ["even", "odd"]
+        );
+        assert!(
+            unused_functions("fn even(n) { odd(n); } fn odd(n) { even(n); } even(2);").is_empty()
+        );
+        // Locals are followed through their scopes, whatever they shadow
+        assert_eq!(
+            unused_functions("fn f() {} { fn f() {} f(); } { fn g() {} }"),
+            ["f", "g"]
+        );
+        assert!(unused_functions("fn f() { fn g() {} return g; } f();").is_empty());
+        assert!(repl_warnings("fn f() {}").is_empty());
+    }
+
+    #[test]
+    fn unreachable() {
+        let unreachable = |col, jump| {
+            vec![Warning::Unreachable {
+                loc: at(col),
+                jump: at(jump),
+            }]
+        };
+        assert_eq!(
+            warnings("fn f() { return 1; print 2; }", false),
+            unreachable(19, 9)
+        );
+        assert_eq!(
+            warnings("fn f(c) { if (c) return 1; print 2; }", false),
+            vec![]
+        );
+        assert_eq!(
+            warnings(
+                "fn f(c) { if (c) return 1; else return 2; print 3; }",
+                false
+            ),
+            unreachable(42, 17)
+        );
+        assert_eq!(warnings("fn f() { print 1; return 2; }", false), vec![]);
+        // Once per block, however much follows
+        assert_eq!(
+            warnings("while (true) { { break; } print 1; continue; }", false),
+            unreachable(26, 17)
+        );
+        assert_eq!(
+            warnings(
+                "a: while (true) { b: for (;;) { break a; continue; } }",
+                false
+            ),
+            unreachable(41, 32)
+        );
+        // An empty block has no location of its own
+        assert_eq!(warnings("fn f() { return; {} }", false), unreachable(9, 9));
+    }
+
+    #[test]
+    fn arity() {
+        let arity = |name: &str, (min, max), got, col, decl: Option<usize>| ResolveError::Arity {
+            name: name.to_string(),
+            min,
+            max,
+            got,
+            loc: at(col),
+            decl: decl.map(at),
+        };
+        assert_eq!(
+            errors("fn f(a, b = 1) {} f(1, 2, 3);"),
+            vec![arity("f", (1, 2), 3, 19, Some(3))]
+        );
+        assert_eq!(
+            errors("print clock(1);"),
+            vec![arity("clock", (0, 0), 1, 11, None)]
+        );
+        assert_eq!(
+            errors("{ fn f(...xs) {} { fn f(a) {} f(); } }"),
+            vec![arity("f", (1, 1), 0, 31, Some(22))]
+        );
+        assert_eq!(
+            warnings("fn f(_a, ..._b) {} f(1); f(1, 2, 3); f(...[]);", false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn arity_rebound() {
+        // Once rebound, the name may lead to any function
+        assert_eq!(warnings("fn f(_a) {} f = clock; f();", false), vec![]);
+        assert_eq!(
+            warnings("fn f() {} { fn f(_a) {} f = clock; f(); }", false),
+            vec![]
+        );
+        assert_eq!(warnings("fn f(_a) {} fn f() {} f();", false), vec![]);
+        assert_eq!(warnings("let clock = 1; clock(2);", false), vec![]);
+        assert_eq!(repl_warnings("fn f(_a) {} f();"), vec![]);
+    }
+
+    #[test]
+    fn unread_assignment() {
+        let unread = |name: &str, col, decl| {
+            vec![Warning::UnreadAssignment {
+                name: name.to_string(),
+                loc: at(col),
+                decl: at(decl),
+            }]
+        };
+        assert_eq!(
+            warnings("fn f() { let x = 0; x = g(); x = h(); return; }", false),
+            unread("x", 29, 13)
+        );
+        // The value of an assignment is read, not the variable it assigns
+        assert_eq!(
+            warnings("{ let x, y; y = (x = 1); print y; }", false),
+            unread("x", 17, 6)
+        );
+        assert_eq!(
+            warnings("{ let x = 0; x = 1; { print x; } }", false),
+            vec![]
+        );
+        assert_eq!(warnings("{ let _x = 0; _x = 1; }", false), vec![]);
+    }
+
+    #[test]
+    fn sibling_functions() {
+        assert_eq!(
+            resolved(
+                "fn f(n) { fn even(n) { return n == 0 or odd(n - 1); } \
+                 fn odd(n) { return n != 0 and even(n - 1); } return even(n); }"
+            )[0],
+            "(fn f (n) (fn even (n) (return (or (== (var n 1) 0) \
+             (call (var odd 2) (- (var n 1) 1))))) \
+             (fn odd (n) (return (and (!= (var n 1) 0) \
+             (call (var even 2) (- (var n 1) 1))))) \
+             (return (call (var even 0) (var n 1))))"
+        );
+        assert_eq!(
+            resolved("{ fn a() { return b(); } fn b() { return 1; } print a(); }"),
+            ["(block (fn a () (return (call (var b 2)))) \
+              (fn b () (return 1)) (print (call (var a 0))))"]
+        );
+        // A variable read before its declaration is still a global
+        assert_eq!(
+            resolved("{ fn f() { return x; } let x = 1; print f() + x; }"),
+            ["(block (fn f () (return (var x))) (let x 1) \
+              (print (+ (call (var f 0)) (var x 0))))"]
+        );
     }
 }
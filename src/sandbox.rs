@@ -0,0 +1,26 @@
+// The limits of `Interpreter::sandboxed`, for running code that is not
+// trusted, kept together so that the profile can be audited at a glance.
+// Each only ever lowers what the host configured. They bound every value
+// and the work of a run, not the memory of the process as a whole
+
+// Calls in progress, well within the native stack of any thread
+pub const MAX_CALL_DEPTH: usize = 128;
+
+// How deeply code may nest, such as parentheses, blocks or chained
+// operators, since the parser, the resolver and the interpreter all recurse
+// into it. Deeper code fails to parse with `ParserError::TooDeep`. Even
+// unoptimized, the deepest code takes well under a thread's stack
+pub const MAX_NESTING: usize = 32;
+
+// Statements a run may execute, a second or so of work
+pub const FUEL: u64 = 1_000_000;
+
+// The longest string a script may build, in bytes
+pub const MAX_STRING_LEN: usize = 1 << 20;
+
+// The most items a script may push to a list
+pub const MAX_LIST_LEN: usize = 1 << 16;
+
+// What a sandboxed script may not do at all, failing with
+// `RuntimeError::SandboxViolation` when it tries
+pub const IMPORT: &str = "import";
@@ -1,6 +1,6 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::{expr::Variable, val::Val};
+use crate::{error::RuntimeError, expr::Variable, val::Val};
 
 #[derive(Debug, PartialEq)]
 pub enum Scope {
@@ -25,7 +25,7 @@ impl Scope {
         })
     }
 
-    fn get_global<'a>(self: &'a Rc<Self>) -> &'a Rc<Self> {
+    pub fn get_global(self: &Rc<Self>) -> &Rc<Self> {
         match self.as_ref() {
             Self::Global(_) => self,
             Self::Local { global, .. } => global,
@@ -45,7 +45,7 @@ impl Scope {
         }
     }
 
-    fn get_ancestor<'a>(self: &'a Rc<Self>, dist: isize) -> &'a Rc<Self> {
+    fn get_ancestor(self: &Rc<Self>, dist: isize) -> &Rc<Self> {
         let mut env = self;
         for _ in 0..dist {
             env = env.get_outer().expect("Resolver must set a valid depth")
@@ -57,7 +57,7 @@ impl Scope {
         self.get_values().borrow_mut().insert(name.to_string(), val);
     }
 
-    pub fn get(self: &Rc<Self>, var: &Variable) -> Result<Val, ()> {
+    pub fn get(self: &Rc<Self>, var: &Variable) -> Result<Val, RuntimeError> {
         let env = if var.depth < 0 {
             self.get_global()
         } else {
@@ -67,12 +67,13 @@ impl Scope {
         if let Some(val) = env.get_values().borrow().get(&*var.name) {
             Ok(val.clone())
         } else {
-            println!("Undefined variable '{}'", var.name);
-            Err(())
+            Err(RuntimeError::UndefinedVariable {
+                name: var.name.to_string(),
+            })
         }
     }
 
-    pub fn asgn(self: &Rc<Self>, var: &Variable, new: Val) -> Result<(), ()> {
+    pub fn asgn(self: &Rc<Self>, var: &Variable, new: Val) -> Result<(), RuntimeError> {
         let env = if var.depth < 0 {
             self.get_global()
         } else {
@@ -83,8 +84,9 @@ impl Scope {
             *val = new;
             Ok(())
         } else {
-            println!("Undefined variable '{}'", var.name);
-            Err(())
+            Err(RuntimeError::UndefinedVariable {
+                name: var.name.to_string(),
+            })
         }
     }
 }
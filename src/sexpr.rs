@@ -0,0 +1,295 @@
+// A compact dump of the tree as S-expressions, `(+ (var x) (* 3 8))`, for
+// tests that check the shape of what was parsed. Locations are left out.
+// Variables show their depth once resolved, `(var x 1)`, and only their
+// name before that or when they are global
+
+use crate::{
+    expr::{Arg, Expr, Variable},
+    stmt::{FunDecl, Stmt},
+    val::Val,
+};
+
+fn list(head: &str, items: impl IntoIterator<Item = String>) -> String {
+    let mut out = format!("({head}");
+    for item in items {
+        out.push(' ');
+        out += &item;
+    }
+    out.push(')');
+    out
+}
+
+fn lit(v: &Val) -> String {
+    match v {
+        Val::String(s) => format!("\"{s}\""),
+        v => v.to_string(),
+    }
+}
+
+// ` 1` for a variable resolved one scope out, nothing for a global
+fn depth(var: &Variable) -> String {
+    match var.depth {
+        -1 => String::new(),
+        depth => format!(" {depth}"),
+    }
+}
+
+fn var(head: &str, var: &Variable) -> String {
+    format!("({head} {}{})", var.name, depth(var))
+}
+
+fn fun(decl: &FunDecl, name: Option<&str>) -> String {
+    let required = decl.params.len() - decl.defaults.len();
+    let params = decl
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, (p, _))| match i.checked_sub(required) {
+            Some(d) => format!("(= {p} {})", decl.defaults[d].to_sexpr()),
+            None => p.clone(),
+        });
+    let rest = decl.rest.iter().map(|(r, _)| format!("(... {r})"));
+    let params = format!("({})", params.chain(rest).collect::<Vec<_>>().join(" "));
+    let Stmt::Block(body) = &decl.body else {
+        unreachable!("function bodies are blocks")
+    };
+    let head = name.map_or("fn".to_string(), |name| format!("fn {name}"));
+    list(
+        &head,
+        [params].into_iter().chain(body.iter().map(Stmt::to_sexpr)),
+    )
+}
+
+impl Expr {
+    pub fn to_sexpr(&self) -> String {
+        use Expr::*;
+        let (op, lhs, rhs) = match self {
+            Asgn(v, value) => return list("=", [var("var", v), value.to_sexpr()]),
+            Call(callee, args, _) => {
+                let args = args.iter().map(|a| match a {
+                    Arg::Positional(e) => e.to_sexpr(),
+                    Arg::Spread(e) => list("...", [e.to_sexpr()]),
+                });
+                return list("call", [callee.to_sexpr()].into_iter().chain(args));
+            }
+            Cond(cond, then_e, else_e) => {
+                return list("?", [cond, then_e, else_e].map(|e| e.to_sexpr()))
+            }
+            Not(arg) => return list("!", [arg.to_sexpr()]),
+            Opp(arg, _) => return list("-", [arg.to_sexpr()]),
+            Lit(v) => return lit(v),
+            Var(v) => return var("var", v),
+            List(items) => return list("list", items.iter().map(Expr::to_sexpr)),
+            Map(entries, _) => {
+                let entries = entries
+                    .iter()
+                    .map(|(k, v)| format!("({} {})", k.to_sexpr(), v.to_sexpr()));
+                return list("map", entries);
+            }
+            SetIndex(obj, idx, value, _) => {
+                return list("[]=", [obj, idx, value].map(|e| e.to_sexpr()))
+            }
+            Get(obj, name, _) => return list(".", [obj.to_sexpr(), name.clone()]),
+            Set(obj, name, value, _) => {
+                return list(".=", [obj.to_sexpr(), name.clone(), value.to_sexpr()])
+            }
+            This(v) => return format!("(this{})", depth(v)),
+            Super(v, method) => return format!("(super {method}{})", depth(v)),
+            Lambda(decl) => return fun(decl, None),
+            Seq(lhs, rhs) => (",", lhs, rhs),
+            And(lhs, rhs) => ("and", lhs, rhs),
+            Or(lhs, rhs) => ("or", lhs, rhs),
+            Eq(lhs, rhs, _) => ("==", lhs, rhs),
+            Ne(lhs, rhs, _) => ("!=", lhs, rhs),
+            Gt(lhs, rhs, _) => (">", lhs, rhs),
+            Ge(lhs, rhs, _) => (">=", lhs, rhs),
+            Lt(lhs, rhs, _) => ("<", lhs, rhs),
+            Le(lhs, rhs, _) => ("<=", lhs, rhs),
+            BitOr(lhs, rhs, _) => ("|", lhs, rhs),
+            BitXor(lhs, rhs, _) => ("^", lhs, rhs),
+            BitAnd(lhs, rhs, _) => ("&", lhs, rhs),
+            Shl(lhs, rhs, _) => ("<<", lhs, rhs),
+            Shr(lhs, rhs, _) => (">>", lhs, rhs),
+            Add(lhs, rhs, _) => ("+", lhs, rhs),
+            Sub(lhs, rhs, _) => ("-", lhs, rhs),
+            Mul(lhs, rhs, _) => ("*", lhs, rhs),
+            Div(lhs, rhs, _) => ("/", lhs, rhs),
+            Rem(lhs, rhs, _) => ("%", lhs, rhs),
+            Pow(lhs, rhs, _) => ("**", lhs, rhs),
+            Index(lhs, rhs, _) => ("[]", lhs, rhs),
+        };
+        list(op, [lhs.to_sexpr(), rhs.to_sexpr()])
+    }
+}
+
+impl Stmt {
+    pub fn to_sexpr(&self) -> String {
+        let opt_expr = |e: &Option<Expr>| e.iter().map(Expr::to_sexpr).collect::<Vec<_>>();
+        let opt_label = |l: &Option<String>| l.iter().cloned().collect::<Vec<_>>();
+        let labeled = |label: &Option<String>, s: String| match label {
+            Some(label) => list("label", [label.clone(), s]),
+            None => s,
+        };
+        match self {
+            Stmt::Block(stmts) => list("block", stmts.iter().map(Stmt::to_sexpr)),
+            Stmt::Expr(e, _) => list("expr", [e.to_sexpr()]),
+            Stmt::Print(e, _) => list("print", [e.to_sexpr()]),
+            Stmt::Assert(cond, message, _) => list(
+                "assert",
+                [cond.to_sexpr()].into_iter().chain(opt_expr(message)),
+            ),
+            Stmt::Decl(name, init, _) => {
+                list("let", [name.clone()].into_iter().chain(opt_expr(init)))
+            }
+            Stmt::DeclPattern(names, rest, init, _) => {
+                let rest = rest.iter().map(|r| format!("(... {r})"));
+                let names = format!(
+                    "({})",
+                    names
+                        .iter()
+                        .cloned()
+                        .chain(rest)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+                list("let", [names, init.to_sexpr()])
+            }
+            Stmt::Let(decls) => list("let*", decls.iter().map(Stmt::to_sexpr)),
+            Stmt::If(cond, then_s, else_s, _) => {
+                let else_s = else_s.iter().map(|s| s.to_sexpr());
+                list(
+                    "if",
+                    [cond.to_sexpr(), then_s.to_sexpr()]
+                        .into_iter()
+                        .chain(else_s),
+                )
+            }
+            Stmt::While(cond, body, inc, label, _) => {
+                let s = [cond.to_sexpr(), body.to_sexpr()]
+                    .into_iter()
+                    .chain(opt_expr(inc));
+                labeled(label, list("while", s))
+            }
+            Stmt::Loop(body, label, _) => labeled(label, list("loop", [body.to_sexpr()])),
+            Stmt::ForIn(name, iterable, body, label, _) => {
+                let s = [name.clone(), iterable.to_sexpr(), body.to_sexpr()];
+                labeled(label, list("for-in", s))
+            }
+            Stmt::Break(label, _) => list("break", opt_label(label)),
+            Stmt::Continue(label, _) => list("continue", opt_label(label)),
+            Stmt::Match(value, arms, default, _) => {
+                let arms = arms
+                    .iter()
+                    .map(|(v, s)| list("case", [lit(v), s.to_sexpr()]));
+                let default = default.iter().map(|s| list("_", [s.to_sexpr()]));
+                list(
+                    "match",
+                    [value.to_sexpr()].into_iter().chain(arms).chain(default),
+                )
+            }
+            Stmt::Func(decl) => fun(decl, Some(&decl.name)),
+            Stmt::Class(decl) => {
+                let superclass = decl.superclass.iter().map(|s| var("<", s));
+                let fields = decl
+                    .fields
+                    .iter()
+                    .map(|(name, init)| list("field", [name.clone(), init.to_sexpr()]));
+                let statics = decl
+                    .statics
+                    .iter()
+                    .map(|m| list("static", [fun(m, Some(&m.name))]));
+                let methods = decl.methods.iter().map(|m| fun(m, Some(&m.name)));
+                let items = superclass.chain(fields).chain(statics).chain(methods);
+                list(&format!("class {}", decl.name), items)
+            }
+            Stmt::Return(value, _) => list("return", opt_expr(value)),
+            Stmt::Throw(value, _) => list("throw", [value.to_sexpr()]),
+            Stmt::Try(body, name, handler, _) => {
+                let catch = list("catch", [name.clone(), handler.to_sexpr()]);
+                list("try", [body.to_sexpr(), catch])
+            }
+            Stmt::Import(path, _) => list("import", [lit(&Val::String(path.as_str().into()))]),
+            Stmt::Error(_) => "(error)".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::expr::At;
+
+    #[test]
+    fn expressions() {
+        use Expr::*;
+
+        let num = |x| Box::new(Lit(Val::Number(x)));
+        let e = Add(
+            Box::new(Var(Variable::new("x"))),
+            Box::new(Mul(num(3.0), num(8.5), At::default())),
+            At::default(),
+        );
+        assert_eq!(e.to_sexpr(), "(+ (var x) (* 3 8.5))");
+        let e = Call(
+            Box::new(Get(
+                Box::new(This(Variable::new("this"))),
+                "f".to_string(),
+                At::default(),
+            )),
+            vec![
+                Arg::Positional(Lit(Val::String("a b".into()))),
+                Arg::Spread(Opp(num(1.0), At::default())),
+            ],
+            At::default(),
+        );
+        assert_eq!(e.to_sexpr(), r#"(call (. (this) f) "a b" (... (- 1)))"#);
+        let e = Asgn(
+            Variable {
+                depth: 2,
+                ..Variable::new("a")
+            },
+            Box::new(List(vec![Lit(Val::Nil), Lit(Val::Boolean(true))])),
+        );
+        assert_eq!(e.to_sexpr(), "(= (var a 2) (list nil true))");
+    }
+
+    #[test]
+    fn statements() {
+        let decl = FunDecl {
+            name: "f".to_string(),
+            params: vec![
+                ("a".to_string(), At::default()),
+                ("b".to_string(), At::default()),
+            ],
+            defaults: vec![Expr::Lit(Val::Number(1.0))],
+            rest: Some(("r".to_string(), At::default())),
+            body: Stmt::Block(vec![Stmt::Return(
+                Some(Expr::Var(Variable::new("a"))),
+                At::default(),
+            )]),
+            loc: At::default(),
+            doc: None,
+            source: Rc::default(),
+        };
+        assert_eq!(
+            Stmt::Func(Rc::new(decl)).to_sexpr(),
+            "(fn f (a (= b 1) (... r)) (return (var a)))"
+        );
+        let s = Stmt::While(
+            Expr::Lit(Val::Boolean(true)),
+            Box::new(Stmt::Block(vec![
+                Stmt::Decl("x".to_string(), None, At::default()),
+                Stmt::Break(Some("outer".to_string()), At::default()),
+            ])),
+            None,
+            Some("outer".to_string()),
+            At::default(),
+        );
+        assert_eq!(
+            s.to_sexpr(),
+            "(label outer (while true (block (let x) (break outer))))"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::{borrow::Cow, rc::Rc};
+
+use crate::lexer::Loc;
+
+pub const TAB_WIDTH: usize = 4;
+
+// A location along with the name of the source it is in, for what runs
+// after the unit of code it belongs to, such as functions of the prelude
+#[derive(Clone, PartialEq, Debug)]
+pub struct Located {
+    pub source: Rc<str>,
+    pub loc: Loc,
+}
+
+// Index over a source text, built once per run so diagnostics can find any
+// line without rescanning the whole text. A REPL session appends to it
+pub struct SourceMap<'src> {
+    name: String,
+    src: Cow<'src, str>,
+    // Byte offset at which each line starts
+    lines: Vec<usize>,
+    // Where the text last appended starts, the one unit of code compiled
+    unit: Loc,
+    // How many columns apart tab stops are when lines are displayed
+    tab_width: usize,
+}
+
+impl<'src> SourceMap<'src> {
+    pub fn new(name: impl Into<String>, src: &'src str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            name: name.into(),
+            src: Cow::Borrowed(src),
+            lines,
+            unit: Loc::default(),
+            tab_width: TAB_WIDTH,
+        }
+    }
+
+    // A map that owns its text, to be kept after the text it was made from
+    pub fn into_owned(self) -> SourceMap<'static> {
+        SourceMap {
+            name: self.name,
+            src: Cow::Owned(self.src.into_owned()),
+            lines: self.lines,
+            unit: self.unit,
+            tab_width: self.tab_width,
+        }
+    }
+
+    // Adds a unit of code on the lines after the text so far, indexing only
+    // those lines
+    pub fn append(&mut self, text: &str) {
+        let src = self.src.to_mut();
+        if !src.is_empty() && !src.ends_with('\n') {
+            src.push('\n');
+            self.lines.push(src.len());
+        }
+        self.unit = Loc {
+            row: self.lines.len() - 1,
+            col: 0,
+            offset: src.len(),
+        };
+        let start = src.len();
+        src.push_str(text);
+        let lines = text.match_indices('\n').map(|(i, _)| start + i + 1);
+        self.lines.extend(lines);
+    }
+
+    // The text last appended, or all of it, and where it starts
+    pub fn unit(&self) -> (&str, Loc) {
+        (&self.src[self.unit.offset..], self.unit)
+    }
+
+    pub fn tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width.max(1);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.src
+    }
+
+    pub fn line_text(&self, row: usize) -> Option<&str> {
+        let start = *self.lines.get(row)?;
+        let end = self.lines.get(row + 1).copied().unwrap_or(self.src.len());
+        Some(self.src[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    // A line as diagnostics display it, with its tabs expanded into spaces,
+    // so that it lines up with markers under it on any terminal
+    pub fn display_line(&self, row: usize) -> Option<String> {
+        let mut out = String::new();
+        for c in self.line_text(row)?.chars() {
+            if c == '\t' {
+                let stop = (out.chars().count() / self.tab_width + 1) * self.tab_width;
+                out.extend(std::iter::repeat_n(' ', stop - out.chars().count()));
+            } else {
+                out.push(c);
+            }
+        }
+        Some(out)
+    }
+
+    // Where the character at `loc` is displayed by `display_line`
+    pub fn display_col(&self, loc: Loc) -> usize {
+        let line = self.line_text(loc.row).unwrap_or_default();
+        line.chars().take(loc.col).fold(0, |col, c| {
+            if c == '\t' {
+                (col / self.tab_width + 1) * self.tab_width
+            } else {
+                col + 1
+            }
+        })
+    }
+
+    // Tokens know their offset, this is for positions that come from
+    // elsewhere, such as an editor. Columns count characters, not bytes
+    pub fn offset_of(&self, row: usize, col: usize) -> Option<usize> {
+        let line = self.line_text(row)?;
+        let start = self.lines[row];
+        match line.char_indices().nth(col) {
+            Some((i, _)) => Some(start + i),
+            None if line.chars().count() == col => Some(start + line.len()),
+            None => None,
+        }
+    }
+
+    pub fn offset_to_loc(&self, offset: usize) -> Option<Loc> {
+        if offset > self.src.len() || !self.src.is_char_boundary(offset) {
+            return None;
+        }
+        let row = self.lines.partition_point(|&start| start <= offset) - 1;
+        let col = self.src[self.lines[row]..offset].chars().count();
+        Some(Loc { row, col, offset })
+    }
+
+    // Where an unexpected end of file is reported: right after the last
+    // character that is not a trailing line break
+    pub fn eof_loc(&self) -> Loc {
+        let end = self.src.trim_end_matches(['\n', '\r']).len();
+        self.offset_to_loc(end)
+            .expect("The end of the trimmed text is a char boundary")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn first_line() {
+        let map = SourceMap::new("test", "let x = 1;\nprint x;\n");
+        assert_eq!(map.line_text(0), Some("let x = 1;"));
+    }
+
+    #[test]
+    fn last_line_without_newline() {
+        let map = SourceMap::new("test", "let x = 1;\r\nprint x;");
+        assert_eq!(map.line_text(0), Some("let x = 1;"));
+        assert_eq!(map.line_text(1), Some("print x;"));
+        assert_eq!(map.line_text(2), None);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let map = SourceMap::new("test", "a\nb\n");
+        assert_eq!(map.line_text(1), Some("b"));
+        assert_eq!(map.line_text(2), Some(""));
+        assert_eq!(map.line_text(3), None);
+        assert_eq!(map.line_text(usize::MAX), None);
+        assert_eq!(SourceMap::new("test", "").line_text(0), Some(""));
+    }
+
+    #[test]
+    fn offsets() {
+        let map = SourceMap::new("test", "ab\nñb\n");
+        let loc = |row, col, offset| Some(Loc { row, col, offset });
+        assert_eq!(map.offset_of(1, 1), Some(5));
+        assert_eq!(map.offset_to_loc(5), loc(1, 1, 5));
+        assert_eq!(map.offset_to_loc(2), loc(0, 2, 2));
+        assert_eq!(map.offset_to_loc(3), loc(1, 0, 3));
+        // In the middle of 'ñ'
+        assert_eq!(map.offset_to_loc(4), None);
+        assert_eq!(map.offset_of(1, 2), Some(6));
+        assert_eq!(map.offset_of(1, 3), None);
+        assert_eq!(map.offset_to_loc(100), None);
+    }
+
+    #[test]
+    fn tabs() {
+        let at = |row, col| Loc {
+            row,
+            col,
+            ..Loc::default()
+        };
+        let map = SourceMap::new("test", "\tlet x;\n \t x\n\ta\tb");
+        assert_eq!(map.display_line(0).unwrap(), "    let x;");
+        assert_eq!(map.display_col(at(0, 1)), 4);
+        // The space and the tab share a tab stop
+        assert_eq!(map.display_line(1).unwrap(), "     x");
+        assert_eq!(map.display_col(at(1, 3)), 5);
+        assert_eq!(map.display_col(at(2, 3)), 8);
+        let map = map.tab_width(8);
+        assert_eq!(map.display_line(2).unwrap(), "        a       b");
+        assert_eq!(map.display_col(at(2, 3)), 16);
+    }
+
+    #[test]
+    fn append() {
+        let mut map = SourceMap::new("<stdin>", "");
+        map.append("let x = 1;\n");
+        map.append("print x;");
+        map.append("x;\n");
+        assert_eq!(map.text(), "let x = 1;\nprint x;\nx;\n");
+        let (unit, start) = map.unit();
+        assert_eq!(unit, "x;\n");
+        assert_eq!(
+            start,
+            Loc {
+                row: 2,
+                col: 0,
+                offset: 20
+            }
+        );
+        assert_eq!(map.line_text(1), Some("print x;"));
+        assert_eq!(map.offset_to_loc(start.offset + 1).map(|l| l.col), Some(1));
+        assert_eq!(map.line_text(3), Some(""));
+    }
+
+    #[test]
+    fn eof() {
+        assert_eq!(
+            SourceMap::new("test", "a\nbc\n\n").eof_loc(),
+            Loc {
+                row: 1,
+                col: 2,
+                offset: 4
+            }
+        );
+        assert_eq!(SourceMap::new("test", "").eof_loc(), Loc::default());
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to compare
+    // against rescanning the text for every diagnostic
+    #[test]
+    #[ignore]
+    fn bench_many_diagnostics() {
+        use std::time::Instant;
+
+        let src = "let x = 1 + 2;\n".repeat(100_000);
+        let rows = (0..100_000).step_by(10);
+
+        let start = Instant::now();
+        let map = SourceMap::new("bench", &src);
+        let total: usize = rows.clone().map(|r| map.line_text(r).unwrap().len()).sum();
+        let indexed = start.elapsed();
+
+        let start = Instant::now();
+        let rescan: usize = rows.map(|r| src.lines().nth(r).unwrap().len()).sum();
+        let rescanned = start.elapsed();
+
+        assert_eq!(total, rescan);
+        println!("10k diagnostics on 100k lines: indexed {indexed:?}, rescanning {rescanned:?}");
+    }
+}
@@ -1,77 +1,1229 @@
-use std::rc::Rc;
+use std::{collections::HashMap, rc::Rc};
 
 use crate::{
-    expr::Expr,
+    class::Class,
+    collections::List,
+    context::Context,
+    error::RuntimeError,
+    expr::{equals, At, Expr, Variable},
+    import::import,
+    lexer::Loc,
+    sandbox,
     scope::Scope,
+    source::Located,
     val::{Function, Val},
 };
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Stmt {
     Block(Vec<Stmt>),
-    Expr(Expr),
-    Print(Expr),
-    Decl(String, Option<Expr>),
-    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
-    Func(String, Vec<String>, Box<Stmt>),
-    Return(Option<Expr>),
+    // At the start of the expression
+    Expr(Expr, At),
+    // At the `print`
+    Print(Expr, At),
+    // `assert cond, message;`, where the message is only evaluated when
+    // the condition does not hold
+    Assert(Expr, Option<Expr>, At),
+    // At the name
+    Decl(String, Option<Expr>, At),
+    // `let [a, b, ...rest] = xs;`, with the names, the rest and the list
+    // destructured, at the `[`
+    DeclPattern(Vec<String>, Option<String>, Expr, At),
+    // `let a = 1, b;`, a `Decl` per binding made in the enclosing scope
+    Let(Vec<Stmt>),
+    // At the `if`
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>, At),
+    // The condition, the body, the increment of a `for` and the label. The
+    // increment is kept apart so that it still runs after a `continue`.
+    // Loops and jumps are at their keyword, a desugared `for` at the `for`
+    While(Expr, Box<Stmt>, Option<Expr>, Option<String>, At),
+    // `loop { ... }`, which only ends by jumping out of it
+    Loop(Box<Stmt>, Option<String>, At),
+    // `for (x in xs) ...`, with the variable, the value iterated over, the
+    // body and the label
+    ForIn(String, Expr, Box<Stmt>, Option<String>, At),
+    Break(Option<String>, At),
+    Continue(Option<String>, At),
+    // The value matched, the arms with a literal each and the `_` arm, at
+    // the `match`
+    Match(Expr, Vec<(Val, Stmt)>, Option<Box<Stmt>>, At),
+    Func(Rc<FunDecl>),
+    Class(ClassDecl),
+    // At the `return`, or the `=>` of an arrow function
+    Return(Option<Expr>, At),
+    // At the `throw`
+    Throw(Expr, At),
+    // The body, and the name the error caught is bound to in the handler,
+    // at that name
+    Try(Box<Stmt>, String, Box<Stmt>, At),
+    // `import "file.lox";`, the path as written
+    Import(String, At),
+    // Stands in for a statement that failed to parse
+    Error(Loc),
+}
+
+// Declarations are shared between the tree and every closure created from
+// them, so executing a declaration never copies the body
+#[derive(PartialEq, Debug, Clone)]
+pub struct FunDecl {
+    pub name: String,
+    // Each at its name
+    pub params: Vec<(String, At)>,
+    // The default values of the last `defaults.len()` parameters
+    pub defaults: Vec<Expr>,
+    // Takes the arguments after the parameters as a list
+    pub rest: Option<(String, At)>,
+    pub body: Stmt,
+    // At the name, or the `fn` of an anonymous function
+    pub loc: At,
+    // The `///` comment just above it, attached by the resolver
+    pub doc: Option<String>,
+    // The name of the source it is declared in, also attached by the
+    // resolver
+    pub source: Rc<str>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ClassDecl {
+    pub name: String,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Rc<FunDecl>>,
+    pub statics: Vec<Rc<FunDecl>>,
+    // Class-level constants, evaluated when the class is declared
+    pub fields: Vec<(String, Expr)>,
+    // At the name
+    pub loc: At,
+}
+
+// Where execution goes after a statement. Jumps carry the label they
+// target, so that loops in between pass them on
+#[derive(PartialEq, Debug, Clone)]
+pub enum Flow {
+    Next,
+    Return(Val),
+    Break(Option<String>),
+    Continue(Option<String>),
 }
 
 impl Stmt {
-    pub fn exec(&self, scope: Rc<Scope>) -> Result<Val, ()> {
+    pub fn exec(&self, ctx: &mut Context, scope: Rc<Scope>) -> Result<Flow, RuntimeError> {
+        if let Err(e) = ctx.burn() {
+            ctx.fail_at(self.start());
+            return Err(e);
+        }
+        let res = self.exec_here(ctx, scope);
+        if res.is_err() {
+            ctx.fail_at(self.fails_at());
+        }
+        res
+    }
+
+    // Where the errors of statements that fail by themselves, rather than
+    // through an expression in them, are reported
+    fn fails_at(&self) -> Option<Loc> {
+        match self {
+            Self::Assert(.., at)
+            | Self::DeclPattern(.., at)
+            | Self::ForIn(.., at)
+            | Self::Throw(_, at)
+            | Self::Import(_, at) => Some(at.0),
+            Self::Class(decl) => Some(decl.loc.0),
+            _ => None,
+        }
+    }
+
+    // Where the statement starts, as far as the tree keeps it. Only an
+    // empty block has no location
+    pub(crate) fn start(&self) -> Option<Loc> {
+        match self {
+            Self::Block(stmts) | Self::Let(stmts) => stmts.iter().find_map(Stmt::start),
+            Self::Expr(_, at)
+            | Self::Print(_, at)
+            | Self::Assert(.., at)
+            | Self::Decl(.., at)
+            | Self::DeclPattern(.., at)
+            | Self::If(.., at)
+            | Self::While(.., at)
+            | Self::Loop(.., at)
+            | Self::ForIn(.., at)
+            | Self::Break(_, at)
+            | Self::Continue(_, at)
+            | Self::Match(.., at)
+            | Self::Return(_, at)
+            | Self::Throw(_, at)
+            | Self::Try(.., at)
+            | Self::Import(_, at) => Some(at.0),
+            Self::Func(decl) => Some(decl.loc.0),
+            Self::Class(decl) => Some(decl.loc.0),
+            Self::Error(loc) => Some(*loc),
+        }
+    }
+
+    fn exec_here(&self, ctx: &mut Context, scope: Rc<Scope>) -> Result<Flow, RuntimeError> {
         match self {
             Self::Block(stmts) => {
                 let inner = Scope::new_local(&scope);
                 for s in stmts {
-                    let val = s.exec(Rc::clone(&inner))?;
-                    if val != Val::NoVal {
-                        return Ok(val);
+                    let flow = s.exec(ctx, Rc::clone(&inner))?;
+                    if flow != Flow::Next {
+                        return Ok(flow);
                     }
                 }
-                Ok(Val::NoVal)
+                Ok(Flow::Next)
             }
-            Self::Expr(e) => e.eval(scope).map(|_| Val::NoVal),
-            Self::Print(e) => {
-                let e = e.eval(scope)?;
-                println!("{e}");
-                Ok(Val::NoVal)
+            Self::Expr(e, _) => e.eval(ctx, scope).map(|_| Flow::Next),
+            Self::Print(e, _) => {
+                let e = e.eval(ctx, scope)?;
+                ctx.print(e);
+                Ok(Flow::Next)
             }
-            Self::Decl(name, expr) => {
+            Self::Assert(cond, message, _) => {
+                if cond.eval(ctx, Rc::clone(&scope))?.is_truthy() {
+                    return Ok(Flow::Next);
+                }
+                let message = match message {
+                    Some(e) => Some(e.eval(ctx, scope)?.to_string()),
+                    None => None,
+                };
+                Err(RuntimeError::AssertionFailed { message })
+            }
+            Self::Decl(name, expr, _) => {
                 let init = if let Some(e) = expr {
-                    e.eval(Rc::clone(&scope))?
+                    e.eval(ctx, Rc::clone(&scope))?
                 } else {
                     Val::Nil
                 };
                 scope.def(name, init);
-                Ok(Val::NoVal)
+                Ok(Flow::Next)
             }
-            Self::If(cond, then_branch, else_branch) => {
-                let ret = if cond.eval(Rc::clone(&scope))?.into() {
-                    then_branch.exec(scope)?
+            Self::DeclPattern(names, rest, init, _) => {
+                let Val::List(list) = init.eval(ctx, Rc::clone(&scope))? else {
+                    return Err(RuntimeError::TypeMismatch {
+                        msg: "can only destructure lists",
+                    });
+                };
+                // SEMANTICS: items past the names are left out without a
+                // rest, the list only needs to be long enough for the names
+                if list.len() < names.len() {
+                    return Err(RuntimeError::TooFewItems {
+                        expected: names.len(),
+                        got: list.len(),
+                    });
+                }
+                let item = |i: usize| list.get(&Val::Number(i as f64));
+                for (i, name) in names.iter().enumerate() {
+                    scope.def(name, item(i)?);
+                }
+                if let Some(rest) = rest {
+                    let items = (names.len()..list.len()).map(item);
+                    let items = items.collect::<Result<_, _>>()?;
+                    scope.def(rest, Val::List(List::new(items)));
+                }
+                Ok(Flow::Next)
+            }
+            Self::Let(decls) => {
+                for d in decls {
+                    d.exec(ctx, Rc::clone(&scope))?;
+                }
+                Ok(Flow::Next)
+            }
+            Self::If(cond, then_branch, else_branch, _) => {
+                if cond.eval(ctx, Rc::clone(&scope))?.is_truthy() {
+                    then_branch.exec(ctx, scope)
                 } else if let Some(else_branch) = else_branch {
-                    else_branch.exec(scope)?
+                    else_branch.exec(ctx, scope)
                 } else {
-                    Val::NoVal
+                    Ok(Flow::Next)
+                }
+            }
+            Self::While(cond, body, inc, label, _) => {
+                run_loop(ctx, scope, Some(cond), body, inc.as_ref(), label)
+            }
+            Self::Loop(body, label, _) => run_loop(ctx, scope, None, body, None, label),
+            Self::ForIn(name, iterable, body, label, _) => {
+                let iterable = iterable.eval(ctx, Rc::clone(&scope))?;
+                run_for_in(ctx, scope, name, &iterable, body, label)
+            }
+            Self::Match(value, arms, default, _) => {
+                let value = value.eval(ctx, Rc::clone(&scope))?;
+                for (pattern, arm) in arms {
+                    if equals(ctx, &value, pattern)? {
+                        return arm.exec(ctx, scope);
+                    }
+                }
+                match default {
+                    Some(arm) => arm.exec(ctx, scope),
+                    None => Ok(Flow::Next),
+                }
+            }
+            Self::Break(label, _) => Ok(Flow::Break(label.clone())),
+            Self::Continue(label, _) => Ok(Flow::Continue(label.clone())),
+            Self::Func(decl) => {
+                let f = Val::Func(Function::UserDef(Rc::clone(decl), Rc::clone(&scope)));
+                scope.def(&decl.name, f);
+                Ok(Flow::Next)
+            }
+            Self::Class(decl) => {
+                let superclass = match &decl.superclass {
+                    Some(var) => match scope.get(var)? {
+                        Val::Class(c) => Some(c),
+                        _ => {
+                            return Err(RuntimeError::TypeMismatch {
+                                msg: "a superclass must be a class",
+                            })
+                        }
+                    },
+                    None => None,
                 };
-                Ok(ret)
-            }
-            Self::While(cond, body) => {
-                let mut ret = Val::NoVal;
-                while cond.eval(Rc::clone(&scope))?.into() {
-                    ret = body.exec(Rc::clone(&scope))?;
-                    if ret != Val::NoVal {
-                        break;
+                let mut fields = HashMap::new();
+                for (name, init) in &decl.fields {
+                    fields.insert(name.clone(), init.eval(ctx, Rc::clone(&scope))?);
+                }
+                let closure = match &superclass {
+                    Some(c) => {
+                        let closure = Scope::new_local(&scope);
+                        closure.def("super", Val::Class(Rc::clone(c)));
+                        closure
+                    }
+                    None => Rc::clone(&scope),
+                };
+                let class = Class::new(decl, closure, superclass, fields);
+                scope.def(&decl.name, Val::Class(Rc::new(class)));
+                Ok(Flow::Next)
+            }
+            Self::Return(ret, _) => {
+                let val = ret.as_ref().map_or(Ok(Val::Nil), |e| e.eval(ctx, scope))?;
+                Ok(Flow::Return(val))
+            }
+            Self::Throw(value, _) => Err(RuntimeError::Thrown {
+                value: value.eval(ctx, scope)?,
+            }),
+            Self::Try(body, name, handler, _) => match body.exec(ctx, Rc::clone(&scope)) {
+                Err(e) => {
+                    let inner = Scope::new_local(&scope);
+                    inner.def(name, e.to_val(ctx.error_at.take().map(|at| at.loc)));
+                    ctx.trace = None;
+                    handler.exec(ctx, inner)
+                }
+                flow => flow,
+            },
+            Self::Import(path, _) => {
+                ctx.allow(sandbox::IMPORT)?;
+                import(ctx, path, Rc::clone(scope.get_global()))?;
+                Ok(Flow::Next)
+            }
+            Self::Error(_) => Err(RuntimeError::ParseErrors),
+        }
+    }
+}
+
+// Runs `body` for as long as `cond` holds, or until a jump out of it
+// without one
+fn run_loop(
+    ctx: &mut Context,
+    scope: Rc<Scope>,
+    cond: Option<&Expr>,
+    body: &Stmt,
+    inc: Option<&Expr>,
+    label: &Option<String>,
+) -> Result<Flow, RuntimeError> {
+    // An unlabeled jump targets the innermost loop
+    let targets = |l: &Option<String>| l.is_none() || l == label;
+    while cond.map_or(Ok(true), |c| {
+        Ok(c.eval(ctx, Rc::clone(&scope))?.is_truthy())
+    })? {
+        match body.exec(ctx, Rc::clone(&scope))? {
+            Flow::Break(l) if targets(&l) => break,
+            Flow::Next => {}
+            Flow::Continue(l) if targets(&l) => {}
+            flow => return Ok(flow),
+        }
+        if let Some(inc) = inc {
+            inc.eval(ctx, Rc::clone(&scope))?;
+        }
+    }
+    Ok(Flow::Next)
+}
+
+// SEMANTICS: exceptions
+//
+// `throw` fails with the value thrown, which unwinds like any runtime
+// error, through blocks, loops and calls, up to the innermost `try`
+// around it. A `try` catches every runtime error: the handler sees a
+// thrown value as is, and any other error as the map of
+// `RuntimeError::to_val`. Returns and jumps out of the body are not
+// errors, so they leave the `try` as usual. Uncaught, a thrown value ends
+// the program like the other runtime errors do.
+
+// SEMANTICS: for-in loops
+//
+// A list is iterated by index, so the loop sees the items added while it
+// runs, and a string character by character. Every iteration binds the
+// variable in a scope of its own, so closures created in the body keep the
+// item of their iteration.
+fn run_for_in(
+    ctx: &mut Context,
+    scope: Rc<Scope>,
+    name: &str,
+    iterable: &Val,
+    body: &Stmt,
+    label: &Option<String>,
+) -> Result<Flow, RuntimeError> {
+    let chars: Vec<char> = match iterable {
+        Val::String(s) => s.chars().collect(),
+        Val::List(_) => Vec::new(),
+        _ => {
+            return Err(RuntimeError::TypeMismatch {
+                msg: "can only iterate over lists and strings",
+            })
+        }
+    };
+    let item = |i: usize| match iterable {
+        Val::List(list) if i < list.len() => Some(list.get(&Val::Number(i as f64))),
+        Val::String(_) => chars.get(i).map(|c| Ok(Val::String(c.to_string().into()))),
+        _ => None,
+    };
+    let targets = |l: &Option<String>| l.is_none() || l == label;
+    let mut i = 0;
+    while let Some(item) = item(i) {
+        let inner = Scope::new_local(&scope);
+        inner.def(name, item?);
+        match body.exec(ctx, inner)? {
+            Flow::Break(l) if targets(&l) => break,
+            Flow::Next => {}
+            Flow::Continue(l) if targets(&l) => {}
+            flow => return Ok(flow),
+        }
+        i += 1;
+    }
+    Ok(Flow::Next)
+}
+
+impl Stmt {
+    const INDENT: &'static str = "    ";
+
+    // Writes the statement starting at the current position of `f`,
+    // indenting any of its following lines `depth` levels
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        match self {
+            Self::Block(stmts) if stmts.is_empty() => write!(f, "{{}}"),
+            Self::Block(stmts) => {
+                writeln!(f, "{{")?;
+                for s in stmts {
+                    write!(f, "{}", Self::INDENT.repeat(depth + 1))?;
+                    s.fmt_indented(f, depth + 1)?;
+                    writeln!(f)?;
+                }
+                write!(f, "{}}}", Self::INDENT.repeat(depth))
+            }
+            Self::Expr(e, _) => {
+                // Only calls, indexing and properties can follow an anonymous
+                // function that starts a statement
+                let text = e.to_string();
+                if text.starts_with("fn (") && !on_lambda(e) {
+                    write!(f, "({text});")
+                } else {
+                    write!(f, "{text};")
+                }
+            }
+            Self::Print(e, _) => write!(f, "print {e};"),
+            Self::Assert(cond, message, _) => {
+                write!(f, "assert {}", item(cond))?;
+                if let Some(message) = message {
+                    write!(f, ", {}", item(message))?;
+                }
+                write!(f, ";")
+            }
+            Self::Decl(name, init, _) => write!(f, "let {};", binding(name, init.as_ref())),
+            Self::DeclPattern(names, rest, init, _) => {
+                write!(f, "let {};", pattern(names, rest, init))
+            }
+            Self::Let(decls) => {
+                let decls: Vec<_> = decls
+                    .iter()
+                    .map(|d| match d {
+                        Self::Decl(name, init, _) => binding(name, init.as_ref()),
+                        Self::DeclPattern(names, rest, init, _) => pattern(names, rest, init),
+                        _ => unreachable!("a `let` only holds declarations"),
+                    })
+                    .collect();
+                write!(f, "let {};", decls.join(", "))
+            }
+            Self::If(cond, then_branch, else_branch, _) => {
+                write!(f, "if ({cond})")?;
+                then_branch.fmt_body(f, depth)?;
+                if let Some(else_branch) = else_branch {
+                    if matches!(**then_branch, Self::Block(_)) {
+                        write!(f, " else")?;
+                    } else {
+                        write!(f, "\n{}else", Self::INDENT.repeat(depth))?;
                     }
+                    // Keep `else if` chains flat instead of nesting each link
+                    if matches!(**else_branch, Self::If(..)) {
+                        write!(f, " ")?;
+                        else_branch.fmt_indented(f, depth)?;
+                    } else {
+                        else_branch.fmt_body(f, depth)?;
+                    }
+                }
+                Ok(())
+            }
+            Self::While(cond, body, inc, label, _) => {
+                if let Some(label) = label {
+                    write!(f, "{label}: ")?;
+                }
+                match inc {
+                    // Only a `for` has an increment, its initializer is printed
+                    // as the declaration before it
+                    Some(inc) => write!(f, "for (; {cond}; {inc})")?,
+                    None => write!(f, "while ({cond})")?,
                 }
-                Ok(ret)
+                body.fmt_body(f, depth)
             }
-            f @ Self::Func(name, ..) => {
-                let f = Val::Func(Function::UserDef(Rc::new(f.clone()), Rc::clone(&scope)));
-                scope.def(name, f);
-                Ok(Val::NoVal)
+            Self::Loop(body, label, _) => {
+                if let Some(label) = label {
+                    write!(f, "{label}: ")?;
+                }
+                write!(f, "loop")?;
+                body.fmt_body(f, depth)
+            }
+            Self::ForIn(name, iterable, body, label, _) => {
+                if let Some(label) = label {
+                    write!(f, "{label}: ")?;
+                }
+                write!(f, "for ({name} in {iterable})")?;
+                body.fmt_body(f, depth)
             }
-            Self::Return(ret) => ret.as_ref().map_or(Ok(Val::Nil), |e| e.eval(scope)),
+            Self::Match(value, arms, default, _) => {
+                writeln!(f, "match ({value}) {{")?;
+                let indent = Self::INDENT.repeat(depth + 1);
+                let arms = arms
+                    .iter()
+                    .map(|(p, arm)| (Expr::Lit(p.clone()).to_string(), arm));
+                let default = default.iter().map(|arm| ("_".to_string(), &**arm));
+                for (pattern, arm) in arms.chain(default) {
+                    write!(f, "{indent}{pattern} =>")?;
+                    arm.fmt_body(f, depth + 1)?;
+                    writeln!(f)?;
+                }
+                write!(f, "{}}}", Self::INDENT.repeat(depth))
+            }
+            Self::Break(Some(label), _) => write!(f, "break {label};"),
+            Self::Break(None, _) => write!(f, "break;"),
+            Self::Continue(Some(label), _) => write!(f, "continue {label};"),
+            Self::Continue(None, _) => write!(f, "continue;"),
+            Self::Func(decl) => {
+                write!(f, "fn ")?;
+                decl.fmt_indented(f, depth)
+            }
+            Self::Class(decl) => {
+                write!(f, "class {}", decl.name)?;
+                if let Some(superclass) = &decl.superclass {
+                    write!(f, " < {}", superclass.name)?;
+                }
+                if decl.methods.is_empty() && decl.statics.is_empty() && decl.fields.is_empty() {
+                    return write!(f, " {{}}");
+                }
+                writeln!(f, " {{")?;
+                let indent = Self::INDENT.repeat(depth + 1);
+                for (name, init) in &decl.fields {
+                    match init {
+                        Expr::Seq(..) => writeln!(f, "{indent}static {name} = ({init});")?,
+                        _ => writeln!(f, "{indent}static {name} = {init};")?,
+                    }
+                }
+                for m in &decl.statics {
+                    write!(f, "{indent}static ")?;
+                    m.fmt_indented(f, depth + 1)?;
+                    writeln!(f)?;
+                }
+                for m in &decl.methods {
+                    write!(f, "{indent}")?;
+                    m.fmt_indented(f, depth + 1)?;
+                    writeln!(f)?;
+                }
+                write!(f, "{}}}", Self::INDENT.repeat(depth))
+            }
+            Self::Return(Some(e), _) => write!(f, "return {e};"),
+            Self::Return(None, _) => write!(f, "return;"),
+            Self::Import(path, _) => write!(f, "import \"{path}\";"),
+            Self::Throw(value, _) => write!(f, "throw {value};"),
+            Self::Try(body, name, handler, _) => {
+                write!(f, "try")?;
+                body.fmt_body(f, depth)?;
+                write!(f, " catch ({name})")?;
+                handler.fmt_body(f, depth)
+            }
+            Self::Error(loc) => write!(f, "<parse error at {loc}>"),
+        }
+    }
+
+    // Blocks open on the same line as their header, other statements
+    // go on the next line one level deeper
+    fn fmt_body(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        if matches!(self, Self::Block(_)) {
+            write!(f, " ")?;
+            self.fmt_indented(f, depth)
+        } else {
+            write!(f, "\n{}", Self::INDENT.repeat(depth + 1))?;
+            self.fmt_indented(f, depth + 1)
         }
     }
 }
+
+// An expression where commas separate items, which is parsed like an
+// argument, without a top level comma
+fn item(e: &Expr) -> String {
+    match e {
+        Expr::Seq(..) => format!("({e})"),
+        e => e.to_string(),
+    }
+}
+
+// A name and its initial value, in a `let` or a parameter list
+fn binding(name: &str, init: Option<&Expr>) -> String {
+    match init {
+        Some(init) => format!("{name} = {}", item(init)),
+        None => name.to_string(),
+    }
+}
+
+// `[a, b, ...rest] = xs`
+fn pattern(names: &[String], rest: &Option<String>, init: &Expr) -> String {
+    let rest = rest.iter().map(|r| format!("...{r}"));
+    let names: Vec<_> = names.iter().cloned().chain(rest).collect();
+    format!("[{}] = {}", names.join(", "), item(init))
+}
+
+fn on_lambda(e: &Expr) -> bool {
+    match e {
+        Expr::Lambda(_) => true,
+        Expr::Call(obj, _, _) | Expr::Index(obj, _, _) | Expr::Get(obj, _, _) => on_lambda(obj),
+        _ => false,
+    }
+}
+
+impl FunDecl {
+    // Where it is declared, in its own source
+    pub fn located(&self) -> Located {
+        Located {
+            source: Rc::clone(&self.source),
+            loc: self.loc.0,
+        }
+    }
+
+    // Everything but the `fn` keyword, which methods do without
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{}({}) ", self.name, self.fmt_params())?;
+        self.body.fmt_indented(f, depth)
+    }
+
+    // The most arguments taken, any number with a rest parameter
+    pub fn arity(&self) -> usize {
+        if self.rest.is_some() {
+            usize::MAX
+        } else {
+            self.params.len()
+        }
+    }
+
+    pub fn min_arity(&self) -> usize {
+        self.params.len() - self.defaults.len()
+    }
+
+    pub(crate) fn fmt_params(&self) -> String {
+        let required = self.params.len() - self.defaults.len();
+        let defaulted = self.params[required..]
+            .iter()
+            .zip(&self.defaults)
+            .map(|((p, _), d)| binding(p, Some(d)));
+        let rest = self.rest.iter().map(|(r, _)| format!("...{r}"));
+        let params: Vec<_> = self.params[..required]
+            .iter()
+            .map(|(p, _)| p.clone())
+            .chain(defaulted)
+            .chain(rest)
+            .collect();
+        params.join(", ")
+    }
+}
+
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::{Parser, RecursiveDescent},
+        test_util::{global, run},
+    };
+
+    fn parse(src: &str) -> Stmt {
+        RecursiveDescent::parse_lexed(Lexer::new(src.chars())).unwrap()
+    }
+
+    // Prints the parsed statement, checks it against the expected text
+    // and that parsing the printed text yields the same tree
+    fn assert_prints(src: &str, expected: &str) {
+        let stmt = parse(src);
+        let printed = stmt.to_string();
+        assert_eq!(printed, expected);
+        assert_eq!(parse(&printed), stmt);
+    }
+
+    #[test]
+    fn else_if_chain() {
+        assert_prints(
+            "if (a) { print 1; } else if (b) { print 2; } else if (c) { print 3; }
+             else if (d) { print 4; } else if (e) { print 5; }",
+            "\
+if (a) {
+    print 1;
+} else if (b) {
+    print 2;
+} else if (c) {
+    print 3;
+} else if (d) {
+    print 4;
+} else if (e) {
+    print 5;
+}",
+        );
+    }
+
+    #[test]
+    fn else_if_chain_with_else() {
+        assert_prints(
+            "if (a) { print 1; } else if (b) { print 2; } else { print 3; }",
+            "\
+if (a) {
+    print 1;
+} else if (b) {
+    print 2;
+} else {
+    print 3;
+}",
+        );
+    }
+
+    #[test]
+    fn else_if_chain_without_braces() {
+        assert_prints(
+            "if (a) print 1; else if (b) print 2; else print 3;",
+            "\
+if (a)
+    print 1;
+else if (b)
+    print 2;
+else
+    print 3;",
+        );
+    }
+
+    #[test]
+    fn explicit_else_block_is_kept() {
+        assert_prints(
+            "if (a) { print 1; } else { if (b) { print 2; } }",
+            "\
+if (a) {
+    print 1;
+} else {
+    if (b) {
+        print 2;
+    }
+}",
+        );
+    }
+
+    #[test]
+    fn nested_chain_in_function() {
+        assert_prints(
+            r#"fn sign(x) { if (x < 0) return -1; else if (x == 0) return 0; else { return 1; } }"#,
+            "\
+fn sign(x) {
+    if (x < 0)
+        return -1;
+    else if (x == 0)
+        return 0;
+    else {
+        return 1;
+    }
+}",
+        );
+    }
+
+    #[test]
+    fn collections() {
+        assert_prints(
+            r#"print [1, "a", {"k": [2]}][(0)];"#,
+            r#"print [1, "a", {"k": [2]}][0];"#,
+        );
+        assert_prints("m[i][j] = -x[0] * 2;", "m[i][j] = -x[0] * 2;");
+        assert_prints("(f(a))[0] = {};", "f(a)[0] = {};");
+    }
+
+    #[test]
+    fn classes() {
+        assert_prints("class A {}", "class A {}");
+        assert_prints(
+            "class Point { norm() { return this.x * this.x + this.y * this.y; } scale(k) { this.x = this.x * k; } }",
+            "\
+class Point {
+    norm() {
+        return this.x * this.x + this.y * this.y;
+    }
+    scale(k) {
+        this.x = this.x * k;
+    }
+}",
+        );
+        assert_prints("print p.at(1).x;", "print p.at(1).x;");
+        assert_prints(
+            "class M { m() {} static s(x) { return x; } static K = 1 + 2; }",
+            "\
+class M {
+    static K = 1 + 2;
+    static s(x) {
+        return x;
+    }
+    m() {}
+}",
+        );
+        assert_prints(
+            "class B<A{m(){return super.m;}}",
+            "\
+class B < A {
+    m() {
+        return super.m;
+    }
+}",
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_prints("x = a ? b : c ? d : e;", "x = a ? b : c ? d : e;");
+        assert_prints("(a ? b : c) ? d : e;", "(a ? b : c) ? d : e;");
+        assert_prints("a ? x = 1 : y;", "a ? x = 1 : y;");
+        assert_prints("a or b ? c and d : -e;", "a or b ? c and d : -e;");
+    }
+
+    #[test]
+    fn sequences() {
+        assert_prints("a = 1, b = 2, c;", "a = 1, b = 2, c;");
+        assert_prints("print (1, 2);", "print 1, 2;");
+        assert_prints("let x = (1, 2);", "let x = (1, 2);");
+        assert_prints(
+            "f((a, b), [(c, d)], {(e, f): g})[(h, i)] = (j, k);",
+            "f((a, b), [(c, d)], {(e, f): g})[(h, i)] = (j, k);",
+        );
+        assert_prints("a, (b, c);", "a, (b, c);");
+        assert_prints(
+            "let f = fn (a, b = 1, c = (2, 3)) {};",
+            "let f = fn (a, b = 1, c = (2, 3)) {};",
+        );
+        assert_prints("let f = fn (a, ...b) {};", "let f = fn (a, ...b) {};");
+        assert_prints("f(...(a, b), ...xs + ys);", "f(...(a, b), ...xs + ys);");
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_prints("x = a | b ^ c & d << 1;", "x = a | b ^ c & d << 1;");
+        assert_prints(
+            "((a | b) ^ c) & (d << 1) + 2;",
+            "((a | b) ^ c) & (d << 1) + 2;",
+        );
+    }
+
+    #[test]
+    fn nested_negations() {
+        assert_prints("x = - -y;", "x = -(-y);");
+        assert_prints("x = -(-(-y));", "x = -(-(-y));");
+        assert_prints("x = !!-y;", "x = !!-y;");
+    }
+
+    #[test]
+    fn powers() {
+        assert_prints("x = -2 ** -y ** 2;", "x = -2 ** -y ** 2;");
+        assert_prints("(2 ** 3) ** (a + 1);", "(2 ** 3) ** (a + 1);");
+        assert_prints("(-2) ** f(x)[0];", "(-2) ** f(x)[0];");
+    }
+
+    #[test]
+    fn loops() {
+        assert_prints(
+            "outer: while (a) { while (b) break outer; continue; }",
+            "\
+outer: while (a) {
+    while (b)
+        break outer;
+    continue;
+}",
+        );
+        // The increment of a `for` is kept apart from its body
+        assert_prints(
+            "l: for (let i = 0; i < 3; i = i + 1) { continue l; }",
+            "\
+{
+    let i = 0;
+    l: for (; i < 3; i = i + 1) {
+        continue l;
+    }
+}",
+        );
+    }
+
+    #[test]
+    fn anonymous_functions() {
+        assert_prints(
+            "sort(xs, fn (a, b) { return a - b; });",
+            "sort(xs, fn (a, b) { return a - b; });",
+        );
+        assert_prints("let f = fn () {};", "let f = fn () {};");
+        assert_prints("fn (x) { print x; }(1).y;", "fn (x) { print x; }(1).y;");
+        assert_prints("(fn () {}() + 1);", "(fn () {}() + 1);");
+        assert_prints("(fn () {}).x = 1;", "(fn () {}.x = 1);");
+        assert_prints(
+            "let add = fn (x) => fn (y) => x + y;",
+            "let add = fn (x) { return fn (y) { return x + y; }; };",
+        );
+    }
+
+    #[test]
+    fn infinite_loop() {
+        assert_prints(
+            "outer: loop { loop { break outer; } }",
+            "\
+outer: loop {
+    loop {
+        break outer;
+    }
+}",
+        );
+        let env = run(r#"
+            let n = 0;
+            loop {
+                n = n + 1;
+                if (n == 10) break;
+            }
+            fn first_square_over(x) {
+                let i = 0;
+                loop {
+                    i = i + 1;
+                    if (i * i > x) return i;
+                }
+            }
+            let r = first_square_over(50);"#);
+        assert_eq!(global(&env, "n"), Val::Number(10.0));
+        assert_eq!(global(&env, "r"), Val::Number(8.0));
+    }
+
+    #[test]
+    fn match_arms() {
+        assert_prints(
+            r#"match (x) { 1 => print "one";, -2.5 => { print "neg"; }, "s" => {} nil => print nil; _ => x = 0; }"#,
+            r#"match (x) {
+    1 =>
+        print "one";
+    -2.5 => {
+        print "neg";
+    }
+    "s" => {}
+    nil =>
+        print nil;
+    _ =>
+        x = 0;
+}"#,
+        );
+        let env = run(r#"
+            fn name(x) {
+                let out = "";
+                match (x) {
+                    1 => out = out + "one";
+                    "1" => out = out + "string";
+                    true => out = out + "true";
+                    1 => out = out + "again";
+                    _ => out = out + "other";
+                }
+                return out;
+            }
+            let a = name(1);
+            let b = name("1");
+            let c = name(true);
+            let d = name(2);
+            let e = "none";
+            match (e) { "x" => e = "x"; }"#);
+        // Only the first arm that matches runs
+        assert_eq!(global(&env, "a"), Val::String("one".into()));
+        assert_eq!(global(&env, "b"), Val::String("string".into()));
+        assert_eq!(global(&env, "c"), Val::String("true".into()));
+        assert_eq!(global(&env, "d"), Val::String("other".into()));
+        assert_eq!(global(&env, "e"), Val::String("none".into()));
+    }
+
+    #[test]
+    fn break_outer_loop() {
+        let env = run(r#"
+            let log = "";
+            outer: while (true) {
+                let i = 0;
+                while (true) {
+                    i = i + 1;
+                    if (i == 3) break outer;
+                    log = log + "i";
+                }
+                log = log + "o";
+            }"#);
+        assert_eq!(global(&env, "log"), Val::String("ii".into()));
+    }
+
+    #[test]
+    fn continue_outer_for() {
+        let env = run(r#"
+            let log = "";
+            outer: for (let i = 0; i < 3; i = i + 1) {
+                for (let j = 0; j < 3; j = j + 1) {
+                    if (j == 1) continue outer;
+                    log = log + "j";
+                }
+                log = log + "i";
+            }"#);
+        // The increment of the outer loop still runs
+        assert_eq!(global(&env, "log"), Val::String("jjj".into()));
+    }
+
+    #[test]
+    fn unlabeled_jumps() {
+        let env = run(r#"
+            let log = "";
+            for (let i = 0; i < 5; i = i + 1) {
+                if (i == 1) continue;
+                if (i == 3) break;
+                let j = 0;
+                while (true) { j = j + 1; if (j > 1) break; }
+                log = log + "i";
+            }
+            fn f() { while (true) { return "r"; } }
+            let r = f();"#);
+        assert_eq!(global(&env, "log"), Val::String("ii".into()));
+        assert_eq!(global(&env, "r"), Val::String("r".into()));
+    }
+
+    #[test]
+    fn several_bindings() {
+        let env = run(r#"
+            let a = 1, b = a + 1, c;
+            let inner;
+            {
+                let a = 10, d = a + b;
+                inner = d;
+            }
+            let sum = 0;
+            for (let i = 0, n = 10; i < n; i = i + 1) sum = sum + i;"#);
+        assert_eq!(global(&env, "b"), Val::Number(2.0));
+        assert_eq!(global(&env, "c"), Val::Nil);
+        assert_eq!(global(&env, "inner"), Val::Number(12.0));
+        // The block's `a` does not outlive it
+        assert_eq!(global(&env, "a"), Val::Number(1.0));
+        assert_eq!(global(&env, "sum"), Val::Number(45.0));
+        assert_prints("let a = 1, b = (2, 3), c;", "let a = 1, b = (2, 3), c;");
+    }
+
+    #[test]
+    fn number_literals() {
+        // Printed as written
+        for lit in ["0", "1", "12.5", "0.1", "3.14159", "9007199254740992"] {
+            assert_prints(&format!("print {lit};"), &format!("print {lit};"));
+        }
+        // Printed differently, but parse back to the same value
+        let huge = "9".repeat(400);
+        for lit in [
+            "0.30000000000000004",
+            "0.1000000000000000055511151231257827",
+            "123456789012345678901234567890",
+            "1.00000000000000000001",
+            "007.50",
+            "0.000000000000000000000000001",
+            &huge,
+        ] {
+            let stmt = parse(&format!("print {lit};"));
+            assert_eq!(parse(&stmt.to_string()), stmt, "{lit}");
+        }
+    }
+
+    #[test]
+    fn for_in() {
+        let env = run(r#"
+            let sum = 0;
+            for (x in [1, 2, 3, 4]) {
+                if (x == 2) continue;
+                sum += x;
+            }
+            let chars = "";
+            outer: for (c in "héllo") {
+                for (d in [1]) if (c == "l") break outer;
+                chars = chars + c + ".";
+            }
+            let x = "outer";
+            let seen;
+            for (x in [nil]) seen = x;
+            let fs = [];
+            for (i in [1, 2]) push(fs, fn () => i);
+            let firsts = fs[0]() + fs[1]();"#);
+        assert_eq!(global(&env, "sum"), Val::Number(8.0));
+        assert_eq!(global(&env, "chars"), Val::String("h.é.".into()));
+        assert_eq!(global(&env, "x"), Val::String("outer".into()));
+        assert_eq!(global(&env, "seen"), Val::Nil);
+        assert_eq!(global(&env, "firsts"), Val::Number(3.0));
+        assert_prints(
+            "l: for (c in s + \"!\") print c;",
+            "\
+l: for (c in s + \"!\")
+    print c;",
+        );
+    }
+
+    #[test]
+    fn for_in_not_iterable() {
+        use crate::{error::LoxError, interpreter::Interpreter, source::SourceMap};
+
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "for (x in 5) print x;")),
+            Err(LoxError::Runtime(RuntimeError::TypeMismatch {
+                msg: "can only iterate over lists and strings"
+            }))
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        let env = run(r#"
+            let [a, b] = [1, 2];
+            let [first, ...rest] = ["x", "y", "z"], [...all] = [];
+            let [] = [1], [one] = [1, 2];
+            let f;
+            { let [x, y] = [3, 4]; f = fn () => x * y; }
+            let n = f();"#);
+        assert_eq!(global(&env, "a"), Val::Number(1.0));
+        assert_eq!(global(&env, "b"), Val::Number(2.0));
+        assert_eq!(global(&env, "first"), Val::String("x".into()));
+        assert_eq!(global(&env, "rest").to_string(), r#"["y", "z"]"#);
+        assert_eq!(global(&env, "all").to_string(), "[]");
+        assert_eq!(global(&env, "one"), Val::Number(1.0));
+        assert_eq!(global(&env, "n"), Val::Number(12.0));
+        assert_prints("let [a, ...r] = (1, xs), b;", "let [a, ...r] = (1, xs), b;");
+    }
+
+    #[test]
+    fn destructuring_errors() {
+        use crate::{error::LoxError, interpreter::Interpreter, source::SourceMap};
+
+        let mut lox = Interpreter::builder().build();
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "let [a, b, ...c] = [1];")),
+            Err(LoxError::Runtime(RuntimeError::TooFewItems {
+                expected: 2,
+                got: 1
+            }))
+        );
+        assert_eq!(
+            lox.run(&SourceMap::new("test", "let [a] = \"a\";")),
+            Err(LoxError::Runtime(RuntimeError::TypeMismatch {
+                msg: "can only destructure lists"
+            }))
+        );
+    }
+
+    #[test]
+    fn assertions() {
+        use crate::{error::LoxError, interpreter::Interpreter, source::SourceMap};
+
+        let env = run(r#"
+            let log = "";
+            fn note(tag) { log = log + tag; return tag; }
+            assert 1 < 2;
+            assert true, note("unused");"#);
+        assert_eq!(global(&env, "log"), Val::String("".into()));
+
+        let mut lox = Interpreter::builder().build();
+        let src = r#"let log = ""; assert 1 > 2, log = "evaluated" + "!"; log = "after";"#;
+        assert_eq!(
+            lox.run(&SourceMap::new("test", src)),
+            Err(LoxError::Runtime(RuntimeError::AssertionFailed {
+                message: Some("evaluated!".to_string())
+            }))
+        );
+        assert_eq!(lox.global("log"), Some(Val::String("evaluated!".into())));
+        let err = lox.run(&SourceMap::new("test", "assert nil;")).unwrap_err();
+        assert_eq!(err.to_string(), "assertion failed");
+
+        assert_prints("assert (a, b), [1, 2];", "assert (a, b), [1, 2];");
+    }
+
+    #[test]
+    fn exceptions() {
+        let env = run(r#"
+            let log = "";
+            fn check(x) {
+                if (x < 0) throw "negative";
+                return x;
+            }
+            fn first(xs) {
+                for (x in xs) {
+                    try {
+                        return check(x);
+                    } catch (e) {
+                        log = log + e + ";";
+                    }
+                }
+            }
+            let got = first([-1, -2, 3, 4]);
+            let rethrown;
+            try {
+                try { check(-5); } catch (e) { throw [e]; }
+            } catch (e) {
+                rethrown = e[0];
+            }
+            let kind;
+            try { nil + 1; } catch (e) { kind = e["kind"]; }"#);
+        assert_eq!(global(&env, "got"), Val::Number(3.0));
+        assert_eq!(
+            global(&env, "log"),
+            Val::String("negative;negative;".into())
+        );
+        assert_eq!(global(&env, "rethrown"), Val::String("negative".into()));
+        assert_eq!(global(&env, "kind"), Val::String("TypeMismatch".into()));
+        assert_prints(
+            "try { throw 1; } catch (e) { print e; }",
+            "\
+try {
+    throw 1;
+} catch (e) {
+    print e;
+}",
+        );
+    }
+
+    #[test]
+    fn uncaught_exception() {
+        use crate::{error::LoxError, interpreter::Interpreter, source::SourceMap};
+
+        let mut lox = Interpreter::builder().build();
+        let err = lox
+            .run(&SourceMap::new("test", "fn f() { throw \"oops\"; } f();"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            LoxError::Runtime(RuntimeError::Thrown {
+                value: Val::String("oops".into())
+            })
+        );
+        assert_eq!(err.to_string(), "uncaught exception: oops");
+    }
+}
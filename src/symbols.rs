@@ -0,0 +1,174 @@
+// Where the names of a program are declared and what each use of them
+// refers to, for editor tooling. Locations are as precise as the tree keeps
+// them: the names of a pattern are at its `[` and the variable of a
+// `for (x in xs)` at the `for`
+
+use crate::{
+    error::LoxError,
+    lexer::{Lexer, Loc},
+    parser::RecursiveDescent,
+    prog::Prog,
+    resolver::Resolver,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DefKind {
+    Var,
+    Param,
+    Func,
+    Class,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Definition {
+    pub name: String,
+    pub loc: Loc,
+    pub kind: DefKind,
+}
+
+// Index of a definition in `SymbolTable::definitions`
+pub type DefId = usize;
+
+#[derive(Default, PartialEq, Debug)]
+pub struct SymbolTable {
+    // In the order they are found
+    pub definitions: Vec<Definition>,
+    // Where each name is used, in the order they are resolved, and the
+    // definition it refers to. A global refers to the latest definition
+    // before the use, or to the first one when it is only declared later,
+    // as by a function called before it
+    pub references: Vec<(Loc, DefId)>,
+}
+
+impl SymbolTable {
+    // The definition of the name at `loc`, which may be anywhere within a
+    // use of it or its definition
+    pub fn definition_at(&self, loc: Loc) -> Option<DefId> {
+        let covers = |at: Loc, def: DefId| {
+            let len = self.definitions[def].name.chars().count();
+            at.row == loc.row && (at.col..at.col + len).contains(&loc.col)
+        };
+        self.references
+            .iter()
+            .find(|&&(at, def)| covers(at, def))
+            .map(|&(_, def)| def)
+            .or_else(|| {
+                (0..self.definitions.len()).find(|&def| covers(self.definitions[def].loc, def))
+            })
+    }
+
+    pub fn references_of(&self, def: DefId) -> impl Iterator<Item = Loc> + '_ {
+        self.references
+            .iter()
+            .filter(move |&&(_, d)| d == def)
+            .map(|&(loc, _)| loc)
+    }
+}
+
+// Lexes, parses and resolves a unit of code only for its symbols, without
+// reporting any diagnostic
+pub fn symbols(src: &str) -> Result<SymbolTable, LoxError> {
+    let (tokens, errors) = Lexer::new(src.chars()).scan_all();
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    let (mut prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(tokens.into_iter());
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    let mut resolver = Resolver::new(false, false).symbols(true);
+    resolver.resolve(&mut prog)?;
+    Ok(resolver.take_symbols().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SRC: &str = "\
+let a = 1;
+fn f(x) {
+  let a = x;
+  { let a = 2; print a; }
+  return a;
+}
+print f(a);
+for (let i = 0; i < 3; i = i + 1) print i;
+";
+
+    fn at(row: usize, col: usize) -> Loc {
+        Loc {
+            row,
+            col,
+            offset: 0,
+        }
+    }
+
+    // Rows and columns, as the offsets are not needed to tell them apart
+    fn row_col(locs: impl Iterator<Item = Loc>) -> Vec<(usize, usize)> {
+        locs.map(|l| (l.row, l.col)).collect()
+    }
+
+    #[test]
+    fn definitions() {
+        let table = symbols(SRC).unwrap();
+        let defs: Vec<_> = table
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.kind, (d.loc.row, d.loc.col)))
+            .collect();
+        assert_eq!(
+            defs,
+            [
+                ("a", DefKind::Var, (0, 4)),
+                ("f", DefKind::Func, (1, 3)),
+                ("x", DefKind::Param, (1, 5)),
+                ("a", DefKind::Var, (2, 6)),
+                ("a", DefKind::Var, (3, 8)),
+                ("i", DefKind::Var, (7, 9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        let table = symbols(SRC).unwrap();
+        // `print a` in the inner block, and `return a`
+        assert_eq!(table.definition_at(at(3, 21)), Some(4));
+        assert_eq!(table.definition_at(at(4, 9)), Some(3));
+        assert_eq!(row_col(table.references_of(3)), [(4, 9)]);
+        // The global is untouched by the locals named like it
+        assert_eq!(row_col(table.references_of(0)), [(6, 8)]);
+        assert_eq!(table.definition_at(at(6, 6)), Some(1));
+        assert_eq!(table.definition_at(at(2, 10)), Some(2));
+    }
+
+    #[test]
+    fn parameters() {
+        let table = symbols("fn f(a, b = a, ...c) { return b + c; }").unwrap();
+        let params: Vec<_> = table
+            .definitions
+            .iter()
+            .filter(|d| d.kind == DefKind::Param)
+            .map(|d| (d.name.as_str(), d.loc.col))
+            .collect();
+        assert_eq!(params, [("a", 5), ("b", 8), ("c", 18)]);
+        // The default refers to the parameter before it
+        assert_eq!(table.definition_at(at(0, 12)), Some(1));
+        assert_eq!(table.definition_at(at(0, 8)), Some(2));
+    }
+
+    #[test]
+    fn desugared_for() {
+        let table = symbols(SRC).unwrap();
+        // The increment is resolved after the body
+        assert_eq!(
+            row_col(table.references_of(5)),
+            [(7, 16), (7, 40), (7, 27), (7, 23)]
+        );
+        // Anywhere within a name, and on definitions themselves
+        assert_eq!(table.definition_at(at(7, 9)), Some(5));
+        assert_eq!(table.definition_at(at(1, 3)), Some(1));
+        assert_eq!(table.definition_at(at(7, 0)), None);
+    }
+}
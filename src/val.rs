@@ -1,15 +1,29 @@
 use std::rc::Rc;
 
-use crate::{scope::Scope, stmt::Stmt};
+use crate::{
+    class::{Class, Instance},
+    collections::{List, Map},
+    context::Context,
+    error::RuntimeError,
+    scope::Scope,
+    source::Located,
+    stmt::{Flow, FunDecl},
+};
 
-#[derive(PartialEq, Debug, Clone)]
+// Equality is structural, see `collections`
+#[derive(Debug, Clone)]
 pub enum Val {
-    NoVal,
     Number(f64),
     Boolean(bool),
     String(Rc<str>),
     Nil,
     Func(Function),
+    List(List),
+    Map(Map),
+    Class(Rc<Class>),
+    Instance(Instance),
+    // Callable values defined by the program embedding the interpreter
+    HostCallable(Rc<dyn Callable>),
 }
 
 impl std::fmt::Display for Val {
@@ -22,63 +36,618 @@ impl std::fmt::Display for Val {
             Nil => write!(f, "nil"),
             Func(Function::Native(..)) => write!(f, "<native fn>"),
             Func(Function::UserDef(..)) => write!(f, "<user fn>"),
-            NoVal => write!(f, "???"),
+            Func(Function::Bound { class, method, .. }) => write!(
+                f,
+                "<bound fn {}.{}/{}>",
+                class.name,
+                method.name,
+                method.params.len()
+            ),
+            Func(Function::Static { class, method }) => write!(
+                f,
+                "<static fn {}.{}/{}>",
+                class.name,
+                method.name,
+                method.params.len()
+            ),
+            List(l) => write!(f, "{l}"),
+            Map(m) => write!(f, "{m}"),
+            Class(c) => write!(f, "{c}"),
+            Instance(i) => write!(f, "{i}"),
+            HostCallable(_) => write!(f, "<host fn>"),
         }
     }
 }
 
-impl From<Val> for bool {
-    fn from(value: Val) -> Self {
-        !matches!(value, Val::Nil | Val::Boolean(false))
+impl Val {
+    // The single definition of truthiness used by every conditional:
+    //
+    //   nil, false  -> falsy
+    //   anything else, including 0, "" and functions -> truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Val::Nil | Val::Boolean(false))
+    }
+
+    // What type errors call the type of a value
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Val::Number(_) => "number",
+            Val::Boolean(_) => "boolean",
+            Val::String(_) => "string",
+            Val::Nil => "nil",
+            Val::Func(_) | Val::HostCallable(_) => "function",
+            Val::List(_) => "list",
+            Val::Map(_) => "map",
+            Val::Class(_) => "class",
+            Val::Instance(_) => "instance",
+        }
     }
 }
 
+pub type NativeFn = fn(&mut Context, Vec<Val>) -> Result<Val, RuntimeError>;
+
 #[derive(Debug, Clone)]
 pub enum Function {
-    Native(u8, fn(Vec<Val>) -> Val),
-    UserDef(Rc<Stmt>, Rc<Scope>),
+    // The name it is defined by, and how many arguments it takes
+    Native(&'static str, u8, NativeFn),
+    UserDef(Rc<FunDecl>, Rc<Scope>),
+    // A method read through an instance. `class` is the one that defines it
+    Bound {
+        receiver: Instance,
+        class: Rc<Class>,
+        method: Rc<FunDecl>,
+    },
+    // A static method read through its class
+    Static {
+        class: Rc<Class>,
+        method: Rc<FunDecl>,
+    },
+}
+
+impl Function {
+    // `Class.method` for methods, which are anonymous on their own
+    fn qualified_name(&self) -> Option<String> {
+        match self {
+            Self::Bound { class, method, .. } | Self::Static { class, method } => {
+                Some(format!("{}.{}", class.name, method.name))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Native(name, ..) => name.to_string(),
+            Self::UserDef(decl, _) => decl.name.clone(),
+            _ => self.qualified_name().unwrap_or_default(),
+        }
+    }
+
+    // Where it is declared, which natives are not
+    pub fn decl(&self) -> Option<Located> {
+        match self {
+            Self::Native(..) => None,
+            Self::UserDef(decl, _)
+            | Self::Bound { method: decl, .. }
+            | Self::Static { method: decl, .. } => Some(decl.located()),
+        }
+    }
 }
 
 impl PartialEq for Function {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Self::Native(a, f), Self::Native(b, g)) => a == b && f == g,
-            (Self::UserDef(a, f), Self::UserDef(b, g)) => a == b && Rc::ptr_eq(f, g),
+            (Self::Native(_, a, f), Self::Native(_, b, g)) => {
+                a == b && std::ptr::fn_addr_eq(*f, *g)
+            }
+            (Self::UserDef(a, f), Self::UserDef(b, g)) => Rc::ptr_eq(a, b) && Rc::ptr_eq(f, g),
+            (
+                Self::Bound {
+                    receiver: r,
+                    method: f,
+                    ..
+                },
+                Self::Bound {
+                    receiver: s,
+                    method: g,
+                    ..
+                },
+            ) => r.same(s) && Rc::ptr_eq(f, g),
+            (
+                Self::Static {
+                    class: a,
+                    method: f,
+                },
+                Self::Static {
+                    class: b,
+                    method: g,
+                },
+            ) => Rc::ptr_eq(a, b) && Rc::ptr_eq(f, g),
             _ => false,
         }
     }
 }
 
+// Anything a script can call. Hosts implement it to add their own kinds of
+// callable values, wrapped in `Val::HostCallable`. The contract is:
+// - `arity` is the most arguments taken, `usize::MAX` for any number, and
+//   `min_arity`, which defaults to it, the fewest. They are checked by
+//   `call_value`, so `call` always receives a number in between;
+// - `call` reports failures as a `RuntimeError`, which unwinds the script
+//   like any other error, and returns `Val::Nil` when it has no result.
 pub trait Callable {
-    fn call(&self, args: Vec<Val>) -> Result<Val, ()>;
+    fn arity(&self) -> usize;
+    fn min_arity(&self) -> usize {
+        self.arity()
+    }
+    fn call(&self, ctx: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError>;
+}
+
+impl std::fmt::Debug for dyn Callable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<host fn/{}>", self.arity())
+    }
+}
+
+// Every call made by a script goes through here
+pub fn call_value(ctx: &mut Context, callee: &Val, args: Vec<Val>) -> Result<Val, RuntimeError> {
+    checked_callee(callee, args.len())?.call(ctx, args)
+}
+
+// What to call, once it is known to take `n` arguments
+pub(crate) fn checked_callee(callee: &Val, n: usize) -> Result<&dyn Callable, RuntimeError> {
+    let f: &dyn Callable = match callee {
+        Val::Func(f) => f,
+        Val::HostCallable(f) => f.as_ref(),
+        Val::Class(c) => c,
+        _ => return Err(RuntimeError::NotCallable),
+    };
+    let (min, max) = (f.min_arity(), f.arity());
+    if !(min..=max).contains(&n) {
+        let (callee, decl) = match callee {
+            Val::Func(f) => (Some(f.name()), f.decl()),
+            Val::Class(c) => (Some(c.name.clone()), c.init_decl()),
+            _ => (None, None),
+        };
+        return Err(RuntimeError::Arity {
+            callee,
+            min,
+            max,
+            got: n,
+            decl,
+        });
+    }
+    Ok(f)
 }
 
 impl Callable for Function {
-    fn call(&self, args: Vec<Val>) -> Result<Val, ()> {
+    fn arity(&self) -> usize {
+        match self {
+            Self::Native(_, arity, _) => *arity as usize,
+            Self::UserDef(decl, _)
+            | Self::Bound { method: decl, .. }
+            | Self::Static { method: decl, .. } => decl.arity(),
+        }
+    }
+
+    fn min_arity(&self) -> usize {
+        match self {
+            Self::Native(_, arity, _) => *arity as usize,
+            Self::UserDef(decl, _)
+            | Self::Bound { method: decl, .. }
+            | Self::Static { method: decl, .. } => decl.min_arity(),
+        }
+    }
+
+    fn call(&self, ctx: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
         match self {
-            Self::Native(arity, f) => {
-                if *arity as usize != args.len() {
-                    println!("Expected {} arguments, got {}", arity, args.len());
-                    Err(())
-                } else {
-                    Ok(f(args))
+            Self::Native(_, _, f) => f(ctx, args),
+            Self::UserDef(decl, closure) => call_user(ctx, decl, closure, args),
+            Self::Bound {
+                receiver,
+                class,
+                method,
+            } => class.call_method(ctx, receiver, method, args),
+            Self::Static { class, method } => class.call_static(ctx, method, args),
+        }
+    }
+}
+
+pub(crate) fn call_user(
+    ctx: &mut Context,
+    decl: &FunDecl,
+    closure: &Rc<Scope>,
+    args: Vec<Val>,
+) -> Result<Val, RuntimeError> {
+    // Runaway recursion fails here rather than overflowing the native stack
+    if ctx.depth == ctx.max_depth || ctx.stack_exhausted() {
+        return Err(RuntimeError::StackOverflow {
+            name: decl.name.clone(),
+        });
+    }
+    ctx.depth += 1;
+    // Its body is in the source it was declared in
+    let caller = std::mem::replace(&mut ctx.source, Rc::clone(&decl.source));
+    let res = enter(ctx, decl, closure, args);
+    ctx.source = caller;
+    ctx.depth -= 1;
+    res
+}
+
+fn enter(
+    ctx: &mut Context,
+    decl: &FunDecl,
+    closure: &Rc<Scope>,
+    args: Vec<Val>,
+) -> Result<Val, RuntimeError> {
+    let inner = Scope::new_local(closure);
+    let required = decl.min_arity();
+    let mut args = args.into_iter();
+    for (i, (p, _)) in decl.params.iter().enumerate() {
+        // A missing argument takes its default, which sees the parameters
+        // defined before it
+        let a = match args.next() {
+            Some(a) => a,
+            None => decl.defaults[i - required].eval(ctx, Rc::clone(&inner))?,
+        };
+        inner.def(p, a);
+    }
+    if let Some((rest, _)) = &decl.rest {
+        inner.def(rest, Val::List(List::new(args.collect())));
+    }
+    // This is the only place where a `return` is consumed:
+    // a body that finishes without one evaluates to nil
+    match decl.body.exec(ctx, inner)? {
+        Flow::Return(ret) => Ok(ret),
+        _ => Ok(Val::Nil),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{global, run};
+
+    #[test]
+    fn truthiness() {
+        fn clock(_: &mut Context, _: Vec<Val>) -> Result<Val, RuntimeError> {
+            Ok(Val::Nil)
+        }
+        let table = [
+            (Val::Nil, false),
+            (Val::Boolean(false), false),
+            (Val::Boolean(true), true),
+            (Val::Number(0.0), true),
+            (Val::Number(f64::NAN), true),
+            (Val::String("".into()), true),
+            (Val::String("false".into()), true),
+            (Val::Func(Function::Native("clock", 0, clock)), true),
+            (Val::List(List::new(vec![])), true),
+            (Val::Map(Map::new(Default::default())), true),
+        ];
+        for (val, truthy) in table {
+            // Not a wildcard on purpose: a new variant must be added to the
+            // table above before this compiles again
+            match val {
+                Val::Number(_)
+                | Val::Boolean(_)
+                | Val::String(_)
+                | Val::Nil
+                | Val::Func(_)
+                | Val::List(_)
+                | Val::Map(_)
+                | Val::Class(_)
+                | Val::Instance(_)
+                | Val::HostCallable(_) => assert_eq!(val.is_truthy(), truthy, "{val:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn conditionals_agree() {
+        let env = run(r#"
+            let a = 0 and "and";
+            let b = nil or "or";
+            let c = !"";
+            let d = "if";
+            if (0) d = "then"; else d = "else";
+            let e = 0;
+            while (e) e = false;"#);
+        assert_eq!(global(&env, "a"), Val::String("and".into()));
+        assert_eq!(global(&env, "b"), Val::String("or".into()));
+        assert_eq!(global(&env, "c"), Val::Boolean(false));
+        assert_eq!(global(&env, "d"), Val::String("then".into()));
+        assert_eq!(global(&env, "e"), Val::Boolean(false));
+    }
+
+    #[test]
+    fn negation() {
+        let table = [
+            ("!nil", true),
+            ("!false", true),
+            ("!true", false),
+            ("!0", false),
+            ("!\"\"", false),
+            ("!\"x\"", false),
+            ("!!nil", false),
+            ("!!0", true),
+            ("!!\"\"", true),
+            ("!!!false", true),
+        ];
+        for (expr, expected) in table {
+            let env = run(&format!("let x = {expr};"));
+            assert_eq!(global(&env, "x"), Val::Boolean(expected), "{expr}");
+        }
+    }
+
+    #[test]
+    fn no_return_is_nil() {
+        let env = run("fn f() {} let x = f();");
+        assert_eq!(global(&env, "x"), Val::Nil);
+    }
+
+    #[test]
+    fn early_returns() {
+        let env = run(r#"
+            fn bare() { return; }
+            fn nested() {
+                while (true) {
+                    { for (let i = 0; i < 10; i = i + 1) { if (i == 3) return i; } }
                 }
             }
-            Self::UserDef(decl, closure) => match Rc::as_ref(decl) {
-                Stmt::Func(_, params, body) => {
-                    if params.len() != args.len() {
-                        println!("Expected {} arguments, got {}", params.len(), args.len());
-                        Err(())
-                    } else {
-                        let inner = Scope::new_local(closure);
-                        for (p, a) in params.iter().zip(args) {
-                            inner.def(p, a);
-                        }
-                        body.exec(inner)
-                    }
+            let n = 0;
+            while (n < 3) { n + 1; "value"; n = n + 1; }
+            let a = bare();
+            let b = nested();"#);
+        assert_eq!(global(&env, "a"), Val::Nil);
+        assert_eq!(global(&env, "a").to_string(), "nil");
+        assert_eq!(global(&env, "b"), Val::Number(3.0));
+        assert_eq!(global(&env, "n"), Val::Number(3.0));
+    }
+
+    #[test]
+    fn inner_call_does_not_return_outer() {
+        let env = run(r#"
+            let after = false;
+            fn outer() {
+                fn inner() { return 1; }
+                let x = inner() + 1;
+                after = true;
+                return x;
+            }
+            let r = outer();"#);
+        assert_eq!(global(&env, "r"), Val::Number(2.0));
+        assert_eq!(global(&env, "after"), Val::Boolean(true));
+    }
+
+    #[test]
+    fn returning_a_call_without_return() {
+        let env = run(r#"
+            let after = false;
+            fn g() {}
+            fn f() {
+                return g();
+                after = true;
+            }
+            let r = f();"#);
+        assert_eq!(global(&env, "r"), Val::Nil);
+        assert_eq!(global(&env, "after"), Val::Boolean(false));
+    }
+
+    #[test]
+    fn returned_inner_function() {
+        let env = run(r#"
+            fn make(n) {
+                fn add(x) {
+                    if (x > 0) { return x + n; }
+                    return n;
                 }
-                _ => unreachable!(),
-            },
+                return add;
+            }
+            let f = make(10);
+            let a = f(1);
+            let b = f(0);"#);
+        assert_eq!(global(&env, "a"), Val::Number(11.0));
+        assert_eq!(global(&env, "b"), Val::Number(10.0));
+    }
+
+    #[test]
+    fn closures_share_declaration() {
+        let env = run(r#"
+            let f0; let f1; let f2;
+            for (let i = 0; i < 3; i = i + 1) {
+                let j = i;
+                fn get() { return j; }
+                if (i == 0) f0 = get;
+                if (i == 1) f1 = get;
+                if (i == 2) f2 = get;
+            }
+            let a = f0(); let b = f1(); let c = f2();"#);
+        assert_eq!(global(&env, "a"), Val::Number(0.0));
+        assert_eq!(global(&env, "b"), Val::Number(1.0));
+        assert_eq!(global(&env, "c"), Val::Number(2.0));
+        match (global(&env, "f0"), global(&env, "f2")) {
+            (
+                Val::Func(Function::UserDef(decl0, scope0)),
+                Val::Func(Function::UserDef(decl2, scope2)),
+            ) => {
+                assert!(Rc::ptr_eq(&decl0, &decl2));
+                assert!(!Rc::ptr_eq(&scope0, &scope2));
+            }
+            _ => panic!("expected user functions"),
         }
     }
+
+    // The variable declared in a `for` initializer is a single binding shared
+    // by every iteration, so closures capturing it see its final value
+    #[test]
+    fn closures_share_loop_variable() {
+        let env = run(r#"
+            let f0; let f1; let f2;
+            for (let i = 0; i < 3; i = i + 1) {
+                fn get() { return i; }
+                if (i == 0) f0 = get;
+                if (i == 1) f1 = get;
+                if (i == 2) f2 = get;
+            }
+            let a = f0(); let b = f1(); let c = f2();"#);
+        assert_eq!(global(&env, "a"), Val::Number(3.0));
+        assert_eq!(global(&env, "b"), Val::Number(3.0));
+        assert_eq!(global(&env, "c"), Val::Number(3.0));
+    }
+
+    #[test]
+    fn anonymous_functions() {
+        let env = run(r#"
+            let doubled = fn (x) { return x * 2; }(21);
+            fn apply(f, x) { return f(x); }
+            let applied = apply(fn (x) { return x + 1; }, 1);
+            fn counter() {
+                let n = 0;
+                return fn () { n = n + 1; return n; };
+            }
+            let c = counter();
+            c();
+            let counted = c();
+            fn (x) { applied = applied + x; }(10);"#);
+        assert_eq!(global(&env, "doubled"), Val::Number(42.0));
+        assert_eq!(global(&env, "applied"), Val::Number(12.0));
+        assert_eq!(global(&env, "counted"), Val::Number(2.0));
+    }
+
+    #[test]
+    fn arrow_functions() {
+        let env = run(r#"
+            let add = fn (x) => fn (y) => x + y;
+            let curried = add(1)(2);
+            let k = 10;
+            let scale = fn (x) => x * k;
+            k = 100;
+            let scaled = scale(2);
+            fn apply(f, x, g) { return g(f(x)); }
+            let applied = apply(fn (x) => x + 1, 1, fn (x) => -x);"#);
+        assert_eq!(global(&env, "curried"), Val::Number(3.0));
+        assert_eq!(global(&env, "scaled"), Val::Number(200.0));
+        assert_eq!(global(&env, "applied"), Val::Number(-2.0));
+    }
+
+    #[test]
+    fn default_params() {
+        let env = run(r#"
+            fn greet(name, greeting = "hello", end = "!") {
+                return greeting + ", " + name + end;
+            }
+            let both = greet("bob");
+            let one = greet("bob", "hi");
+            let none = greet("bob", "hi", ".");
+            fn range(from, to = from + 10) { return to - from; }
+            let span = range(5);
+            // Evaluated on every call that leaves them out
+            fn add(x, xs = []) { push(xs, x); return len(xs); }
+            add(1);
+            let fresh = add(2);
+            class P { init(x, y = x) { this.y = y; } }
+            let y = P(3).y;"#);
+        assert_eq!(global(&env, "both"), Val::String("hello, bob!".into()));
+        assert_eq!(global(&env, "one"), Val::String("hi, bob!".into()));
+        assert_eq!(global(&env, "none"), Val::String("hi, bob.".into()));
+        assert_eq!(global(&env, "span"), Val::Number(10.0));
+        assert_eq!(global(&env, "fresh"), Val::Number(1.0));
+        assert_eq!(global(&env, "y"), Val::Number(3.0));
+    }
+
+    #[test]
+    fn rest_params() {
+        let env = run(r#"
+            fn sum(first, ...rest) {
+                for (let i = 0; i < len(rest); i = i + 1) first = first + rest[i];
+                return first;
+            }
+            let one = sum(1);
+            let many = sum(1, 2, 3, 4);
+            fn count(...xs) { return len(xs); }
+            let none = count();
+            fn collect(...xs) { return fn () => xs; }
+            let got = collect(1, "a")();
+            fn pad(x, y = 0, ...zs) { return [x, y, len(zs)]; }
+            let padded = pad(1);"#);
+        assert_eq!(global(&env, "one"), Val::Number(1.0));
+        assert_eq!(global(&env, "many"), Val::Number(10.0));
+        assert_eq!(global(&env, "none"), Val::Number(0.0));
+        assert_eq!(global(&env, "got").to_string(), r#"[1, "a"]"#);
+        assert_eq!(global(&env, "padded").to_string(), "[1, 0, 0]");
+    }
+
+    #[test]
+    fn spread_args() {
+        let env = run(r#"
+            fn three(a, b, c) { return [a, b, c]; }
+            let mixed = three(1, ...[2], ...[], 3);
+            let xs = ["a", "b", "c"];
+            let all = three(...xs);
+            fn count(...xs) { return len(xs); }
+            let none = count(...[], ...[]);
+            let native = len(...[xs]);"#);
+        assert_eq!(global(&env, "mixed").to_string(), "[1, 2, 3]");
+        assert_eq!(global(&env, "all").to_string(), r#"["a", "b", "c"]"#);
+        assert_eq!(global(&env, "none"), Val::Number(0.0));
+        assert_eq!(global(&env, "native"), Val::Number(3.0));
+    }
+
+    #[test]
+    fn spread_errors() {
+        use crate::{
+            error::LoxError,
+            interpreter::Interpreter,
+            lexer::Loc,
+            source::{Located, SourceMap},
+        };
+
+        let mut lox = Interpreter::builder().build();
+        let mut run = |src| lox.run(&SourceMap::new("test", src));
+        assert_eq!(
+            run("fn f(...xs) {} f(...\"abc\");"),
+            Err(LoxError::Runtime(RuntimeError::TypeMismatch {
+                msg: "can only spread lists"
+            }))
+        );
+        assert_eq!(
+            run("fn f(a, b) {} f(1, ...[2, 3]);"),
+            Err(LoxError::Runtime(RuntimeError::Arity {
+                callee: Some("f".to_string()),
+                min: 2,
+                max: 2,
+                got: 3,
+                decl: Some(Located {
+                    source: "test".into(),
+                    loc: Loc {
+                        row: 0,
+                        col: 3,
+                        offset: 3
+                    },
+                }),
+            }))
+        );
+        assert_eq!(
+            run("let xs = []; for (let i = 0; i < 256; i += 1) push(xs, i); fn f(...a) {} f(...xs);"),
+            Err(LoxError::Runtime(RuntimeError::TooManyArgs { got: 256 }))
+        );
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_declaration_in_loop() {
+        use std::time::Instant;
+
+        let body = "x = x + 1;\n".repeat(200);
+        let src =
+            format!("for (let i = 0; i < 10000; i = i + 1) {{ fn f(x) {{ {body} return x; }} }}");
+        let start = Instant::now();
+        run(&src);
+        println!(
+            "declaring a 200 statement function 10k times: {:?}",
+            start.elapsed()
+        );
+    }
 }
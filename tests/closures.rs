@@ -0,0 +1,153 @@
+// Executable specification of closures: a function captures the variables it
+// refers to, not their values, so it sees every later write to them and its
+// own writes are seen by everything else that shares them. Any backend must
+// pass this suite unchanged.
+
+use lox::{context::Capture, source::SourceMap, Interpreter};
+
+// Runs a program and returns the lines it prints
+fn run(src: &str) -> Vec<String> {
+    let out = Capture::default();
+    let mut lox = Interpreter::builder().output(Box::new(out.clone())).build();
+    assert_eq!(lox.run(&SourceMap::new("test", src)), Ok(()));
+    let text = out.take();
+    text.lines().map(String::from).collect()
+}
+
+#[test]
+fn mutation_after_capture() {
+    assert_eq!(
+        run(r#"
+            let x = "before";
+            fn show() { print x; }
+            show();
+            x = "after";
+            show();
+            {
+                let y = 1;
+                fn get() { return y; }
+                y = 2;
+                print get();
+            }"#),
+        ["before", "after", "2"]
+    );
+}
+
+#[test]
+fn closures_share_a_variable() {
+    assert_eq!(
+        run(r#"
+            let get; let set;
+            fn make() {
+                let shared = 0;
+                fn g() { return shared; }
+                fn s(v) { shared = v; }
+                get = g;
+                set = s;
+            }
+            make();
+            print get();
+            set(5);
+            print get();
+            set(get() + 1);
+            print get();"#),
+        ["0", "5", "6"]
+    );
+}
+
+#[test]
+fn captured_parameters() {
+    assert_eq!(
+        run(r#"
+            fn adder(n) {
+                fn add(x) { return x + n; }
+                fn bump() { n = n + 10; }
+                bump();
+                return add;
+            }
+            let add = adder(1);
+            print add(1);
+            print adder(2)(2);
+            print add(3);"#),
+        ["12", "14", "14"]
+    );
+}
+
+#[test]
+fn counter_factory() {
+    assert_eq!(
+        run(r#"
+            fn makeCounter() {
+                let i = 0;
+                fn count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            let a = makeCounter();
+            let b = makeCounter();
+            print a();
+            print a();
+            print b();
+            print a();"#),
+        ["1", "2", "1", "3"]
+    );
+}
+
+#[test]
+fn three_levels_of_nesting() {
+    assert_eq!(
+        run(r#"
+            fn outer() {
+                let x = "outer";
+                fn middle() {
+                    fn inner() {
+                        print x;
+                        x = "inner";
+                    }
+                    return inner;
+                }
+                let f = middle();
+                f();
+                print x;
+                x = "again";
+                f();
+            }
+            outer();"#),
+        ["outer", "inner", "again"]
+    );
+}
+
+#[test]
+fn shadowing_after_capture() {
+    assert_eq!(
+        run(r#"
+            let a = "global";
+            {
+                fn show() { print a; }
+                show();
+                let a = "block";
+                show();
+                print a;
+            }"#),
+        ["global", "global", "block"]
+    );
+}
+
+#[test]
+fn sibling_functions() {
+    // A local function captures its block, so it sees the functions declared
+    // after it there by the time it is called
+    assert_eq!(
+        run(r#"
+            {
+                fn even(n) { return n == 0 or odd(n - 1); }
+                fn odd(n) { return n != 0 and even(n - 1); }
+                print even(10);
+                print odd(10);
+            }
+        "#),
+        ["true", "false"]
+    );
+}
@@ -0,0 +1,208 @@
+// Runs the vendored Crafting Interpreters test programs and checks them
+// against their annotations:
+//
+//   // expect: <line>                 a line the program prints
+//   // expect runtime error: <msg>    the program stops with a runtime error
+//   // Error at '<tok>': <msg>        the program does not compile
+//   // [line N] Error...              (same)
+//
+// Only the kind of error is checked, since our messages are worded
+// differently. The suite grows with every chapter vendored, so it only
+// runs when asked for:
+//
+//   cargo test --test crafting -- --ignored
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use lox::{context::Capture, error::LoxError, source::SourceMap, Interpreter};
+
+const SUITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/crafting");
+
+// Programs that are known not to pass, grouped by the reason. Entries are
+// paths relative to the suite, a trailing `/` skips a whole chapter
+const SKIP: &[(&str, &[&str])] = &[
+    ("functions print as <user fn>", &["function/print.lox"]),
+    ("`==` binds as tightly as `<`", &["operator/precedence.lox"]),
+    (
+        "calls with the wrong number of arguments do not compile",
+        &[
+            "function/extra_arguments.lox",
+            "function/missing_arguments.lox",
+        ],
+    ),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Ok,
+    CompileError,
+    RuntimeError,
+}
+
+#[derive(Debug, PartialEq)]
+struct Expected {
+    output: Vec<String>,
+    outcome: Outcome,
+}
+
+fn parse_annotations(src: &str) -> Expected {
+    let mut expected = Expected {
+        output: Vec::new(),
+        outcome: Outcome::Ok,
+    };
+    for line in src.lines() {
+        let Some((_, comment)) = line.split_once("//") else {
+            continue;
+        };
+        let comment = comment.trim_start();
+        if let Some(out) = comment.strip_prefix("expect: ") {
+            expected.output.push(out.to_string());
+        } else if comment.starts_with("expect runtime error:") {
+            expected.outcome = Outcome::RuntimeError;
+        } else if comment.starts_with("Error") || comment.starts_with("[line ") {
+            expected.outcome = Outcome::CompileError;
+        }
+    }
+    expected
+}
+
+fn run(name: &str, src: &str) -> (Vec<String>, Outcome) {
+    let out = Capture::default();
+    let mut lox = Interpreter::builder()
+        .prelude(false)
+        // The suite is written in the reference dialect
+        .jlox(true)
+        .output(Box::new(out.clone()))
+        .build();
+    let outcome = match lox.run(&SourceMap::new(name, src)) {
+        Ok(()) => Outcome::Ok,
+        Err(LoxError::Runtime(_)) => Outcome::RuntimeError,
+        Err(_) => Outcome::CompileError,
+    };
+    let text = out.take();
+    (text.lines().map(String::from).collect(), outcome)
+}
+
+// A line diff of the expected and actual output, using their longest
+// common subsequence. Empty if they are the same
+fn diff(expected: &[String], actual: &[String]) -> String {
+    let (n, m) = (expected.len(), actual.len());
+    let mut lcs = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            writeln!(out, "  {}", expected[i]).unwrap();
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            writeln!(out, "- {}", expected[i]).unwrap();
+            i += 1;
+        } else {
+            writeln!(out, "+ {}", actual[j]).unwrap();
+            j += 1;
+        }
+    }
+    if out.lines().all(|l| l.starts_with("  ")) {
+        out.clear();
+    }
+    out
+}
+
+fn programs(dir: &Path, found: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            programs(&path, found);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            found.push(path);
+        }
+    }
+}
+
+fn skip_reason(name: &str) -> Option<&'static str> {
+    SKIP.iter()
+        .find(|(_, paths)| {
+            paths
+                .iter()
+                .any(|p| name == *p || (p.ends_with('/') && name.starts_with(p)))
+        })
+        .map(|(reason, _)| *reason)
+}
+
+#[test]
+#[ignore]
+fn crafting_interpreters() {
+    let mut paths = Vec::new();
+    programs(Path::new(SUITE), &mut paths);
+    paths.sort();
+
+    let (mut passed, mut skipped, mut failures) = (0, 0, Vec::new());
+    for path in paths {
+        let name = path.strip_prefix(SUITE).unwrap().to_string_lossy();
+        if let Some(reason) = skip_reason(&name) {
+            println!("skip {name} ({reason})");
+            skipped += 1;
+            continue;
+        }
+        let src = fs::read_to_string(&path).unwrap();
+        let expected = parse_annotations(&src);
+        let (output, outcome) = run(&name, &src);
+
+        let mut report = diff(&expected.output, &output);
+        if outcome != expected.outcome {
+            writeln!(report, "expected {:?}, got {outcome:?}", expected.outcome).unwrap();
+        }
+        if report.is_empty() {
+            passed += 1;
+        } else {
+            failures.push(format!("{name}\n{report}"));
+        }
+    }
+
+    println!(
+        "{passed} passed, {} failed, {skipped} skipped",
+        failures.len()
+    );
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn annotations() {
+    let src = "print 1; // expect: 1\n\
+               f(); // expect runtime error: Expected 0 arguments but got 1.\n";
+    assert_eq!(
+        parse_annotations(src),
+        Expected {
+            output: vec!["1".to_string()],
+            outcome: Outcome::RuntimeError,
+        }
+    );
+    let src = "var a = a; // Error at 'a': Can't read local variable.";
+    assert_eq!(parse_annotations(src).outcome, Outcome::CompileError);
+}
+
+#[test]
+fn diffs() {
+    let lines = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    assert_eq!(diff(&lines("a b c"), &lines("a b c")), "");
+    assert_eq!(
+        diff(&lines("a b c"), &lines("a x c")),
+        "  a\n- b\n+ x\n  c\n"
+    );
+    assert_eq!(diff(&lines("a b"), &lines("a")), "  a\n- b\n");
+}
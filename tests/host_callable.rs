@@ -0,0 +1,102 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use lox::{
+    context::Context,
+    error::{LoxError, RuntimeError},
+    source::SourceMap,
+    val::{call_value, Callable, Val},
+    Interpreter,
+};
+
+// Remembers the results of a one argument function, keyed by the number
+// it was called with
+struct Memo {
+    f: Val,
+    cache: RefCell<HashMap<u64, Val>>,
+    misses: Rc<Cell<usize>>,
+}
+
+impl Callable for Memo {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(&self, ctx: &mut Context, args: Vec<Val>) -> Result<Val, RuntimeError> {
+        let Val::Number(x) = args[0] else {
+            return Err(RuntimeError::TypeMismatch {
+                msg: "memoized functions take a number",
+            });
+        };
+        if let Some(v) = self.cache.borrow().get(&x.to_bits()) {
+            return Ok(v.clone());
+        }
+        self.misses.set(self.misses.get() + 1);
+        let v = call_value(ctx, &self.f, args)?;
+        self.cache.borrow_mut().insert(x.to_bits(), v.clone());
+        Ok(v)
+    }
+}
+
+fn run(lox: &mut Interpreter, src: &str) -> Result<(), LoxError> {
+    lox.run(&SourceMap::new("test", src))
+}
+
+#[test]
+fn memoized_callable() {
+    let mut lox = Interpreter::builder().build();
+    run(
+        &mut lox,
+        "let calls = 0; fn square(x) { calls = calls + 1; return x * x; }",
+    )
+    .unwrap();
+    let misses = Rc::new(Cell::new(0));
+    let memo = Memo {
+        f: lox.global("square").unwrap(),
+        cache: RefCell::default(),
+        misses: Rc::clone(&misses),
+    };
+    lox.define("square", Val::HostCallable(Rc::new(memo)));
+
+    run(
+        &mut lox,
+        "let a = square(3); let b = square(3); let c = square(4);",
+    )
+    .unwrap();
+    assert_eq!(lox.global("a"), Some(Val::Number(9.0)));
+    assert_eq!(lox.global("b"), Some(Val::Number(9.0)));
+    assert_eq!(lox.global("c"), Some(Val::Number(16.0)));
+    assert_eq!(misses.get(), 2);
+    assert_eq!(lox.global("calls"), Some(Val::Number(2.0)));
+}
+
+#[test]
+fn host_callable_errors() {
+    let mut lox = Interpreter::builder().build();
+    run(&mut lox, "fn id(x) { return x; }").unwrap();
+    let memo = Memo {
+        f: lox.global("id").unwrap(),
+        cache: RefCell::default(),
+        misses: Rc::default(),
+    };
+    lox.define("id", Val::HostCallable(Rc::new(memo)));
+    assert_eq!(
+        run(&mut lox, "id(1, 2);"),
+        Err(LoxError::Runtime(RuntimeError::Arity {
+            callee: None,
+            min: 1,
+            max: 1,
+            got: 2,
+            decl: None,
+        }))
+    );
+    assert!(matches!(
+        run(&mut lox, "id(\"a\");"),
+        Err(LoxError::Runtime(RuntimeError::TypeMismatch { .. }))
+    ));
+    run(&mut lox, "let f = id; let same = f == id; print id;").unwrap();
+    assert_eq!(lox.global("same"), Some(Val::Boolean(true)));
+}
@@ -0,0 +1,88 @@
+// Programs split over several files, found under `tests/imports`
+
+use lox::{
+    context::Capture,
+    error::{LoxError, RuntimeError},
+    source::SourceMap,
+    Interpreter,
+};
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/imports");
+
+// Loads a program of the directory, returning how it ended and what it printed
+fn load(name: &str) -> (Result<(), LoxError>, String) {
+    let out = Capture::default();
+    let mut lox = Interpreter::builder().output(Box::new(out.clone())).build();
+    let res = lox.load(&format!("{DIR}/{name}"));
+    (res, out.take())
+}
+
+#[test]
+fn imported_function() {
+    assert_eq!(load("main.lox"), (Ok(()), "base\n42\n".to_string()));
+}
+
+#[test]
+fn diamond_runs_once() {
+    assert_eq!(load("diamond.lox"), (Ok(()), "base\n11\n".to_string()));
+}
+
+#[test]
+fn cycle() {
+    let lib = format!("{DIR}/lib");
+    assert_eq!(
+        load("cycle.lox").0,
+        Err(LoxError::Runtime(RuntimeError::ImportCycle {
+            chain: vec![
+                format!("{lib}/ping.lox"),
+                format!("{lib}/pong.lox"),
+                format!("{lib}/ping.lox"),
+            ]
+        }))
+    );
+}
+
+#[test]
+fn broken_import() {
+    assert_eq!(
+        load("broken.lox"),
+        (
+            Err(LoxError::Runtime(RuntimeError::ImportFailed {
+                path: format!("{DIR}/lib/broken.lox")
+            })),
+            String::new()
+        )
+    );
+}
+
+#[test]
+fn missing_import() {
+    let mut lox = Interpreter::builder().build();
+    let src = SourceMap::new("test", "import \"no/such/file.lox\";");
+    assert_eq!(
+        lox.run(&src),
+        Err(LoxError::Runtime(RuntimeError::ImportFailed {
+            path: "no/such/file.lox".to_string()
+        }))
+    );
+}
+
+// A file that failed to import is imported again once it is fixed
+#[test]
+fn retry_after_failure() {
+    let path = std::env::temp_dir().join(format!("lox-retry-{}.lox", std::process::id()));
+    let import = format!("import \"{}\";", path.display());
+    let out = Capture::default();
+    let mut lox = Interpreter::builder().output(Box::new(out.clone())).build();
+    let mut run = |text: &str, src: &str| {
+        std::fs::write(&path, text).unwrap();
+        lox.run(&SourceMap::new("test", src))
+    };
+    for broken in ["fn helper( {}", "fn helper() { return 1; } nope;"] {
+        assert!(run(broken, &import).is_err());
+    }
+    let fixed = "fn helper() { return 2; }";
+    assert_eq!(run(fixed, &format!("{import} print helper();")), Ok(()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(out.take(), "2\n");
+}
@@ -0,0 +1,69 @@
+// Counts the allocations made while lexing. Identifiers and strings are
+// interned, so a program that keeps repeating the same names allocates
+// for each of them once rather than once per token
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use lox::lexer::Lexer;
+
+struct Counting;
+
+thread_local! {
+    // Per thread, so that tests running alongside do not count
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// A program of `lines` lines, which uses 100 distinct names
+fn program(lines: usize) -> String {
+    (0..lines)
+        .map(|i| format!("let v{} = v{} + \"s{}\";\n", i % 100, (i + 1) % 100, i % 10))
+        .collect()
+}
+
+// The number of tokens and of allocations made lexing them
+fn lex(src: &str) -> (usize, usize) {
+    let before = ALLOCS.with(Cell::get);
+    let tokens = Lexer::new(src.chars()).count();
+    (tokens, ALLOCS.with(Cell::get) - before)
+}
+
+#[test]
+fn repeated_names_allocate_once() {
+    let src = program(10_000);
+    let (tokens, allocs) = lex(&src);
+    assert_eq!(tokens, 70_000);
+    // The 110 names and strings, plus the interner growing. Without
+    // interning it was one per identifier and string, 30,000
+    assert!(allocs < 500, "{allocs} allocations");
+}
+
+// Run with `cargo test --release --test lexer_allocs -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_lexing() {
+    let src = program(200_000);
+    let start = std::time::Instant::now();
+    let (tokens, allocs) = lex(&src);
+    println!(
+        "lexing {} MB, {tokens} tokens: {:?}, {allocs} allocations",
+        src.len() / 1_000_000,
+        start.elapsed()
+    );
+}
@@ -0,0 +1,80 @@
+// Metrics of the functions in a fixture, as `lox lint --metrics` reports them
+
+use lox::{
+    error::Warning,
+    metrics::{metrics, FunMetrics, Thresholds},
+    source::SourceMap,
+    Interpreter,
+};
+
+const SHAPES: &str = include_str!("metrics/shapes.lox");
+
+fn measure() -> Vec<FunMetrics> {
+    let mut lox = Interpreter::builder().prelude(false).build();
+    metrics(&lox.compile(&SourceMap::new("shapes.lox", SHAPES)).unwrap())
+}
+
+#[test]
+fn known_shapes() {
+    assert_eq!(
+        measure(),
+        vec![
+            FunMetrics {
+                name: "straight".to_string(),
+                statements: 3,
+                depth: 0,
+                params: 2,
+                captures: 0,
+                complexity: 1,
+            },
+            FunMetrics {
+                name: "counter".to_string(),
+                statements: 3,
+                depth: 0,
+                params: 1,
+                captures: 0,
+                complexity: 1,
+            },
+            FunMetrics {
+                name: "step".to_string(),
+                statements: 9,
+                depth: 2,
+                params: 1,
+                captures: 2,
+                complexity: 7,
+            },
+        ]
+    );
+}
+
+#[test]
+fn methods() {
+    let src = "class A { static make() { return A(); } get(x) { return this.x; } }";
+    let mut lox = Interpreter::builder().prelude(false).build();
+    let funcs = metrics(&lox.compile(&SourceMap::new("a.lox", src)).unwrap());
+    let names: Vec<_> = funcs.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["A.make", "A.get"]);
+    assert_eq!(funcs[1].captures, 0);
+}
+
+#[test]
+fn threshold_warning() {
+    let limits = Thresholds {
+        complexity: Some(5),
+        ..Thresholds::default()
+    };
+    let warnings: Vec<_> = measure().iter().flat_map(|f| f.check(&limits)).collect();
+    assert_eq!(
+        warnings,
+        vec![Warning::Metric {
+            func: "step".to_string(),
+            metric: "complexity",
+            value: 7,
+            max: 5,
+        }]
+    );
+    assert_eq!(
+        warnings[0].to_string(),
+        "'step' has a complexity of 7, over the limit of 5"
+    );
+}
@@ -0,0 +1,48 @@
+// Prints every program of the Crafting Interpreters suite that parses and
+// checks that parsing the printed text yields the same tree. Locations are
+// not compared, so the layout is free to differ from the original
+
+use std::{fs, path::Path};
+
+use lox::{lexer::Lexer, parser::RecursiveDescent, prog::Prog};
+
+const SUITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/crafting");
+
+fn parse(src: &str, jlox: bool) -> Option<Prog> {
+    let (tokens, errors) = Lexer::new(src.chars()).jlox(jlox).scan_all();
+    if !errors.is_empty() {
+        return None;
+    }
+    let (prog, errors, _) = RecursiveDescent::<Prog>::parse_with_recovery(tokens.into_iter());
+    errors.is_empty().then_some(prog)
+}
+
+fn programs(dir: &Path, found: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            programs(&path, found);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            found.push(fs::read_to_string(path).unwrap());
+        }
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut sources = Vec::new();
+    programs(Path::new(SUITE), &mut sources);
+    let mut checked = 0;
+    for src in &sources {
+        let Some(prog) = parse(src, true) else {
+            continue;
+        };
+        // Printed in our own dialect, so it must parse without jlox
+        let printed = prog.to_string();
+        let reparsed = parse(&printed, false)
+            .unwrap_or_else(|| panic!("printed program does not parse:\n{printed}"));
+        assert_eq!(reparsed.stmts, prog.stmts, "\n{src}\nprinted as\n{printed}");
+        checked += 1;
+    }
+    assert!(checked >= 50, "only {checked} programs parsed");
+}
@@ -0,0 +1,157 @@
+// Hostile scripts under `Interpreter::sandboxed`, each of which must be
+// stopped with its own error while leaving the interpreter usable
+
+use std::rc::Rc;
+
+use lox::{
+    context::{Capture, Context},
+    error::{LoxError, ParserError, RuntimeError},
+    sandbox,
+    source::SourceMap,
+    val::{Callable, Val},
+    Interpreter,
+};
+
+// Runs the script, then a harmless one in the same interpreter, returning
+// how the first ended and what the second printed
+fn contain(src: &str) -> (Result<(), LoxError>, String) {
+    let out = Capture::default();
+    let mut lox = Interpreter::sandboxed()
+        .output(Box::new(out.clone()))
+        .build();
+    let res = lox.run(&SourceMap::new("hostile", src));
+    out.take();
+    assert_eq!(lox.run(&SourceMap::new("after", "print 1 + 1;")), Ok(()));
+    (res, out.take())
+}
+
+fn stopped_with(src: &str, err: RuntimeError) {
+    assert_eq!(
+        contain(src),
+        (Err(LoxError::Runtime(err)), "2\n".to_string())
+    );
+}
+
+#[test]
+fn infinite_loop() {
+    let out_of_fuel = RuntimeError::OutOfFuel { max: sandbox::FUEL };
+    stopped_with("loop {}", out_of_fuel.clone());
+    // Catching the error does not give the fuel back
+    stopped_with(
+        "loop { try { loop {} } catch (e) { print e; } }",
+        out_of_fuel,
+    );
+}
+
+#[test]
+fn deep_recursion() {
+    stopped_with(
+        "fn f(n) { return f(n + 1); } f(0);",
+        RuntimeError::StackOverflow {
+            name: "f".to_string(),
+        },
+    );
+}
+
+// Each would overflow the native stack as it is parsed, resolved, run or
+// dropped, so it is refused before any of that
+#[test]
+fn deep_nesting() {
+    let n = 200_000;
+    for src in [
+        format!("print {}1{};", "(".repeat(n), ")".repeat(n)),
+        format!("print {}1;", "!".repeat(n)),
+        format!("{}print 1;{}", "{".repeat(n), "}".repeat(n)),
+        format!("print 1{};", " + 1".repeat(n)),
+        format!("print f{};", "()".repeat(n)),
+    ] {
+        let (res, out) = contain(&src);
+        assert!(
+            matches!(
+                res,
+                Err(LoxError::Parse(ref errors)) if matches!(
+                    errors[..],
+                    [ParserError::TooDeep { max: sandbox::MAX_NESTING, .. }]
+                )
+            ),
+            "{res:?}"
+        );
+        assert_eq!(
+            out,
+            "2
+"
+        );
+    }
+}
+
+// A host callable with a bug
+struct Panics;
+
+impl Callable for Panics {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(&self, _: &mut Context, _: Vec<Val>) -> Result<Val, RuntimeError> {
+        panic!("bug in the host");
+    }
+}
+
+#[test]
+fn host_panic() {
+    let mut lox = Interpreter::sandboxed().max_call_depth(10).build();
+    lox.define("panics", Val::HostCallable(Rc::new(Panics)));
+    let res = lox.run(&SourceMap::new("test", "fn f() { panics(); } f();"));
+    assert_eq!(
+        res,
+        Err(LoxError::Runtime(RuntimeError::Panicked {
+            message: "bug in the host".to_string()
+        }))
+    );
+    // The call it was in is forgotten, so all ten are left
+    let deepest = "fn f(n) { if (n > 1) f(n - 1); } f(10);";
+    assert_eq!(lox.run(&SourceMap::new("test", deepest)), Ok(()));
+}
+
+#[test]
+fn giant_string() {
+    let too_long = RuntimeError::StringTooLong {
+        max: sandbox::MAX_STRING_LEN,
+    };
+    stopped_with("let s = \"x\"; loop { s = s + s; }", too_long.clone());
+    stopped_with("print \"x\" * 1e7;", too_long);
+}
+
+#[test]
+fn giant_list() {
+    stopped_with(
+        "let xs = []; loop { push(xs, xs); }",
+        RuntimeError::ListTooLong {
+            max: sandbox::MAX_LIST_LEN,
+        },
+    );
+}
+
+#[test]
+fn file_read() {
+    stopped_with(
+        "import \"/etc/passwd\";",
+        RuntimeError::SandboxViolation(sandbox::IMPORT),
+    );
+}
+
+// The limits only ever lower what the host asked for
+#[test]
+fn host_limits_kept() {
+    let mut lox = Interpreter::sandboxed().max_call_depth(10).build();
+    let res = lox.run(&SourceMap::new(
+        "test",
+        "fn f(n) { if (n > 0) f(n - 1); } f(20);",
+    ));
+    assert_eq!(
+        res,
+        Err(LoxError::Runtime(RuntimeError::StackOverflow {
+            name: "f".to_string()
+        }))
+    );
+}